@@ -1,55 +1,466 @@
 //! ABCI Interface Implementation
-//! 
+//!
 //! This module implements the Tendermint ABCI (Application Blockchain Interface)
 //! for the Optimic blockchain, handling all consensus-related interactions.
-//! 
+//!
 //! NOTE: This is a simplified version for initial development.
 //! Full Tendermint integration will be added later.
 
-use crate::app::{OptimicApp, GenesisData};
+use crate::app::{GenesisData, OptimicApp};
+use crate::codes;
+use crate::consensus::CommitInfo;
+use crate::events::{BlockEvents, Event, TxEvents};
+use crate::fees;
+use crate::query::QueryError;
+use crate::state::{MemoryStorage, StateStorage};
+use crate::tx::{gas_used, tx_hash, Tx, TxHash};
+use crate::types::{AssetId, Timestamp, Uint128, ValAddress};
 use anyhow::{anyhow, Result};
-use tracing::{info, warn, error};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use tracing::{error, info, warn};
+
+/// Storage key of the last committed height
+const HEIGHT_KEY: &[u8] = b"app/height";
+
+/// Storage key of the last committed application state
+const STATE_KEY: &[u8] = b"app/state";
+
+/// Storage key of the state committed at `height`, kept for historical queries
+fn state_key_at(height: u64) -> Vec<u8> {
+    format!("app/state/{}", height).into_bytes()
+}
+
+fn block_key(height: u64) -> Vec<u8> {
+    format!("block/{}", height).into_bytes()
+}
+
+fn block_hash_key(hash: &str) -> Vec<u8> {
+    format!("block_hash/{}", hash).into_bytes()
+}
+
+fn events_key(height: u64) -> Vec<u8> {
+    format!("events/{}", height).into_bytes()
+}
+
+/// Storage key of the receipt of the transaction at `index` in the block at `height`
+fn tx_result_key(height: u64, index: u32) -> Vec<u8> {
+    format!("tx_result/{}/{}", height, index).into_bytes()
+}
+
+/// Storage key of the `height/index` location a transaction hash resolves to
+fn tx_key(hash: &str) -> Vec<u8> {
+    format!("tx/{}", hash).into_bytes()
+}
+
+/// Receipt of one transaction included in a block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxResult {
+    pub hash: TxHash,
+    pub height: u64,
+
+    /// Position of the transaction in the block
+    pub index: u32,
+
+    /// Result code, see [`crate::codes`]
+    pub code: u32,
+
+    /// Error message of a failed transaction
+    pub log: String,
+
+    /// Gas consumed, see [`crate::tx::gas_used`]
+    pub gas_used: u64,
+
+    /// Fees charged to the sender, by asset
+    #[serde(default)]
+    pub fees: BTreeMap<AssetId, Uint128>,
+
+    /// Hex-encoded transaction bytes
+    pub tx: String,
+
+    /// Events emitted by the transaction, empty if it failed
+    #[serde(default)]
+    pub events: Vec<Event>,
+}
+
+/// A committed block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Block {
+    pub height: u64,
+
+    /// Hex-encoded hash of the other header fields
+    pub hash: String,
+
+    pub time: Timestamp,
+
+    /// Validator that proposed the block
+    pub proposer: ValAddress,
+
+    /// Hash of the previous block, empty for the first block of the chain
+    pub prev_hash: String,
+
+    /// Hex-encoded application hash after executing the block
+    pub app_hash: String,
+
+    /// Hashes of the included transactions, in execution order
+    pub txs: Vec<TxHash>,
+}
+
+impl Block {
+    fn new(header: BlockHeader, app_hash: &[u8], tx_results: &[TxResult]) -> Result<Self> {
+        let mut block = Self {
+            height: header.height,
+            hash: String::new(),
+            time: header.time,
+            proposer: header.proposer,
+            prev_hash: header.prev_hash,
+            app_hash: hex::encode(app_hash),
+            txs: tx_results
+                .iter()
+                .map(|result| result.hash.clone())
+                .collect(),
+        };
+        let header = serde_json::to_vec(&serde_json::to_value(&block)?)?;
+        block.hash = hex::encode(Sha256::digest(header));
+        Ok(block)
+    }
+}
+
+/// Consensus inputs of a block
+#[derive(Debug, Clone)]
+pub struct BlockHeader {
+    pub height: u64,
+    pub time: Timestamp,
+    pub proposer: ValAddress,
+
+    /// Filled in from the stored previous block
+    pub prev_hash: String,
+
+    /// Signatures of the previous block and misbehavior evidence
+    pub commit_info: CommitInfo,
+}
+
+/// A committed block together with its transaction results
+#[derive(Debug, Clone)]
+pub struct BlockResult {
+    pub block: Block,
+    pub tx_results: Vec<TxResult>,
+
+    /// Events emitted by `end_block`
+    pub end_block_events: Vec<Event>,
+}
+
+impl BlockResult {
+    /// Every event of the block, grouped by transaction
+    pub fn events(&self) -> BlockEvents {
+        BlockEvents {
+            height: self.block.height,
+            txs: self
+                .tx_results
+                .iter()
+                .filter(|result| !result.events.is_empty())
+                .map(|result| TxEvents {
+                    hash: result.hash.clone(),
+                    events: result.events.clone(),
+                })
+                .collect(),
+            end_block: self.end_block_events.clone(),
+        }
+    }
+}
+
+/// Default number of recent heights kept for historical queries; each one
+/// is a full copy of the state, so only the latest is kept unless asked
+const DEFAULT_KEEP_RECENT: u64 = 0;
 
 /// ABCI Application wrapper for OptimicApp
 pub struct OptimicABCI {
     app: OptimicApp,
+    storage: Box<dyn StateStorage>,
+
+    /// Number of heights before the latest whose state is kept
+    keep_recent: u64,
 }
 
 impl OptimicABCI {
     /// Create a new ABCI application
     pub fn new(app: OptimicApp) -> Self {
-        Self { app }
+        Self::with_storage(app, Box::new(MemoryStorage::new()))
+    }
+
+    /// Create a new ABCI application persisting into `storage`
+    pub fn with_storage(app: OptimicApp, storage: Box<dyn StateStorage>) -> Self {
+        Self {
+            app,
+            storage,
+            keep_recent: DEFAULT_KEEP_RECENT,
+        }
+    }
+
+    /// Keep the state of the last `keep_recent` heights queryable
+    pub fn with_keep_recent(mut self, keep_recent: u64) -> Self {
+        self.keep_recent = keep_recent;
+        self
+    }
+
+    /// Restore the last committed state from storage, if any
+    ///
+    /// Returns `true` when stored state was found and loaded.
+    pub fn load_committed_state(&mut self) -> Result<bool> {
+        let (Some(height), Some(state)) =
+            (self.storage.get(HEIGHT_KEY)?, self.storage.get(STATE_KEY)?)
+        else {
+            return Ok(false);
+        };
+        let height = String::from_utf8_lossy(&height).parse()?;
+        self.app.restore(height, &state)?;
+        Ok(true)
+    }
+
+    /// Access the application
+    pub fn app(&self) -> &OptimicApp {
+        &self.app
+    }
+
+    /// Last committed block height
+    pub fn height(&self) -> u64 {
+        self.app.height
     }
-    
+
+    /// Query the latest state, or a recent height given as `?height=N`
+    pub fn query(&self, path: &str, data: &[u8]) -> Result<Vec<u8>, QueryError> {
+        let requested = crate::query::QueryRequest::parse(path)?.height()?;
+        let latest = self.app.height;
+        match requested {
+            Some(height) if height != latest => {
+                let unavailable = QueryError::HeightUnavailable {
+                    requested: height,
+                    latest,
+                };
+                if height > latest {
+                    return Err(unavailable);
+                }
+                self.historical_app(height)
+                    .map_err(|e| QueryError::Internal(e.to_string()))?
+                    .ok_or(unavailable)?
+                    .query(path, data)
+            }
+            _ => self.app.query(path, data),
+        }
+    }
+
+    /// Export the state at `height` as a genesis document
+    ///
+    /// The last committed height is always available; an earlier one only
+    /// while its state is still stored, that is within `keep_recent`.
+    pub fn export_genesis(&self, height: u64) -> Result<GenesisData> {
+        if height == self.app.height {
+            return self.app.export_genesis(height);
+        }
+        let unavailable = || {
+            anyhow!(
+                "state at height {} is not available (current height is {}, see storage.keep_recent)",
+                height,
+                self.app.height
+            )
+        };
+        if height > self.app.height {
+            return Err(unavailable());
+        }
+        self.historical_app(height)?
+            .ok_or_else(unavailable)?
+            .export_genesis(height)
+    }
+
+    /// Application restored from the state stored for `height`, if kept
+    fn historical_app(&self, height: u64) -> Result<Option<OptimicApp>> {
+        let Some(state) = self.storage.get(&state_key_at(height))? else {
+            return Ok(None);
+        };
+        let mut historical = OptimicApp::new(self.app.config.clone());
+        historical.restore(height, &state)?;
+        Ok(Some(historical))
+    }
+
+    /// Committed block at `height`
+    pub fn block(&self, height: u64) -> Result<Option<Block>> {
+        self.storage
+            .get(&block_key(height))?
+            .map(|bytes| Ok(serde_json::from_slice(&bytes)?))
+            .transpose()
+    }
+
+    /// Committed block with the given hex-encoded hash
+    pub fn block_by_hash(&self, hash: &str) -> Result<Option<Block>> {
+        match self.storage.get(&block_hash_key(hash))? {
+            Some(height) => self.block(String::from_utf8_lossy(&height).parse()?),
+            None => Ok(None),
+        }
+    }
+
+    /// Events of the committed block at `height`
+    pub fn block_events(&self, height: u64) -> Result<Option<BlockEvents>> {
+        self.storage
+            .get(&events_key(height))?
+            .map(|bytes| Ok(serde_json::from_slice(&bytes)?))
+            .transpose()
+    }
+
+    /// Receipts of every transaction in the committed block at `height`
+    pub fn block_results(&self, height: u64) -> Result<Option<Vec<TxResult>>> {
+        let Some(block) = self.block(height)? else {
+            return Ok(None);
+        };
+        let mut results = Vec::with_capacity(block.txs.len());
+        for index in 0..block.txs.len() {
+            if let Some(result) = self.tx_result(height, index as u32)? {
+                results.push(result);
+            }
+        }
+        Ok(Some(results))
+    }
+
+    /// Receipt of a committed transaction
+    ///
+    /// The same transaction bytes can be included more than once, say as a
+    /// rejected replay; the hash resolves to the receipt that executed it,
+    /// or else to the first one.
+    pub fn tx(&self, hash: &str) -> Result<Option<TxResult>> {
+        let Some(location) = self.storage.get(&tx_key(hash))? else {
+            return Ok(None);
+        };
+        let location = String::from_utf8_lossy(&location);
+        let (height, index) = location
+            .split_once('/')
+            .ok_or_else(|| anyhow!("invalid receipt location '{}'", location))?;
+        self.tx_result(height.parse()?, index.parse()?)
+    }
+
+    /// Receipt of the transaction at `index` in the block at `height`
+    fn tx_result(&self, height: u64, index: u32) -> Result<Option<TxResult>> {
+        self.storage
+            .get(&tx_result_key(height, index))?
+            .map(|bytes| Ok(serde_json::from_slice(&bytes)?))
+            .transpose()
+    }
+
     /// Simplified interface for initial development
-    pub fn process_block(&mut self, height: u64, transactions: Vec<Vec<u8>>) -> Result<Vec<u8>> {
-        info!("Processing block {} with {} transactions", height, transactions.len());
-        
+    pub fn process_block(
+        &mut self,
+        mut header: BlockHeader,
+        transactions: Vec<Vec<u8>>,
+    ) -> Result<BlockResult> {
+        let height = header.height;
+        info!(
+            "Processing block {} with {} transactions",
+            height,
+            transactions.len()
+        );
+        header.prev_hash = match height.checked_sub(1) {
+            Some(prev) => self
+                .block(prev)?
+                .map(|block| block.hash)
+                .unwrap_or_default(),
+            None => String::new(),
+        };
+
         // Begin block
-        self.app.begin_block(height)?;
-        
+        self.app.begin_block(height, header.time)?;
+        self.app.commit_info = header.commit_info.clone();
+
         // Process transactions
-        for tx_bytes in transactions {
-            // Check transaction
-            if let Err(e) = self.app.check_tx(&tx_bytes) {
-                warn!("Transaction validation failed: {}", e);
-                continue;
-            }
-            
-            // Execute transaction
-            if let Err(e) = self.app.deliver_tx(&tx_bytes) {
-                error!("Transaction execution failed: {}", e);
-                continue;
-            }
+        let mut tx_results = Vec::with_capacity(transactions.len());
+        for (index, tx_bytes) in transactions.iter().enumerate() {
+            // Decode transaction, then execute it; `deliver_tx` checks it
+            // against the height being built
+            let mut msgs = 0;
+            let mut sender = None;
+            let outcome: Result<Vec<Event>> =
+                match Tx::decode(tx_bytes).map_err(anyhow::Error::from) {
+                    Err(e) => {
+                        warn!("Transaction decoding failed: {}", e);
+                        Err(e)
+                    }
+                    Ok(tx) => {
+                        msgs = tx.body.msgs.len();
+                        sender = Some(tx.body.sender);
+                        self.app.deliver_tx(tx_bytes).inspect_err(|e| {
+                            error!("Transaction execution failed: {}", e);
+                        })
+                    }
+                };
+
+            let (code, log, events) = match outcome {
+                Ok(events) => (codes::OK, String::new(), events),
+                Err(e) => (codes::result_code(&e), e.to_string(), vec![]),
+            };
+            tx_results.push(TxResult {
+                hash: tx_hash(tx_bytes),
+                height,
+                index: index as u32,
+                code,
+                log,
+                gas_used: gas_used(tx_bytes.len(), msgs),
+                fees: match &sender {
+                    Some(sender) => fees::charged(&events, sender)?,
+                    None => BTreeMap::new(),
+                },
+                tx: hex::encode(tx_bytes),
+                events,
+            });
         }
-        
+
         // End block
-        self.app.end_block()?;
-        
+        let end_block_events = self.app.end_block()?;
+
         // Commit
         let app_hash = self.app.commit()?;
-        
-        Ok(app_hash)
+        let block = Block::new(header, &app_hash, &tx_results)?;
+        let result = BlockResult {
+            block,
+            tx_results,
+            end_block_events,
+        };
+
+        // Persist
+        let state = self.app.encode_state()?;
+        if self.keep_recent > 0 {
+            self.storage.set(&state_key_at(height), state.clone())?;
+            if let Some(pruned) = height.checked_sub(self.keep_recent + 1) {
+                self.storage.delete(&state_key_at(pruned))?;
+            }
+        }
+        self.storage.set(STATE_KEY, state)?;
+        self.storage
+            .set(HEIGHT_KEY, height.to_string().into_bytes())?;
+        self.storage
+            .set(&block_key(height), serde_json::to_vec(&result.block)?)?;
+        self.storage.set(
+            &block_hash_key(&result.block.hash),
+            height.to_string().into_bytes(),
+        )?;
+        self.storage
+            .set(&events_key(height), serde_json::to_vec(&result.events())?)?;
+        for tx_result in &result.tx_results {
+            self.storage.set(
+                &tx_result_key(height, tx_result.index),
+                serde_json::to_vec(tx_result)?,
+            )?;
+            let indexed = match self.tx(&tx_result.hash)? {
+                Some(existing) => existing.code == codes::OK || tx_result.code != codes::OK,
+                None => false,
+            };
+            if !indexed {
+                self.storage.set(
+                    &tx_key(&tx_result.hash),
+                    format!("{}/{}", height, tx_result.index).into_bytes(),
+                )?;
+            }
+        }
+        self.storage.commit()?;
+
+        Ok(result)
     }
 }
 
@@ -72,7 +483,7 @@ impl Application for OptimicABCI {
     /// Begin processing a new block
     fn begin_block(&mut self, request: BeginBlock) -> BeginBlockResponse {
         let height = request.header.as_ref().map(|h| h.height.value()).unwrap_or(0);
-        
+
         match self.app.begin_block(height) {
             Ok(_) => {
                 info!("Successfully began block {}", height);
@@ -151,7 +562,7 @@ impl Application for OptimicABCI {
     /// Query application state
     fn query(&mut self, request: Query) -> QueryResponse {
         let path = String::from_utf8_lossy(&request.path);
-        
+
         match self.app.query(&path, &request.data) {
             Ok(data) => QueryResponse {
                 code: Code::Ok,
@@ -185,7 +596,7 @@ impl OptimicABCI {
 
         // Return initial validator set
         let validators = request.validators.clone();
-        
+
         Ok(InitChainResponse {
             validators,
             ..Default::default()
@@ -224,6 +635,7 @@ impl OptimicABCI {
                 rate: "0.05".to_string(),        // 5%
                 max_rate: "0.20".to_string(),    // 20%
                 max_change_rate: "0.01".to_string(), // 1% per day
+                update_time: None,
             },
         };
 
@@ -233,6 +645,7 @@ impl OptimicABCI {
             base_asset: "ETH".to_string(),
             quote_asset: "USD".to_string(),
             min_order_size: "1000000".to_string(), // 0.001 ETH
+            lot_size: "1".to_string(),
             tick_size: "0.01".to_string(),         // $0.01
             market_type: MarketType::Spot,
             status: MarketStatus::Active,
@@ -243,6 +656,7 @@ impl OptimicABCI {
             base_asset: "BTC".to_string(),
             quote_asset: "USD".to_string(),
             min_order_size: "1000".to_string(),    // 0.00001 BTC
+            lot_size: "1".to_string(),
             tick_size: "1.0".to_string(),          // $1.00
             market_type: MarketType::Spot,
             status: MarketStatus::Active,
@@ -258,3 +672,81 @@ impl OptimicABCI {
     }
 }
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::AppConfig;
+    use crate::bank;
+    use crate::keys::KeyPair;
+    use crate::tx::{Msg, TxBody};
+    use crate::types::Coin;
+
+    fn header(height: u64) -> BlockHeader {
+        BlockHeader {
+            height,
+            time: Timestamp::UNIX_EPOCH,
+            proposer: String::new(),
+            prev_hash: String::new(),
+            commit_info: CommitInfo::default(),
+        }
+    }
+
+    #[test]
+    fn test_repeated_transactions_keep_their_receipts() {
+        let mut app = OptimicApp::new(AppConfig {
+            chain_id: "optimic-1".to_string(),
+            genesis_path: concat!(env!("CARGO_MANIFEST_DIR"), "/genesis.json").to_string(),
+            data_dir: String::new(),
+        });
+        app.load_genesis().unwrap();
+        let key = KeyPair::generate();
+        bank::mint(
+            &mut app.state,
+            &key.account_address(),
+            &"USD".to_string(),
+            100,
+        )
+        .unwrap();
+        let mut abci = OptimicABCI::new(app);
+        let send = |sequence| {
+            let body = TxBody {
+                chain_id: "optimic-1".to_string(),
+                sender: key.account_address(),
+                sequence,
+                timeout_height: 0,
+                msgs: vec![Msg::Send {
+                    to: KeyPair::generate().account_address(),
+                    amount: vec![Coin {
+                        denom: "USD".to_string(),
+                        amount: "1".to_string(),
+                    }],
+                }],
+                memo: String::new(),
+            };
+            Tx::sign(body, &key).unwrap().encode().unwrap()
+        };
+
+        // A replay in a later block is rejected without replacing the receipt
+        let first = send(0);
+        abci.process_block(header(1), vec![first.clone()]).unwrap();
+        let replay = abci.process_block(header(2), vec![first.clone()]).unwrap();
+        assert_eq!(replay.tx_results[0].code, codes::TX_SEQUENCE_USED);
+        let receipt = abci.tx(&tx_hash(&first)).unwrap().unwrap();
+        assert_eq!((receipt.height, receipt.code), (1, codes::OK));
+        let results = abci.block_results(2).unwrap().unwrap();
+        assert_eq!(results, replay.tx_results);
+
+        // Within a block, the hash resolves to the copy that executed
+        let second = send(1);
+        let result = abci
+            .process_block(header(3), vec![second.clone(), second.clone()])
+            .unwrap();
+        assert_eq!(abci.block_results(3).unwrap().unwrap(), result.tx_results);
+        let receipt = abci.tx(&tx_hash(&second)).unwrap().unwrap();
+        assert_eq!(
+            (receipt.height, receipt.index, receipt.code),
+            (3, 0, codes::OK)
+        );
+    }
+}
@@ -1,57 +1,138 @@
 //! Optimic Application
-//! 
+//!
 //! This module implements the main ABCI application that handles
 //! all blockchain state transitions and business logic.
 
+use crate::bank;
+use crate::bridge::{self, BridgeParams, BridgeState};
+use crate::consensus::{CommitInfo, ValidatorUpdate};
+use crate::distribution::{self, DistributionParams, DistributionState};
+use crate::events::{CancelReason, Event};
+use crate::fees::{self, FeeSchedule, FeeState, StakingDiscount, TREASURY};
+use crate::governance::{self, GovernanceParams, GovernanceState, ProposalContent, ProposalId};
+use crate::keys::{derive_address, verify_signature, ACCOUNT_PREFIX};
+use crate::markets::{self, MarketParams};
+use crate::math::{parse_amount, parse_decimal, parse_fraction};
+use crate::oracle::{self, OracleParams, OracleState};
+use crate::portfolio;
+use crate::query::QueryError;
+use crate::slashing::{self, SlashingParams, SlashingState};
+use crate::staking::{self, StakingParams, StakingState};
+use crate::state::StateError;
+use crate::trading::{TradingEngine, TradingError};
+use crate::tx::{Msg, Output, Tx, TxError};
 use crate::types::*;
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use tracing::{info, warn, error};
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use tracing::{debug, info};
 
 /// Main Optimic application state
 #[derive(Debug, Clone)]
 pub struct OptimicApp {
     /// Current block height
     pub height: u64,
-    
+
     /// Application state
     pub state: AppState,
-    
+
+    /// Time of the current block
+    pub block_time: Timestamp,
+
+    /// Order books, rebuilt from `state.orders`
+    pub trading: TradingEngine,
+
+    /// Events of the transaction or block step being executed
+    events: Vec<Event>,
+
+    /// Signatures of the previous block and evidence, set by consensus
+    /// after `begin_block` and handled by `end_block`
+    pub commit_info: CommitInfo,
+
+    /// Voting power changes of the last `end_block`
+    pub validator_updates: Vec<ValidatorUpdate>,
+
     /// Configuration
     pub config: AppConfig,
 }
 
 /// Application state containing all blockchain data
+///
+/// Collections that grow with the chain are persistent (`imbl`) maps:
+/// cloning one is O(1) and a later write copies only the path to the entry
+/// it changes, so transactions can snapshot the state to roll back.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppState {
     /// All user accounts
-    pub accounts: HashMap<AccAddress, Account>,
-    
+    pub accounts: imbl::HashMap<AccAddress, Account>,
+
+    /// Asset registry
+    #[serde(default)]
+    pub assets: imbl::HashMap<AssetId, AssetMetadata>,
+
+    /// Total supply of every asset
+    #[serde(default)]
+    pub supply: imbl::HashMap<AssetId, Uint128>,
+
     /// All validators
-    pub validators: HashMap<ValAddress, Validator>,
-    
+    pub validators: imbl::HashMap<ValAddress, Validator>,
+
     /// All markets
-    pub markets: HashMap<MarketId, Market>,
-    
+    pub markets: imbl::HashMap<MarketId, Market>,
+
     /// All orders
-    pub orders: HashMap<OrderId, Order>,
-    
+    pub orders: imbl::HashMap<OrderId, Order>,
+
     /// All option contracts
-    pub options: HashMap<OptionId, OptionContract>,
-    
+    pub options: imbl::HashMap<OptionId, OptionContract>,
+
     /// All trades
-    pub trades: HashMap<TradeId, Trade>,
-    
+    pub trades: imbl::HashMap<TradeId, Trade>,
+
     /// Portfolio data
-    pub portfolios: HashMap<AccAddress, Portfolio>,
-    
+    pub portfolios: imbl::HashMap<AccAddress, Portfolio>,
+
+    /// Last trade price by market; positions are marked at the oracle
+    /// price instead when both assets of the market are priced
+    #[serde(default)]
+    pub mark_prices: imbl::HashMap<MarketId, Price>,
+
+    /// Processed deposits, withdrawal requests and rate limit usage
+    #[serde(default)]
+    pub bridge: BridgeState,
+
+    /// Price submissions and aggregated oracle prices
+    #[serde(default)]
+    pub oracle: OracleState,
+
+    /// Delegations, unbonding entries and the active validator set
+    #[serde(default)]
+    pub staking: StakingState,
+
+    /// Reward periods, commission and delegator starting points
+    #[serde(default)]
+    pub distribution: DistributionState,
+
+    /// Signing records of validators
+    #[serde(default)]
+    pub slashing: SlashingState,
+
+    /// Trading volume for fee tiers
+    #[serde(default)]
+    pub fees: FeeState,
+
+    /// Proposals, deposits and votes
+    #[serde(default)]
+    pub governance: GovernanceState,
+
     /// Next order ID
     pub next_order_id: OrderId,
-    
+
     /// Next trade ID
     pub next_trade_id: TradeId,
-    
+
     /// Chain parameters
     pub params: ChainParams,
 }
@@ -61,18 +142,46 @@ pub struct AppState {
 pub struct ChainParams {
     /// Native token symbol
     pub native_token: String,
-    
+
     /// Block time in seconds
     pub block_time: u64,
-    
+
     /// Maximum block size
     pub max_block_size: u64,
-    
+
     /// Trading fees
     pub trading_fees: TradingFees,
-    
+
     /// Collateral parameters
     pub collateral_params: CollateralParams,
+
+    /// Bridge attesters and limits
+    #[serde(default)]
+    pub bridge: BridgeParams,
+
+    /// Price feeders and aggregation settings
+    #[serde(default)]
+    pub oracle: OracleParams,
+
+    /// Validator set size and unbonding period
+    #[serde(default)]
+    pub staking: StakingParams,
+
+    /// Block reward emission
+    #[serde(default)]
+    pub distribution: DistributionParams,
+
+    /// Downtime and double-sign penalties
+    #[serde(default)]
+    pub slashing: SlashingParams,
+
+    /// Proposal deposits, voting period and tally thresholds
+    #[serde(default)]
+    pub governance: GovernanceParams,
+
+    /// Who lists markets and changes their status besides governance
+    #[serde(default)]
+    pub markets: MarketParams,
 }
 
 /// Trading fee configuration
@@ -80,10 +189,26 @@ pub struct ChainParams {
 pub struct TradingFees {
     /// Premium fee rate (percentage)
     pub premium_fee_rate: String,
-    
+
     /// Penalty fee rate (percentage)
     pub penalty_fee_rate: String,
-    
+
+    /// Maker and taker rates of markets without their own schedule
+    #[serde(default)]
+    pub default_schedule: FeeSchedule,
+
+    /// Maker and taker rates by market
+    #[serde(default)]
+    pub market_schedules: BTreeMap<MarketId, FeeSchedule>,
+
+    /// Days of trading volume counted towards volume tiers
+    #[serde(default = "fees::default_volume_window_days")]
+    pub volume_window_days: u64,
+
+    /// Discounts for accounts staking the native token, by ascending stake
+    #[serde(default = "fees::default_staking_discounts")]
+    pub staking_discounts: Vec<StakingDiscount>,
+
     /// Fee distribution
     pub fee_distribution: FeeDistribution,
 }
@@ -93,13 +218,13 @@ pub struct TradingFees {
 pub struct FeeDistribution {
     /// Percentage to liquidity providers
     pub to_liquidity_providers: String,
-    
+
     /// Percentage to OMC stakers
     pub to_stakers: String,
-    
+
     /// Percentage to burn
     pub to_burn: String,
-    
+
     /// Percentage to treasury
     pub to_treasury: String,
 }
@@ -109,13 +234,13 @@ pub struct FeeDistribution {
 pub struct CollateralParams {
     /// Minimum collateral ratio for buyers
     pub buyer_min_collateral_ratio: String,
-    
+
     /// Minimum collateral ratio for sellers
     pub seller_min_collateral_ratio: String,
-    
+
     /// Liquidation threshold
     pub liquidation_threshold: String,
-    
+
     /// Penalty distribution
     pub penalty_distribution: PenaltyDistribution,
 }
@@ -125,7 +250,7 @@ pub struct CollateralParams {
 pub struct PenaltyDistribution {
     /// Percentage to platform
     pub to_platform: String,
-    
+
     /// Percentage to counterparty
     pub to_counterparty: String,
 }
@@ -135,10 +260,10 @@ pub struct PenaltyDistribution {
 pub struct AppConfig {
     /// Chain ID
     pub chain_id: String,
-    
+
     /// Genesis file path
     pub genesis_path: String,
-    
+
     /// Data directory
     pub data_dir: String,
 }
@@ -147,13 +272,23 @@ impl OptimicApp {
     /// Create a new Optimic application
     pub fn new(config: AppConfig) -> Self {
         let state = AppState {
-            accounts: HashMap::new(),
-            validators: HashMap::new(),
-            markets: HashMap::new(),
-            orders: HashMap::new(),
-            options: HashMap::new(),
-            trades: HashMap::new(),
-            portfolios: HashMap::new(),
+            accounts: imbl::HashMap::new(),
+            assets: imbl::HashMap::new(),
+            supply: imbl::HashMap::new(),
+            validators: imbl::HashMap::new(),
+            markets: imbl::HashMap::new(),
+            orders: imbl::HashMap::new(),
+            options: imbl::HashMap::new(),
+            trades: imbl::HashMap::new(),
+            portfolios: imbl::HashMap::new(),
+            mark_prices: imbl::HashMap::new(),
+            bridge: BridgeState::default(),
+            oracle: OracleState::default(),
+            staking: StakingState::default(),
+            distribution: DistributionState::default(),
+            slashing: SlashingState::default(),
+            fees: FeeState::default(),
+            governance: GovernanceState::default(),
             next_order_id: 1,
             next_trade_id: 1,
             params: ChainParams::default(),
@@ -162,17 +297,58 @@ impl OptimicApp {
         Self {
             height: 0,
             state,
+            block_time: Timestamp::UNIX_EPOCH,
+            trading: TradingEngine::new(),
+            events: Vec::new(),
+            commit_info: CommitInfo::default(),
+            validator_updates: Vec::new(),
             config,
         }
     }
 
+    /// Load, validate and apply the genesis file at `config.genesis_path`
+    pub fn load_genesis(&mut self) -> Result<()> {
+        let genesis = GenesisData::from_file(&self.config.genesis_path)?;
+        if genesis.chain_id != self.config.chain_id {
+            return Err(anyhow!(
+                "genesis chain_id '{}' does not match configured chain_id '{}'",
+                genesis.chain_id,
+                self.config.chain_id
+            ));
+        }
+        self.init_genesis(genesis)
+    }
+
     /// Initialize the application with genesis state
     pub fn init_genesis(&mut self, genesis_data: GenesisData) -> Result<()> {
-        info!("Initializing genesis state");
+        info!(
+            "Initializing genesis state for chain {}",
+            genesis_data.chain_id
+        );
+
+        genesis_data.validate()?;
+
+        // An exported genesis carries the complete application state
+        if let Some(mut state) = genesis_data.app_state {
+            self.trading = TradingEngine::rebuild(&state.markets, &state.orders)?;
+            bank::init_supply(&mut state)?;
+            self.state = state;
+            self.height = genesis_data.initial_height.saturating_sub(1);
+            info!(
+                "Genesis state restored from export at height {}",
+                self.height
+            );
+            return Ok(());
+        }
 
         // Set chain parameters
         self.state.params = genesis_data.params;
 
+        // Register genesis assets
+        for asset in genesis_data.assets {
+            self.state.assets.insert(asset.id.clone(), asset);
+        }
+
         // Initialize genesis accounts
         for account in genesis_data.accounts {
             self.state.accounts.insert(account.address.clone(), account);
@@ -180,127 +356,3216 @@ impl OptimicApp {
 
         // Initialize genesis validators
         for validator in genesis_data.validators {
-            self.state.validators.insert(validator.operator_address.clone(), validator);
+            self.state
+                .validators
+                .insert(validator.operator_address.clone(), validator);
         }
 
         // Initialize genesis markets
         for market in genesis_data.markets {
+            self.trading.add_market(&market)?;
             self.state.markets.insert(market.id.clone(), market);
         }
 
+        staking::init_genesis(&mut self.state, self.block_time)?;
+        bank::init_supply(&mut self.state)?;
+        self.height = genesis_data.initial_height.saturating_sub(1);
+
         info!("Genesis state initialized successfully");
         Ok(())
     }
 
+    /// Export the application state at `height` as a genesis document
+    ///
+    /// The exported genesis starts the new chain at `height + 1` and carries
+    /// the complete `AppState`, so it can be used for chain upgrades. Only
+    /// the current height is held in memory; earlier heights are exported
+    /// from stored state by [`crate::abci::OptimicABCI::export_genesis`].
+    pub fn export_genesis(&self, height: u64) -> Result<GenesisData> {
+        if height != self.height {
+            return Err(anyhow!(
+                "state at height {} is not available (current height is {})",
+                height,
+                self.height
+            ));
+        }
+
+        info!("Exporting genesis at height {}", height);
+
+        Ok(GenesisData {
+            chain_id: self.config.chain_id.clone(),
+            initial_height: height + 1,
+            params: self.state.params.clone(),
+            assets: vec![],
+            accounts: vec![],
+            validators: vec![],
+            markets: vec![],
+            app_state: Some(self.state.clone()),
+        })
+    }
+
+    /// Validator expected to propose the next block
+    ///
+    /// Blocks are produced by a single validator for now: the bonded,
+    /// unjailed validator with the most tokens (ties by address).
+    pub fn proposer(&self) -> Option<ValAddress> {
+        self.state
+            .validators
+            .values()
+            .filter(|v| matches!(v.status, BondStatus::Bonded) && !v.jailed)
+            .max_by(|a, b| {
+                let tokens = |v: &Validator| parse_amount(&v.tokens).unwrap_or(0);
+                tokens(a)
+                    .cmp(&tokens(b))
+                    .then_with(|| b.operator_address.cmp(&a.operator_address))
+            })
+            .map(|v| v.operator_address.clone())
+    }
+
     /// Process a new block
-    pub fn begin_block(&mut self, height: u64) -> Result<()> {
+    pub fn begin_block(&mut self, height: u64, time: Timestamp) -> Result<()> {
         info!("Beginning block {}", height);
         self.height = height;
-        
+        self.block_time = time;
+
         // TODO: Implement begin block logic
         // - Update validator set
         // - Process expired options
         // - Calculate rewards
-        
+
         Ok(())
     }
 
-    /// End block processing
-    pub fn end_block(&mut self) -> Result<()> {
+    /// End block processing, returning the events it emitted
+    pub fn end_block(&mut self) -> Result<Vec<Event>> {
         info!("Ending block {}", self.height);
-        
+        self.events.clear();
+
+        // Expire good-till-date orders before they can trade again
+        let expired = self
+            .trading
+            .expire_orders(&mut self.state.orders, self.block_time)?;
+        for order_id in expired {
+            portfolio::release(&mut self.state, order_id)?;
+            self.emit_cancelled(order_id, CancelReason::Expired);
+        }
+
+        // Clear batch auction markets
+        let bands = markets::price_bands(&self.state)?;
+        let auctions = self.trading.run_auctions(
+            &mut self.state.orders,
+            &mut self.state.next_trade_id,
+            self.block_time,
+            &bands,
+        )?;
+        self.record_trades(auctions.trades)?;
+        for order_id in auctions.expired {
+            portfolio::release(&mut self.state, order_id)?;
+            self.emit_cancelled(order_id, CancelReason::TimeInForce);
+        }
+        let prices = oracle::aggregate(&mut self.state, self.height)?;
+        self.events.extend(prices);
+        portfolio::mark_to_market(&mut self.state)?;
+        let rewards = distribution::allocate_block_rewards(&mut self.state, self.height)?;
+        self.events.extend(rewards);
+        let fees = fees::distribute(&mut self.state)?;
+        self.events.extend(fees);
+        let commit_info = std::mem::take(&mut self.commit_info);
+        let slashes =
+            slashing::handle_commit(&mut self.state, self.height, self.block_time, &commit_info)?;
+        self.events.extend(slashes);
+        let (staking_events, updates) = staking::end_block(&mut self.state, self.block_time)?;
+        self.events.extend(staking_events);
+        self.validator_updates = updates;
+        let (governance_events, passed) = governance::end_block(&mut self.state, self.block_time)?;
+        self.events.extend(governance_events);
+        for proposal_id in passed {
+            let events = self.execute_proposal(proposal_id)?;
+            self.events.extend(events);
+        }
+        portfolio::check_invariants(&self.state)?;
+        bank::check_invariants(&self.state)?;
+        staking::check_invariants(&self.state)?;
+        distribution::check_invariants(&self.state)?;
+
         // TODO: Implement end block logic
-        // - Distribute rewards
-        // - Update validator voting power
         // - Process pending liquidations
-        
+
+        Ok(std::mem::take(&mut self.events))
+    }
+
+    /// Check if a transaction is valid for the next block
+    ///
+    /// The sequence may run ahead of the account sequence so that a sender
+    /// can queue several transactions; `deliver_tx` requires the exact value.
+    pub fn check_tx(&self, tx_bytes: &[u8]) -> Result<Tx> {
+        self.verify_tx(tx_bytes, self.height + 1)
+    }
+
+    /// Check if a transaction is valid for inclusion at `height`
+    fn verify_tx(&self, tx_bytes: &[u8], height: u64) -> Result<Tx> {
+        let tx = Tx::decode(tx_bytes)?;
+        let body = &tx.body;
+
+        if body.chain_id != self.config.chain_id {
+            return Err(TxError::WrongChain {
+                got: body.chain_id.clone(),
+                expected: self.config.chain_id.clone(),
+            }
+            .into());
+        }
+        if crate::tx::timed_out(body.timeout_height, height) {
+            return Err(TxError::TimedOut(body.timeout_height).into());
+        }
+
+        let account = self
+            .state
+            .accounts
+            .get(&body.sender)
+            .ok_or_else(|| TxError::UnknownSender(body.sender.clone()))?;
+        verify_tx_signature(&tx, account)?;
+        if body.sequence < account.sequence {
+            return Err(TxError::SequenceUsed {
+                sequence: body.sequence,
+                expected: account.sequence,
+            }
+            .into());
+        }
+
+        for msg in &body.msgs {
+            self.validate_msg(&body.sender, msg)?;
+        }
+
+        Ok(tx)
+    }
+
+    /// Execute a transaction, returning the events it emitted
+    ///
+    /// A failed transaction emits no events.
+    pub fn deliver_tx(&mut self, tx_bytes: &[u8]) -> Result<Vec<Event>> {
+        self.events.clear();
+        let tx = self.verify_tx(tx_bytes, self.height)?;
+        let sender = tx.body.sender.clone();
+
+        let account = self
+            .state
+            .accounts
+            .get_mut(&sender)
+            .ok_or_else(|| TxError::UnknownSender(sender.clone()))?;
+        if tx.body.sequence != account.sequence {
+            return Err(TxError::SequenceMismatch {
+                sequence: tx.body.sequence,
+                expected: account.sequence,
+            }
+            .into());
+        }
+        account.sequence += 1;
+        if account.public_key.is_none() {
+            account.public_key = Some(tx.public_key.clone());
+        }
+
+        // A message can fail halfway through, so every transaction rolls back
+        // to a snapshot. The snapshot keeps the sequence bump so a failed
+        // transaction cannot be replayed, and holds the order books so they
+        // never disagree with the state. State and books share structure with
+        // their snapshot, so taking one costs nothing and the transaction
+        // only pays for the entries it writes.
+        let snapshot = (self.state.clone(), self.trading.clone());
+        for msg in tx.body.msgs {
+            if let Err(e) = self.execute_msg(&sender, msg) {
+                (self.state, self.trading) = snapshot;
+                self.events.clear();
+                return Err(e);
+            }
+        }
+
+        Ok(std::mem::take(&mut self.events))
+    }
+
+    /// Stateful validation of a single message
+    fn validate_msg(&self, sender: &AccAddress, msg: &Msg) -> Result<()> {
+        match msg {
+            Msg::PlaceOrder {
+                market,
+                side,
+                order_type,
+                quantity,
+                price,
+                time_in_force,
+            } => {
+                let market = self
+                    .state
+                    .markets
+                    .get(market)
+                    .ok_or_else(|| TradingError::UnknownMarket(market.clone()))?;
+                if market.status != MarketStatus::Active {
+                    return Err(TradingError::MarketNotActive {
+                        market: market.id.clone(),
+                        status: market.status,
+                    }
+                    .into());
+                }
+                if matches!(order_type, OrderType::Stop | OrderType::StopLimit) {
+                    return Err(TradingError::UnsupportedOrderType(order_type.clone()).into());
+                }
+                if market.matching_mode == MatchingMode::BatchAuction
+                    && matches!(time_in_force, TimeInForce::FOK)
+                {
+                    return Err(TradingError::FillOrKillInBatchAuction.into());
+                }
+                if market.matching_mode == MatchingMode::BatchAuction
+                    && *order_type == OrderType::Market
+                    && *side == OrderSide::Buy
+                {
+                    return Err(TradingError::MarketBuyInBatchAuction.into());
+                }
+                let quantity = parse_amount(quantity)?;
+                if quantity == 0 {
+                    return Err(TradingError::InvalidQuantity.into());
+                }
+                match (order_type, price) {
+                    (OrderType::Market, _) => {}
+                    (_, Some(price)) if parse_decimal(price)? > Decimal::ZERO => {}
+                    (_, Some(_)) => return Err(TradingError::InvalidPrice.into()),
+                    (_, None) => return Err(TradingError::MissingPrice(order_type.clone()).into()),
+                }
+                markets::validate_order(&self.state, market, order_type, quantity, price)?;
+                let (asset, amount) = self.order_lock(market, side, order_type, quantity, price)?;
+                portfolio::ensure_available(&self.state, sender, &asset, amount)?;
+            }
+            Msg::CancelOrder { order_id } => {
+                let order = self
+                    .state
+                    .orders
+                    .get(order_id)
+                    .ok_or(TradingError::UnknownOrder(*order_id))?;
+                if &order.trader != sender {
+                    return Err(TradingError::NotOrderOwner {
+                        order_id: *order_id,
+                        sender: sender.clone(),
+                    }
+                    .into());
+                }
+                if !order.is_open() {
+                    return Err(TradingError::OrderClosed {
+                        order_id: *order_id,
+                        status: order.status.to_string(),
+                    }
+                    .into());
+                }
+            }
+            Msg::Send { to, amount } => {
+                let output = Output {
+                    address: to.clone(),
+                    coins: amount.clone(),
+                };
+                bank::validate_send(&self.state, sender, &[output])?;
+            }
+            Msg::MultiSend { outputs } => {
+                bank::validate_send(&self.state, sender, outputs)?;
+            }
+            Msg::Deposit {
+                claim,
+                attestations,
+            } => {
+                bridge::validate_deposit(
+                    &self.state,
+                    &self.config.chain_id,
+                    self.height,
+                    claim,
+                    attestations,
+                )?;
+            }
+            Msg::Withdraw {
+                asset,
+                amount,
+                destination,
+            } => {
+                bridge::validate_withdrawal(
+                    &self.state,
+                    self.height,
+                    sender,
+                    asset,
+                    amount,
+                    destination,
+                )?;
+            }
+            Msg::SubmitPrices { prices } => {
+                oracle::validate_submission(&self.state, sender, prices)?;
+            }
+            Msg::CreateValidator {
+                consensus_pubkey,
+                commission,
+                amount,
+            } => {
+                staking::validate_create_validator(
+                    &self.state,
+                    sender,
+                    consensus_pubkey,
+                    commission,
+                    amount,
+                )?;
+            }
+            Msg::EditValidator { commission_rate } => {
+                staking::validate_edit_validator(
+                    &self.state,
+                    self.block_time,
+                    sender,
+                    commission_rate,
+                )?;
+            }
+            Msg::Delegate { validator, amount } => {
+                staking::validate_delegate(&self.state, sender, validator, amount)?;
+            }
+            Msg::Undelegate { validator, amount } => {
+                staking::validate_undelegate(&self.state, sender, validator, amount)?;
+            }
+            Msg::Redelegate {
+                src_validator,
+                dst_validator,
+                amount,
+            } => {
+                staking::validate_redelegate(
+                    &self.state,
+                    sender,
+                    src_validator,
+                    dst_validator,
+                    amount,
+                )?;
+            }
+            Msg::WithdrawRewards { validator } => {
+                distribution::validate_withdraw_rewards(&self.state, sender, validator)?;
+            }
+            Msg::WithdrawCommission => {
+                distribution::validate_withdraw_commission(&self.state, sender)?;
+            }
+            Msg::Unjail => {
+                slashing::validate_unjail(&self.state, self.block_time, sender)?;
+            }
+            Msg::SubmitProposal {
+                title,
+                content,
+                initial_deposit,
+                ..
+            } => {
+                governance::validate_submit(&self.state, sender, title, content, initial_deposit)?;
+            }
+            Msg::DepositProposal {
+                proposal_id,
+                amount,
+            } => {
+                governance::validate_deposit(&self.state, sender, *proposal_id, amount)?;
+            }
+            Msg::Vote { proposal_id, .. } => {
+                governance::validate_vote(&self.state, *proposal_id)?;
+            }
+            Msg::ListMarket { market } => {
+                markets::authorize(&self.state, sender)?;
+                markets::validate_listing(&self.state, market)?;
+            }
+            Msg::SuspendMarket { market } => {
+                markets::authorize(&self.state, sender)?;
+                markets::validate_status_change(&self.state, market, MarketStatus::Suspended)?;
+            }
+            Msg::ResumeMarket { market } => {
+                markets::authorize(&self.state, sender)?;
+                markets::validate_status_change(&self.state, market, MarketStatus::Active)?;
+            }
+            Msg::CloseMarket { market } => {
+                markets::authorize(&self.state, sender)?;
+                markets::validate_status_change(&self.state, market, MarketStatus::Closed)?;
+            }
+            Msg::SetMatchingMode { market, mode } => {
+                markets::authorize(&self.state, sender)?;
+                markets::validate_mode_change(&self.state, market, *mode)?;
+            }
+        }
         Ok(())
     }
 
-    /// Check if a transaction is valid
-    pub fn check_tx(&self, tx_bytes: &[u8]) -> Result<()> {
-        // TODO: Implement transaction validation
-        // - Decode transaction
-        // - Validate signature
-        // - Check account sequence
-        // - Validate transaction-specific logic
-        
-        warn!("Transaction validation not yet implemented");
+    /// Apply a single validated message to the state
+    fn execute_msg(&mut self, sender: &AccAddress, msg: Msg) -> Result<()> {
+        self.validate_msg(sender, &msg)?;
+
+        match msg {
+            Msg::PlaceOrder {
+                market,
+                side,
+                order_type,
+                quantity,
+                price,
+                time_in_force,
+            } => {
+                let (asset, amount) = self.order_lock(
+                    &self.state.markets[&market],
+                    &side,
+                    &order_type,
+                    parse_amount(&quantity)?,
+                    &price,
+                )?;
+                let id = self.state.next_order_id;
+                self.state.next_order_id += 1;
+                self.events.push(Event::OrderPlaced {
+                    order_id: id,
+                    trader: sender.clone(),
+                    market: market.clone(),
+                    side: side.clone(),
+                    order_type: order_type.clone(),
+                    quantity: quantity.clone(),
+                    price: price.clone(),
+                });
+                let mut order = Order {
+                    id,
+                    trader: sender.clone(),
+                    market,
+                    side,
+                    order_type,
+                    quantity,
+                    price,
+                    filled_quantity: "0".to_string(),
+                    status: OrderStatus::Submitted,
+                    created_at: self.block_time,
+                    updated_at: self.block_time,
+                    time_in_force,
+                    locked: "0".to_string(),
+                    filled_value: "0".to_string(),
+                };
+                portfolio::lock_order(&mut self.state, &mut order, &asset, amount)?;
+                let band = markets::price_band(&self.state, &self.state.markets[&order.market])?;
+                let trades = self.trading.place_order(
+                    order,
+                    &mut self.state.orders,
+                    &mut self.state.next_trade_id,
+                    band,
+                )?;
+                self.record_trades(trades)?;
+                portfolio::release(&mut self.state, id)?;
+                if matches!(self.state.orders[&id].status, OrderStatus::Cancelled) {
+                    self.emit_cancelled(id, CancelReason::TimeInForce);
+                }
+            }
+            Msg::CancelOrder { order_id } => {
+                self.trading
+                    .cancel_order(order_id, &mut self.state.orders)?;
+                portfolio::release(&mut self.state, order_id)?;
+                if let Some(order) = self.state.orders.get_mut(&order_id) {
+                    order.updated_at = self.block_time;
+                }
+                self.emit_cancelled(order_id, CancelReason::User);
+            }
+            Msg::Send { to, amount } => {
+                let output = Output {
+                    address: to,
+                    coins: amount,
+                };
+                let events = bank::send(&mut self.state, sender, &[output])?;
+                self.events.extend(events);
+            }
+            Msg::MultiSend { outputs } => {
+                let events = bank::send(&mut self.state, sender, &outputs)?;
+                self.events.extend(events);
+            }
+            Msg::Deposit {
+                claim,
+                attestations,
+            } => {
+                let events = bridge::deposit(
+                    &mut self.state,
+                    &self.config.chain_id,
+                    self.height,
+                    &claim,
+                    &attestations,
+                )?;
+                self.events.extend(events);
+            }
+            Msg::Withdraw {
+                asset,
+                amount,
+                destination,
+            } => {
+                let events = bridge::withdraw(
+                    &mut self.state,
+                    self.height,
+                    sender,
+                    &asset,
+                    &amount,
+                    &destination,
+                )?;
+                self.events.extend(events);
+            }
+            Msg::SubmitPrices { prices } => {
+                oracle::submit(&mut self.state, self.height, sender, &prices)?;
+            }
+            Msg::CreateValidator {
+                consensus_pubkey,
+                commission,
+                amount,
+            } => {
+                let events = staking::create_validator(
+                    &mut self.state,
+                    self.height,
+                    self.block_time,
+                    sender,
+                    &consensus_pubkey,
+                    &commission,
+                    &amount,
+                )?;
+                self.events.extend(events);
+            }
+            Msg::EditValidator { commission_rate } => {
+                let events = staking::edit_validator(
+                    &mut self.state,
+                    self.block_time,
+                    sender,
+                    &commission_rate,
+                )?;
+                self.events.extend(events);
+            }
+            Msg::Delegate { validator, amount } => {
+                let events =
+                    staking::delegate(&mut self.state, self.height, sender, &validator, &amount)?;
+                self.events.extend(events);
+            }
+            Msg::Undelegate { validator, amount } => {
+                let events = staking::undelegate(
+                    &mut self.state,
+                    self.height,
+                    self.block_time,
+                    sender,
+                    &validator,
+                    &amount,
+                )?;
+                self.events.extend(events);
+            }
+            Msg::Redelegate {
+                src_validator,
+                dst_validator,
+                amount,
+            } => {
+                let events = staking::redelegate(
+                    &mut self.state,
+                    self.height,
+                    sender,
+                    &src_validator,
+                    &dst_validator,
+                    &amount,
+                )?;
+                self.events.extend(events);
+            }
+            Msg::WithdrawRewards { validator } => {
+                let events = distribution::withdraw_rewards(
+                    &mut self.state,
+                    self.height,
+                    sender,
+                    &validator,
+                )?;
+                self.events.extend(events);
+            }
+            Msg::WithdrawCommission => {
+                let events = distribution::withdraw_commission(&mut self.state, sender)?;
+                self.events.extend(events);
+            }
+            Msg::Unjail => {
+                let events = slashing::unjail(&mut self.state, self.block_time, sender)?;
+                self.events.extend(events);
+            }
+            Msg::SubmitProposal {
+                title,
+                description,
+                content,
+                initial_deposit,
+            } => {
+                let events = governance::submit_proposal(
+                    &mut self.state,
+                    self.block_time,
+                    sender,
+                    &title,
+                    &description,
+                    content,
+                    &initial_deposit,
+                )?;
+                self.events.extend(events);
+            }
+            Msg::DepositProposal {
+                proposal_id,
+                amount,
+            } => {
+                let events = governance::deposit(
+                    &mut self.state,
+                    self.block_time,
+                    sender,
+                    proposal_id,
+                    &amount,
+                )?;
+                self.events.extend(events);
+            }
+            Msg::Vote {
+                proposal_id,
+                option,
+            } => {
+                let events = governance::vote(&mut self.state, sender, proposal_id, option)?;
+                self.events.extend(events);
+            }
+            Msg::ListMarket { market } => {
+                let events = self.list_market(market)?;
+                self.events.extend(events);
+            }
+            Msg::SuspendMarket { market } => {
+                let events = self.set_market_status(&market, MarketStatus::Suspended)?;
+                self.events.extend(events);
+            }
+            Msg::ResumeMarket { market } => {
+                let events = self.set_market_status(&market, MarketStatus::Active)?;
+                self.events.extend(events);
+            }
+            Msg::CloseMarket { market } => {
+                let events = self.set_market_status(&market, MarketStatus::Closed)?;
+                self.events.extend(events);
+            }
+            Msg::SetMatchingMode { market, mode } => {
+                let events = self.set_matching_mode(&market, mode)?;
+                self.events.extend(events);
+            }
+        }
+
         Ok(())
     }
 
-    /// Execute a transaction
-    pub fn deliver_tx(&mut self, tx_bytes: &[u8]) -> Result<()> {
-        // TODO: Implement transaction execution
-        // - Decode transaction
-        // - Execute transaction logic
-        // - Update state
-        // - Emit events
-        
-        warn!("Transaction execution not yet implemented");
+    /// Asset and amount a new order locks
+    ///
+    /// Limit orders lock their full value; market buys lock what sweeping
+    /// the current book would cost.
+    fn order_lock(
+        &self,
+        market: &Market,
+        side: &OrderSide,
+        order_type: &OrderType,
+        quantity: u128,
+        price: &Option<Price>,
+    ) -> Result<(AssetId, u128)> {
+        match (order_type, side, price) {
+            (OrderType::Market, OrderSide::Buy, _) => Ok((
+                market.quote_asset.clone(),
+                self.trading.market_buy_cost(&market.id, quantity)?,
+            )),
+            (OrderType::Market, OrderSide::Sell, _) => Ok((market.base_asset.clone(), quantity)),
+            (_, _, Some(price)) => Ok(portfolio::order_lock(market, side, quantity, price)?),
+            (_, _, None) => Err(TradingError::MissingPrice(order_type.clone()).into()),
+        }
+    }
+
+    /// Settle executed trades, store them and emit their events
+    ///
+    /// Locks of orders the trades closed are released once every trade is
+    /// settled.
+    fn record_trades(&mut self, trades: Vec<Trade>) -> Result<()> {
+        let mut touched = Vec::new();
+        for trade in trades {
+            portfolio::settle_trade(&mut self.state, &trade)?;
+            let market = self.state.markets[&trade.market_id].clone();
+            let fees = fees::charge_trade(&mut self.state, &market, &trade)?;
+            touched.extend([trade.buy_order_id, trade.sell_order_id]);
+            self.events.push(Event::trade(&trade));
+            self.events.extend(fees);
+            self.state.trades.insert(trade.id, trade);
+        }
+        for order_id in touched {
+            portfolio::release(&mut self.state, order_id)?;
+        }
         Ok(())
     }
 
+    /// Execute a passed proposal
+    ///
+    /// Content that no longer applies, such as a treasury spend the treasury
+    /// cannot cover, leaves the state untouched and marks the proposal failed.
+    fn execute_proposal(&mut self, proposal_id: ProposalId) -> Result<Vec<Event>> {
+        let content = self.state.governance.proposals[&proposal_id]
+            .content
+            .clone();
+        let snapshot = (self.state.clone(), self.trading.clone(), self.events.len());
+        let outcome: Result<Vec<Event>> = match content {
+            ProposalContent::Text => Ok(Vec::new()),
+            ProposalContent::ParamChange { changes } => {
+                governance::apply_param_changes(&self.state.params, &changes)
+                    .map(|params| {
+                        self.state.params = params;
+                        Vec::new()
+                    })
+                    .map_err(Into::into)
+            }
+            ProposalContent::MarketListing { market } => self.list_market(market),
+            ProposalContent::MarketStatusChange { market, status } => {
+                self.set_market_status(&market, status)
+            }
+            ProposalContent::MatchingModeChange { market, mode } => {
+                self.set_matching_mode(&market, mode)
+            }
+            ProposalContent::TreasurySpend { recipient, amount } => {
+                let output = Output {
+                    address: recipient,
+                    coins: amount,
+                };
+                bank::send(&mut self.state, &TREASURY.to_string(), &[output]).map_err(Into::into)
+            }
+        };
+
+        match outcome {
+            Ok(mut events) => {
+                events.push(Event::ProposalExecuted { proposal_id });
+                Ok(events)
+            }
+            Err(e) => {
+                let events;
+                (self.state, self.trading, events) = snapshot;
+                self.events.truncate(events);
+                Ok(vec![governance::fail(
+                    &mut self.state,
+                    proposal_id,
+                    e.to_string(),
+                )])
+            }
+        }
+    }
+
+    /// Add a market to the state and the trading engine
+    fn list_market(&mut self, market: Market) -> Result<Vec<Event>> {
+        markets::validate_listing(&self.state, &market)?;
+        self.trading.add_market(&market)?;
+        let event = Event::MarketListed {
+            market: market.id.clone(),
+        };
+        self.state.markets.insert(market.id.clone(), market);
+        Ok(vec![event])
+    }
+
+    /// Suspend, resume or close a market
+    ///
+    /// Closing cancels every order left on the market's book and releases
+    /// the balances they lock.
+    fn set_market_status(
+        &mut self,
+        market_id: &MarketId,
+        status: MarketStatus,
+    ) -> Result<Vec<Event>> {
+        let from = markets::validate_status_change(&self.state, market_id, status)?;
+        let cancelled = self.trading.set_market_status(
+            market_id,
+            status,
+            &mut self.state.orders,
+            self.block_time,
+        )?;
+        self.state
+            .markets
+            .get_mut(market_id)
+            .expect("market exists")
+            .status = status;
+
+        let mut events = Vec::new();
+        for order_id in cancelled {
+            portfolio::release(&mut self.state, order_id)?;
+            let order = &self.state.orders[&order_id];
+            events.push(Event::OrderCancelled {
+                order_id,
+                trader: order.trader.clone(),
+                market: order.market.clone(),
+                reason: CancelReason::MarketClosed,
+            });
+        }
+        events.push(Event::MarketStatusChanged {
+            market: market_id.clone(),
+            from,
+            to: status,
+        });
+        Ok(events)
+    }
+
+    /// Switch a market between continuous matching and batch auctions
+    ///
+    /// Leaving batch mode clears the pending auction: its trades settle and
+    /// its unfilled immediate orders are cancelled, as at the end of a block.
+    fn set_matching_mode(
+        &mut self,
+        market_id: &MarketId,
+        mode: MatchingMode,
+    ) -> Result<Vec<Event>> {
+        let from = markets::validate_mode_change(&self.state, market_id, mode)?;
+        let band = markets::price_band(&self.state, &self.state.markets[market_id])?;
+        let auction = self.trading.set_matching_mode(
+            market_id,
+            mode,
+            &mut self.state.orders,
+            &mut self.state.next_trade_id,
+            self.block_time,
+            band,
+        )?;
+        self.state
+            .markets
+            .get_mut(market_id)
+            .expect("market exists")
+            .matching_mode = mode;
+
+        let start = self.events.len();
+        self.record_trades(auction.trades)?;
+        for order_id in auction.expired {
+            portfolio::release(&mut self.state, order_id)?;
+            self.emit_cancelled(order_id, CancelReason::TimeInForce);
+        }
+        let mut events = self.events.split_off(start);
+        events.push(Event::MatchingModeChanged {
+            market: market_id.clone(),
+            from,
+            to: mode,
+        });
+        Ok(events)
+    }
+
+    /// Emit the cancellation of an order
+    fn emit_cancelled(&mut self, order_id: OrderId, reason: CancelReason) {
+        if let Some(order) = self.state.orders.get(&order_id) {
+            self.events.push(Event::OrderCancelled {
+                order_id,
+                trader: order.trader.clone(),
+                market: order.market.clone(),
+                reason,
+            });
+        }
+    }
+
     /// Commit the current state
     pub fn commit(&mut self) -> Result<Vec<u8>> {
         info!("Committing state at height {}", self.height);
-        
-        // TODO: Implement state commitment
-        // - Calculate state root hash
-        // - Persist state to storage
-        
-        // Return dummy hash for now
-        Ok(vec![0u8; 32])
+
+        use sha2::{Digest, Sha256};
+        Ok(Sha256::digest(self.encode_state()?).to_vec())
+    }
+
+    /// Encode the application state canonically (object keys sorted)
+    ///
+    /// `AppState` is built on hash maps, so it is routed through
+    /// `serde_json::Value` to get a byte-for-byte deterministic encoding.
+    pub fn encode_state(&self) -> Result<Vec<u8>> {
+        let value = serde_json::to_value(&self.state)?;
+        Ok(serde_json::to_vec(&value)?)
+    }
+
+    /// Restore previously committed state
+    pub fn restore(&mut self, height: u64, state_bytes: &[u8]) -> Result<()> {
+        self.state = serde_json::from_slice(state_bytes)
+            .map_err(|e| StateError::Corrupt(format!("failed to decode stored state: {}", e)))?;
+        self.trading = TradingEngine::rebuild(&self.state.markets, &self.state.orders)?;
+        self.height = height;
+        info!("Restored state at height {}", height);
+        Ok(())
     }
 
     /// Query application state
-    pub fn query(&self, path: &str, data: &[u8]) -> Result<Vec<u8>> {
-        info!("Processing query: {}", path);
-        
-        // TODO: Implement query handling
-        // - Parse query path
-        // - Return requested data
-        
-        warn!("Query handling not yet implemented");
-        Ok(vec![])
+    ///
+    /// Paths are routed by [`crate::query::handle`]; results are JSON.
+    pub fn query(&self, path: &str, _data: &[u8]) -> Result<Vec<u8>, QueryError> {
+        debug!("Processing query: {}", path);
+        crate::query::handle(self, path)
+    }
+}
+
+/// Verify the transaction signature and that the key controls the sender
+///
+/// Accounts that have not signed before are bound to the key whose derived
+/// address matches; afterwards the stored public key must be used.
+fn verify_tx_signature(tx: &Tx, account: &Account) -> Result<(), TxError> {
+    let unauthorized = TxError::Unauthorized;
+    match &account.public_key {
+        Some(public_key) if public_key != &tx.public_key => {
+            return Err(unauthorized(format!(
+                "public key does not match account {}",
+                account.address
+            )));
+        }
+        Some(_) => {}
+        None => {
+            let key_bytes = hex::decode(&tx.public_key)
+                .map_err(|_| unauthorized("malformed public key".to_string()))?;
+            if derive_address(ACCOUNT_PREFIX, &key_bytes) != account.address {
+                return Err(unauthorized(format!(
+                    "public key does not control account {}",
+                    account.address
+                )));
+            }
+        }
     }
+    let sign_bytes = tx
+        .body
+        .sign_bytes()
+        .map_err(|e| TxError::Decode(e.to_string()))?;
+    verify_signature(&tx.public_key, &sign_bytes, &tx.signature)
+        .map_err(|e| unauthorized(e.to_string()))
 }
 
 /// Genesis data structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GenesisData {
     pub chain_id: String,
+
+    /// First block height of the chain (non-zero for exported genesis)
+    #[serde(default)]
+    pub initial_height: u64,
+
     pub params: ChainParams,
+
+    /// Asset registry; assets held or traded without an entry are
+    /// registered with default metadata
+    #[serde(default)]
+    pub assets: Vec<AssetMetadata>,
+
     pub accounts: Vec<Account>,
     pub validators: Vec<Validator>,
     pub markets: Vec<Market>,
+
+    /// Complete application state, present only in exported genesis files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app_state: Option<AppState>,
+}
+
+impl GenesisData {
+    /// Read and parse a genesis file
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read genesis file {}", path.display()))?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("failed to parse genesis file {}", path.display()))
+    }
+
+    /// Write the genesis document as pretty-printed JSON
+    pub fn to_file(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let contents = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, contents)
+            .with_context(|| format!("failed to write genesis file {}", path.display()))
+    }
+
+    /// Validate the genesis document, reporting every problem found
+    pub fn validate(&self) -> Result<()> {
+        let mut errors = Vec::new();
+
+        if self.chain_id.trim().is_empty() {
+            errors.push("chain_id must not be empty".to_string());
+        }
+
+        match &self.app_state {
+            Some(state) => {
+                if !self.accounts.is_empty()
+                    || !self.validators.is_empty()
+                    || !self.markets.is_empty()
+                    || !self.assets.is_empty()
+                {
+                    errors.push(
+                        "exported genesis must not list accounts, validators, markets or assets outside app_state"
+                            .to_string(),
+                    );
+                }
+                validate_params(&state.params, &mut errors);
+                validate_keys(state, &mut errors);
+                validate_assets(state.assets.values(), &mut errors);
+                validate_accounts(state.accounts.values(), &mut errors);
+                validate_validators(state.validators.values(), &mut errors);
+                validate_markets(state.markets.values(), &mut errors);
+            }
+            None => {
+                validate_params(&self.params, &mut errors);
+                validate_assets(self.assets.iter(), &mut errors);
+                validate_accounts(self.accounts.iter(), &mut errors);
+                validate_validators(self.validators.iter(), &mut errors);
+                validate_markets(self.markets.iter(), &mut errors);
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("invalid genesis:\n  - {}", errors.join("\n  - ")))
+        }
+    }
+}
+
+/// Check that a set of fractions each lie within [0, 1] and sum to exactly 1
+fn validate_distribution(name: &str, parts: &[(&str, &str)], errors: &mut Vec<String>) {
+    let mut sum = Decimal::ZERO;
+    for (field, value) in parts {
+        match parse_fraction(value) {
+            Ok(fraction) => sum += fraction,
+            Err(e) => {
+                errors.push(format!("{}.{}: {}", name, field, e));
+                return;
+            }
+        }
+    }
+    if sum != Decimal::ONE {
+        errors.push(format!("{} fractions sum to {}, expected 1", name, sum));
+    }
+}
+
+pub(crate) fn validate_params(params: &ChainParams, errors: &mut Vec<String>) {
+    if params.native_token.trim().is_empty() {
+        errors.push("params.native_token must not be empty".to_string());
+    }
+    if params.block_time == 0 {
+        errors.push("params.block_time must be positive".to_string());
+    }
+    if params.max_block_size == 0 {
+        errors.push("params.max_block_size must be positive".to_string());
+    }
+
+    let fees = &params.trading_fees;
+    for (field, value) in [
+        ("premium_fee_rate", &fees.premium_fee_rate),
+        ("penalty_fee_rate", &fees.penalty_fee_rate),
+    ] {
+        if let Err(e) = parse_fraction(value) {
+            errors.push(format!("params.trading_fees.{}: {}", field, e));
+        }
+    }
+
+    fees::validate_params(fees, errors);
+
+    let split = &fees.fee_distribution;
+    validate_distribution(
+        "params.trading_fees.fee_distribution",
+        &[
+            ("to_liquidity_providers", &split.to_liquidity_providers),
+            ("to_stakers", &split.to_stakers),
+            ("to_burn", &split.to_burn),
+            ("to_treasury", &split.to_treasury),
+        ],
+        errors,
+    );
+
+    let collateral = &params.collateral_params;
+    let mut ratios = Vec::new();
+    for (field, value) in [
+        (
+            "buyer_min_collateral_ratio",
+            &collateral.buyer_min_collateral_ratio,
+        ),
+        (
+            "seller_min_collateral_ratio",
+            &collateral.seller_min_collateral_ratio,
+        ),
+        ("liquidation_threshold", &collateral.liquidation_threshold),
+    ] {
+        match parse_decimal(value) {
+            Ok(ratio) if ratio >= Decimal::ONE => ratios.push(ratio),
+            Ok(_) => errors.push(format!(
+                "params.collateral_params.{} must be at least 1",
+                field
+            )),
+            Err(e) => errors.push(format!("params.collateral_params.{}: {}", field, e)),
+        }
+    }
+    if let [buyer, seller, liquidation] = ratios[..] {
+        if liquidation > buyer || liquidation > seller {
+            errors.push(
+                "params.collateral_params.liquidation_threshold must not exceed the minimum collateral ratios"
+                    .to_string(),
+            );
+        }
+    }
+
+    let penalty = &collateral.penalty_distribution;
+    validate_distribution(
+        "params.collateral_params.penalty_distribution",
+        &[
+            ("to_platform", &penalty.to_platform),
+            ("to_counterparty", &penalty.to_counterparty),
+        ],
+        errors,
+    );
+
+    bridge::validate_params(&params.bridge, errors);
+    oracle::validate_params(&params.oracle, errors);
+    staking::validate_params(&params.staking, errors);
+    distribution::validate_params(&params.distribution, errors);
+    slashing::validate_params(&params.slashing, errors);
+    governance::validate_params(&params.governance, errors);
+    markets::validate_params(&params.markets, errors);
+}
+
+/// Check that every map key matches the identifier stored in its value
+fn validate_keys(state: &AppState, errors: &mut Vec<String>) {
+    for (key, account) in &state.accounts {
+        if key != &account.address {
+            errors.push(format!(
+                "app_state.accounts key '{}' does not match address '{}'",
+                key, account.address
+            ));
+        }
+    }
+    for (key, validator) in &state.validators {
+        if key != &validator.operator_address {
+            errors.push(format!(
+                "app_state.validators key '{}' does not match operator_address '{}'",
+                key, validator.operator_address
+            ));
+        }
+    }
+    for (key, market) in &state.markets {
+        if key != &market.id {
+            errors.push(format!(
+                "app_state.markets key '{}' does not match id '{}'",
+                key, market.id
+            ));
+        }
+    }
+}
+
+fn validate_assets<'a>(assets: impl Iterator<Item = &'a AssetMetadata>, errors: &mut Vec<String>) {
+    let mut ids = HashSet::new();
+    for asset in assets {
+        if asset.id.trim().is_empty() {
+            errors.push("asset with empty id".to_string());
+        }
+        if !ids.insert(&asset.id) {
+            errors.push(format!("duplicate asset '{}'", asset.id));
+        }
+        if asset.display.trim().is_empty() {
+            errors.push(format!("asset '{}' has an empty display symbol", asset.id));
+        }
+        if asset.decimals > bank::MAX_DECIMALS {
+            errors.push(format!(
+                "asset '{}' decimals must be at most {}",
+                asset.id,
+                bank::MAX_DECIMALS
+            ));
+        }
+    }
+}
+
+fn validate_accounts<'a>(accounts: impl Iterator<Item = &'a Account>, errors: &mut Vec<String>) {
+    let mut addresses = HashSet::new();
+    let mut numbers = HashSet::new();
+    for account in accounts {
+        if account.address.trim().is_empty() {
+            errors.push("account with empty address".to_string());
+        }
+        if !addresses.insert(&account.address) {
+            errors.push(format!("duplicate account address '{}'", account.address));
+        }
+        if !numbers.insert(account.account_number) {
+            errors.push(format!(
+                "duplicate account_number {} (account '{}')",
+                account.account_number, account.address
+            ));
+        }
+        for (asset, amount) in &account.balances {
+            if let Err(e) = parse_amount(amount) {
+                errors.push(format!(
+                    "account '{}' balance of {}: {}",
+                    account.address, asset, e
+                ));
+            }
+        }
+    }
+}
+
+fn validate_validators<'a>(
+    validators: impl Iterator<Item = &'a Validator>,
+    errors: &mut Vec<String>,
+) {
+    let mut addresses = HashSet::new();
+    for validator in validators {
+        let address = &validator.operator_address;
+        if address.trim().is_empty() {
+            errors.push("validator with empty operator_address".to_string());
+        }
+        if !addresses.insert(address) {
+            errors.push(format!("duplicate validator address '{}'", address));
+        }
+        if validator.consensus_pubkey.trim().is_empty() {
+            errors.push(format!(
+                "validator '{}' has an empty consensus_pubkey",
+                address
+            ));
+        }
+        for (field, value) in [
+            ("tokens", &validator.tokens),
+            ("delegator_shares", &validator.delegator_shares),
+        ] {
+            if let Err(e) = parse_amount(value) {
+                errors.push(format!("validator '{}' {}: {}", address, field, e));
+            }
+        }
+
+        let commission = &validator.commission;
+        let rates: Vec<_> = [
+            ("rate", &commission.rate),
+            ("max_rate", &commission.max_rate),
+            ("max_change_rate", &commission.max_change_rate),
+        ]
+        .into_iter()
+        .filter_map(|(field, value)| match parse_fraction(value) {
+            Ok(rate) => Some(rate),
+            Err(e) => {
+                errors.push(format!(
+                    "validator '{}' commission.{}: {}",
+                    address, field, e
+                ));
+                None
+            }
+        })
+        .collect();
+        if let [rate, max_rate, max_change_rate] = rates[..] {
+            if rate > max_rate {
+                errors.push(format!(
+                    "validator '{}' commission rate exceeds max_rate",
+                    address
+                ));
+            }
+            if max_change_rate > max_rate {
+                errors.push(format!(
+                    "validator '{}' commission max_change_rate exceeds max_rate",
+                    address
+                ));
+            }
+        }
+    }
+}
+
+pub(crate) fn validate_markets<'a>(
+    markets: impl Iterator<Item = &'a Market>,
+    errors: &mut Vec<String>,
+) {
+    let mut ids = HashSet::new();
+    for market in markets {
+        if market.id.trim().is_empty() {
+            errors.push("market with empty id".to_string());
+        }
+        if !ids.insert(&market.id) {
+            errors.push(format!("duplicate market id '{}'", market.id));
+        }
+        if market.base_asset.trim().is_empty() || market.quote_asset.trim().is_empty() {
+            errors.push(format!(
+                "market '{}' must name both a base and a quote asset",
+                market.id
+            ));
+        } else if market.base_asset == market.quote_asset {
+            errors.push(format!(
+                "market '{}' has identical base and quote assets",
+                market.id
+            ));
+        }
+        match parse_amount(&market.min_order_size) {
+            Ok(0) => errors.push(format!(
+                "market '{}' min_order_size must be positive",
+                market.id
+            )),
+            Ok(_) => {}
+            Err(e) => errors.push(format!("market '{}' min_order_size: {}", market.id, e)),
+        }
+        match (
+            parse_amount(&market.lot_size),
+            parse_amount(&market.min_order_size),
+        ) {
+            (Ok(0), _) => errors.push(format!("market '{}' lot_size must be positive", market.id)),
+            (Ok(lot), Ok(min)) if min % lot != 0 => errors.push(format!(
+                "market '{}' min_order_size must be a multiple of lot_size",
+                market.id
+            )),
+            (Ok(_), _) => {}
+            (Err(e), _) => errors.push(format!("market '{}' lot_size: {}", market.id, e)),
+        }
+        match parse_decimal(&market.tick_size) {
+            Ok(tick) if tick <= Decimal::ZERO => {
+                errors.push(format!("market '{}' tick_size must be positive", market.id))
+            }
+            Ok(_) => {}
+            Err(e) => errors.push(format!("market '{}' tick_size: {}", market.id, e)),
+        }
+    }
 }
 
 impl Default for ChainParams {
     fn default() -> Self {
         Self {
             native_token: "OMC".to_string(),
-            block_time: 1, // 1 second blocks
+            block_time: 1,               // 1 second blocks
             max_block_size: 1024 * 1024, // 1MB
             trading_fees: TradingFees {
                 premium_fee_rate: "1.0".to_string(), // 100% of premiums to platform
                 penalty_fee_rate: "0.1".to_string(), // 10% penalty rate
+                default_schedule: FeeSchedule::default(),
+                market_schedules: BTreeMap::new(),
+                volume_window_days: fees::default_volume_window_days(),
+                staking_discounts: fees::default_staking_discounts(),
                 fee_distribution: FeeDistribution {
                     to_liquidity_providers: "0.4".to_string(), // 40%
-                    to_stakers: "0.3".to_string(), // 30%
-                    to_burn: "0.2".to_string(), // 20%
-                    to_treasury: "0.1".to_string(), // 10%
+                    to_stakers: "0.3".to_string(),             // 30%
+                    to_burn: "0.2".to_string(),                // 20%
+                    to_treasury: "0.1".to_string(),            // 10%
                 },
             },
             collateral_params: CollateralParams {
-                buyer_min_collateral_ratio: "1.2".to_string(), // 120%
+                buyer_min_collateral_ratio: "1.2".to_string(),  // 120%
                 seller_min_collateral_ratio: "1.5".to_string(), // 150%
-                liquidation_threshold: "1.1".to_string(), // 110%
+                liquidation_threshold: "1.1".to_string(),       // 110%
                 penalty_distribution: PenaltyDistribution {
-                    to_platform: "0.5".to_string(), // 50%
+                    to_platform: "0.5".to_string(),     // 50%
                     to_counterparty: "0.5".to_string(), // 50%
                 },
             },
+            bridge: BridgeParams::default(),
+            oracle: OracleParams::default(),
+            staking: StakingParams::default(),
+            distribution: DistributionParams::default(),
+            slashing: SlashingParams::default(),
+            governance: GovernanceParams::default(),
+            markets: MarketParams::default(),
         }
     }
 }
+
+/// Fresh application for unit tests, on chain `optimic-test`
+#[cfg(test)]
+pub(crate) fn test_app() -> OptimicApp {
+    OptimicApp::new(AppConfig {
+        chain_id: "optimic-test".to_string(),
+        genesis_path: String::new(),
+        data_dir: String::new(),
+    })
+}
+
+/// Fresh application state for unit tests, with `assets` registered
+#[cfg(test)]
+pub(crate) fn test_state(assets: &[&str]) -> AppState {
+    let mut state = test_app().state;
+    for asset in assets.iter().map(|asset| asset.to_string()) {
+        state
+            .assets
+            .insert(asset.clone(), AssetMetadata::unnamed(&asset));
+    }
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config() -> AppConfig {
+        AppConfig {
+            chain_id: "optimic-1".to_string(),
+            genesis_path: concat!(env!("CARGO_MANIFEST_DIR"), "/genesis.json").to_string(),
+            data_dir: "./data".to_string(),
+        }
+    }
+
+    fn repo_genesis() -> GenesisData {
+        GenesisData::from_file(test_config().genesis_path).unwrap()
+    }
+
+    #[test]
+    fn test_load_repo_genesis() {
+        let mut app = OptimicApp::new(test_config());
+        app.load_genesis().unwrap();
+        // The genesis account and the pool backing the validator's tokens
+        assert_eq!(app.state.accounts.len(), 2);
+        assert_eq!(
+            app.state.accounts[staking::BONDED_POOL].balances["OMC"],
+            "100000000"
+        );
+        assert_eq!(app.state.markets.len(), 2);
+    }
+
+    #[test]
+    fn test_genesis_validation_rejects_bad_data() {
+        let mut genesis = repo_genesis();
+        genesis.params.trading_fees.fee_distribution.to_burn = "0.3".to_string();
+        let market = genesis.markets[0].clone();
+        genesis.markets.push(market);
+        genesis.accounts[0]
+            .balances
+            .insert("ETH".to_string(), "-5".to_string());
+
+        let err = genesis.validate().unwrap_err().to_string();
+        assert!(err.contains("fee_distribution fractions sum to 1.1"));
+        assert!(err.contains("duplicate market id 'ETH-USD'"));
+        assert!(err.contains("invalid amount '-5'"));
+
+        let mut app = OptimicApp::new(test_config());
+        assert!(app.init_genesis(genesis).is_err());
+    }
+
+    #[test]
+    fn test_export_genesis_round_trip() {
+        let mut app = OptimicApp::new(test_config());
+        app.load_genesis().unwrap();
+        app.height = 42;
+        assert!(app.export_genesis(41).is_err());
+
+        let exported = app.export_genesis(42).unwrap();
+        assert_eq!(exported.initial_height, 43);
+
+        let json = serde_json::to_string(&exported).unwrap();
+        let mut restored = OptimicApp::new(test_config());
+        restored
+            .init_genesis(serde_json::from_str(&json).unwrap())
+            .unwrap();
+        assert_eq!(restored.height, 42);
+        assert_eq!(restored.state.markets.len(), 2);
+    }
+
+    /// App at height 1 with two funded traders
+    fn trading_app() -> (OptimicApp, [crate::keys::KeyPair; 2]) {
+        let keys = [
+            crate::keys::KeyPair::generate(),
+            crate::keys::KeyPair::generate(),
+        ];
+        let mut genesis = repo_genesis();
+        for (i, key) in keys.iter().enumerate() {
+            genesis.accounts.push(Account {
+                address: key.account_address(),
+                public_key: None,
+                account_number: i as u64 + 1,
+                sequence: 0,
+                balances: [
+                    ("ETH".to_string(), "5000000".to_string()),
+                    ("USD".to_string(), "1000000000".to_string()),
+                ]
+                .into(),
+            });
+        }
+        let mut app = OptimicApp::new(test_config());
+        app.init_genesis(genesis).unwrap();
+        app.begin_block(1, Timestamp::UNIX_EPOCH).unwrap();
+        (app, keys)
+    }
+
+    fn sign(key: &crate::keys::KeyPair, sequence: u64, msg: Msg) -> Vec<u8> {
+        let body = crate::tx::TxBody {
+            chain_id: "optimic-1".to_string(),
+            sender: key.account_address(),
+            sequence,
+            timeout_height: 0,
+            msgs: vec![msg],
+            memo: String::new(),
+        };
+        Tx::sign(body, key).unwrap().encode().unwrap()
+    }
+
+    fn limit(side: OrderSide, quantity: &str, price: &str, time_in_force: TimeInForce) -> Msg {
+        Msg::PlaceOrder {
+            market: "ETH-USD".to_string(),
+            side,
+            order_type: OrderType::Limit,
+            quantity: quantity.to_string(),
+            price: Some(price.to_string()),
+            time_in_force,
+        }
+    }
+
+    #[test]
+    fn test_deliver_tx_emits_events() {
+        use crate::keys::KeyPair;
+
+        let (mut app, keys) = trading_app();
+        let place = |key: &KeyPair,
+                     sequence: u64,
+                     side: OrderSide,
+                     quantity: &str,
+                     time_in_force: TimeInForce| {
+            sign(key, sequence, limit(side, quantity, "100", time_in_force))
+        };
+
+        let events = app
+            .deliver_tx(&place(
+                &keys[0],
+                0,
+                OrderSide::Sell,
+                "2000000",
+                TimeInForce::GTC,
+            ))
+            .unwrap();
+        assert!(matches!(
+            events[..],
+            [Event::OrderPlaced { order_id: 1, .. }]
+        ));
+
+        // The unfilled remainder of an immediate-or-cancel order is cancelled
+        let events = app
+            .deliver_tx(&place(
+                &keys[1],
+                0,
+                OrderSide::Buy,
+                "3000000",
+                TimeInForce::IOC,
+            ))
+            .unwrap();
+        assert!(matches!(
+            &events[..],
+            [
+                Event::OrderPlaced { order_id: 2, .. },
+                Event::TradeExecuted { buy_order_id: 2, sell_order_id: 1, quantity, .. },
+                Event::FeeCharged { .. },
+                Event::FeeCharged { .. },
+                Event::OrderCancelled { order_id: 2, reason: CancelReason::TimeInForce, .. },
+            ] if quantity == "2000000"
+        ));
+
+        // A rejected transaction emits nothing
+        let rejected = app
+            .deliver_tx(&place(&keys[1], 1, OrderSide::Buy, "0", TimeInForce::GTC))
+            .unwrap_err();
+        assert_eq!(
+            crate::codes::result_code(&rejected),
+            crate::codes::TRADING_INVALID_QUANTITY
+        );
+        let events = app.end_block().unwrap();
+        assert!(events.iter().all(|event| matches!(
+            event,
+            Event::RewardsAllocated { .. } | Event::FeeDistributed { .. }
+        )));
+    }
+
+    #[test]
+    fn test_orders_lock_and_settle_balances() {
+        let (mut app, keys) = trading_app();
+        let [seller, buyer] = [keys[0].account_address(), keys[1].account_address()];
+        let balance = |app: &OptimicApp, owner: &AccAddress, asset: &str| {
+            let balance = portfolio::balance(&app.state, owner, &asset.to_string()).unwrap();
+            (balance.total, balance.locked)
+        };
+
+        app.deliver_tx(&sign(
+            &keys[0],
+            0,
+            limit(OrderSide::Sell, "2000000", "100", TimeInForce::GTC),
+        ))
+        .unwrap();
+        assert_eq!(
+            balance(&app, &seller, "ETH"),
+            ("5000000".to_string(), "2000000".to_string())
+        );
+
+        // Locked funds cannot back a second order
+        let overdrawn = app
+            .deliver_tx(&sign(
+                &keys[0],
+                1,
+                limit(OrderSide::Sell, "4000000", "100", TimeInForce::GTC),
+            ))
+            .unwrap_err();
+        assert_eq!(
+            crate::codes::result_code(&overdrawn),
+            crate::codes::PORTFOLIO_INSUFFICIENT_FUNDS
+        );
+
+        // The buyer locks at its limit, pays the maker's price and gets the rest back;
+        // both sides pay their fee out of what they receive
+        app.deliver_tx(&sign(
+            &keys[1],
+            0,
+            limit(OrderSide::Buy, "1500000", "101", TimeInForce::GTC),
+        ))
+        .unwrap();
+        assert_eq!(
+            balance(&app, &buyer, "USD"),
+            ("850000000".to_string(), "0".to_string())
+        );
+        assert_eq!(
+            balance(&app, &buyer, "ETH"),
+            ("6499250".to_string(), "0".to_string())
+        );
+        assert_eq!(
+            balance(&app, &seller, "USD"),
+            ("1149970000".to_string(), "0".to_string())
+        );
+        assert_eq!(
+            balance(&app, &seller, "ETH"),
+            ("3500000".to_string(), "500000".to_string())
+        );
+        assert_eq!(
+            app.state.portfolios[&buyer].positions["ETH-USD"].quantity,
+            "1500000"
+        );
+
+        app.deliver_tx(&sign(&keys[0], 1, Msg::CancelOrder { order_id: 1 }))
+            .unwrap();
+        assert_eq!(
+            balance(&app, &seller, "ETH"),
+            ("3500000".to_string(), "0".to_string())
+        );
+        assert!(app.state.portfolios[&seller].orders.is_empty());
+        portfolio::check_invariants(&app.state).unwrap();
+    }
+
+    #[test]
+    fn test_good_till_date_orders_expire() {
+        let (mut app, keys) = trading_app();
+        let buyer = keys[1].account_address();
+        let until = Timestamp::UNIX_EPOCH + chrono::TimeDelta::seconds(10);
+        let locked = |app: &OptimicApp| {
+            portfolio::balance(&app.state, &buyer, &"USD".to_string())
+                .unwrap()
+                .locked
+        };
+
+        app.deliver_tx(&sign(
+            &keys[1],
+            0,
+            limit(OrderSide::Buy, "1000000", "100", TimeInForce::GTD(until)),
+        ))
+        .unwrap();
+        assert!(app
+            .end_block()
+            .unwrap()
+            .iter()
+            .all(|event| !matches!(event, Event::OrderCancelled { .. })));
+        assert_eq!(locked(&app), "100000000");
+
+        // The order expires in the first block at or after its date
+        app.begin_block(2, until).unwrap();
+        let events = app.end_block().unwrap();
+        assert!(events.iter().any(|event| matches!(
+            event,
+            Event::OrderCancelled {
+                order_id: 1,
+                reason: CancelReason::Expired,
+                ..
+            }
+        )));
+        assert!(matches!(app.state.orders[&1].status, OrderStatus::Expired));
+        assert_eq!(locked(&app), "0");
+        assert!(app
+            .trading
+            .get_order_book(&"ETH-USD".to_string())
+            .unwrap()
+            .bids
+            .is_empty());
+        assert!(app.state.portfolios[&buyer].orders.is_empty());
+    }
+
+    #[test]
+    fn test_buyers_pay_fills_rounded_up() {
+        let (mut app, keys) = trading_app();
+        let [seller, buyer] = [keys[0].account_address(), keys[1].account_address()];
+        let market = app.state.markets.get_mut("ETH-USD").unwrap();
+        (market.min_order_size, market.lot_size) = ("1".to_string(), "1".to_string());
+        let usd = |app: &OptimicApp, owner: &AccAddress| {
+            portfolio::balance(&app.state, owner, &"USD".to_string())
+                .unwrap()
+                .total
+        };
+
+        // A fill worth half a quote unit is not free
+        app.deliver_tx(&sign(
+            &keys[0],
+            0,
+            limit(OrderSide::Sell, "1", "0.5", TimeInForce::GTC),
+        ))
+        .unwrap();
+        app.deliver_tx(&sign(
+            &keys[1],
+            0,
+            limit(OrderSide::Buy, "1", "0.5", TimeInForce::GTC),
+        ))
+        .unwrap();
+        assert_eq!(usd(&app, &buyer), "999999999");
+        assert_eq!(usd(&app, &seller), "1000000001");
+
+        // An order's fills are rounded up once, in total, so a later seller
+        // is not left with nothing once earlier fills used up the lock
+        let other = crate::keys::KeyPair::generate();
+        let eth = "ETH".to_string();
+        bank::mint(&mut app.state, &other.account_address(), &eth, 1).unwrap();
+        for sequence in 1..3 {
+            app.deliver_tx(&sign(
+                &keys[0],
+                sequence,
+                limit(OrderSide::Sell, "1", "0.5", TimeInForce::GTC),
+            ))
+            .unwrap();
+        }
+        app.deliver_tx(&sign(
+            &other,
+            0,
+            limit(OrderSide::Sell, "1", "0.5", TimeInForce::GTC),
+        ))
+        .unwrap();
+        app.deliver_tx(&sign(
+            &keys[1],
+            1,
+            limit(OrderSide::Buy, "3", "0.5", TimeInForce::GTC),
+        ))
+        .unwrap();
+        assert_eq!(usd(&app, &buyer), "999999997");
+        assert_eq!(usd(&app, &seller), "1000000002");
+        assert_eq!(usd(&app, &other.account_address()), "1");
+        assert!(app.state.portfolios[&buyer].orders.is_empty());
+
+        // What the fills leave of the lock is released when the order closes
+        for sequence in 3..5 {
+            app.deliver_tx(&sign(
+                &keys[0],
+                sequence,
+                limit(OrderSide::Sell, "1", "0.5", TimeInForce::GTC),
+            ))
+            .unwrap();
+        }
+        app.deliver_tx(&sign(
+            &keys[1],
+            2,
+            limit(OrderSide::Buy, "2", "0.55", TimeInForce::GTC),
+        ))
+        .unwrap();
+        let balance = portfolio::balance(&app.state, &buyer, &"USD".to_string()).unwrap();
+        assert_eq!(
+            (balance.total.as_str(), balance.locked.as_str()),
+            ("999999996", "0")
+        );
+        assert_eq!(usd(&app, &seller), "1000000003");
+        portfolio::check_invariants(&app.state).unwrap();
+    }
+
+    #[test]
+    fn test_fees_are_split_at_end_block() {
+        use crate::fees::{FEE_COLLECTOR, LIQUIDITY_REWARDS_POOL, TREASURY};
+
+        let (mut app, keys) = trading_app();
+        let usd = "USD".to_string();
+        let supply = bank::supply_of(&app.state, &usd).unwrap();
+        app.deliver_tx(&sign(
+            &keys[0],
+            0,
+            limit(OrderSide::Sell, "1500000", "100", TimeInForce::GTC),
+        ))
+        .unwrap();
+        app.deliver_tx(&sign(
+            &keys[1],
+            0,
+            limit(OrderSide::Buy, "1500000", "100", TimeInForce::GTC),
+        ))
+        .unwrap();
+        assert_eq!(app.state.accounts[FEE_COLLECTOR].balances["USD"], "30000");
+        assert_eq!(app.state.accounts[FEE_COLLECTOR].balances["ETH"], "750");
+
+        let events = app.end_block().unwrap();
+        assert!(events.iter().any(|event| matches!(
+            event,
+            Event::FeeDistributed { asset, to_liquidity_providers, to_stakers, burned, to_treasury }
+                if asset == "USD" && to_liquidity_providers == "12000" && to_stakers == "9000" && burned == "6000" && to_treasury == "3000"
+        )));
+        assert!(events.iter().any(|event| matches!(
+            event,
+            Event::RewardsAllocated { validator, asset, amount, .. }
+                if validator == "optimicval1genesis" && asset == "USD" && amount == "9000"
+        )));
+        assert_eq!(app.state.accounts[FEE_COLLECTOR].balances["USD"], "0");
+        assert_eq!(
+            app.state.accounts[LIQUIDITY_REWARDS_POOL].balances["USD"],
+            "12000"
+        );
+        assert_eq!(app.state.accounts[TREASURY].balances["USD"], "3000");
+        assert_eq!(bank::supply_of(&app.state, &usd).unwrap(), supply - 6000);
+    }
+
+    #[test]
+    fn test_maker_rebates_volume_tiers_and_staking_discounts() {
+        use crate::events::Liquidity;
+        use crate::fees::{VolumeTier, FEE_COLLECTOR};
+
+        let (mut app, keys) = trading_app();
+        let [maker, taker] = [keys[0].account_address(), keys[1].account_address()];
+        let market = app.state.markets["ETH-USD"].clone();
+        app.state.params.trading_fees.market_schedules.insert(
+            market.id.clone(),
+            FeeSchedule {
+                maker_rate: "0.0002".to_string(),
+                taker_rate: "0.001".to_string(),
+                tiers: vec![VolumeTier {
+                    min_volume: "100000000".to_string(),
+                    maker_rate: "-0.0003".to_string(),
+                    taker_rate: "0.0008".to_string(),
+                }],
+            },
+        );
+        app.state.params.trading_fees.staking_discounts = vec![StakingDiscount {
+            min_stake: "1000000".to_string(),
+            discount: "0.5".to_string(),
+        }];
+        let charged = |events: &[Event]| -> Vec<(AccAddress, String, String)> {
+            events
+                .iter()
+                .filter_map(|event| match event {
+                    Event::FeeCharged {
+                        account,
+                        asset,
+                        amount,
+                        ..
+                    }
+                    | Event::FeeRebated {
+                        account,
+                        asset,
+                        amount,
+                        ..
+                    } => Some((account.clone(), asset.clone(), amount.clone())),
+                    _ => None,
+                })
+                .collect()
+        };
+        let fee = |account: &str, asset: &str, amount: &str| {
+            (account.to_string(), asset.to_string(), amount.to_string())
+        };
+
+        // Base rates: the resting seller pays 0.02% of its USD, the buyer 0.1% of its ETH
+        app.deliver_tx(&sign(
+            &keys[0],
+            0,
+            limit(OrderSide::Sell, "1000000", "100", TimeInForce::GTC),
+        ))
+        .unwrap();
+        let events = app
+            .deliver_tx(&sign(
+                &keys[1],
+                0,
+                limit(OrderSide::Buy, "1000000", "100", TimeInForce::GTC),
+            ))
+            .unwrap();
+        assert!(events.iter().any(|event| matches!(event, Event::FeeCharged { liquidity: Liquidity::Maker, account, .. } if account == &maker)));
+        assert_eq!(
+            charged(&events),
+            vec![fee(&taker, "ETH", "1000"), fee(&maker, "USD", "20000")]
+        );
+
+        // 100 USD of volume reaches the rebate tier; staking halves the taker's fee
+        bank::mint(&mut app.state, &taker, &"OMC".to_string(), 1_000_000).unwrap();
+        let delegate = Msg::Delegate {
+            validator: "optimicval1genesis".to_string(),
+            amount: "1000000".to_string(),
+        };
+        app.deliver_tx(&sign(&keys[1], 1, delegate)).unwrap();
+        app.deliver_tx(&sign(
+            &keys[0],
+            1,
+            limit(OrderSide::Sell, "1000000", "100", TimeInForce::GTC),
+        ))
+        .unwrap();
+        let collected =
+            portfolio::balance(&app.state, &FEE_COLLECTOR.to_string(), &"ETH".to_string())
+                .unwrap()
+                .total;
+        let events = app
+            .deliver_tx(&sign(
+                &keys[1],
+                2,
+                limit(OrderSide::Buy, "1000000", "100", TimeInForce::GTC),
+            ))
+            .unwrap();
+        assert_eq!(
+            charged(&events),
+            vec![fee(&taker, "ETH", "400"), fee(&maker, "ETH", "300")]
+        );
+        let after = portfolio::balance(&app.state, &FEE_COLLECTOR.to_string(), &"ETH".to_string())
+            .unwrap()
+            .total;
+        assert_eq!(
+            after.parse::<u128>().unwrap() - collected.parse::<u128>().unwrap(),
+            100
+        );
+        assert_eq!(
+            fees::volume(&app.state, &maker, &market.quote_asset, app.block_time).unwrap(),
+            200_000_000
+        );
+
+        // Volume older than the window no longer counts
+        let later = app.block_time + chrono::TimeDelta::days(30);
+        assert_eq!(
+            fees::volume(&app.state, &maker, &market.quote_asset, later).unwrap(),
+            0
+        );
+        let rate = fees::fee_rate(&app.state, &market, &maker, Liquidity::Maker, later).unwrap();
+        assert_eq!(rate, "0.0002".parse().unwrap());
+
+        // A rebate may not exceed the taker rate it is paid from
+        let mut params = app.state.params.trading_fees.clone();
+        params.default_schedule.maker_rate = "-0.001".to_string();
+        let mut errors = Vec::new();
+        fees::validate_params(&params, &mut errors);
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn test_transfers_keep_supply() {
+        let (mut app, keys) = trading_app();
+        let sender = keys[0].account_address();
+        let recipient = crate::keys::KeyPair::generate().account_address();
+        let coin = |denom: &str, amount: &str| Coin {
+            denom: denom.to_string(),
+            amount: amount.to_string(),
+        };
+        let supply = app.state.supply.clone();
+
+        let events = app
+            .deliver_tx(&sign(
+                &keys[0],
+                0,
+                Msg::Send {
+                    to: recipient.clone(),
+                    amount: vec![coin("ETH", "1000000")],
+                },
+            ))
+            .unwrap();
+        assert!(
+            matches!(&events[..], [Event::Transfer { to, amount, .. }] if to == &recipient && amount == "1000000")
+        );
+        app.deliver_tx(&sign(
+            &keys[0],
+            1,
+            Msg::MultiSend {
+                outputs: vec![
+                    Output {
+                        address: recipient.clone(),
+                        coins: vec![coin("USD", "500")],
+                    },
+                    Output {
+                        address: keys[1].account_address(),
+                        coins: vec![coin("ETH", "1000000")],
+                    },
+                ],
+            },
+        ))
+        .unwrap();
+        assert_eq!(app.state.accounts[&recipient].balances["ETH"], "1000000");
+        assert_eq!(app.state.accounts[&recipient].balances["USD"], "500");
+        assert_eq!(app.state.accounts[&sender].balances["ETH"], "3000000");
+        assert_eq!(app.state.supply, supply);
+
+        // Funds locked by an open order cannot be sent
+        app.deliver_tx(&sign(
+            &keys[0],
+            2,
+            limit(OrderSide::Sell, "3000000", "100", TimeInForce::GTC),
+        ))
+        .unwrap();
+        let locked = app
+            .deliver_tx(&sign(
+                &keys[0],
+                3,
+                Msg::Send {
+                    to: recipient.clone(),
+                    amount: vec![coin("ETH", "1")],
+                },
+            ))
+            .unwrap_err();
+        assert_eq!(
+            crate::codes::result_code(&locked),
+            crate::codes::PORTFOLIO_INSUFFICIENT_FUNDS
+        );
+
+        let unknown = app
+            .deliver_tx(&sign(
+                &keys[0],
+                4,
+                Msg::Send {
+                    to: recipient,
+                    amount: vec![coin("DOGE", "1")],
+                },
+            ))
+            .unwrap_err();
+        assert_eq!(
+            crate::codes::result_code(&unknown),
+            crate::codes::BANK_UNKNOWN_ASSET
+        );
+        app.end_block().unwrap();
+    }
+
+    #[test]
+    fn test_bridge_deposits_and_withdrawals() {
+        use crate::bridge::{BridgedAsset, DepositClaim};
+        use crate::keys::KeyPair;
+
+        let (mut app, keys) = trading_app();
+        let attesters = [
+            KeyPair::generate(),
+            KeyPair::generate(),
+            KeyPair::generate(),
+        ];
+        app.state.params.bridge = BridgeParams {
+            attesters: attesters.iter().map(KeyPair::public_key_hex).collect(),
+            threshold: 2,
+            window_blocks: 100,
+            assets: vec![BridgedAsset {
+                asset: "ETH".to_string(),
+                deposit_limit: "1000".to_string(),
+                withdrawal_limit: "300".to_string(),
+            }],
+        };
+        let recipient = KeyPair::generate().account_address();
+        let claim = |nonce: u64, amount: &str| DepositClaim {
+            source_chain: "ethereum".to_string(),
+            nonce,
+            recipient: recipient.clone(),
+            asset: "ETH".to_string(),
+            amount: amount.to_string(),
+        };
+        let deposit = |claim: DepositClaim, signers: &[&KeyPair]| Msg::Deposit {
+            attestations: signers
+                .iter()
+                .map(|key| bridge::attest("optimic-1", &claim, key).unwrap())
+                .collect(),
+            claim,
+        };
+        let code = |error: anyhow::Error| crate::codes::result_code(&error);
+        let supply = bank::supply_of(&app.state, &"ETH".to_string()).unwrap();
+
+        let below_threshold = app.deliver_tx(&sign(
+            &keys[0],
+            0,
+            deposit(claim(1, "600"), &[&attesters[0]]),
+        ));
+        assert_eq!(
+            code(below_threshold.unwrap_err()),
+            crate::codes::BRIDGE_INSUFFICIENT_ATTESTATIONS
+        );
+
+        let events = app
+            .deliver_tx(&sign(
+                &keys[0],
+                0,
+                deposit(claim(1, "600"), &[&attesters[0], &attesters[2]]),
+            ))
+            .unwrap();
+        assert!(
+            matches!(&events[..], [Event::Deposited { deposit_id, .. }] if deposit_id == "ethereum:1")
+        );
+        assert_eq!(app.state.accounts[&recipient].balances["ETH"], "600");
+        assert_eq!(
+            bank::supply_of(&app.state, &"ETH".to_string()).unwrap(),
+            supply + 600
+        );
+
+        // A claim is minted once, and deposits are capped per window
+        let replay = app.deliver_tx(&sign(
+            &keys[0],
+            1,
+            deposit(claim(1, "600"), &[&attesters[0], &attesters[1]]),
+        ));
+        assert_eq!(
+            code(replay.unwrap_err()),
+            crate::codes::BRIDGE_DEPOSIT_PROCESSED
+        );
+        let over_limit = app.deliver_tx(&sign(
+            &keys[0],
+            1,
+            deposit(claim(2, "401"), &[&attesters[0], &attesters[1]]),
+        ));
+        assert_eq!(
+            code(over_limit.unwrap_err()),
+            crate::codes::BRIDGE_RATE_LIMITED
+        );
+
+        let withdraw = |amount: &str| Msg::Withdraw {
+            asset: "ETH".to_string(),
+            amount: amount.to_string(),
+            destination: "0x00000000000000000000000000000000000000aa".to_string(),
+        };
+        app.deliver_tx(&sign(&keys[1], 0, withdraw("300"))).unwrap();
+        assert_eq!(app.state.bridge.withdrawals[&1].amount, "300");
+        assert_eq!(
+            bank::supply_of(&app.state, &"ETH".to_string()).unwrap(),
+            supply + 300
+        );
+        let over_limit = app.deliver_tx(&sign(&keys[1], 1, withdraw("1")));
+        assert_eq!(
+            code(over_limit.unwrap_err()),
+            crate::codes::BRIDGE_RATE_LIMITED
+        );
+        app.end_block().unwrap();
+
+        // Limits reset in the next window
+        app.begin_block(100, Timestamp::UNIX_EPOCH).unwrap();
+        app.deliver_tx(&sign(&keys[1], 1, withdraw("1"))).unwrap();
+    }
+
+    #[test]
+    fn test_oracle_aggregates_weighted_median() {
+        use crate::keys::KeyPair;
+        use crate::oracle::{AssetPrice, Feeder};
+
+        let (mut app, keys) = trading_app();
+        let [extra, outsider] = [KeyPair::generate(), KeyPair::generate()];
+        for (account_number, key) in [(3, &extra), (4, &outsider)] {
+            app.state.accounts.insert(
+                key.account_address(),
+                Account {
+                    address: key.account_address(),
+                    public_key: None,
+                    account_number,
+                    sequence: 0,
+                    balances: std::collections::HashMap::new(),
+                },
+            );
+        }
+        let feeders = [&keys[0], &keys[1], &extra];
+        app.state.params.oracle = OracleParams {
+            assets: vec!["ETH".to_string()],
+            feeders: feeders
+                .iter()
+                .map(|key| Feeder {
+                    address: key.account_address(),
+                    weight: "1".to_string(),
+                })
+                .collect(),
+            validator_feeders: false,
+            max_age_blocks: 3,
+            min_sources: 2,
+            twap_window_blocks: 10,
+            ..OracleParams::default()
+        };
+        let submit = |price: &str| Msg::SubmitPrices {
+            prices: vec![AssetPrice {
+                asset: "ETH".to_string(),
+                price: price.to_string(),
+            }],
+        };
+
+        app.deliver_tx(&sign(
+            &keys[0],
+            0,
+            limit(OrderSide::Sell, "1000000", "90", TimeInForce::GTC),
+        ))
+        .unwrap();
+        app.deliver_tx(&sign(
+            &keys[1],
+            0,
+            limit(OrderSide::Buy, "1000000", "90", TimeInForce::GTC),
+        ))
+        .unwrap();
+        for (key, sequence, price) in [
+            (&keys[0], 1, "100"),
+            (&keys[1], 1, "102"),
+            (&extra, 0, "150"),
+        ] {
+            app.deliver_tx(&sign(key, sequence, submit(price))).unwrap();
+        }
+        let rejected = app
+            .deliver_tx(&sign(&outsider, 0, submit("100")))
+            .unwrap_err();
+        assert_eq!(
+            crate::codes::result_code(&rejected),
+            crate::codes::ORACLE_NOT_FEEDER
+        );
+
+        // 150 is more than 10% from the median of 102 and is left out
+        let events = app.end_block().unwrap();
+        assert!(events.iter().any(|event| matches!(
+            event,
+            Event::OraclePriceUpdated { price, sources: 2, outliers, .. } if price == "100" && outliers == &[extra.account_address()]
+        )));
+        assert_eq!(
+            oracle::mark_price(&app.state, &"ETH".to_string()).unwrap(),
+            Decimal::from(100)
+        );
+        let buyer = &app.state.portfolios[&keys[1].account_address()];
+        assert_eq!(buyer.positions["ETH-USD"].unrealized_pnl, "10000000");
+
+        app.begin_block(2, Timestamp::UNIX_EPOCH).unwrap();
+        app.deliver_tx(&sign(&keys[0], 2, submit("110"))).unwrap();
+        app.deliver_tx(&sign(&keys[1], 2, submit("110"))).unwrap();
+        app.end_block().unwrap();
+        assert_eq!(app.state.oracle.prices["ETH"].price, "110");
+        assert_eq!(app.state.oracle.prices["ETH"].twap, "105");
+
+        // Without fresh submissions the price goes stale
+        app.begin_block(5, Timestamp::UNIX_EPOCH).unwrap();
+        app.end_block().unwrap();
+        let stale = oracle::mark_price(&app.state, &"ETH".to_string()).unwrap_err();
+        assert_eq!(stale.code(), crate::codes::ORACLE_STALE_PRICE);
+    }
+
+    #[test]
+    fn test_staking_moves_the_validator_set() {
+        use crate::keys::operator_of;
+
+        let (mut app, keys) = trading_app();
+        let genesis_validator = "optimicval1genesis".to_string();
+        let operator = operator_of(&keys[0].account_address()).unwrap();
+        let delegator = keys[1].account_address();
+        for key in &keys {
+            bank::mint(
+                &mut app.state,
+                &key.account_address(),
+                &"OMC".to_string(),
+                300_000_000,
+            )
+            .unwrap();
+        }
+        app.state.params.staking = StakingParams {
+            max_validators: 1,
+            unbonding_time: 60,
+            ..StakingParams::default()
+        };
+        let at = |seconds| Timestamp::UNIX_EPOCH + chrono::TimeDelta::seconds(seconds);
+
+        let create = Msg::CreateValidator {
+            consensus_pubkey: hex::encode([7u8; 32]),
+            commission: Commission {
+                rate: "0.1".to_string(),
+                max_rate: "0.2".to_string(),
+                max_change_rate: "0.01".to_string(),
+                update_time: None,
+            },
+            amount: "50000000".to_string(),
+        };
+        app.deliver_tx(&sign(&keys[0], 0, create)).unwrap();
+        let delegate = Msg::Delegate {
+            validator: operator.clone(),
+            amount: "200000000".to_string(),
+        };
+        app.deliver_tx(&sign(&keys[1], 0, delegate)).unwrap();
+
+        // 250 OMC outweigh the genesis validator's 100 and take its seat
+        let events = app.end_block().unwrap();
+        let powers: Vec<_> = app
+            .validator_updates
+            .iter()
+            .map(|u| (u.address.clone(), u.power))
+            .collect();
+        assert_eq!(
+            powers,
+            [(operator.clone(), 250), (genesis_validator.clone(), 0)]
+        );
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, Event::ValidatorPowerChanged { power: 250, .. })));
+        assert!(matches!(
+            app.state.validators[&genesis_validator].status,
+            BondStatus::Unbonding
+        ));
+        assert_eq!(app.proposer(), Some(operator.clone()));
+
+        app.begin_block(2, at(10)).unwrap();
+        let redelegate = |source: &ValAddress, destination: &ValAddress| Msg::Redelegate {
+            src_validator: source.clone(),
+            dst_validator: destination.clone(),
+            amount: "50000000".to_string(),
+        };
+        let rejected = app
+            .deliver_tx(&sign(&keys[1], 1, redelegate(&operator, &operator)))
+            .unwrap_err();
+        assert_eq!(
+            crate::codes::result_code(&rejected),
+            crate::codes::STAKING_SELF_REDELEGATION
+        );
+        let undelegate = Msg::Undelegate {
+            validator: operator.clone(),
+            amount: "100000000".to_string(),
+        };
+        app.deliver_tx(&sign(&keys[1], 1, undelegate)).unwrap();
+        app.deliver_tx(&sign(
+            &keys[1],
+            2,
+            redelegate(&operator, &genesis_validator),
+        ))
+        .unwrap();
+        assert_eq!(app.state.validators[&operator].tokens, "100000000");
+        assert_eq!(app.state.validators[&genesis_validator].tokens, "150000000");
+        assert_eq!(
+            portfolio::available(&app.state, &delegator, &"OMC".to_string()).unwrap(),
+            100_000_000
+        );
+
+        // The genesis validator is back on top; the new one starts unbonding
+        app.end_block().unwrap();
+        let powers: Vec<_> = app
+            .validator_updates
+            .iter()
+            .map(|u| (u.address.clone(), u.power))
+            .collect();
+        assert_eq!(
+            powers,
+            [(operator.clone(), 0), (genesis_validator.clone(), 150)]
+        );
+        assert!(matches!(
+            app.state.validators[&operator].status,
+            BondStatus::Unbonding
+        ));
+
+        app.begin_block(3, at(70)).unwrap();
+        let events = app.end_block().unwrap();
+        assert!(events.iter().any(|event| matches!(
+            event,
+            Event::UnbondingCompleted { delegator: to, amount, .. } if to == &delegator && amount == "100000000"
+        )));
+        assert_eq!(
+            portfolio::available(&app.state, &delegator, &"OMC".to_string()).unwrap(),
+            200_000_000
+        );
+        assert!(matches!(
+            app.state.validators[&operator].status,
+            BondStatus::Unbonded
+        ));
+        assert!(app.validator_updates.is_empty());
+    }
+
+    #[test]
+    fn test_commission_changes_are_limited() {
+        use crate::keys::operator_of;
+
+        let (mut app, keys) = trading_app();
+        let operator = operator_of(&keys[0].account_address()).unwrap();
+        bank::mint(
+            &mut app.state,
+            &keys[0].account_address(),
+            &"OMC".to_string(),
+            10_000_000,
+        )
+        .unwrap();
+        let create = Msg::CreateValidator {
+            consensus_pubkey: hex::encode([3u8; 32]),
+            commission: Commission {
+                rate: "0.1".to_string(),
+                max_rate: "0.2".to_string(),
+                max_change_rate: "0.01".to_string(),
+                update_time: None,
+            },
+            amount: "10000000".to_string(),
+        };
+        app.deliver_tx(&sign(&keys[0], 0, create)).unwrap();
+        let edit = |rate: &str| Msg::EditValidator {
+            commission_rate: rate.to_string(),
+        };
+        let code = |result: Result<Vec<Event>>| crate::codes::result_code(&result.unwrap_err());
+
+        assert_eq!(
+            code(app.deliver_tx(&sign(&keys[0], 1, edit("0.105")))),
+            crate::codes::STAKING_COMMISSION_CHANGE_TOO_SOON
+        );
+
+        app.begin_block(2, Timestamp::UNIX_EPOCH + chrono::TimeDelta::days(1))
+            .unwrap();
+        assert_eq!(
+            code(app.deliver_tx(&sign(&keys[0], 1, edit("0.25")))),
+            crate::codes::STAKING_INVALID_COMMISSION
+        );
+        assert_eq!(
+            code(app.deliver_tx(&sign(&keys[0], 1, edit("0.2")))),
+            crate::codes::STAKING_COMMISSION_CHANGE_TOO_LARGE
+        );
+        let events = app.deliver_tx(&sign(&keys[0], 1, edit("0.11"))).unwrap();
+        assert!(matches!(&events[..], [Event::CommissionChanged { rate, .. }] if rate == "0.11"));
+        assert_eq!(app.state.validators[&operator].commission.rate, "0.11");
+        assert_eq!(
+            code(app.deliver_tx(&sign(&keys[0], 2, edit("0.1")))),
+            crate::codes::STAKING_COMMISSION_CHANGE_TOO_SOON
+        );
+    }
+
+    #[test]
+    fn test_downtime_and_double_sign_slashing() {
+        use crate::consensus::{Evidence, VoteInfo};
+        use crate::events::Infraction;
+        use crate::keys::operator_of;
+
+        let (mut app, keys) = trading_app();
+        let operator = operator_of(&keys[0].account_address()).unwrap();
+        for key in &keys {
+            bank::mint(
+                &mut app.state,
+                &key.account_address(),
+                &"OMC".to_string(),
+                100_000_000,
+            )
+            .unwrap();
+        }
+        app.state.params.slashing = SlashingParams {
+            signed_blocks_window: 4,
+            min_signed_per_window: "0.5".to_string(),
+            downtime_jail_duration: 60,
+            slash_fraction_downtime: "0.1".to_string(),
+            slash_fraction_double_sign: "0.5".to_string(),
+            ..SlashingParams::default()
+        };
+        let at = |height: u64| Timestamp::UNIX_EPOCH + chrono::TimeDelta::seconds(height as i64);
+        let missed = || CommitInfo {
+            votes: vec![VoteInfo {
+                validator: operator_of(&keys[0].account_address()).unwrap(),
+                signed: false,
+            }],
+            evidence: vec![],
+        };
+        let code = |result: Result<Vec<Event>>| crate::codes::result_code(&result.unwrap_err());
+
+        let create = Msg::CreateValidator {
+            consensus_pubkey: hex::encode([5u8; 32]),
+            commission: Commission {
+                rate: "0.1".to_string(),
+                max_rate: "0.2".to_string(),
+                max_change_rate: "0.01".to_string(),
+                update_time: None,
+            },
+            amount: "100000000".to_string(),
+        };
+        app.deliver_tx(&sign(&keys[0], 0, create)).unwrap();
+        let delegate = Msg::Delegate {
+            validator: operator.clone(),
+            amount: "100000000".to_string(),
+        };
+        app.deliver_tx(&sign(&keys[1], 0, delegate)).unwrap();
+        app.end_block().unwrap();
+
+        // Missing more than half of a 4 block window slashes 10% of the 150 OMC power
+        let mut slashed = Vec::new();
+        for height in 2..=7 {
+            app.begin_block(height, at(height)).unwrap();
+            app.commit_info = missed();
+            if height == 2 {
+                let undelegate = Msg::Undelegate {
+                    validator: operator.clone(),
+                    amount: "50000000".to_string(),
+                };
+                app.deliver_tx(&sign(&keys[1], 1, undelegate)).unwrap();
+            }
+            slashed = app.end_block().unwrap();
+        }
+        assert!(slashed.iter().any(|event| matches!(
+            event,
+            Event::ValidatorSlashed { infraction: Infraction::Downtime, amount, .. } if amount == "15000000"
+        )));
+        assert!(app.state.validators[&operator].jailed);
+        assert_eq!(app.state.validators[&operator].tokens, "135000000");
+        assert!(app
+            .validator_updates
+            .iter()
+            .any(|update| update.address == operator && update.power == 0));
+
+        app.begin_block(8, at(8)).unwrap();
+        assert_eq!(
+            code(app.deliver_tx(&sign(&keys[0], 1, Msg::Unjail))),
+            crate::codes::SLASHING_STILL_JAILED
+        );
+        app.end_block().unwrap();
+        app.begin_block(9, at(70)).unwrap();
+        app.deliver_tx(&sign(&keys[0], 1, Msg::Unjail)).unwrap();
+        app.end_block().unwrap();
+        assert_eq!(app.state.staking.last_powers[&operator], 135);
+
+        // Double-signing at height 2 also slashes the unbonding started there
+        app.begin_block(10, at(71)).unwrap();
+        app.commit_info.evidence.push(Evidence {
+            validator: operator.clone(),
+            height: 2,
+            power: 200,
+        });
+        let events = app.end_block().unwrap();
+        assert!(events.iter().any(|event| matches!(
+            event,
+            Event::ValidatorSlashed { infraction: Infraction::DoubleSign, amount, .. } if amount == "100000000"
+        )));
+        assert_eq!(app.state.staking.unbonding[0].amount, "25000000");
+        assert_eq!(app.state.validators[&operator].tokens, "60000000");
+        let delegation = &app.state.staking.delegations[&keys[1].account_address()][&operator];
+        assert_eq!(
+            staking::delegation_tokens(&app.state, delegation).unwrap(),
+            20_000_000
+        );
+
+        app.begin_block(11, at(200)).unwrap();
+        assert_eq!(
+            code(app.deliver_tx(&sign(&keys[0], 2, Msg::Unjail))),
+            crate::codes::SLASHING_TOMBSTONED
+        );
+        app.deliver_tx(&sign(
+            &keys[1],
+            2,
+            Msg::WithdrawRewards {
+                validator: operator.clone(),
+            },
+        ))
+        .unwrap();
+        app.end_block().unwrap();
+    }
+
+    #[test]
+    fn test_governance_votes_and_executes_proposals() {
+        use crate::governance::{ParamChange, ProposalStatus, VoteOption};
+        use crate::keys::operator_of;
+
+        let (mut app, keys) = trading_app();
+        let operator = operator_of(&keys[0].account_address()).unwrap();
+        let omc = "OMC".to_string();
+        for key in &keys {
+            bank::mint(&mut app.state, &key.account_address(), &omc, 1_000_000_000).unwrap();
+        }
+        app.state.params.governance = GovernanceParams {
+            min_deposit: "1000000".to_string(),
+            max_deposit_period: 60,
+            voting_period: 60,
+            ..GovernanceParams::default()
+        };
+        let at = |seconds: i64| Timestamp::UNIX_EPOCH + chrono::TimeDelta::seconds(seconds);
+        let code = |result: Result<Vec<Event>>| crate::codes::result_code(&result.unwrap_err());
+        let submit = |content, initial_deposit: &str| Msg::SubmitProposal {
+            title: "Raise the taker fee".to_string(),
+            description: String::new(),
+            content,
+            initial_deposit: initial_deposit.to_string(),
+        };
+        let vote = |proposal_id, option| Msg::Vote {
+            proposal_id,
+            option,
+        };
+
+        // keys[0] operates a validator with 300 OMC, 100 of them delegated by keys[1]
+        let create = Msg::CreateValidator {
+            consensus_pubkey: hex::encode([7u8; 32]),
+            commission: Commission {
+                rate: "0.1".to_string(),
+                max_rate: "0.2".to_string(),
+                max_change_rate: "0.01".to_string(),
+                update_time: None,
+            },
+            amount: "200000000".to_string(),
+        };
+        app.deliver_tx(&sign(&keys[0], 0, create)).unwrap();
+        let delegate = Msg::Delegate {
+            validator: operator.clone(),
+            amount: "100000000".to_string(),
+        };
+        app.deliver_tx(&sign(&keys[1], 0, delegate)).unwrap();
+        app.end_block().unwrap();
+
+        let invalid = ProposalContent::ParamChange {
+            changes: vec![ParamChange {
+                key: "trading_fees.default_schedule.taker_rate".to_string(),
+                value: serde_json::json!("2"),
+            }],
+        };
+        app.begin_block(2, at(0)).unwrap();
+        assert_eq!(
+            code(app.deliver_tx(&sign(&keys[1], 1, submit(invalid, "0")))),
+            crate::codes::GOVERNANCE_INVALID_PROPOSAL
+        );
+
+        // Voting starts once the deposits reach min_deposit
+        let change = ProposalContent::ParamChange {
+            changes: vec![ParamChange {
+                key: "trading_fees.default_schedule.taker_rate".to_string(),
+                value: serde_json::json!("0.001"),
+            }],
+        };
+        app.deliver_tx(&sign(&keys[1], 1, submit(change, "500000")))
+            .unwrap();
+        let events = app
+            .deliver_tx(&sign(
+                &keys[0],
+                1,
+                Msg::DepositProposal {
+                    proposal_id: 1,
+                    amount: "500000".to_string(),
+                },
+            ))
+            .unwrap();
+        assert!(matches!(
+            events[..],
+            [
+                Event::ProposalDeposited { .. },
+                Event::VotingStarted { proposal_id: 1, .. }
+            ]
+        ));
+
+        // The delegator's own vote overrides the operator's for its 100 OMC
+        app.deliver_tx(&sign(&keys[0], 2, vote(1, VoteOption::Yes)))
+            .unwrap();
+        app.deliver_tx(&sign(&keys[1], 2, vote(1, VoteOption::No)))
+            .unwrap();
+        let tally = governance::tally(&app.state, 1).unwrap();
+        assert_eq!(
+            (tally.yes.as_str(), tally.no.as_str()),
+            ("200000000", "100000000")
+        );
+        app.end_block().unwrap();
+        assert_eq!(
+            app.state.governance.proposals[&1].status,
+            ProposalStatus::VotingPeriod
+        );
+
+        app.begin_block(3, at(60)).unwrap();
+        let events = app.end_block().unwrap();
+        assert!(events.iter().any(|event| matches!(
+            event,
+            Event::ProposalTallied {
+                proposal_id: 1,
+                status: ProposalStatus::Passed,
+                ..
+            }
+        )));
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, Event::ProposalExecuted { proposal_id: 1 })));
+        assert_eq!(
+            app.state.params.trading_fees.default_schedule.taker_rate,
+            "0.001"
+        );
+        assert_eq!(
+            portfolio::balance(&app.state, &keys[1].account_address(), &omc)
+                .unwrap()
+                .total,
+            "900000000"
+        );
+
+        // Votes close with the voting period; a veto burns the deposit
+        app.begin_block(4, at(61)).unwrap();
+        assert_eq!(
+            code(app.deliver_tx(&sign(&keys[0], 3, vote(1, VoteOption::Yes)))),
+            crate::codes::GOVERNANCE_NOT_VOTING
+        );
+        app.deliver_tx(&sign(&keys[1], 3, submit(ProposalContent::Text, "1000000")))
+            .unwrap();
+        app.deliver_tx(&sign(&keys[0], 3, vote(2, VoteOption::NoWithVeto)))
+            .unwrap();
+        app.end_block().unwrap();
+        app.begin_block(5, at(121)).unwrap();
+        app.end_block().unwrap();
+        assert_eq!(
+            app.state.governance.proposals[&2].status,
+            ProposalStatus::Rejected
+        );
+        assert_eq!(
+            portfolio::balance(&app.state, &keys[1].account_address(), &omc)
+                .unwrap()
+                .total,
+            "899000000"
+        );
+        assert_eq!(
+            app.state.accounts[governance::GOVERNANCE_ACCOUNT].balances[&omc],
+            "0"
+        );
+
+        // A spend the treasury cannot cover passes but fails to execute
+        let spend = ProposalContent::TreasurySpend {
+            recipient: keys[1].account_address(),
+            amount: vec![Coin {
+                denom: "USD".to_string(),
+                amount: "1000000000000".to_string(),
+            }],
+        };
+        app.begin_block(6, at(122)).unwrap();
+        app.deliver_tx(&sign(&keys[1], 4, submit(spend, "1000000")))
+            .unwrap();
+        app.deliver_tx(&sign(&keys[0], 4, vote(3, VoteOption::Yes)))
+            .unwrap();
+        app.end_block().unwrap();
+        app.begin_block(7, at(182)).unwrap();
+        let events = app.end_block().unwrap();
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, Event::ProposalFailed { proposal_id: 3, .. })));
+        assert_eq!(
+            app.state.governance.proposals[&3].status,
+            ProposalStatus::Failed
+        );
+    }
+
+    #[test]
+    fn test_failed_transaction_rolls_back_order_books() {
+        let (mut app, keys) = trading_app();
+        let send = Msg::Send {
+            to: keys[1].account_address(),
+            amount: vec![Coin {
+                denom: "ETH".to_string(),
+                amount: "4500000".to_string(),
+            }],
+        };
+
+        // The send fails once the sell locks its ETH; the ask must go with it
+        let body = crate::tx::TxBody {
+            chain_id: "optimic-1".to_string(),
+            sender: keys[0].account_address(),
+            sequence: 0,
+            timeout_height: 0,
+            msgs: vec![
+                limit(OrderSide::Sell, "1000000", "100", TimeInForce::GTC),
+                send,
+            ],
+            memo: String::new(),
+        };
+        let tx = Tx::sign(body, &keys[0]).unwrap().encode().unwrap();
+        let portfolios = app.state.portfolios.clone();
+        assert!(app.deliver_tx(&tx).is_err());
+        assert!(app.state.orders.is_empty());
+        // Rolling back restores the shared snapshot rather than a copy
+        assert!(app.state.portfolios.ptr_eq(&portfolios));
+        assert!(app
+            .trading
+            .get_order_book(&"ETH-USD".to_string())
+            .unwrap()
+            .asks
+            .is_empty());
+
+        let events = app
+            .deliver_tx(&sign(
+                &keys[1],
+                0,
+                limit(OrderSide::Buy, "1000000", "100", TimeInForce::GTC),
+            ))
+            .unwrap();
+        assert!(!events
+            .iter()
+            .any(|event| matches!(event, Event::TradeExecuted { .. })));
+        assert!(app.state.orders[&1].is_open());
+        portfolio::check_invariants(&app.state).unwrap();
+    }
+
+    #[test]
+    fn test_market_admin_suspends_resumes_and_closes_markets() {
+        let (mut app, keys) = trading_app();
+        let (trader, admin) = (keys[0].account_address(), keys[1].account_address());
+        app.state.params.markets.admin = Some(admin.clone());
+        let code = |result: Result<Vec<Event>>| crate::codes::result_code(&result.unwrap_err());
+        let market = || "ETH-USD".to_string();
+        let usd =
+            |app: &OptimicApp| portfolio::balance(&app.state, &trader, &"USD".to_string()).unwrap();
+
+        app.deliver_tx(&sign(
+            &keys[0],
+            0,
+            limit(OrderSide::Buy, "1000000", "10", TimeInForce::GTC),
+        ))
+        .unwrap();
+        app.deliver_tx(&sign(
+            &keys[0],
+            1,
+            limit(OrderSide::Buy, "1000000", "11", TimeInForce::GTC),
+        ))
+        .unwrap();
+        assert_eq!(usd(&app).locked, "21000000");
+        assert_eq!(
+            code(app.deliver_tx(&sign(&keys[0], 2, Msg::SuspendMarket { market: market() }))),
+            crate::codes::TRADING_NOT_MARKET_ADMIN
+        );
+
+        // A suspended market only accepts cancellations
+        let events = app
+            .deliver_tx(&sign(&keys[1], 0, Msg::SuspendMarket { market: market() }))
+            .unwrap();
+        assert!(matches!(
+            events[..],
+            [Event::MarketStatusChanged {
+                from: MarketStatus::Active,
+                to: MarketStatus::Suspended,
+                ..
+            }]
+        ));
+        assert_eq!(
+            code(app.deliver_tx(&sign(
+                &keys[1],
+                1,
+                limit(OrderSide::Sell, "1000000", "10", TimeInForce::GTC)
+            ))),
+            crate::codes::TRADING_MARKET_NOT_ACTIVE
+        );
+        app.deliver_tx(&sign(&keys[0], 2, Msg::CancelOrder { order_id: 1 }))
+            .unwrap();
+        assert_eq!(
+            code(app.deliver_tx(&sign(&keys[1], 1, Msg::SuspendMarket { market: market() }))),
+            crate::codes::TRADING_INVALID_STATUS_CHANGE
+        );
+
+        app.deliver_tx(&sign(&keys[1], 1, Msg::ResumeMarket { market: market() }))
+            .unwrap();
+        app.deliver_tx(&sign(
+            &keys[0],
+            3,
+            limit(OrderSide::Buy, "1000000", "12", TimeInForce::GTC),
+        ))
+        .unwrap();
+        assert_eq!(usd(&app).locked, "23000000");
+
+        // Closing cancels the resting orders and unlocks their funds for good
+        let events = app
+            .deliver_tx(&sign(&keys[1], 2, Msg::CloseMarket { market: market() }))
+            .unwrap();
+        assert!(matches!(
+            events[..],
+            [
+                Event::OrderCancelled {
+                    order_id: 2,
+                    reason: CancelReason::MarketClosed,
+                    ..
+                },
+                Event::OrderCancelled {
+                    order_id: 3,
+                    reason: CancelReason::MarketClosed,
+                    ..
+                },
+                Event::MarketStatusChanged {
+                    to: MarketStatus::Closed,
+                    ..
+                },
+            ]
+        ));
+        assert_eq!(usd(&app).locked, "0");
+        assert!(app
+            .trading
+            .get_order_book(&market())
+            .unwrap()
+            .bids
+            .is_empty());
+        assert_eq!(app.state.markets[&market()].status, MarketStatus::Closed);
+        assert_eq!(
+            code(app.deliver_tx(&sign(&keys[1], 3, Msg::ResumeMarket { market: market() }))),
+            crate::codes::TRADING_INVALID_STATUS_CHANGE
+        );
+        portfolio::check_invariants(&app.state).unwrap();
+
+        // Listings are checked like genesis markets
+        let mut listing = app.state.markets[&market()].clone();
+        listing.id = "ETH-BTC".to_string();
+        listing.quote_asset = "BTC".to_string();
+        listing.status = MarketStatus::Active;
+        listing.tick_size = "0".to_string();
+        assert_eq!(
+            code(app.deliver_tx(&sign(
+                &keys[1],
+                3,
+                Msg::ListMarket {
+                    market: listing.clone()
+                }
+            ))),
+            crate::codes::TRADING_INVALID_MARKET
+        );
+        listing.tick_size = "0.0001".to_string();
+        assert_eq!(
+            code(app.deliver_tx(&sign(
+                &keys[0],
+                4,
+                Msg::ListMarket {
+                    market: listing.clone()
+                }
+            ))),
+            crate::codes::TRADING_NOT_MARKET_ADMIN
+        );
+        let events = app
+            .deliver_tx(&sign(&keys[1], 3, Msg::ListMarket { market: listing }))
+            .unwrap();
+        assert!(matches!(&events[..], [Event::MarketListed { market }] if market == "ETH-BTC"));
+        let sell = Msg::PlaceOrder {
+            market: "ETH-BTC".to_string(),
+            side: OrderSide::Sell,
+            order_type: OrderType::Limit,
+            quantity: "1000000".to_string(),
+            price: Some("0.05".to_string()),
+            time_in_force: TimeInForce::GTC,
+        };
+        app.deliver_tx(&sign(&keys[0], 4, sell)).unwrap();
+    }
+
+    #[test]
+    fn test_matching_mode_switches_by_admin_and_governance() {
+        use crate::governance::VoteOption;
+        use crate::trading::TradingEngine;
+
+        let (mut app, keys) = trading_app();
+        app.state.params.markets.admin = Some(keys[1].account_address());
+        let code = |result: Result<Vec<Event>>| crate::codes::result_code(&result.unwrap_err());
+        let market = || "ETH-USD".to_string();
+        let switch = |mode| Msg::SetMatchingMode {
+            market: market(),
+            mode,
+        };
+
+        assert_eq!(
+            code(app.deliver_tx(&sign(&keys[0], 0, switch(MatchingMode::BatchAuction)))),
+            crate::codes::TRADING_NOT_MARKET_ADMIN
+        );
+        let events = app
+            .deliver_tx(&sign(&keys[1], 0, switch(MatchingMode::BatchAuction)))
+            .unwrap();
+        assert!(matches!(
+            events[..],
+            [Event::MatchingModeChanged {
+                from: MatchingMode::Continuous,
+                to: MatchingMode::BatchAuction,
+                ..
+            }]
+        ));
+        assert_eq!(
+            app.state.markets[&market()].matching_mode,
+            MatchingMode::BatchAuction
+        );
+        assert_eq!(
+            code(app.deliver_tx(&sign(&keys[1], 1, switch(MatchingMode::BatchAuction)))),
+            crate::codes::TRADING_INVALID_MARKET
+        );
+
+        // Crossing orders wait for the auction
+        app.deliver_tx(&sign(
+            &keys[0],
+            0,
+            limit(OrderSide::Buy, "1000000", "11", TimeInForce::GTC),
+        ))
+        .unwrap();
+        let events = app
+            .deliver_tx(&sign(
+                &keys[1],
+                1,
+                limit(OrderSide::Sell, "1000000", "10", TimeInForce::GTC),
+            ))
+            .unwrap();
+        assert!(!events
+            .iter()
+            .any(|event| matches!(event, Event::TradeExecuted { .. })));
+
+        // Switching back clears the pending auction and settles its trade
+        let events = app
+            .deliver_tx(&sign(&keys[1], 2, switch(MatchingMode::Continuous)))
+            .unwrap();
+        assert!(matches!(events[0], Event::TradeExecuted { .. }));
+        assert!(matches!(
+            events.last(),
+            Some(Event::MatchingModeChanged {
+                from: MatchingMode::BatchAuction,
+                to: MatchingMode::Continuous,
+                ..
+            })
+        ));
+        for order_id in [1, 2] {
+            assert_eq!(app.state.orders[&order_id].filled_quantity, "1000000");
+        }
+        portfolio::check_invariants(&app.state).unwrap();
+        let rebuilt = TradingEngine::rebuild(&app.state.markets, &app.state.orders).unwrap();
+        assert_eq!(
+            rebuilt.get_order_book(&market()).unwrap().matching_mode,
+            MatchingMode::Continuous
+        );
+        app.end_block().unwrap();
+
+        // Governance switches markets the same way
+        let operator = crate::keys::operator_of(&keys[0].account_address()).unwrap();
+        let omc = "OMC".to_string();
+        bank::mint(
+            &mut app.state,
+            &keys[0].account_address(),
+            &omc,
+            1_000_000_000,
+        )
+        .unwrap();
+        app.state.params.governance = GovernanceParams {
+            min_deposit: "1000000".to_string(),
+            voting_period: 60,
+            ..GovernanceParams::default()
+        };
+        let at = |seconds: i64| Timestamp::UNIX_EPOCH + chrono::TimeDelta::seconds(seconds);
+        app.begin_block(2, at(0)).unwrap();
+        let create = Msg::CreateValidator {
+            consensus_pubkey: hex::encode([7u8; 32]),
+            commission: Commission {
+                rate: "0.1".to_string(),
+                max_rate: "0.2".to_string(),
+                max_change_rate: "0.01".to_string(),
+                update_time: None,
+            },
+            amount: "200000000".to_string(),
+        };
+        app.deliver_tx(&sign(&keys[0], 1, create)).unwrap();
+        app.end_block().unwrap();
+        assert!(app.state.staking.last_powers.contains_key(&operator));
+
+        app.begin_block(3, at(0)).unwrap();
+        let proposal = Msg::SubmitProposal {
+            title: "Batch ETH-USD".to_string(),
+            description: String::new(),
+            content: ProposalContent::MatchingModeChange {
+                market: market(),
+                mode: MatchingMode::BatchAuction,
+            },
+            initial_deposit: "1000000".to_string(),
+        };
+        app.deliver_tx(&sign(&keys[0], 2, proposal)).unwrap();
+        let vote = Msg::Vote {
+            proposal_id: 1,
+            option: VoteOption::Yes,
+        };
+        app.deliver_tx(&sign(&keys[0], 3, vote)).unwrap();
+        app.end_block().unwrap();
+
+        app.begin_block(4, at(60)).unwrap();
+        let events = app.end_block().unwrap();
+        assert!(events.iter().any(|event| matches!(
+            event,
+            Event::MatchingModeChanged {
+                to: MatchingMode::BatchAuction,
+                ..
+            }
+        )));
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, Event::ProposalExecuted { proposal_id: 1 })));
+        assert_eq!(
+            app.state.markets[&market()].matching_mode,
+            MatchingMode::BatchAuction
+        );
+        assert_eq!(
+            app.trading.get_order_book(&market()).unwrap().matching_mode,
+            MatchingMode::BatchAuction
+        );
+    }
+
+    #[test]
+    fn test_order_entry_checks_tick_lot_and_price_band() {
+        let (mut app, keys) = trading_app();
+        let code = |result: Result<Vec<Event>>| crate::codes::result_code(&result.unwrap_err());
+        let buy =
+            |quantity: &str, price: &str| limit(OrderSide::Buy, quantity, price, TimeInForce::GTC);
+
+        // ETH-USD trades in lots of 1000 from 1000000 at a tick of 0.01
+        assert_eq!(
+            code(app.deliver_tx(&sign(&keys[0], 0, buy("1000000", "10.001")))),
+            crate::codes::TRADING_OFF_TICK
+        );
+        assert_eq!(
+            code(app.deliver_tx(&sign(&keys[0], 0, buy("999000", "10")))),
+            crate::codes::TRADING_BELOW_MIN_SIZE
+        );
+        assert_eq!(
+            code(app.deliver_tx(&sign(&keys[0], 0, buy("1000500", "10")))),
+            crate::codes::TRADING_OFF_LOT
+        );
+        let market_sell = Msg::PlaceOrder {
+            market: "ETH-USD".to_string(),
+            side: OrderSide::Sell,
+            order_type: OrderType::Market,
+            quantity: "1000".to_string(),
+            price: None,
+            time_in_force: TimeInForce::IOC,
+        };
+        assert_eq!(
+            code(app.deliver_tx(&sign(&keys[0], 0, market_sell))),
+            crate::codes::TRADING_BELOW_MIN_SIZE
+        );
+
+        // Without a mark price any price on the grid is accepted; a trade sets one
+        app.deliver_tx(&sign(&keys[0], 0, buy("1000000", "10.01")))
+            .unwrap();
+        app.deliver_tx(&sign(
+            &keys[1],
+            0,
+            limit(OrderSide::Sell, "1000000", "10", TimeInForce::GTC),
+        ))
+        .unwrap();
+        assert_eq!(app.state.mark_prices["ETH-USD"], "10.01");
+
+        // Limit prices must stay within 10% of the mark
+        assert_eq!(
+            code(app.deliver_tx(&sign(&keys[0], 1, buy("1000000", "11.02")))),
+            crate::codes::TRADING_OUTSIDE_PRICE_BAND
+        );
+        assert_eq!(
+            code(app.deliver_tx(&sign(&keys[0], 1, buy("1000000", "9")))),
+            crate::codes::TRADING_OUTSIDE_PRICE_BAND
+        );
+        app.deliver_tx(&sign(&keys[0], 1, buy("1000000", "11.01")))
+            .unwrap();
+        app.state.params.markets.price_band = "0".to_string();
+        app.deliver_tx(&sign(&keys[0], 2, buy("1000000", "1")))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_block_rewards_pay_commission_and_delegators() {
+        use crate::keys::operator_of;
+
+        let (mut app, keys) = trading_app();
+        let operator = operator_of(&keys[0].account_address()).unwrap();
+        let omc = |app: &OptimicApp, key: &crate::keys::KeyPair| {
+            portfolio::available(&app.state, &key.account_address(), &"OMC".to_string()).unwrap()
+        };
+        for key in &keys {
+            bank::mint(
+                &mut app.state,
+                &key.account_address(),
+                &"OMC".to_string(),
+                300_000_000,
+            )
+            .unwrap();
+        }
+        app.state.params.distribution = DistributionParams {
+            block_reward: "1000000".to_string(),
+            halving_interval: 0,
+        };
+        let halving = DistributionParams {
+            halving_interval: 10,
+            ..app.state.params.distribution.clone()
+        };
+        assert_eq!(distribution::emission(&halving, 25).unwrap(), 250_000);
+
+        let create = Msg::CreateValidator {
+            consensus_pubkey: hex::encode([9u8; 32]),
+            commission: Commission {
+                rate: "0.1".to_string(),
+                max_rate: "0.2".to_string(),
+                max_change_rate: "0.01".to_string(),
+                update_time: None,
+            },
+            amount: "100000000".to_string(),
+        };
+        app.deliver_tx(&sign(&keys[0], 0, create)).unwrap();
+        let delegate = Msg::Delegate {
+            validator: operator.clone(),
+            amount: "300000000".to_string(),
+        };
+        app.deliver_tx(&sign(&keys[1], 0, delegate)).unwrap();
+
+        // The new validator joins the set at the end of block 1 and earns from block 2
+        app.end_block().unwrap();
+        app.begin_block(2, Timestamp::UNIX_EPOCH).unwrap();
+        let events = app.end_block().unwrap();
+        assert!(events.iter().any(|event| matches!(
+            event,
+            Event::RewardsAllocated { validator, amount, commission, .. }
+                if validator == &operator && amount == "800000" && commission == "80000"
+        )));
+        let pending =
+            distribution::pending_rewards(&app.state, &keys[1].account_address(), &operator)
+                .unwrap();
+        assert_eq!(pending[0].amount, "540000");
+
+        app.begin_block(3, Timestamp::UNIX_EPOCH).unwrap();
+        app.deliver_tx(&sign(
+            &keys[1],
+            1,
+            Msg::WithdrawRewards {
+                validator: operator.clone(),
+            },
+        ))
+        .unwrap();
+        app.deliver_tx(&sign(
+            &keys[0],
+            1,
+            Msg::WithdrawRewards {
+                validator: operator.clone(),
+            },
+        ))
+        .unwrap();
+        app.deliver_tx(&sign(&keys[0], 2, Msg::WithdrawCommission))
+            .unwrap();
+        assert_eq!(omc(&app, &keys[1]), 540_000);
+        assert_eq!(omc(&app, &keys[0]), 200_000_000 + 180_000 + 80_000);
+        let rejected = app
+            .deliver_tx(&sign(&keys[1], 2, Msg::WithdrawCommission))
+            .unwrap_err();
+        assert_eq!(
+            crate::codes::result_code(&rejected),
+            crate::codes::DISTRIBUTION_UNKNOWN_VALIDATOR
+        );
+
+        // Delegating more withdraws what the delegation earned in the meantime
+        app.end_block().unwrap();
+        app.begin_block(4, Timestamp::UNIX_EPOCH).unwrap();
+        let delegate = Msg::Delegate {
+            validator: operator.clone(),
+            amount: "540000".to_string(),
+        };
+        app.deliver_tx(&sign(&keys[1], 2, delegate)).unwrap();
+        assert_eq!(omc(&app, &keys[1]), 540_000);
+        app.end_block().unwrap();
+    }
+}
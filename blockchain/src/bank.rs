@@ -0,0 +1,424 @@
+//! Bank Module
+//!
+//! This module moves assets between accounts and maintains the asset
+//! registry and the total supply of every asset. Transfers only spend
+//! available balances, so funds locked by open orders cannot be sent away;
+//! minting and burning are the only operations that change supply.
+
+use crate::app::AppState;
+use crate::codes;
+use crate::events::Event;
+use crate::keys::is_account_address;
+use crate::math::{parse_amount, NumberError};
+use crate::portfolio::{self, PortfolioError};
+use crate::tx::Output;
+use crate::types::*;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use thiserror::Error;
+
+type Result<T> = std::result::Result<T, BankError>;
+
+/// Largest number of decimals an asset may declare
+pub const MAX_DECIMALS: u8 = 18;
+
+/// Reasons a transfer or supply operation is rejected
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum BankError {
+    #[error("unknown asset {0}")]
+    UnknownAsset(AssetId),
+
+    #[error("amount of {0} must be positive")]
+    ZeroAmount(AssetId),
+
+    #[error("{0} is listed twice")]
+    DuplicateDenom(AssetId),
+
+    #[error("no coins to send")]
+    NoCoins,
+
+    #[error("invalid recipient address '{0}'")]
+    InvalidRecipient(AccAddress),
+
+    #[error("supply of {asset} is {supply} but balances sum to {balances}")]
+    SupplyMismatch {
+        asset: AssetId,
+        supply: Uint128,
+        balances: Uint128,
+    },
+
+    #[error(transparent)]
+    Portfolio(#[from] PortfolioError),
+
+    #[error(transparent)]
+    Number(#[from] NumberError),
+}
+
+impl BankError {
+    /// Stable result code, see [`crate::codes`]
+    pub fn code(&self) -> u32 {
+        match self {
+            BankError::UnknownAsset(_) => codes::BANK_UNKNOWN_ASSET,
+            BankError::ZeroAmount(_) => codes::BANK_ZERO_AMOUNT,
+            BankError::DuplicateDenom(_) => codes::BANK_DUPLICATE_DENOM,
+            BankError::NoCoins => codes::BANK_NO_COINS,
+            BankError::InvalidRecipient(_) => codes::BANK_INVALID_RECIPIENT,
+            BankError::SupplyMismatch { .. } => codes::BANK_SUPPLY_MISMATCH,
+            BankError::Portfolio(error) => error.code(),
+            BankError::Number(error) => error.code(),
+        }
+    }
+}
+
+impl AssetMetadata {
+    /// Metadata for an asset that was used without being registered
+    pub fn unnamed(id: &AssetId) -> Self {
+        Self {
+            id: id.clone(),
+            display: id.clone(),
+            decimals: 0,
+            description: String::new(),
+        }
+    }
+}
+
+/// Register every asset held or traded without metadata and recompute the
+/// supply of each asset from the account balances
+pub fn init_supply(state: &mut AppState) -> Result<()> {
+    let held = state
+        .accounts
+        .values()
+        .flat_map(|account| account.balances.keys());
+    let traded = state
+        .markets
+        .values()
+        .flat_map(|market| [&market.base_asset, &market.quote_asset]);
+    let unregistered: Vec<_> = held
+        .chain(traded)
+        .filter(|id| !state.assets.contains_key(*id))
+        .cloned()
+        .collect();
+    for id in unregistered {
+        state.assets.insert(id.clone(), AssetMetadata::unnamed(&id));
+    }
+
+    state.supply = balance_sums(state)?
+        .into_iter()
+        .map(|(asset, amount)| (asset, amount.to_string()))
+        .collect();
+    Ok(())
+}
+
+/// Check that `from` can send every output
+pub fn validate_send(state: &AppState, from: &AccAddress, outputs: &[Output]) -> Result<()> {
+    let mut totals: BTreeMap<&AssetId, u128> = BTreeMap::new();
+    for output in outputs {
+        if !is_account_address(&output.address) {
+            return Err(BankError::InvalidRecipient(output.address.clone()));
+        }
+        if output.coins.is_empty() {
+            return Err(BankError::NoCoins);
+        }
+        let mut denoms = HashSet::new();
+        for coin in &output.coins {
+            if !state.assets.contains_key(&coin.denom) {
+                return Err(BankError::UnknownAsset(coin.denom.clone()));
+            }
+            if !denoms.insert(&coin.denom) {
+                return Err(BankError::DuplicateDenom(coin.denom.clone()));
+            }
+            let amount = parse_amount(&coin.amount)?;
+            if amount == 0 {
+                return Err(BankError::ZeroAmount(coin.denom.clone()));
+            }
+            let total = totals.entry(&coin.denom).or_default();
+            *total = total
+                .checked_add(amount)
+                .ok_or_else(|| NumberError::Overflow(format!("total {} sent", coin.denom)))?;
+        }
+    }
+    if totals.is_empty() {
+        return Err(BankError::NoCoins);
+    }
+    for (asset, total) in totals {
+        portfolio::ensure_available(state, from, asset, total)?;
+    }
+    Ok(())
+}
+
+/// Send coins from `from` to every output, creating missing recipients
+pub fn send(state: &mut AppState, from: &AccAddress, outputs: &[Output]) -> Result<Vec<Event>> {
+    validate_send(state, from, outputs)?;
+    let mut events = Vec::new();
+    for output in outputs {
+        ensure_account(state, &output.address);
+        for coin in &output.coins {
+            let amount = parse_amount(&coin.amount)?;
+            portfolio::debit(state, from, &coin.denom, amount)?;
+            portfolio::credit(state, &output.address, &coin.denom, amount)?;
+            events.push(Event::Transfer {
+                from: from.clone(),
+                to: output.address.clone(),
+                asset: coin.denom.clone(),
+                amount: coin.amount.clone(),
+            });
+        }
+    }
+    Ok(events)
+}
+
+/// Move available funds between accounts, such as into a module account
+pub fn transfer(
+    state: &mut AppState,
+    from: &AccAddress,
+    to: &AccAddress,
+    asset: &AssetId,
+    amount: u128,
+) -> Result<()> {
+    ensure_account(state, to);
+    portfolio::debit(state, from, asset, amount)?;
+    portfolio::credit(state, to, asset, amount)?;
+    Ok(())
+}
+
+/// Create new units of `asset` in the account of `to`
+pub fn mint(state: &mut AppState, to: &AccAddress, asset: &AssetId, amount: u128) -> Result<()> {
+    if !state.assets.contains_key(asset) {
+        return Err(BankError::UnknownAsset(asset.clone()));
+    }
+    ensure_account(state, to);
+    portfolio::credit(state, to, asset, amount)?;
+    let supply = supply_of(state, asset)?
+        .checked_add(amount)
+        .ok_or_else(|| NumberError::Overflow(format!("supply of {}", asset)))?;
+    state.supply.insert(asset.clone(), supply.to_string());
+    Ok(())
+}
+
+/// Destroy units of `asset` from the available balance of `from`
+pub fn burn(state: &mut AppState, from: &AccAddress, asset: &AssetId, amount: u128) -> Result<()> {
+    portfolio::debit(state, from, asset, amount)?;
+    let supply = supply_of(state, asset)?.saturating_sub(amount);
+    state.supply.insert(asset.clone(), supply.to_string());
+    Ok(())
+}
+
+/// Total supply of an asset
+pub fn supply_of(state: &AppState, asset: &AssetId) -> Result<u128> {
+    match state.supply.get(asset) {
+        Some(amount) => Ok(parse_amount(amount)?),
+        None => Ok(0),
+    }
+}
+
+/// Verify that the balances of every asset sum to its supply
+pub fn check_invariants(state: &AppState) -> Result<()> {
+    let sums = balance_sums(state)?;
+    let assets: HashSet<&AssetId> = sums.keys().chain(state.supply.keys()).collect();
+    for asset in assets {
+        let (supply, balances) = (
+            supply_of(state, asset)?,
+            sums.get(asset).copied().unwrap_or(0),
+        );
+        if supply != balances {
+            return Err(BankError::SupplyMismatch {
+                asset: asset.clone(),
+                supply: supply.to_string(),
+                balances: balances.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn balance_sums(state: &AppState) -> Result<HashMap<AssetId, u128>> {
+    let mut sums: HashMap<AssetId, u128> = HashMap::new();
+    for (asset, amount) in state
+        .accounts
+        .values()
+        .flat_map(|account| &account.balances)
+    {
+        let sum = sums.entry(asset.clone()).or_default();
+        *sum = sum
+            .checked_add(parse_amount(amount)?)
+            .ok_or_else(|| NumberError::Overflow(format!("supply of {}", asset)))?;
+    }
+    Ok(sums)
+}
+
+/// Create an empty account for `address` if it does not exist yet
+pub fn ensure_account(state: &mut AppState, address: &AccAddress) {
+    if state.accounts.contains_key(address) {
+        return;
+    }
+    let account_number = state
+        .accounts
+        .values()
+        .map(|account| account.account_number + 1)
+        .max()
+        .unwrap_or(0);
+    state.accounts.insert(
+        address.clone(),
+        Account {
+            address: address.clone(),
+            public_key: None,
+            account_number,
+            sequence: 0,
+            balances: HashMap::new(),
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::test_state;
+    use crate::keys::KeyPair;
+
+    /// Empty state with USD registered and 1000 minted to a fresh account
+    fn setup() -> (AppState, AccAddress) {
+        let mut state = test_state(&["USD"]);
+        let usd = "USD".to_string();
+        let owner = KeyPair::generate().account_address();
+        mint(&mut state, &owner, &usd, 1000).unwrap();
+        (state, owner)
+    }
+
+    fn output(address: &AccAddress, coins: &[(&str, &str)]) -> Output {
+        Output {
+            address: address.clone(),
+            coins: coins
+                .iter()
+                .map(|(denom, amount)| Coin {
+                    denom: denom.to_string(),
+                    amount: amount.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_send_rejects_bad_outputs() {
+        let (mut state, owner) = setup();
+        let to = KeyPair::generate().account_address();
+        let code = |state: &AppState, outputs: &[Output]| {
+            validate_send(state, &owner, outputs).unwrap_err().code()
+        };
+
+        assert_eq!(code(&state, &[]), codes::BANK_NO_COINS);
+        assert_eq!(code(&state, &[output(&to, &[])]), codes::BANK_NO_COINS);
+        let nobody = "optimic1nobody".to_string();
+        assert_eq!(
+            code(&state, &[output(&nobody, &[("USD", "1")])]),
+            codes::BANK_INVALID_RECIPIENT
+        );
+        assert_eq!(
+            code(&state, &[output(&to, &[("EUR", "1")])]),
+            codes::BANK_UNKNOWN_ASSET
+        );
+        assert_eq!(
+            code(&state, &[output(&to, &[("USD", "0")])]),
+            codes::BANK_ZERO_AMOUNT
+        );
+        let twice = output(&to, &[("USD", "1"), ("USD", "2")]);
+        assert_eq!(code(&state, &[twice]), codes::BANK_DUPLICATE_DENOM);
+        assert_eq!(
+            code(&state, &[output(&to, &[("USD", "-1")])]),
+            codes::INVALID_NUMBER
+        );
+
+        // Totals are checked across outputs, and without overflowing
+        let half = output(&to, &[("USD", "600")]);
+        assert_eq!(
+            code(&state, &[half.clone(), half]),
+            codes::PORTFOLIO_INSUFFICIENT_FUNDS
+        );
+        let max = output(&to, &[("USD", &u128::MAX.to_string())]);
+        assert_eq!(code(&state, &[max.clone(), max]), codes::INVALID_NUMBER);
+
+        // Locked funds cannot be sent
+        portfolio::lock(&mut state, &owner, &"USD".to_string(), 500).unwrap();
+        let locked = output(&to, &[("USD", "501")]);
+        assert_eq!(code(&state, &[locked]), codes::PORTFOLIO_INSUFFICIENT_FUNDS);
+        validate_send(&state, &owner, &[output(&to, &[("USD", "500")])]).unwrap();
+    }
+
+    #[test]
+    fn test_send_creates_recipients_and_keeps_supply() {
+        let (mut state, owner) = setup();
+        let [a, b] = [
+            KeyPair::generate().account_address(),
+            KeyPair::generate().account_address(),
+        ];
+
+        let events = send(
+            &mut state,
+            &owner,
+            &[output(&a, &[("USD", "300")]), output(&b, &[("USD", "200")])],
+        )
+        .unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(state.accounts[&a].balances["USD"], "300");
+        assert_eq!(state.accounts[&b].balances["USD"], "200");
+        assert_eq!(state.accounts[&owner].balances["USD"], "500");
+        // New accounts get the next account number
+        assert_eq!(
+            state.accounts[&b].account_number,
+            state.accounts[&a].account_number + 1
+        );
+        check_invariants(&state).unwrap();
+
+        // A failed send moves nothing
+        let err = send(&mut state, &owner, &[output(&a, &[("USD", "501")])]).unwrap_err();
+        assert_eq!(err.code(), codes::PORTFOLIO_INSUFFICIENT_FUNDS);
+        assert_eq!(state.accounts[&owner].balances["USD"], "500");
+    }
+
+    #[test]
+    fn test_mint_and_burn_track_supply() {
+        let (mut state, owner) = setup();
+        let usd = "USD".to_string();
+
+        let err = mint(&mut state, &owner, &"EUR".to_string(), 1).unwrap_err();
+        assert_eq!(err.code(), codes::BANK_UNKNOWN_ASSET);
+
+        burn(&mut state, &owner, &usd, 400).unwrap();
+        assert_eq!(supply_of(&state, &usd).unwrap(), 600);
+        portfolio::lock(&mut state, &owner, &usd, 500).unwrap();
+        let err = burn(&mut state, &owner, &usd, 101).unwrap_err();
+        assert_eq!(err.code(), codes::PORTFOLIO_INSUFFICIENT_FUNDS);
+        check_invariants(&state).unwrap();
+
+        // Balances that drift from the supply are caught
+        state
+            .accounts
+            .get_mut(&owner)
+            .unwrap()
+            .balances
+            .insert(usd.clone(), "601".to_string());
+        let err = check_invariants(&state).unwrap_err();
+        assert_eq!(err.code(), codes::BANK_SUPPLY_MISMATCH);
+        assert!(err
+            .to_string()
+            .contains("supply of USD is 600 but balances sum to 601"));
+    }
+
+    #[test]
+    fn test_init_supply_registers_unnamed_assets() {
+        let (mut state, owner) = setup();
+        state
+            .accounts
+            .get_mut(&owner)
+            .unwrap()
+            .balances
+            .insert("ETH".to_string(), "7".to_string());
+        state.supply.clear();
+
+        init_supply(&mut state).unwrap();
+        assert_eq!(
+            state.assets["ETH"],
+            AssetMetadata::unnamed(&"ETH".to_string())
+        );
+        assert_eq!(state.supply["ETH"], "7");
+        assert_eq!(state.supply["USD"], "1000");
+    }
+}
@@ -1,12 +1,15 @@
 //! Optimic Node Binary
-//! 
+//!
 //! Main executable for running an Optimic blockchain node
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
-use optimic_core::{init_node, VERSION, NAME};
+use optimic_core::config::{LogFormat, NodeConfig};
+use optimic_core::init;
+use optimic_core::node::Node;
+use optimic_core::{init_node, Market, MarketStatus, MarketType, MatchingMode, NAME, VERSION};
+use std::path::PathBuf;
 use tracing::{info, Level};
-use tracing_subscriber;
 
 #[derive(Parser)]
 #[command(name = NAME)]
@@ -15,10 +18,10 @@ use tracing_subscriber;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
-    
-    /// Set the logging level
-    #[arg(short, long, default_value = "info")]
-    log_level: String,
+
+    /// Set the logging level (defaults to the config file setting, or info)
+    #[arg(short, long)]
+    log_level: Option<String>,
 }
 
 #[derive(Subcommand)]
@@ -28,19 +31,96 @@ enum Commands {
         /// Configuration file path
         #[arg(short, long, default_value = "config.toml")]
         config: String,
-        
-        /// Genesis file path
-        #[arg(short, long, default_value = "genesis.json")]
-        genesis: String,
+
+        /// Genesis file path (overrides the config file)
+        #[arg(short, long)]
+        genesis: Option<String>,
     },
-    
-    /// Initialize a new node
+
+    /// Initialize a new node home directory
     Init {
         /// Chain ID
         #[arg(short, long, default_value = "optimic-1")]
         chain_id: String,
+
+        /// Node home directory
+        #[arg(long, default_value = ".optimic")]
+        home: PathBuf,
+
+        /// Overwrite an existing home directory
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Add a funded account to the genesis file
+    AddGenesisAccount {
+        /// Account address
+        address: String,
+
+        /// Initial balances, e.g. 1000000OMC,500ETH
+        coins: String,
+
+        /// Node home directory
+        #[arg(long, default_value = ".optimic")]
+        home: PathBuf,
+    },
+
+    /// Add a market to the genesis file
+    AddGenesisMarket {
+        /// Market ID, e.g. ETH-USD
+        id: String,
+
+        /// Base asset
+        base_asset: String,
+
+        /// Quote asset
+        quote_asset: String,
+
+        /// Minimum order size in base units
+        #[arg(long, default_value = "1")]
+        min_order_size: String,
+
+        /// Order quantities must be multiples of this many base units
+        #[arg(long, default_value = "1")]
+        lot_size: String,
+
+        /// Price tick size
+        #[arg(long, default_value = "0.01")]
+        tick_size: String,
+
+        /// Create an options market instead of a spot market
+        #[arg(long)]
+        options: bool,
+
+        /// Clear orders in per-block batch auctions instead of continuously
+        #[arg(long)]
+        batch_auction: bool,
+
+        /// Node home directory
+        #[arg(long, default_value = ".optimic")]
+        home: PathBuf,
+    },
+
+    /// Export the committed application state as a genesis file
+    ExportGenesis {
+        /// Configuration file path
+        #[arg(short, long, default_value = "config.toml")]
+        config: String,
+
+        /// Genesis file path (overrides the config file)
+        #[arg(short, long)]
+        genesis: Option<String>,
+
+        /// Height to export state at (defaults to the last committed height;
+        /// earlier heights need their state kept, see storage.keep_recent)
+        #[arg(long)]
+        height: Option<u64>,
+
+        /// Output file path (defaults to stdout)
+        #[arg(short, long)]
+        output: Option<String>,
     },
-    
+
     /// Show node version
     Version,
 }
@@ -48,9 +128,104 @@ enum Commands {
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
-    
-    // Initialize logging
-    let log_level = match cli.log_level.as_str() {
+
+    match cli.command {
+        Commands::Start { config, genesis } => {
+            let config = load_config(&config, genesis)?;
+            init_logging(
+                cli.log_level.as_deref().unwrap_or(&config.logging.level),
+                config.logging.format,
+            );
+            info!("Starting {} v{}", NAME, VERSION);
+            start_node(config).await?;
+        }
+        Commands::Init {
+            chain_id,
+            home,
+            force,
+        } => {
+            init_logging(cli.log_level.as_deref().unwrap_or("info"), LogFormat::Text);
+            info!("Initializing new node for chain: {}", chain_id);
+            let summary = init_node(&home, &chain_id, force)?;
+            println!(
+                "Initialized {} for chain {}",
+                summary.home.display(),
+                summary.chain_id
+            );
+            println!("Validator address: {}", summary.validator_address);
+            println!("Node ID: {}", summary.node_id);
+            println!(
+                "Start with: optimic-node start --config {}",
+                summary.home.join(init::CONFIG_FILE).display()
+            );
+        }
+        Commands::AddGenesisAccount {
+            address,
+            coins,
+            home,
+        } => {
+            init_logging(cli.log_level.as_deref().unwrap_or("info"), LogFormat::Text);
+            init::add_genesis_account(&home, &address, &coins)?;
+        }
+        Commands::AddGenesisMarket {
+            id,
+            base_asset,
+            quote_asset,
+            min_order_size,
+            lot_size,
+            tick_size,
+            options,
+            batch_auction,
+            home,
+        } => {
+            init_logging(cli.log_level.as_deref().unwrap_or("info"), LogFormat::Text);
+            init::add_genesis_market(
+                &home,
+                Market {
+                    id,
+                    base_asset,
+                    quote_asset,
+                    min_order_size,
+                    lot_size,
+                    tick_size,
+                    market_type: if options {
+                        MarketType::Options
+                    } else {
+                        MarketType::Spot
+                    },
+                    status: MarketStatus::Active,
+                    matching_mode: if batch_auction {
+                        MatchingMode::BatchAuction
+                    } else {
+                        MatchingMode::Continuous
+                    },
+                },
+            )?;
+        }
+        Commands::ExportGenesis {
+            config,
+            genesis,
+            height,
+            output,
+        } => {
+            let config = load_config(&config, genesis)?;
+            init_logging(
+                cli.log_level.as_deref().unwrap_or(&config.logging.level),
+                config.logging.format,
+            );
+            export_genesis(config, height, output)?;
+        }
+        Commands::Version => {
+            println!("{} v{}", NAME, VERSION);
+        }
+    }
+
+    Ok(())
+}
+
+/// Initialize logging to stderr, keeping stdout free for command output
+fn init_logging(level: &str, format: LogFormat) {
+    let log_level = match level {
         "trace" => Level::TRACE,
         "debug" => Level::DEBUG,
         "info" => Level::INFO,
@@ -58,36 +233,56 @@ async fn main() -> Result<()> {
         "error" => Level::ERROR,
         _ => Level::INFO,
     };
-    
-    tracing_subscriber::fmt()
+
+    let builder = tracing_subscriber::fmt()
         .with_max_level(log_level)
-        .init();
+        .with_writer(std::io::stderr);
+
+    match format {
+        LogFormat::Json => builder.json().init(),
+        LogFormat::Text => builder.init(),
+    }
+}
 
-    info!("Starting {} v{}", NAME, VERSION);
+fn load_config(config_path: &str, genesis_path: Option<String>) -> Result<NodeConfig> {
+    let mut config = NodeConfig::from_file(config_path)?;
+    if let Some(genesis_path) = genesis_path {
+        config.node.genesis_path = genesis_path;
+    }
+    Ok(config)
+}
 
-    match cli.command {
-        Commands::Start { config, genesis } => {
-            info!("Starting node with config: {} and genesis: {}", config, genesis);
-            start_node(config, genesis).await?;
-        }
-        Commands::Init { chain_id } => {
-            info!("Initializing new node for chain: {}", chain_id);
-            init_node().await?;
-        }
-        Commands::Version => {
-            println!("{} v{}", NAME, VERSION);
+async fn start_node(config: NodeConfig) -> Result<()> {
+    info!("Chain ID: {}", config.node.chain_id);
+    info!("Genesis file: {}", config.node.genesis_path);
+    info!("Data directory: {}", config.node.data_dir);
+
+    let node = Node::new(config)?;
+    node.run(async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for shutdown signal: {}", e);
+            std::future::pending::<()>().await;
         }
-    }
+    })
+    .await?;
 
+    info!("Node stopped");
     Ok(())
 }
 
-async fn start_node(config_path: String, genesis_path: String) -> Result<()> {
-    info!("Node configuration: {}", config_path);
-    info!("Genesis file: {}", genesis_path);
-    
-    // TODO: Implement actual node startup logic
-    info!("Node startup logic not yet implemented");
-    
+fn export_genesis(config: NodeConfig, height: Option<u64>, output: Option<String>) -> Result<()> {
+    let node = Node::new(config)?;
+    let exported = node
+        .abci()
+        .export_genesis(height.unwrap_or(node.abci().height()))?;
+
+    match output {
+        Some(path) => {
+            exported.to_file(&path)?;
+            info!("Genesis exported to {}", path);
+        }
+        None => println!("{}", serde_json::to_string_pretty(&exported)?),
+    }
+
     Ok(())
 }
@@ -0,0 +1,233 @@
+//! Optimic Mock Relayer
+//!
+//! Local stand-in for the bridge relayer, for development and tests. It
+//! signs deposit claims with attester key files, submits them to a node
+//! over its REST API and lists the withdrawal requests a real relayer
+//! would release on the other chain.
+
+use anyhow::{anyhow, Context, Result};
+use clap::{Parser, Subcommand};
+use optimic_core::bridge::{self, DepositClaim};
+use optimic_core::keys::KeyPair;
+use optimic_core::tx::{Msg, Tx, TxBody};
+use optimic_core::{Account, VERSION};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+#[derive(Parser)]
+#[command(name = "optimic-relayer")]
+#[command(version = VERSION)]
+#[command(about = "Mock bridge relayer for local Optimic networks")]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Generate an attester or relayer key file
+    Keygen {
+        /// Key file to write
+        path: PathBuf,
+    },
+
+    /// Attest a deposit and submit it to the chain
+    Deposit {
+        /// Node REST endpoint
+        #[arg(long, default_value = "http://127.0.0.1:26657")]
+        rpc: String,
+
+        /// Chain ID the deposit is minted on
+        #[arg(long, default_value = "optimic-1")]
+        chain_id: String,
+
+        /// Key file of the account that submits the transaction
+        #[arg(long)]
+        key: PathBuf,
+
+        /// Attester key file (repeat for each attester)
+        #[arg(long = "attester", required = true)]
+        attesters: Vec<PathBuf>,
+
+        /// Chain the deposit was made on
+        #[arg(long, default_value = "ethereum")]
+        source_chain: String,
+
+        /// Deposit number on the source chain
+        #[arg(long)]
+        nonce: u64,
+
+        /// Account credited on Optimic
+        #[arg(long)]
+        recipient: String,
+
+        /// Asset deposited
+        #[arg(long)]
+        asset: String,
+
+        /// Amount in base units
+        #[arg(long)]
+        amount: String,
+
+        /// Sequence of the submitting account (queried from the node by default)
+        #[arg(long)]
+        sequence: Option<u64>,
+
+        /// Print the signed transaction instead of submitting it
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// List withdrawal requests to release on the other chain
+    Withdrawals {
+        /// Node REST endpoint
+        #[arg(long, default_value = "http://127.0.0.1:26657")]
+        rpc: String,
+
+        /// Only list requests with a greater id
+        #[arg(long, default_value = "0")]
+        after: u64,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    match Cli::parse().command {
+        Commands::Keygen { path } => {
+            let key = KeyPair::generate();
+            key.write_file(&path, &key.account_address())?;
+            println!("Address: {}", key.account_address());
+            println!("Public key: {}", key.public_key_hex());
+        }
+        Commands::Deposit {
+            rpc,
+            chain_id,
+            key,
+            attesters,
+            source_chain,
+            nonce,
+            recipient,
+            asset,
+            amount,
+            sequence,
+            dry_run,
+        } => {
+            let key = KeyPair::read_file(&key)?;
+            let claim = DepositClaim {
+                source_chain,
+                nonce,
+                recipient,
+                asset,
+                amount,
+            };
+            let attestations = attesters
+                .iter()
+                .map(|path| {
+                    Ok(bridge::attest(
+                        &chain_id,
+                        &claim,
+                        &KeyPair::read_file(path)?,
+                    )?)
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            let sequence = match sequence {
+                Some(sequence) => sequence,
+                None if dry_run => 0,
+                None => {
+                    let response = request(
+                        &rpc,
+                        "GET",
+                        &format!("/query/account/{}", key.account_address()),
+                        None,
+                    )
+                    .await?;
+                    serde_json::from_value::<Account>(response["result"].clone())?.sequence
+                }
+            };
+            let body = TxBody {
+                chain_id,
+                sender: key.account_address(),
+                sequence,
+                timeout_height: 0,
+                msgs: vec![Msg::Deposit {
+                    claim,
+                    attestations,
+                }],
+                memo: String::new(),
+            };
+            let tx = Tx::sign(body, &key)?;
+
+            if dry_run {
+                println!("{}", serde_json::to_string_pretty(&tx)?);
+            } else {
+                let broadcast = json!({ "tx": hex::encode(tx.encode()?), "mode": "commit" });
+                let response = request(&rpc, "POST", "/txs", Some(&broadcast)).await?;
+                println!("{}", serde_json::to_string_pretty(&response)?);
+            }
+        }
+        Commands::Withdrawals { rpc, after } => {
+            let response =
+                request(&rpc, "GET", "/query/bridge/withdrawals?limit=500", None).await?;
+            let withdrawals = response["result"].as_array().cloned().unwrap_or_default();
+            for withdrawal in withdrawals
+                .iter()
+                .filter(|w| w["id"].as_u64().is_some_and(|id| id > after))
+            {
+                println!(
+                    "release #{}: {} {} to {} (requested by {} at height {})",
+                    withdrawal["id"],
+                    withdrawal["amount"].as_str().unwrap_or_default(),
+                    withdrawal["asset"].as_str().unwrap_or_default(),
+                    withdrawal["destination"].as_str().unwrap_or_default(),
+                    withdrawal["sender"].as_str().unwrap_or_default(),
+                    withdrawal["height"],
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Send a plain HTTP/1.1 request to the node and decode the JSON response
+async fn request(rpc: &str, method: &str, path: &str, body: Option<&Value>) -> Result<Value> {
+    let host = rpc.trim_start_matches("http://").trim_end_matches('/');
+    let body = body
+        .map(serde_json::to_vec)
+        .transpose()?
+        .unwrap_or_default();
+
+    let mut stream = TcpStream::connect(host)
+        .await
+        .with_context(|| format!("failed to connect to {}", host))?;
+    let head = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        method,
+        path,
+        host,
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&body).await?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+    let response = String::from_utf8(response)?;
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| anyhow!("malformed response from {}", host))?;
+    let status = head.split_whitespace().nth(1).unwrap_or_default();
+    if !status.starts_with('2') {
+        return Err(anyhow!(
+            "{} {} failed with status {}: {}",
+            method,
+            path,
+            status,
+            body
+        ));
+    }
+    Ok(serde_json::from_str(body)?)
+}
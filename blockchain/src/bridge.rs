@@ -0,0 +1,710 @@
+//! Bridge Module
+//!
+//! This module moves assets on and off the chain. Deposits made on another
+//! chain are minted once a threshold of registered attesters has signed the
+//! deposit claim; each claim is minted at most once. Withdrawals burn the
+//! sender's balance and record a request for the relayer to release the
+//! funds on the other side. Both directions are capped per asset and per
+//! window of blocks.
+
+use crate::app::AppState;
+use crate::bank::{self, BankError};
+use crate::codes;
+use crate::events::Event;
+use crate::keys::{verify_signature, KeyPair};
+use crate::math::{parse_amount, NumberError};
+use crate::portfolio::{self, PortfolioError};
+use crate::types::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use thiserror::Error;
+
+type Result<T> = std::result::Result<T, BridgeError>;
+
+/// Longest accepted withdrawal destination
+pub const MAX_DESTINATION_LENGTH: usize = 128;
+
+/// Reasons a deposit or withdrawal is rejected
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum BridgeError {
+    #[error("the bridge has no registered attesters")]
+    Disabled,
+
+    #[error("{0} is not a registered attester")]
+    UnknownAttester(String),
+
+    #[error("invalid attestation: {0}")]
+    InvalidAttestation(String),
+
+    #[error("{got} valid attestations, {required} required")]
+    InsufficientAttestations { got: usize, required: u32 },
+
+    #[error("deposit {0} was already processed")]
+    DepositProcessed(String),
+
+    #[error("{0} cannot be bridged")]
+    AssetNotBridged(AssetId),
+
+    #[error("{direction} of {amount} {asset} exceeds the {remaining} left in this window")]
+    RateLimited {
+        asset: AssetId,
+        direction: &'static str,
+        amount: Uint128,
+        remaining: Uint128,
+    },
+
+    #[error("invalid withdrawal destination '{0}'")]
+    InvalidDestination(String),
+
+    #[error(transparent)]
+    Bank(#[from] BankError),
+
+    #[error(transparent)]
+    Portfolio(#[from] PortfolioError),
+
+    #[error(transparent)]
+    Number(#[from] NumberError),
+}
+
+impl BridgeError {
+    /// Stable result code, see [`crate::codes`]
+    pub fn code(&self) -> u32 {
+        match self {
+            BridgeError::Disabled => codes::BRIDGE_DISABLED,
+            BridgeError::UnknownAttester(_) => codes::BRIDGE_UNKNOWN_ATTESTER,
+            BridgeError::InvalidAttestation(_) => codes::BRIDGE_INVALID_ATTESTATION,
+            BridgeError::InsufficientAttestations { .. } => codes::BRIDGE_INSUFFICIENT_ATTESTATIONS,
+            BridgeError::DepositProcessed(_) => codes::BRIDGE_DEPOSIT_PROCESSED,
+            BridgeError::AssetNotBridged(_) => codes::BRIDGE_ASSET_NOT_BRIDGED,
+            BridgeError::RateLimited { .. } => codes::BRIDGE_RATE_LIMITED,
+            BridgeError::InvalidDestination(_) => codes::BRIDGE_INVALID_DESTINATION,
+            BridgeError::Bank(error) => error.code(),
+            BridgeError::Portfolio(error) => error.code(),
+            BridgeError::Number(error) => error.code(),
+        }
+    }
+}
+
+/// Bridge parameters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BridgeParams {
+    /// Hex-encoded public keys of the registered attesters
+    pub attesters: Vec<String>,
+
+    /// Distinct attester signatures a deposit claim needs
+    pub threshold: u32,
+
+    /// Length of a rate limit window in blocks
+    pub window_blocks: u64,
+
+    /// Assets that may cross the bridge
+    pub assets: Vec<BridgedAsset>,
+}
+
+impl Default for BridgeParams {
+    fn default() -> Self {
+        Self {
+            attesters: vec![],
+            threshold: 0,
+            window_blocks: 3600, // 1 hour of 1 second blocks
+            assets: vec![],
+        }
+    }
+}
+
+/// An asset that may cross the bridge and its limits per window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BridgedAsset {
+    pub asset: AssetId,
+    pub deposit_limit: Uint128,
+    pub withdrawal_limit: Uint128,
+}
+
+/// Bridge state
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BridgeState {
+    /// Ids of the deposits already minted
+    pub deposits: imbl::OrdSet<String>,
+
+    /// Withdrawal requests by id
+    pub withdrawals: imbl::HashMap<u64, Withdrawal>,
+
+    /// Id of the latest withdrawal request
+    pub last_withdrawal_id: u64,
+
+    /// Amounts bridged in the current window, by asset
+    pub flows: imbl::HashMap<AssetId, Flow>,
+}
+
+/// Amounts of an asset bridged during one window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Flow {
+    /// Index of the window (height / window_blocks)
+    pub window: u64,
+    pub deposited: Uint128,
+    pub withdrawn: Uint128,
+}
+
+/// A deposit observed on another chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepositClaim {
+    /// Chain the deposit was made on
+    pub source_chain: String,
+
+    /// Deposit number on the source chain, unique per source chain
+    pub nonce: u64,
+
+    pub recipient: AccAddress,
+    pub asset: AssetId,
+    pub amount: Uint128,
+}
+
+/// An attester's signature over a deposit claim
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attestation {
+    /// Hex-encoded public key of the attester
+    pub attester: String,
+
+    /// Hex-encoded ed25519 signature over the claim's sign bytes
+    pub signature: String,
+}
+
+/// A request to release burned funds on another chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Withdrawal {
+    pub id: u64,
+    pub sender: AccAddress,
+    pub asset: AssetId,
+    pub amount: Uint128,
+
+    /// Address on the destination chain
+    pub destination: String,
+
+    /// Height the request was made at
+    pub height: u64,
+}
+
+impl Flow {
+    fn new(window: u64) -> Self {
+        Self {
+            window,
+            deposited: "0".to_string(),
+            withdrawn: "0".to_string(),
+        }
+    }
+}
+
+impl DepositClaim {
+    /// Identifier used for replay protection
+    pub fn id(&self) -> String {
+        format!("{}:{}", self.source_chain, self.nonce)
+    }
+
+    /// Bytes attesters sign, bound to the chain the deposit is minted on
+    pub fn sign_bytes(&self, chain_id: &str) -> Result<Vec<u8>> {
+        #[derive(Serialize)]
+        struct SignDoc<'a> {
+            chain_id: &'a str,
+            claim: &'a DepositClaim,
+        }
+
+        serde_json::to_vec(&SignDoc {
+            chain_id,
+            claim: self,
+        })
+        .map_err(|e| BridgeError::InvalidAttestation(e.to_string()))
+    }
+}
+
+/// Sign `claim` for `chain_id` as an attester
+pub fn attest(chain_id: &str, claim: &DepositClaim, key: &KeyPair) -> Result<Attestation> {
+    Ok(Attestation {
+        attester: key.public_key_hex(),
+        signature: key.sign_hex(&claim.sign_bytes(chain_id)?),
+    })
+}
+
+/// Validate bridge parameters, collecting every problem
+pub fn validate_params(params: &BridgeParams, errors: &mut Vec<String>) {
+    let mut attesters = HashSet::new();
+    for attester in &params.attesters {
+        if !hex::decode(attester).is_ok_and(|key| key.len() == 32) {
+            errors.push(format!(
+                "params.bridge.attesters: '{}' is not a hex-encoded ed25519 key",
+                attester
+            ));
+        }
+        if !attesters.insert(attester) {
+            errors.push(format!(
+                "params.bridge.attesters: duplicate attester '{}'",
+                attester
+            ));
+        }
+    }
+    if !params.attesters.is_empty()
+        && (params.threshold == 0 || params.threshold as usize > params.attesters.len())
+    {
+        errors.push(
+            "params.bridge.threshold must be between 1 and the number of attesters".to_string(),
+        );
+    }
+    if params.window_blocks == 0 {
+        errors.push("params.bridge.window_blocks must be positive".to_string());
+    }
+
+    let mut assets = HashSet::new();
+    for bridged in &params.assets {
+        if !assets.insert(&bridged.asset) {
+            errors.push(format!(
+                "params.bridge.assets: duplicate asset '{}'",
+                bridged.asset
+            ));
+        }
+        for (field, value) in [
+            ("deposit_limit", &bridged.deposit_limit),
+            ("withdrawal_limit", &bridged.withdrawal_limit),
+        ] {
+            if let Err(e) = parse_amount(value) {
+                errors.push(format!(
+                    "params.bridge.assets.{}.{}: {}",
+                    bridged.asset, field, e
+                ));
+            }
+        }
+    }
+}
+
+/// Check that a deposit claim is attested, new and within limits
+pub fn validate_deposit(
+    state: &AppState,
+    chain_id: &str,
+    height: u64,
+    claim: &DepositClaim,
+    attestations: &[Attestation],
+) -> Result<()> {
+    let params = &state.params.bridge;
+    if params.attesters.is_empty() {
+        return Err(BridgeError::Disabled);
+    }
+
+    let sign_bytes = claim.sign_bytes(chain_id)?;
+    let mut signers = HashSet::new();
+    for attestation in attestations {
+        if !params.attesters.contains(&attestation.attester) {
+            return Err(BridgeError::UnknownAttester(attestation.attester.clone()));
+        }
+        if !signers.insert(&attestation.attester) {
+            return Err(BridgeError::InvalidAttestation(format!(
+                "{} attested more than once",
+                attestation.attester
+            )));
+        }
+        verify_signature(&attestation.attester, &sign_bytes, &attestation.signature).map_err(
+            |e| BridgeError::InvalidAttestation(format!("{}: {}", attestation.attester, e)),
+        )?;
+    }
+    if signers.len() < params.threshold as usize {
+        return Err(BridgeError::InsufficientAttestations {
+            got: signers.len(),
+            required: params.threshold,
+        });
+    }
+
+    if state.bridge.deposits.contains(&claim.id()) {
+        return Err(BridgeError::DepositProcessed(claim.id()));
+    }
+    if !crate::keys::is_account_address(&claim.recipient) {
+        return Err(BankError::InvalidRecipient(claim.recipient.clone()).into());
+    }
+    let amount = positive(&claim.asset, &claim.amount)?;
+    check_limit(state, height, &claim.asset, Direction::Deposit, amount)
+}
+
+/// Mint an attested deposit to its recipient
+pub fn deposit(
+    state: &mut AppState,
+    chain_id: &str,
+    height: u64,
+    claim: &DepositClaim,
+    attestations: &[Attestation],
+) -> Result<Vec<Event>> {
+    validate_deposit(state, chain_id, height, claim, attestations)?;
+    let amount = parse_amount(&claim.amount)?;
+    bank::mint(state, &claim.recipient, &claim.asset, amount)?;
+    record_flow(state, height, &claim.asset, Direction::Deposit, amount)?;
+    state.bridge.deposits.insert(claim.id());
+
+    Ok(vec![Event::Deposited {
+        deposit_id: claim.id(),
+        recipient: claim.recipient.clone(),
+        asset: claim.asset.clone(),
+        amount: claim.amount.clone(),
+    }])
+}
+
+/// Check that `sender` can withdraw `amount` of `asset` to `destination`
+pub fn validate_withdrawal(
+    state: &AppState,
+    height: u64,
+    sender: &AccAddress,
+    asset: &AssetId,
+    amount: &Uint128,
+    destination: &str,
+) -> Result<()> {
+    if destination.trim().is_empty() || destination.len() > MAX_DESTINATION_LENGTH {
+        return Err(BridgeError::InvalidDestination(destination.to_string()));
+    }
+    let amount = positive(asset, amount)?;
+    check_limit(state, height, asset, Direction::Withdrawal, amount)?;
+    portfolio::ensure_available(state, sender, asset, amount)?;
+    Ok(())
+}
+
+/// Burn `amount` of `asset` from `sender` and record a withdrawal request
+pub fn withdraw(
+    state: &mut AppState,
+    height: u64,
+    sender: &AccAddress,
+    asset: &AssetId,
+    amount: &Uint128,
+    destination: &str,
+) -> Result<Vec<Event>> {
+    validate_withdrawal(state, height, sender, asset, amount, destination)?;
+    let value = parse_amount(amount)?;
+    bank::burn(state, sender, asset, value)?;
+    record_flow(state, height, asset, Direction::Withdrawal, value)?;
+
+    let id = state.bridge.last_withdrawal_id + 1;
+    state.bridge.last_withdrawal_id = id;
+    state.bridge.withdrawals.insert(
+        id,
+        Withdrawal {
+            id,
+            sender: sender.clone(),
+            asset: asset.clone(),
+            amount: amount.clone(),
+            destination: destination.to_string(),
+            height,
+        },
+    );
+
+    Ok(vec![Event::WithdrawalRequested {
+        withdrawal_id: id,
+        sender: sender.clone(),
+        asset: asset.clone(),
+        amount: amount.clone(),
+        destination: destination.to_string(),
+    }])
+}
+
+#[derive(Clone, Copy)]
+enum Direction {
+    Deposit,
+    Withdrawal,
+}
+
+impl Direction {
+    fn name(self) -> &'static str {
+        match self {
+            Direction::Deposit => "deposit",
+            Direction::Withdrawal => "withdrawal",
+        }
+    }
+}
+
+fn positive(asset: &AssetId, amount: &Uint128) -> Result<u128> {
+    match parse_amount(amount)? {
+        0 => Err(BankError::ZeroAmount(asset.clone()).into()),
+        amount => Ok(amount),
+    }
+}
+
+/// Amount of `asset` already bridged in `direction` during the current window
+fn used(state: &AppState, height: u64, asset: &AssetId, direction: Direction) -> Result<u128> {
+    let window = height / state.params.bridge.window_blocks.max(1);
+    match state.bridge.flows.get(asset) {
+        Some(flow) if flow.window == window => Ok(parse_amount(match direction {
+            Direction::Deposit => &flow.deposited,
+            Direction::Withdrawal => &flow.withdrawn,
+        })?),
+        _ => Ok(0),
+    }
+}
+
+fn check_limit(
+    state: &AppState,
+    height: u64,
+    asset: &AssetId,
+    direction: Direction,
+    amount: u128,
+) -> Result<()> {
+    let bridged = state
+        .params
+        .bridge
+        .assets
+        .iter()
+        .find(|bridged| &bridged.asset == asset)
+        .ok_or_else(|| BridgeError::AssetNotBridged(asset.clone()))?;
+    let limit = parse_amount(match direction {
+        Direction::Deposit => &bridged.deposit_limit,
+        Direction::Withdrawal => &bridged.withdrawal_limit,
+    })?;
+    let remaining = limit.saturating_sub(used(state, height, asset, direction)?);
+    if amount > remaining {
+        return Err(BridgeError::RateLimited {
+            asset: asset.clone(),
+            direction: direction.name(),
+            amount: amount.to_string(),
+            remaining: remaining.to_string(),
+        });
+    }
+    Ok(())
+}
+
+fn record_flow(
+    state: &mut AppState,
+    height: u64,
+    asset: &AssetId,
+    direction: Direction,
+    amount: u128,
+) -> Result<()> {
+    let window = height / state.params.bridge.window_blocks.max(1);
+    let total = used(state, height, asset, direction)? + amount;
+    let flow = state
+        .bridge
+        .flows
+        .entry(asset.clone())
+        .or_insert_with(|| Flow::new(window));
+    if flow.window != window {
+        *flow = Flow::new(window);
+    }
+    match direction {
+        Direction::Deposit => flow.deposited = total.to_string(),
+        Direction::Withdrawal => flow.withdrawn = total.to_string(),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::test_state;
+
+    const CHAIN_ID: &str = "optimic-test";
+
+    /// Bridge of three attesters with a threshold of two, moving USD in
+    /// windows of 10 blocks
+    fn setup() -> (AppState, [KeyPair; 3]) {
+        let mut state = test_state(&["USD", "ETH"]);
+        let keys = [
+            KeyPair::generate(),
+            KeyPair::generate(),
+            KeyPair::generate(),
+        ];
+        state.params.bridge = BridgeParams {
+            attesters: keys.iter().map(KeyPair::public_key_hex).collect(),
+            threshold: 2,
+            window_blocks: 10,
+            assets: vec![BridgedAsset {
+                asset: "USD".to_string(),
+                deposit_limit: "1000".to_string(),
+                withdrawal_limit: "500".to_string(),
+            }],
+        };
+        (state, keys)
+    }
+
+    fn claim(nonce: u64, recipient: &AccAddress, asset: &str, amount: &str) -> DepositClaim {
+        DepositClaim {
+            source_chain: "ethereum".to_string(),
+            nonce,
+            recipient: recipient.clone(),
+            asset: asset.to_string(),
+            amount: amount.to_string(),
+        }
+    }
+
+    fn attestations(claim: &DepositClaim, keys: &[&KeyPair]) -> Vec<Attestation> {
+        keys.iter()
+            .map(|key| attest(CHAIN_ID, claim, key).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_deposits_need_threshold_of_valid_attestations() {
+        let (mut state, keys) = setup();
+        let recipient = KeyPair::generate().account_address();
+        let claim = claim(1, &recipient, "USD", "100");
+        let code = |state: &AppState, attestations: &[Attestation]| {
+            validate_deposit(state, CHAIN_ID, 1, &claim, attestations)
+                .unwrap_err()
+                .code()
+        };
+
+        assert_eq!(code(&state, &[]), codes::BRIDGE_INSUFFICIENT_ATTESTATIONS);
+        assert_eq!(
+            code(&state, &attestations(&claim, &[&keys[0]])),
+            codes::BRIDGE_INSUFFICIENT_ATTESTATIONS
+        );
+        // The same attester twice does not meet the threshold
+        let twice = attestations(&claim, &[&keys[0], &keys[0]]);
+        assert_eq!(code(&state, &twice), codes::BRIDGE_INVALID_ATTESTATION);
+        let outsider = KeyPair::generate();
+        let unknown = attestations(&claim, &[&keys[0], &outsider]);
+        assert_eq!(code(&state, &unknown), codes::BRIDGE_UNKNOWN_ATTESTER);
+
+        // Signatures are bound to the claim and to this chain
+        let mut forged = attestations(&claim, &[&keys[0]]);
+        forged.push(attest("other-chain", &claim, &keys[1]).unwrap());
+        assert_eq!(code(&state, &forged), codes::BRIDGE_INVALID_ATTESTATION);
+        let mut inflated = claim.clone();
+        inflated.amount = "1000".to_string();
+        let mut mixed = attestations(&claim, &[&keys[0]]);
+        mixed.extend(attestations(&inflated, &[&keys[1]]));
+        assert_eq!(code(&state, &mixed), codes::BRIDGE_INVALID_ATTESTATION);
+
+        deposit(
+            &mut state,
+            CHAIN_ID,
+            1,
+            &claim,
+            &attestations(&claim, &[&keys[0], &keys[2]]),
+        )
+        .unwrap();
+        assert_eq!(state.accounts[&recipient].balances["USD"], "100");
+        assert_eq!(state.supply["USD"], "100");
+
+        // Without attesters the bridge is off
+        state.params.bridge.attesters.clear();
+        assert_eq!(code(&state, &[]), codes::BRIDGE_DISABLED);
+    }
+
+    #[test]
+    fn test_deposits_mint_once_within_limits() {
+        let (mut state, keys) = setup();
+        let recipient = KeyPair::generate().account_address();
+        let signed = |claim: &DepositClaim| attestations(claim, &[&keys[0], &keys[1]]);
+        let code = |state: &AppState, height: u64, claim: &DepositClaim| {
+            validate_deposit(state, CHAIN_ID, height, claim, &signed(claim))
+                .unwrap_err()
+                .code()
+        };
+
+        let first = claim(1, &recipient, "USD", "600");
+        deposit(&mut state, CHAIN_ID, 1, &first, &signed(&first)).unwrap();
+        assert_eq!(code(&state, 2, &first), codes::BRIDGE_DEPOSIT_PROCESSED);
+
+        assert_eq!(
+            code(&state, 2, &claim(2, &recipient, "USD", "0")),
+            codes::BANK_ZERO_AMOUNT
+        );
+        assert_eq!(
+            code(&state, 2, &claim(2, &recipient, "ETH", "1")),
+            codes::BRIDGE_ASSET_NOT_BRIDGED
+        );
+        let stranger = "optimic1nobody".to_string();
+        assert_eq!(
+            code(&state, 2, &claim(2, &stranger, "USD", "1")),
+            codes::BANK_INVALID_RECIPIENT
+        );
+
+        // 400 of the window's 1000 are left until height 10 starts a new window
+        let second = claim(2, &recipient, "USD", "401");
+        assert_eq!(code(&state, 9, &second), codes::BRIDGE_RATE_LIMITED);
+        let rest = claim(3, &recipient, "USD", "400");
+        deposit(&mut state, CHAIN_ID, 9, &rest, &signed(&rest)).unwrap();
+        deposit(&mut state, CHAIN_ID, 10, &second, &signed(&second)).unwrap();
+        assert_eq!(state.bridge.flows["USD"].window, 1);
+        assert_eq!(state.bridge.flows["USD"].deposited, "401");
+        assert_eq!(state.accounts[&recipient].balances["USD"], "1401");
+    }
+
+    #[test]
+    fn test_withdrawals_burn_within_limits() {
+        let (mut state, keys) = setup();
+        let sender = KeyPair::generate().account_address();
+        let funding = claim(1, &sender, "USD", "1000");
+        deposit(
+            &mut state,
+            CHAIN_ID,
+            1,
+            &funding,
+            &attestations(&funding, &[&keys[0], &keys[1]]),
+        )
+        .unwrap();
+        let usd = "USD".to_string();
+        let code = |state: &AppState, height: u64, amount: &str, destination: &str| {
+            validate_withdrawal(
+                state,
+                height,
+                &sender,
+                &usd,
+                &amount.to_string(),
+                destination,
+            )
+            .unwrap_err()
+            .code()
+        };
+
+        assert_eq!(code(&state, 1, "1", " "), codes::BRIDGE_INVALID_DESTINATION);
+        let too_long = "0".repeat(MAX_DESTINATION_LENGTH + 1);
+        assert_eq!(
+            code(&state, 1, "1", &too_long),
+            codes::BRIDGE_INVALID_DESTINATION
+        );
+        assert_eq!(code(&state, 1, "0", "0xabc"), codes::BANK_ZERO_AMOUNT);
+        assert_eq!(code(&state, 1, "501", "0xabc"), codes::BRIDGE_RATE_LIMITED);
+
+        let events = withdraw(&mut state, 1, &sender, &usd, &"300".to_string(), "0xabc").unwrap();
+        assert!(matches!(
+            &events[..],
+            [Event::WithdrawalRequested {
+                withdrawal_id: 1,
+                ..
+            }]
+        ));
+        assert_eq!(state.bridge.withdrawals[&1].destination, "0xabc");
+        assert_eq!(state.supply["USD"], "700");
+        assert_eq!(code(&state, 9, "201", "0xabc"), codes::BRIDGE_RATE_LIMITED);
+
+        // A new window resets the limit but not the balance
+        portfolio::lock(&mut state, &sender, &usd, 300).unwrap();
+        assert_eq!(
+            code(&state, 10, "401", "0xabc"),
+            codes::PORTFOLIO_INSUFFICIENT_FUNDS
+        );
+        withdraw(&mut state, 10, &sender, &usd, &"400".to_string(), "0xabc").unwrap();
+        assert_eq!(state.bridge.last_withdrawal_id, 2);
+        bank::check_invariants(&state).unwrap();
+    }
+
+    #[test]
+    fn test_params_validation() {
+        let (state, _) = setup();
+        let mut params = state.params.bridge.clone();
+        params.threshold = 6;
+        params.window_blocks = 0;
+        params.attesters.push(params.attesters[0].clone());
+        params.attesters.push("abcd".to_string());
+        params.assets.push(params.assets[0].clone());
+        params.assets[0].deposit_limit = "-1".to_string();
+
+        let mut errors = Vec::new();
+        validate_params(&params, &mut errors);
+        assert_eq!(errors.len(), 6, "{:?}", errors);
+        assert!(errors.iter().any(|e| e.contains("duplicate attester")));
+        assert!(errors
+            .iter()
+            .any(|e| e.contains("'abcd' is not a hex-encoded ed25519 key")));
+        assert!(errors
+            .iter()
+            .any(|e| e.contains("threshold must be between 1 and the number of attesters")));
+        assert!(errors
+            .iter()
+            .any(|e| e.contains("window_blocks must be positive")));
+        assert!(errors.iter().any(|e| e.contains("duplicate asset 'USD'")));
+        assert!(errors
+            .iter()
+            .any(|e| e.contains("assets.USD.deposit_limit")));
+    }
+}
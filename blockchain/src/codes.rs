@@ -0,0 +1,428 @@
+//! Result Codes
+//!
+//! Stable numeric codes reported in transaction receipts and RPC errors.
+//! A code never changes meaning once released; new failure kinds get new
+//! codes. Codes are grouped by module:
+//!
+//! | Range     | Module                           |
+//! |-----------|----------------------------------|
+//! | 0         | success                          |
+//! | 1-99      | generic                          |
+//! | 100-199   | transaction decoding & admission |
+//! | 200-299   | state & storage                  |
+//! | 300-399   | trading                          |
+//! | 400-499   | options                          |
+//! | 500-599   | collateral                       |
+//! | 600-699   | queries                          |
+//! | 700-799   | balances & portfolios            |
+//! | 800-899   | bank & supply                    |
+//! | 900-999   | bridge                           |
+//! | 1000-1099 | oracle                           |
+//! | 1100-1199 | staking                          |
+//! | 1200-1299 | rewards distribution             |
+//! | 1300-1399 | slashing                         |
+//! | 1400-1499 | governance                       |
+
+use crate::bank::BankError;
+use crate::bridge::BridgeError;
+use crate::collateral::CollateralError;
+use crate::distribution::DistributionError;
+use crate::fees::FeeError;
+use crate::governance::GovernanceError;
+use crate::math::NumberError;
+use crate::options::OptionsError;
+use crate::oracle::OracleError;
+use crate::portfolio::PortfolioError;
+use crate::query::QueryError;
+use crate::slashing::SlashingError;
+use crate::staking::StakingError;
+use crate::state::StateError;
+use crate::trading::TradingError;
+use crate::tx::TxError;
+
+/// The transaction succeeded
+pub const OK: u32 = 0;
+
+/// Unexpected failure inside the node
+pub const INTERNAL: u32 = 1;
+
+/// A message failed for a reason without a dedicated code
+pub const EXECUTION_FAILED: u32 = 2;
+
+/// An amount, price or rate is not a valid number
+pub const INVALID_NUMBER: u32 = 3;
+
+/// Transaction bytes are not a valid transaction
+pub const TX_DECODE: u32 = 101;
+
+/// Transaction carries no messages
+pub const TX_NO_MESSAGES: u32 = 102;
+
+/// Transaction was signed for another chain
+pub const TX_WRONG_CHAIN: u32 = 103;
+
+/// Transaction's timeout height has passed
+pub const TX_TIMED_OUT: u32 = 104;
+
+/// Sender account does not exist
+pub const TX_UNKNOWN_SENDER: u32 = 105;
+
+/// Public key or signature does not authorize the sender
+pub const TX_UNAUTHORIZED: u32 = 106;
+
+/// Sequence was already used
+pub const TX_SEQUENCE_USED: u32 = 107;
+
+/// Sequence does not follow the sender's last sequence
+pub const TX_SEQUENCE_MISMATCH: u32 = 108;
+
+/// Transaction exceeds the size limit
+pub const TX_TOO_LARGE: u32 = 109;
+
+/// Transaction is already pending
+pub const TX_DUPLICATE: u32 = 110;
+
+/// Mempool has no room for the transaction
+pub const TX_MEMPOOL_FULL: u32 = 111;
+
+/// Reading or writing the storage backend failed
+pub const STATE_STORAGE: u32 = 201;
+
+/// A value could not be encoded or decoded
+pub const STATE_CODEC: u32 = 202;
+
+/// Stored data is inconsistent
+pub const STATE_CORRUPT: u32 = 203;
+
+/// The configured storage backend is not available
+pub const STATE_BACKEND_UNAVAILABLE: u32 = 204;
+
+/// Market does not exist
+pub const TRADING_UNKNOWN_MARKET: u32 = 301;
+
+/// Market already exists
+pub const TRADING_MARKET_EXISTS: u32 = 302;
+
+/// Order does not exist
+pub const TRADING_UNKNOWN_ORDER: u32 = 303;
+
+/// Order is no longer open
+pub const TRADING_ORDER_CLOSED: u32 = 304;
+
+/// Order belongs to another account
+pub const TRADING_NOT_ORDER_OWNER: u32 = 305;
+
+/// Order type is not supported
+pub const TRADING_UNSUPPORTED_ORDER_TYPE: u32 = 306;
+
+/// Time in force is not supported by the market's matching mode
+pub const TRADING_UNSUPPORTED_TIME_IN_FORCE: u32 = 307;
+
+/// Order quantity is zero
+pub const TRADING_INVALID_QUANTITY: u32 = 308;
+
+/// Order price is not positive
+pub const TRADING_INVALID_PRICE: u32 = 309;
+
+/// Priced order type without a price
+pub const TRADING_MISSING_PRICE: u32 = 310;
+
+/// Order book does not match the stored orders
+pub const TRADING_BOOK_INCONSISTENT: u32 = 311;
+
+/// Market buy orders cannot be bounded in batch auction markets
+pub const TRADING_MARKET_BUY_IN_BATCH_AUCTION: u32 = 312;
+
+/// Market is suspended or closed and accepts no new orders
+pub const TRADING_MARKET_NOT_ACTIVE: u32 = 313;
+
+/// Market listing has an invalid asset, tick size or minimum size
+pub const TRADING_INVALID_MARKET: u32 = 314;
+
+/// Sender may not list or change the status of markets
+pub const TRADING_NOT_MARKET_ADMIN: u32 = 315;
+
+/// Market cannot move from its current status to the requested one
+pub const TRADING_INVALID_STATUS_CHANGE: u32 = 316;
+
+/// Order price is not a multiple of the market's tick size
+pub const TRADING_OFF_TICK: u32 = 317;
+
+/// Order quantity is below the market's minimum order size
+pub const TRADING_BELOW_MIN_SIZE: u32 = 318;
+
+/// Order quantity is not a multiple of the market's lot size
+pub const TRADING_OFF_LOT: u32 = 319;
+
+/// Limit price is too far from the market's mark price
+pub const TRADING_OUTSIDE_PRICE_BAND: u32 = 320;
+
+/// Option contract does not exist
+pub const OPTIONS_UNKNOWN_OPTION: u32 = 401;
+
+/// Option contract already exists
+pub const OPTIONS_OPTION_EXISTS: u32 = 402;
+
+/// Option contract has expired
+pub const OPTIONS_EXPIRED: u32 = 403;
+
+/// Option quantity is zero or exceeds the holding
+pub const OPTIONS_INVALID_QUANTITY: u32 = 404;
+
+/// Pricing inputs are out of range
+pub const OPTIONS_INVALID_PARAMETERS: u32 = 405;
+
+/// Posted collateral does not cover the requirement
+pub const COLLATERAL_INSUFFICIENT: u32 = 501;
+
+/// Account has no collateral posted
+pub const COLLATERAL_NOT_POSTED: u32 = 502;
+
+/// Collateral is locked by open positions
+pub const COLLATERAL_LOCKED: u32 = 503;
+
+/// Penalty rate is outside [0, 1]
+pub const COLLATERAL_INVALID_PENALTY_RATE: u32 = 504;
+
+/// Query path does not exist
+pub const QUERY_UNKNOWN_PATH: u32 = 601;
+
+/// Query parameter is missing or malformed
+pub const QUERY_INVALID_PARAM: u32 = 602;
+
+/// Queried object does not exist
+pub const QUERY_NOT_FOUND: u32 = 603;
+
+/// Queried height is not kept by the node
+pub const QUERY_HEIGHT_UNAVAILABLE: u32 = 604;
+
+/// Query failed inside the node
+pub const QUERY_INTERNAL: u32 = 605;
+
+/// Available balance does not cover the amount to lock or send
+pub const PORTFOLIO_INSUFFICIENT_FUNDS: u32 = 701;
+
+/// Account does not exist
+pub const PORTFOLIO_UNKNOWN_ACCOUNT: u32 = 702;
+
+/// Balances, locks and open orders disagree
+pub const PORTFOLIO_INVARIANT: u32 = 703;
+
+/// Asset is not registered
+pub const BANK_UNKNOWN_ASSET: u32 = 801;
+
+/// Coin amount is zero
+pub const BANK_ZERO_AMOUNT: u32 = 802;
+
+/// Denomination listed twice in one coin list
+pub const BANK_DUPLICATE_DENOM: u32 = 803;
+
+/// Transfer carries no coins
+pub const BANK_NO_COINS: u32 = 804;
+
+/// Recipient is not a valid account address
+pub const BANK_INVALID_RECIPIENT: u32 = 805;
+
+/// Balances of an asset do not sum to its supply
+pub const BANK_SUPPLY_MISMATCH: u32 = 806;
+
+/// The bridge has no attesters
+pub const BRIDGE_DISABLED: u32 = 901;
+
+/// Attestation signed by a key that is not a registered attester
+pub const BRIDGE_UNKNOWN_ATTESTER: u32 = 902;
+
+/// Attestation signature is invalid or repeated
+pub const BRIDGE_INVALID_ATTESTATION: u32 = 903;
+
+/// Fewer valid attestations than the threshold
+pub const BRIDGE_INSUFFICIENT_ATTESTATIONS: u32 = 904;
+
+/// Deposit was already minted
+pub const BRIDGE_DEPOSIT_PROCESSED: u32 = 905;
+
+/// Asset is not enabled on the bridge
+pub const BRIDGE_ASSET_NOT_BRIDGED: u32 = 906;
+
+/// Deposit or withdrawal exceeds the window's limit
+pub const BRIDGE_RATE_LIMITED: u32 = 907;
+
+/// Withdrawal destination is empty or too long
+pub const BRIDGE_INVALID_DESTINATION: u32 = 908;
+
+/// Sender is not a price feeder
+pub const ORACLE_NOT_FEEDER: u32 = 1001;
+
+/// Asset is not priced by the oracle
+pub const ORACLE_UNKNOWN_ASSET: u32 = 1002;
+
+/// Submitted price is not positive
+pub const ORACLE_INVALID_PRICE: u32 = 1003;
+
+/// Asset submitted twice in one message
+pub const ORACLE_DUPLICATE_ASSET: u32 = 1004;
+
+/// Submission carries no prices
+pub const ORACLE_NO_PRICES: u32 = 1005;
+
+/// Asset has no oracle price yet
+pub const ORACLE_NO_PRICE: u32 = 1006;
+
+/// Oracle price is too old to use
+pub const ORACLE_STALE_PRICE: u32 = 1007;
+
+/// Validator does not exist
+pub const STAKING_UNKNOWN_VALIDATOR: u32 = 1101;
+
+/// Sender already operates a validator
+pub const STAKING_VALIDATOR_EXISTS: u32 = 1102;
+
+/// Delegator has no delegation to the validator
+pub const STAKING_NO_DELEGATION: u32 = 1103;
+
+/// Delegation is worth less than the amount requested
+pub const STAKING_INSUFFICIENT_DELEGATION: u32 = 1104;
+
+/// Delegator has too many unbonding entries with the validator
+pub const STAKING_TOO_MANY_UNBONDING_ENTRIES: u32 = 1105;
+
+/// Redelegation source and destination are the same validator
+pub const STAKING_SELF_REDELEGATION: u32 = 1106;
+
+/// Staking amount is zero
+pub const STAKING_ZERO_AMOUNT: u32 = 1107;
+
+/// Commission rates are out of range
+pub const STAKING_INVALID_COMMISSION: u32 = 1108;
+
+/// Consensus public key is not a 32-byte hex key
+pub const STAKING_INVALID_PUBKEY: u32 = 1109;
+
+/// Validator has shares but no tokens backing them
+pub const STAKING_INVALID_EXCHANGE_RATE: u32 = 1110;
+
+/// Staking pools or shares are out of balance
+pub const STAKING_INVARIANT: u32 = 1111;
+
+/// Commission changed less than a day ago
+pub const STAKING_COMMISSION_CHANGE_TOO_SOON: u32 = 1112;
+
+/// Commission change exceeds the validator's max_change_rate
+pub const STAKING_COMMISSION_CHANGE_TOO_LARGE: u32 = 1113;
+
+/// Validator does not exist
+pub const DISTRIBUTION_UNKNOWN_VALIDATOR: u32 = 1201;
+
+/// Delegator has no delegation earning rewards from the validator
+pub const DISTRIBUTION_NO_DELEGATION: u32 = 1202;
+
+/// Validator has no whole unit of commission to withdraw
+pub const DISTRIBUTION_NO_COMMISSION: u32 = 1203;
+
+/// Distribution pool does not match the outstanding rewards
+pub const DISTRIBUTION_INVARIANT: u32 = 1204;
+
+/// Sender does not operate a validator
+pub const SLASHING_UNKNOWN_VALIDATOR: u32 = 1301;
+
+/// Validator is not jailed
+pub const SLASHING_NOT_JAILED: u32 = 1302;
+
+/// Validator was tombstoned for double-signing
+pub const SLASHING_TOMBSTONED: u32 = 1303;
+
+/// Validator's jail time has not passed
+pub const SLASHING_STILL_JAILED: u32 = 1304;
+
+/// Proposal does not exist
+pub const GOVERNANCE_UNKNOWN_PROPOSAL: u32 = 1401;
+
+/// Proposal is past its deposit period
+pub const GOVERNANCE_NOT_ACCEPTING_DEPOSITS: u32 = 1402;
+
+/// Proposal is not open for votes
+pub const GOVERNANCE_NOT_VOTING: u32 = 1403;
+
+/// Proposal content could not be executed
+pub const GOVERNANCE_INVALID_PROPOSAL: u32 = 1404;
+
+/// Deposit of zero tokens
+pub const GOVERNANCE_ZERO_DEPOSIT: u32 = 1405;
+
+/// Result code of a failed check or execution
+///
+/// Walks the error chain, so a typed error keeps its code when wrapped with
+/// context.
+pub fn result_code(error: &anyhow::Error) -> u32 {
+    error
+        .chain()
+        .find_map(typed_code)
+        .unwrap_or(EXECUTION_FAILED)
+}
+
+fn typed_code(error: &(dyn std::error::Error + 'static)) -> Option<u32> {
+    if let Some(error) = error.downcast_ref::<TxError>() {
+        return Some(error.code());
+    }
+    if let Some(error) = error.downcast_ref::<StateError>() {
+        return Some(error.code());
+    }
+    if let Some(error) = error.downcast_ref::<TradingError>() {
+        return Some(error.code());
+    }
+    if let Some(error) = error.downcast_ref::<OptionsError>() {
+        return Some(error.code());
+    }
+    if let Some(error) = error.downcast_ref::<CollateralError>() {
+        return Some(error.code());
+    }
+    if let Some(error) = error.downcast_ref::<OracleError>() {
+        return Some(error.code());
+    }
+    if let Some(error) = error.downcast_ref::<GovernanceError>() {
+        return Some(error.code());
+    }
+    if let Some(error) = error.downcast_ref::<SlashingError>() {
+        return Some(error.code());
+    }
+    if let Some(error) = error.downcast_ref::<FeeError>() {
+        return Some(error.code());
+    }
+    if let Some(error) = error.downcast_ref::<DistributionError>() {
+        return Some(error.code());
+    }
+    if let Some(error) = error.downcast_ref::<StakingError>() {
+        return Some(error.code());
+    }
+    if let Some(error) = error.downcast_ref::<BridgeError>() {
+        return Some(error.code());
+    }
+    if let Some(error) = error.downcast_ref::<BankError>() {
+        return Some(error.code());
+    }
+    if let Some(error) = error.downcast_ref::<PortfolioError>() {
+        return Some(error.code());
+    }
+    if let Some(error) = error.downcast_ref::<NumberError>() {
+        return Some(error.code());
+    }
+    if let Some(error) = error.downcast_ref::<QueryError>() {
+        return Some(error.code());
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_result_code_sees_through_context() {
+        let error = Err::<(), _>(TradingError::UnknownMarket("DOGE-USD".to_string()))
+            .context("message 0 failed")
+            .unwrap_err();
+        assert_eq!(result_code(&error), TRADING_UNKNOWN_MARKET);
+        assert_eq!(result_code(&anyhow::anyhow!("boom")), EXECUTION_FAILED);
+    }
+}
@@ -1,18 +1,63 @@
 //! Collateral Management Module
-//! 
+//!
 //! This module implements the mandatory collateral system for both
 //! option buyers and sellers, including penalty calculation and distribution.
 
+use crate::app::{AppState, PenaltyDistribution};
+use crate::codes;
+use crate::math::NumberError;
+use crate::oracle::{self, OracleError};
 use crate::types::*;
-use crate::app::PenaltyDistribution;
-use anyhow::Result;
+use thiserror::Error;
 use tracing::warn;
 
+type Result<T> = std::result::Result<T, CollateralError>;
+
+/// Reasons a collateral operation is rejected
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum CollateralError {
+    #[error("insufficient collateral for {account}: required {required}, available {available}")]
+    Insufficient {
+        account: AccAddress,
+        required: Uint128,
+        available: Uint128,
+    },
+
+    #[error("no collateral posted by {0}")]
+    NotPosted(AccAddress),
+
+    #[error("collateral of {0} is locked")]
+    Locked(AccAddress),
+
+    #[error("penalty rate {0} must be between 0 and 1")]
+    InvalidPenaltyRate(String),
+
+    #[error(transparent)]
+    Oracle(#[from] OracleError),
+
+    #[error(transparent)]
+    Number(#[from] NumberError),
+}
+
+impl CollateralError {
+    /// Stable result code, see [`crate::codes`]
+    pub fn code(&self) -> u32 {
+        match self {
+            CollateralError::Insufficient { .. } => codes::COLLATERAL_INSUFFICIENT,
+            CollateralError::NotPosted(_) => codes::COLLATERAL_NOT_POSTED,
+            CollateralError::Locked(_) => codes::COLLATERAL_LOCKED,
+            CollateralError::InvalidPenaltyRate(_) => codes::COLLATERAL_INVALID_PENALTY_RATE,
+            CollateralError::Oracle(error) => error.code(),
+            CollateralError::Number(error) => error.code(),
+        }
+    }
+}
+
 /// Collateral manager
 pub struct CollateralManager {
     /// Collateral requirements by account
     collateral_requirements: std::collections::HashMap<AccAddress, CollateralRequirement>,
-    
+
     /// Posted collateral by account
     posted_collateral: std::collections::HashMap<AccAddress, PostedCollateral>,
 }
@@ -93,6 +138,28 @@ impl CollateralManager {
         Ok("0".to_string())
     }
 
+    /// Required collateral for an option buyer at the underlying's oracle price
+    pub fn buyer_collateral_at_mark(
+        &self,
+        state: &AppState,
+        option: &OptionContract,
+        position_size: u32,
+    ) -> Result<Uint128> {
+        let mark = oracle::mark_price(state, &option.underlying_asset)?;
+        self.calculate_buyer_collateral(option, position_size, &mark.to_string())
+    }
+
+    /// Required collateral for an option seller at the underlying's oracle price
+    pub fn seller_collateral_at_mark(
+        &self,
+        state: &AppState,
+        option: &OptionContract,
+        position_size: u32,
+    ) -> Result<Uint128> {
+        let mark = oracle::mark_price(state, &option.underlying_asset)?;
+        self.calculate_seller_collateral(option, position_size, &mark.to_string())
+    }
+
     /// Post collateral for an account
     pub fn post_collateral(
         &mut self,
@@ -106,11 +173,7 @@ impl CollateralManager {
     }
 
     /// Calculate penalty for non-execution
-    pub fn calculate_penalty(
-        &self,
-        collateral: &Uint128,
-        penalty_rate: f64,
-    ) -> Result<Uint128> {
+    pub fn calculate_penalty(&self, collateral: &Uint128, penalty_rate: f64) -> Result<Uint128> {
         warn!("Penalty calculation not yet implemented");
         // TODO: Implement penalty calculation
         Ok("0".to_string())
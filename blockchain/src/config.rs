@@ -0,0 +1,277 @@
+//! Node Configuration
+//!
+//! This module defines the typed node configuration loaded from `config.toml`.
+//! These settings are local to a node; consensus-critical parameters always
+//! come from the genesis file.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Complete node configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NodeConfig {
+    pub node: NodeSection,
+    pub consensus: ConsensusSection,
+    pub network: NetworkSection,
+    pub storage: StorageSection,
+    pub mempool: MempoolSection,
+    pub trading: TradingSection,
+    pub collateral: CollateralSection,
+    pub fees: FeesSection,
+    pub logging: LoggingSection,
+}
+
+/// `[node]` section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NodeSection {
+    /// Chain ID
+    pub chain_id: String,
+
+    /// Data directory
+    pub data_dir: String,
+
+    /// Genesis file path
+    pub genesis_path: String,
+}
+
+/// `[consensus]` section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConsensusSection {
+    /// Block time in seconds
+    pub block_time: u64,
+
+    /// Maximum block size in bytes
+    pub max_block_size: u64,
+}
+
+/// `[network]` section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkSection {
+    /// P2P listen address
+    pub listen_addr: String,
+
+    /// RPC listen address
+    pub rpc_listen_addr: String,
+
+    /// Maximum number of peers
+    pub max_peers: u32,
+}
+
+/// `[storage]` section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StorageSection {
+    /// Storage backend
+    pub backend: StorageBackend,
+
+    /// Cache size in MB
+    pub cache_size: u64,
+
+    /// Number of recent heights whose state stays queryable (0 = latest only);
+    /// each one stores a full copy of the state, so this is opt-in
+    pub keep_recent: u64,
+}
+
+/// Available storage backends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// Volatile in-memory storage
+    Memory,
+    /// Snapshot file in the data directory
+    File,
+    /// RocksDB database in the data directory
+    RocksDB,
+}
+
+/// `[mempool]` section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MempoolSection {
+    /// Maximum number of pending transactions
+    pub size: usize,
+}
+
+/// `[trading]` section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TradingSection {
+    pub enable_options: bool,
+    pub enable_spot: bool,
+    pub max_orders_per_user: u32,
+}
+
+/// `[collateral]` section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CollateralSection {
+    pub buyer_min_ratio: String,
+    pub seller_min_ratio: String,
+    pub liquidation_threshold: String,
+}
+
+/// `[fees]` section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FeesSection {
+    pub premium_fee_rate: String,
+    pub penalty_fee_rate: String,
+}
+
+/// `[logging]` section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoggingSection {
+    /// Log level (trace, debug, info, warn, error)
+    pub level: String,
+
+    /// Log format
+    pub format: LogFormat,
+}
+
+/// Log output format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl NodeConfig {
+    /// Read and parse a TOML configuration file
+    ///
+    /// Relative `data_dir` and `genesis_path` values are resolved against the
+    /// directory containing the configuration file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config file {}", path.display()))?;
+        let mut config: NodeConfig = toml::from_str(&contents)
+            .with_context(|| format!("failed to parse config file {}", path.display()))?;
+
+        if let Some(base) = path.parent() {
+            config.node.data_dir = resolve(base, &config.node.data_dir);
+            config.node.genesis_path = resolve(base, &config.node.genesis_path);
+        }
+
+        Ok(config)
+    }
+
+    /// Serialize the configuration as TOML
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+}
+
+fn resolve(base: &Path, value: &str) -> String {
+    let path = PathBuf::from(value);
+    if path.is_absolute() {
+        value.to_string()
+    } else {
+        base.join(path).to_string_lossy().into_owned()
+    }
+}
+
+impl Default for NodeSection {
+    fn default() -> Self {
+        Self {
+            chain_id: "optimic-1".to_string(),
+            data_dir: "./data".to_string(),
+            genesis_path: "./genesis.json".to_string(),
+        }
+    }
+}
+
+impl Default for ConsensusSection {
+    fn default() -> Self {
+        Self {
+            block_time: 1,
+            max_block_size: 1024 * 1024,
+        }
+    }
+}
+
+impl Default for NetworkSection {
+    fn default() -> Self {
+        Self {
+            listen_addr: "0.0.0.0:26656".to_string(),
+            rpc_listen_addr: "0.0.0.0:26657".to_string(),
+            max_peers: 50,
+        }
+    }
+}
+
+impl Default for StorageSection {
+    fn default() -> Self {
+        Self {
+            backend: StorageBackend::Memory,
+            cache_size: 1024,
+            keep_recent: 0,
+        }
+    }
+}
+
+impl Default for MempoolSection {
+    fn default() -> Self {
+        Self { size: 5000 }
+    }
+}
+
+impl Default for TradingSection {
+    fn default() -> Self {
+        Self {
+            enable_options: true,
+            enable_spot: true,
+            max_orders_per_user: 1000,
+        }
+    }
+}
+
+impl Default for CollateralSection {
+    fn default() -> Self {
+        Self {
+            buyer_min_ratio: "1.2".to_string(),
+            seller_min_ratio: "1.5".to_string(),
+            liquidation_threshold: "1.1".to_string(),
+        }
+    }
+}
+
+impl Default for FeesSection {
+    fn default() -> Self {
+        Self {
+            premium_fee_rate: "1.0".to_string(),
+            penalty_fee_rate: "0.1".to_string(),
+        }
+    }
+}
+
+impl Default for LoggingSection {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            format: LogFormat::Text,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_repo_config() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/config.toml");
+        let config = NodeConfig::from_file(path).unwrap();
+        assert_eq!(config.node.chain_id, "optimic-1");
+        assert_eq!(config.storage.backend, StorageBackend::Memory);
+        assert_eq!(config.logging.format, LogFormat::Json);
+        assert!(config.node.genesis_path.ends_with("genesis.json"));
+        assert!(Path::new(&config.node.genesis_path).is_absolute());
+    }
+}
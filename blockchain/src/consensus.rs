@@ -1,17 +1,19 @@
 //! Consensus Module
-//! 
+//!
 //! This module handles consensus-related functionality including
 //! validator management, stake distribution, and rewards.
 
+use crate::distribution::{self, DistributionParams};
 use crate::types::*;
 use anyhow::Result;
-use tracing::{info, warn};
+use serde::{Deserialize, Serialize};
+use tracing::info;
 
 /// Consensus manager for validator operations
 pub struct ConsensusManager {
     /// Current validator set
     validators: std::collections::HashMap<ValAddress, Validator>,
-    
+
     /// Pending validator updates
     pending_updates: Vec<ValidatorUpdate>,
 }
@@ -23,6 +25,35 @@ pub struct ValidatorUpdate {
     pub power: i64,
 }
 
+/// Signatures of the previous block and misbehavior reported by consensus
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CommitInfo {
+    /// Active validators and whether they signed the previous block
+    pub votes: Vec<VoteInfo>,
+
+    /// Double-sign evidence
+    pub evidence: Vec<Evidence>,
+}
+
+/// Whether a validator signed the previous block
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VoteInfo {
+    pub validator: ValAddress,
+    pub signed: bool,
+}
+
+/// A validator signed two different blocks at the same height
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Evidence {
+    pub validator: ValAddress,
+
+    /// Height of the conflicting votes
+    pub height: u64,
+
+    /// Voting power of the validator at that height
+    pub power: i64,
+}
+
 impl ConsensusManager {
     /// Create a new consensus manager
     pub fn new() -> Self {
@@ -35,7 +66,8 @@ impl ConsensusManager {
     /// Add a new validator
     pub fn add_validator(&mut self, validator: Validator) -> Result<()> {
         info!("Adding validator: {}", validator.operator_address);
-        self.validators.insert(validator.operator_address.clone(), validator);
+        self.validators
+            .insert(validator.operator_address.clone(), validator);
         Ok(())
     }
 
@@ -52,7 +84,7 @@ impl ConsensusManager {
             info!("Updating validator {} power to {}", address, new_power);
             // Update validator tokens based on new power
             validator.tokens = new_power.to_string();
-            
+
             // Add to pending updates
             self.pending_updates.push(ValidatorUpdate {
                 address: address.clone(),
@@ -74,15 +106,25 @@ impl ConsensusManager {
         updates
     }
 
-    /// Calculate block rewards
-    pub fn calculate_block_rewards(&self, block_height: u64) -> Result<BlockRewards> {
-        // TODO: Implement proper reward calculation
-        warn!("Block reward calculation not yet implemented");
-        
-        Ok(BlockRewards {
-            total_rewards: "1000000".to_string(), // 1 OMC per block
-            validator_rewards: std::collections::HashMap::new(),
-        })
+    /// Calculate the block reward at `block_height` and its split between
+    /// the bonded, unjailed validators, see [`distribution::block_rewards`]
+    pub fn calculate_block_rewards(
+        &self,
+        params: &DistributionParams,
+        block_height: u64,
+    ) -> Result<BlockRewards> {
+        let powers = self
+            .validators
+            .values()
+            .filter(|v| matches!(v.status, BondStatus::Bonded) && !v.jailed)
+            .map(|v| {
+                (
+                    v.operator_address.clone(),
+                    v.tokens.parse::<u64>().unwrap_or(0),
+                )
+            })
+            .collect();
+        Ok(distribution::block_rewards(params, block_height, &powers)?)
     }
 }
 
@@ -0,0 +1,992 @@
+//! Distribution Module
+//!
+//! Block rewards are minted on a halving emission schedule and split
+//! between the active validators by voting power. Each validator keeps its
+//! commission; the rest is shared by its delegators through F1 lazy
+//! accounting: a validator records the cumulative reward per token at the
+//! end of every period, a period ends whenever one of its delegations
+//! changes, and a delegator's rewards are the difference between two such
+//! entries times its stake. Nothing is iterated per delegator when rewards
+//! are allocated.
+//!
+//! Rewards and commission wait in the distribution pool until withdrawn.
+//! Changing a delegation withdraws its rewards first.
+
+use crate::app::AppState;
+use crate::bank::{self, BankError};
+use crate::codes;
+use crate::consensus::BlockRewards;
+use crate::events::Event;
+use crate::keys::operator_of;
+use crate::math::{
+    floor_signed, parse_amount, parse_decimal, parse_fraction, to_decimal, NumberError,
+};
+use crate::portfolio::{self, PortfolioError};
+use crate::types::*;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use thiserror::Error;
+
+type Result<T> = std::result::Result<T, DistributionError>;
+
+/// Module account holding allocated rewards and commission until they are withdrawn
+pub const DISTRIBUTION_POOL: &str = "optimic1distribution";
+
+/// Decimal amounts by asset, as carried in the state
+pub type DecCoins = BTreeMap<AssetId, String>;
+
+/// Reasons a reward operation is rejected
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum DistributionError {
+    #[error("unknown validator {0}")]
+    UnknownValidator(ValAddress),
+
+    #[error("{delegator} has no delegation to {validator}")]
+    NoDelegation {
+        delegator: AccAddress,
+        validator: ValAddress,
+    },
+
+    #[error("validator {0} has no commission to withdraw")]
+    NoCommission(ValAddress),
+
+    #[error("distribution invariant violated: {0}")]
+    Invariant(String),
+
+    #[error(transparent)]
+    Bank(#[from] BankError),
+
+    #[error(transparent)]
+    Portfolio(#[from] PortfolioError),
+
+    #[error(transparent)]
+    Number(#[from] NumberError),
+}
+
+impl DistributionError {
+    /// Stable result code, see [`crate::codes`]
+    pub fn code(&self) -> u32 {
+        match self {
+            DistributionError::UnknownValidator(_) => codes::DISTRIBUTION_UNKNOWN_VALIDATOR,
+            DistributionError::NoDelegation { .. } => codes::DISTRIBUTION_NO_DELEGATION,
+            DistributionError::NoCommission(_) => codes::DISTRIBUTION_NO_COMMISSION,
+            DistributionError::Invariant(_) => codes::DISTRIBUTION_INVARIANT,
+            DistributionError::Bank(error) => error.code(),
+            DistributionError::Portfolio(error) => error.code(),
+            DistributionError::Number(error) => error.code(),
+        }
+    }
+}
+
+/// Reward emission parameters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DistributionParams {
+    /// Native tokens minted per block before the first halving
+    pub block_reward: Uint128,
+
+    /// Blocks between halvings of the block reward, 0 to never halve
+    pub halving_interval: u64,
+}
+
+impl Default for DistributionParams {
+    fn default() -> Self {
+        Self {
+            block_reward: "1000000".to_string(),      // 1 OMC per block
+            halving_interval: 4 * 365 * 24 * 60 * 60, // 4 years of 1 second blocks
+        }
+    }
+}
+
+/// Reward accounting state
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DistributionState {
+    /// Reward periods and commission by validator
+    pub validators: imbl::HashMap<ValAddress, ValidatorRewards>,
+
+    /// Period each delegation started earning in, by delegator and validator
+    pub starting_info: imbl::HashMap<AccAddress, HashMap<ValAddress, StartingInfo>>,
+}
+
+/// Reward accounting of one validator
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidatorRewards {
+    /// Period rewards are currently accrued in
+    pub period: u64,
+
+    /// Delegator rewards of the current period
+    pub current: DecCoins,
+
+    /// Cumulative reward per token at the end of each period still referenced
+    pub historical: BTreeMap<u64, HistoricalRewards>,
+
+    /// Commission not yet withdrawn
+    pub commission: DecCoins,
+
+    /// Rewards and commission allocated to the validator and not yet withdrawn
+    pub outstanding: DecCoins,
+
+    /// Slashes of the validator's bonded tokens, oldest first
+    #[serde(default)]
+    pub slash_events: Vec<SlashEvent>,
+}
+
+/// A slash ending a reward period; delegations that started before it earn
+/// on a reduced stake afterwards
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlashEvent {
+    pub height: u64,
+
+    /// Period the slash ended
+    pub period: u64,
+
+    /// Part of the validator's tokens burned
+    pub fraction: Price,
+}
+
+/// Cumulative reward ratio at the end of a period
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoricalRewards {
+    pub ratio: DecCoins,
+
+    /// Delegations starting at this period, plus one for the latest period
+    pub reference_count: u32,
+}
+
+/// Where a delegation started earning rewards
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StartingInfo {
+    pub previous_period: u64,
+
+    /// Tokens the delegation was worth when it started
+    pub stake: Price,
+
+    pub height: u64,
+}
+
+impl Default for ValidatorRewards {
+    fn default() -> Self {
+        let zero = HistoricalRewards {
+            ratio: DecCoins::new(),
+            reference_count: 1,
+        };
+        Self {
+            period: 1,
+            current: DecCoins::new(),
+            historical: [(0, zero)].into(),
+            commission: DecCoins::new(),
+            outstanding: DecCoins::new(),
+            slash_events: Vec::new(),
+        }
+    }
+}
+
+/// Validate reward parameters, collecting every problem
+pub fn validate_params(params: &DistributionParams, errors: &mut Vec<String>) {
+    if let Err(e) = parse_amount(&params.block_reward) {
+        errors.push(format!("params.distribution.block_reward: {}", e));
+    }
+}
+
+/// Native tokens minted at `height`
+pub fn emission(params: &DistributionParams, height: u64) -> Result<u128> {
+    let reward = parse_amount(&params.block_reward)?;
+    let halvings = height.checked_div(params.halving_interval).unwrap_or(0);
+    Ok(reward
+        .checked_shr(u32::try_from(halvings).unwrap_or(u32::MAX))
+        .unwrap_or(0))
+}
+
+/// The block reward at `height` and its split between validators by voting power
+///
+/// Shares are rounded down; the remainder goes to the validator with the
+/// most power.
+pub fn block_rewards(
+    params: &DistributionParams,
+    height: u64,
+    powers: &imbl::HashMap<ValAddress, u64>,
+) -> Result<BlockRewards> {
+    let shares = split_by_power(emission(params, height)?, powers)?;
+    let total: u128 = shares.iter().map(|(_, share)| share).sum();
+    Ok(BlockRewards {
+        total_rewards: total.to_string(),
+        validator_rewards: shares
+            .into_iter()
+            .map(|(address, share)| (address, share.to_string()))
+            .collect(),
+    })
+}
+
+/// Mint the block reward and allocate it to the validators that were
+/// active during the block
+pub fn allocate_block_rewards(state: &mut AppState, height: u64) -> Result<Vec<Event>> {
+    if !has_active_validators(state) {
+        return Ok(Vec::new());
+    }
+    let total = emission(&state.params.distribution, height)?;
+    if total == 0 {
+        return Ok(Vec::new());
+    }
+    let native = state.params.native_token.clone();
+    bank::mint(state, &DISTRIBUTION_POOL.to_string(), &native, total)?;
+    allocate_by_power(state, &native, total)
+}
+
+/// Whether any validator was active during the block to receive rewards
+pub fn has_active_validators(state: &AppState) -> bool {
+    state.staking.last_powers.values().any(|power| *power > 0)
+}
+
+/// Allocate `amount` of `asset`, already held by the distribution pool, to
+/// the validators that were active during the block by voting power
+pub fn allocate_by_power(
+    state: &mut AppState,
+    asset: &AssetId,
+    amount: u128,
+) -> Result<Vec<Event>> {
+    let shares = split_by_power(amount, &state.staking.last_powers)?;
+    let mut events = Vec::new();
+    for (validator, share) in shares {
+        events.push(allocate(state, &validator, asset, share)?);
+    }
+    Ok(events)
+}
+
+/// Allocate `amount` of `asset`, already held by the distribution pool, to
+/// a validator, splitting off its commission
+pub fn allocate(
+    state: &mut AppState,
+    validator: &ValAddress,
+    asset: &AssetId,
+    amount: u128,
+) -> Result<Event> {
+    let rate = parse_fraction(&find(state, validator)?.commission.rate)?;
+    let total = decimal(amount)?;
+    let commission = total
+        .checked_mul(rate)
+        .ok_or_else(|| overflow("commission"))?;
+    let rewards = state
+        .distribution
+        .validators
+        .entry(validator.clone())
+        .or_default();
+    add(&mut rewards.commission, asset, commission)?;
+    add(&mut rewards.current, asset, total - commission)?;
+    add(&mut rewards.outstanding, asset, total)?;
+
+    Ok(Event::RewardsAllocated {
+        validator: validator.clone(),
+        asset: asset.clone(),
+        amount: amount.to_string(),
+        commission: commission.normalize().to_string(),
+    })
+}
+
+/// Withdraw the rewards of a delegation before its shares change
+pub fn before_delegation_modified(
+    state: &mut AppState,
+    delegator: &AccAddress,
+    validator: &ValAddress,
+) -> Result<Vec<Event>> {
+    state
+        .distribution
+        .validators
+        .entry(validator.clone())
+        .or_default();
+    if !has_starting_info(state, delegator, validator) {
+        return Ok(Vec::new());
+    }
+    Ok(withdraw(state, delegator, validator)?.into_iter().collect())
+}
+
+/// Start a new reward period for a delegation after its shares changed
+pub fn after_delegation_modified(
+    state: &mut AppState,
+    height: u64,
+    delegator: &AccAddress,
+    validator: &ValAddress,
+) -> Result<()> {
+    let Some(delegation) = state
+        .staking
+        .delegations
+        .get(delegator)
+        .and_then(|delegations| delegations.get(validator))
+    else {
+        return Ok(());
+    };
+    let record = find(state, validator)?;
+    let tokens = decimal(parse_amount(&record.tokens)?)?;
+    let total_shares = decimal(parse_amount(&record.delegator_shares)?)?;
+    let shares = decimal(parse_amount(&delegation.shares)?)?;
+    let stake = if total_shares.is_zero() {
+        Decimal::ZERO
+    } else {
+        tokens
+            .checked_mul(shares)
+            .and_then(|value| value.checked_div(total_shares))
+            .ok_or_else(|| overflow("delegation stake"))?
+    };
+
+    let rewards = state
+        .distribution
+        .validators
+        .entry(validator.clone())
+        .or_default();
+    let previous_period = rewards.period - 1;
+    reference(rewards, previous_period, 1)?;
+    state
+        .distribution
+        .starting_info
+        .entry(delegator.clone())
+        .or_default()
+        .insert(
+            validator.clone(),
+            StartingInfo {
+                previous_period,
+                stake: stake.normalize().to_string(),
+                height,
+            },
+        );
+    Ok(())
+}
+
+/// End the validator's reward period before `fraction` of its tokens is burned
+pub fn before_validator_slashed(
+    state: &mut AppState,
+    height: u64,
+    validator: &ValAddress,
+    fraction: Decimal,
+) -> Result<()> {
+    let period = increment_period(state, validator)?;
+    let rewards = state
+        .distribution
+        .validators
+        .get_mut(validator)
+        .expect("validator has rewards");
+    reference(rewards, period, 1)?;
+    rewards.slash_events.push(SlashEvent {
+        height,
+        period,
+        fraction: fraction.normalize().to_string(),
+    });
+    Ok(())
+}
+
+/// Check that `delegator` has rewards accruing from `validator`
+pub fn validate_withdraw_rewards(
+    state: &AppState,
+    delegator: &AccAddress,
+    validator: &ValAddress,
+) -> Result<()> {
+    find(state, validator)?;
+    if !has_starting_info(state, delegator, validator) {
+        return Err(DistributionError::NoDelegation {
+            delegator: delegator.clone(),
+            validator: validator.clone(),
+        });
+    }
+    Ok(())
+}
+
+/// Pay out the rewards of a delegation and keep it earning
+pub fn withdraw_rewards(
+    state: &mut AppState,
+    height: u64,
+    delegator: &AccAddress,
+    validator: &ValAddress,
+) -> Result<Vec<Event>> {
+    validate_withdraw_rewards(state, delegator, validator)?;
+    let events = withdraw(state, delegator, validator)?.into_iter().collect();
+    after_delegation_modified(state, height, delegator, validator)?;
+    Ok(events)
+}
+
+/// Check that `sender` operates a validator with commission to withdraw
+pub fn validate_withdraw_commission(state: &AppState, sender: &AccAddress) -> Result<()> {
+    let validator = operated_by(state, sender)?;
+    let commission = state
+        .distribution
+        .validators
+        .get(&validator)
+        .map(|rewards| parse_coins(&rewards.commission))
+        .transpose()?
+        .unwrap_or_default();
+    if !commission.values().any(|amount| *amount >= Decimal::ONE) {
+        return Err(DistributionError::NoCommission(validator));
+    }
+    Ok(())
+}
+
+/// Pay the whole units of the sender's validator commission to the sender
+pub fn withdraw_commission(state: &mut AppState, sender: &AccAddress) -> Result<Vec<Event>> {
+    validate_withdraw_commission(state, sender)?;
+    let validator = operated_by(state, sender)?;
+    let rewards = state
+        .distribution
+        .validators
+        .get_mut(&validator)
+        .expect("validator has rewards");
+    let mut commission = parse_coins(&rewards.commission)?;
+    let mut paid = BTreeMap::new();
+    for (asset, amount) in commission.iter_mut() {
+        let whole = amount.floor();
+        *amount -= whole;
+        paid.insert(asset.clone(), whole);
+    }
+    rewards.commission = store(commission);
+    let coins = pay(state, &validator, sender, paid)?;
+
+    Ok(vec![Event::CommissionWithdrawn {
+        validator,
+        operator: sender.clone(),
+        amount: coins,
+    }])
+}
+
+/// Rewards a delegation has earned so far, rounded down
+pub fn pending_rewards(
+    state: &AppState,
+    delegator: &AccAddress,
+    validator: &ValAddress,
+) -> Result<Vec<Coin>> {
+    let info = starting_info(state, delegator, validator)?;
+    let record = find(state, validator)?;
+    let rewards = state
+        .distribution
+        .validators
+        .get(validator)
+        .ok_or_else(|| {
+            DistributionError::Invariant(format!("no reward record for {}", validator))
+        })?;
+
+    // Close the current period without storing it
+    let mut ratio = parse_coins(&historical(rewards, rewards.period - 1)?.ratio)?;
+    let tokens = decimal(parse_amount(&record.tokens)?)?;
+    if tokens > Decimal::ZERO {
+        for (asset, amount) in parse_coins(&rewards.current)? {
+            *ratio.entry(asset).or_default() += amount
+                .checked_div(tokens)
+                .ok_or_else(|| overflow("reward ratio"))?;
+        }
+    }
+    let earned = earned(rewards, info, &ratio)?;
+    Ok(earned
+        .into_iter()
+        .filter_map(|(asset, amount)| {
+            let amount = floor_signed(amount).ok()?;
+            (amount > 0).then(|| Coin {
+                denom: asset,
+                amount: amount.to_string(),
+            })
+        })
+        .collect())
+}
+
+/// Verify that the distribution pool holds exactly the outstanding rewards
+pub fn check_invariants(state: &AppState) -> Result<()> {
+    let mut outstanding: BTreeMap<AssetId, Decimal> = BTreeMap::new();
+    for rewards in state.distribution.validators.values() {
+        for (asset, amount) in parse_coins(&rewards.outstanding)? {
+            *outstanding.entry(asset).or_default() += amount;
+        }
+    }
+    let pool = DISTRIBUTION_POOL.to_string();
+    let mut assets: Vec<_> = outstanding.keys().cloned().collect();
+    if let Some(account) = state.accounts.get(&pool) {
+        assets.extend(account.balances.keys().cloned());
+    }
+    for asset in assets {
+        let held = parse_amount(&portfolio::balance(state, &pool, &asset)?.total)?;
+        let expected = outstanding.get(&asset).copied().unwrap_or_default();
+        if decimal(held)? != expected {
+            return Err(DistributionError::Invariant(format!(
+                "{} holds {} {}, outstanding rewards are {}",
+                pool, held, asset, expected
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Split `total` between the validators with power, in address order
+///
+/// Shares are rounded down; the remainder goes to the validator with the
+/// most power. Without any power nothing is split.
+fn split_by_power(
+    total: u128,
+    powers: &imbl::HashMap<ValAddress, u64>,
+) -> Result<Vec<(ValAddress, u128)>> {
+    let mut validators: Vec<_> = powers.iter().filter(|(_, power)| **power > 0).collect();
+    validators.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    let Some((largest, _)) = validators.first() else {
+        return Ok(Vec::new());
+    };
+
+    let total_power: u128 = validators.iter().map(|(_, power)| **power as u128).sum();
+    let mut shares = BTreeMap::new();
+    let mut remaining = total;
+    for (address, power) in &validators {
+        let share = total
+            .checked_mul(**power as u128)
+            .ok_or_else(|| NumberError::Overflow(format!("reward share of {}", address)))?
+            / total_power;
+        shares.insert((*address).clone(), share);
+        remaining -= share;
+    }
+    *shares.get_mut(*largest).expect("validator has a share") += remaining;
+    Ok(shares.into_iter().collect())
+}
+
+/// End the delegation's reward period and pay what it earned
+fn withdraw(
+    state: &mut AppState,
+    delegator: &AccAddress,
+    validator: &ValAddress,
+) -> Result<Option<Event>> {
+    let info = starting_info(state, delegator, validator)?.clone();
+    let ending = increment_period(state, validator)?;
+    let rewards = state
+        .distribution
+        .validators
+        .get_mut(validator)
+        .expect("validator has rewards");
+    let ratio = parse_coins(&historical(rewards, ending)?.ratio)?;
+    let earned = earned(rewards, &info, &ratio)?;
+    reference(rewards, info.previous_period, -1)?;
+
+    let delegations = state
+        .distribution
+        .starting_info
+        .get_mut(delegator)
+        .expect("starting info exists");
+    delegations.remove(validator);
+    if delegations.is_empty() {
+        state.distribution.starting_info.remove(delegator);
+    }
+
+    let paid = earned
+        .into_iter()
+        .map(|(asset, amount)| (asset, amount.floor()))
+        .collect();
+    let coins = pay(state, validator, delegator, paid)?;
+    if coins.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Event::RewardsWithdrawn {
+        delegator: delegator.clone(),
+        validator: validator.clone(),
+        amount: coins,
+    }))
+}
+
+/// Close the validator's current period, returning its number
+fn increment_period(state: &mut AppState, validator: &ValAddress) -> Result<u64> {
+    let tokens = decimal(parse_amount(&find(state, validator)?.tokens)?)?;
+    let rewards = state
+        .distribution
+        .validators
+        .entry(validator.clone())
+        .or_default();
+    let mut ratio = parse_coins(&historical(rewards, rewards.period - 1)?.ratio)?;
+    let current = parse_coins(&std::mem::take(&mut rewards.current))?;
+    if tokens > Decimal::ZERO {
+        for (asset, amount) in current {
+            *ratio.entry(asset).or_default() += amount
+                .checked_div(tokens)
+                .ok_or_else(|| overflow("reward ratio"))?;
+        }
+    } else {
+        // Nobody is delegated to share the rewards; the operator keeps them
+        for (asset, amount) in current {
+            add(&mut rewards.commission, &asset, amount)?;
+        }
+    }
+
+    let period = rewards.period;
+    rewards.historical.insert(
+        period,
+        HistoricalRewards {
+            ratio: store(ratio),
+            reference_count: 1,
+        },
+    );
+    reference(rewards, period - 1, -1)?;
+    rewards.period += 1;
+    Ok(period)
+}
+
+/// Rewards earned since `info` up to a cumulative `ratio`, with the stake
+/// reduced at every slash in between
+fn earned(
+    rewards: &ValidatorRewards,
+    info: &StartingInfo,
+    ratio: &BTreeMap<AssetId, Decimal>,
+) -> Result<BTreeMap<AssetId, Decimal>> {
+    let mut stake = parse_decimal(&info.stake)?;
+    let mut start = info.previous_period;
+    let mut earned = BTreeMap::new();
+    for slash in rewards
+        .slash_events
+        .iter()
+        .filter(|slash| slash.period > info.previous_period)
+    {
+        let end = parse_coins(&historical(rewards, slash.period)?.ratio)?;
+        accrue(&mut earned, rewards, start, &end, stake)?;
+        start = slash.period;
+        stake = stake
+            .checked_mul(Decimal::ONE - parse_decimal(&slash.fraction)?)
+            .ok_or_else(|| overflow("slashed stake"))?;
+    }
+    accrue(&mut earned, rewards, start, ratio, stake)?;
+    Ok(earned)
+}
+
+/// Add the rewards of `stake` between period `start` and the cumulative `end` ratio
+fn accrue(
+    earned: &mut BTreeMap<AssetId, Decimal>,
+    rewards: &ValidatorRewards,
+    start: u64,
+    end: &BTreeMap<AssetId, Decimal>,
+    stake: Decimal,
+) -> Result<()> {
+    let start = parse_coins(&historical(rewards, start)?.ratio)?;
+    for (asset, end) in end {
+        let difference = *end - start.get(asset).copied().unwrap_or_default();
+        let amount = difference
+            .checked_mul(stake)
+            .ok_or_else(|| overflow("delegation rewards"))?;
+        if amount > Decimal::ZERO {
+            *earned.entry(asset.clone()).or_default() += amount;
+        }
+    }
+    Ok(())
+}
+
+/// Transfer whole `amounts` from the pool, capped at the validator's outstanding rewards
+fn pay(
+    state: &mut AppState,
+    validator: &ValAddress,
+    to: &AccAddress,
+    amounts: BTreeMap<AssetId, Decimal>,
+) -> Result<Vec<Coin>> {
+    let rewards = state
+        .distribution
+        .validators
+        .get_mut(validator)
+        .expect("validator has rewards");
+    let mut outstanding = parse_coins(&rewards.outstanding)?;
+    let mut coins = Vec::new();
+    for (asset, amount) in amounts {
+        let available = outstanding.get(&asset).copied().unwrap_or_default();
+        let amount = amount.min(available.floor());
+        if amount <= Decimal::ZERO {
+            continue;
+        }
+        outstanding.insert(asset.clone(), available - amount);
+        coins.push(Coin {
+            denom: asset,
+            amount: floor_signed(amount)?.to_string(),
+        });
+    }
+    rewards.outstanding = store(outstanding);
+
+    for coin in &coins {
+        bank::transfer(
+            state,
+            &DISTRIBUTION_POOL.to_string(),
+            to,
+            &coin.denom,
+            parse_amount(&coin.amount)?,
+        )?;
+    }
+    Ok(coins)
+}
+
+/// Change the reference count of a historical entry, dropping it at zero
+fn reference(rewards: &mut ValidatorRewards, period: u64, change: i64) -> Result<()> {
+    let entry = rewards
+        .historical
+        .get_mut(&period)
+        .ok_or_else(|| DistributionError::Invariant(format!("missing reward period {}", period)))?;
+    entry.reference_count =
+        u32::try_from(i64::from(entry.reference_count) + change).map_err(|_| {
+            DistributionError::Invariant(format!("reference count of period {} below zero", period))
+        })?;
+    if entry.reference_count == 0 {
+        rewards.historical.remove(&period);
+    }
+    Ok(())
+}
+
+fn historical(rewards: &ValidatorRewards, period: u64) -> Result<&HistoricalRewards> {
+    rewards
+        .historical
+        .get(&period)
+        .ok_or_else(|| DistributionError::Invariant(format!("missing reward period {}", period)))
+}
+
+fn has_starting_info(state: &AppState, delegator: &AccAddress, validator: &ValAddress) -> bool {
+    starting_info(state, delegator, validator).is_ok()
+}
+
+fn starting_info<'a>(
+    state: &'a AppState,
+    delegator: &AccAddress,
+    validator: &ValAddress,
+) -> Result<&'a StartingInfo> {
+    state
+        .distribution
+        .starting_info
+        .get(delegator)
+        .and_then(|delegations| delegations.get(validator))
+        .ok_or_else(|| DistributionError::NoDelegation {
+            delegator: delegator.clone(),
+            validator: validator.clone(),
+        })
+}
+
+fn find<'a>(state: &'a AppState, validator: &ValAddress) -> Result<&'a Validator> {
+    state
+        .validators
+        .get(validator)
+        .ok_or_else(|| DistributionError::UnknownValidator(validator.clone()))
+}
+
+/// Validator operated by `sender`
+fn operated_by(state: &AppState, sender: &AccAddress) -> Result<ValAddress> {
+    let validator =
+        operator_of(sender).ok_or_else(|| DistributionError::UnknownValidator(sender.clone()))?;
+    find(state, &validator)?;
+    Ok(validator)
+}
+
+fn overflow(what: &str) -> DistributionError {
+    NumberError::Overflow(what.to_string()).into()
+}
+
+fn decimal(amount: u128) -> Result<Decimal> {
+    Ok(to_decimal(
+        i128::try_from(amount).map_err(|_| overflow("amount"))?,
+    )?)
+}
+
+fn parse_coins(coins: &DecCoins) -> Result<BTreeMap<AssetId, Decimal>> {
+    coins
+        .iter()
+        .map(|(asset, amount)| Ok((asset.clone(), parse_decimal(amount)?)))
+        .collect()
+}
+
+fn store(coins: BTreeMap<AssetId, Decimal>) -> DecCoins {
+    coins
+        .into_iter()
+        .filter(|(_, amount)| !amount.is_zero())
+        .map(|(asset, amount)| (asset, amount.normalize().to_string()))
+        .collect()
+}
+
+fn add(coins: &mut DecCoins, asset: &AssetId, amount: Decimal) -> Result<()> {
+    let current = coins
+        .get(asset)
+        .map(|value| parse_decimal(value))
+        .transpose()?
+        .unwrap_or_default();
+    let total = current
+        .checked_add(amount)
+        .ok_or_else(|| overflow("reward total"))?;
+    if total.is_zero() {
+        coins.remove(asset);
+    } else {
+        coins.insert(asset.clone(), total.normalize().to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::test_state;
+    use crate::keys::KeyPair;
+    use crate::staking;
+
+    const OMC: u128 = 1_000_000;
+
+    /// A validator with a commission of 10% and 10 OMC of its own stake,
+    /// and a delegator bonding another 10 OMC to it; blocks mint 1000
+    fn setup() -> (AppState, KeyPair, AccAddress, ValAddress) {
+        let mut state = test_state(&["OMC"]);
+        state.params.distribution.block_reward = "1000".to_string();
+        let native = state.params.native_token.clone();
+        let (operator, delegator) = (KeyPair::generate(), KeyPair::generate().account_address());
+        for account in [&operator.account_address(), &delegator] {
+            bank::mint(&mut state, account, &native, 100 * OMC).unwrap();
+        }
+
+        let commission = Commission {
+            rate: "0.1".to_string(),
+            max_rate: "0.2".to_string(),
+            max_change_rate: "0.01".to_string(),
+            update_time: None,
+        };
+        let (sender, pubkey) = (operator.account_address(), operator.public_key_hex());
+        let stake = (10 * OMC).to_string();
+        staking::create_validator(
+            &mut state,
+            1,
+            Timestamp::UNIX_EPOCH,
+            &sender,
+            &pubkey,
+            &commission,
+            &stake,
+        )
+        .unwrap();
+        let validator = operator_of(&sender).unwrap();
+        staking::delegate(&mut state, 1, &delegator, &validator, &stake).unwrap();
+        staking::end_block(&mut state, Timestamp::UNIX_EPOCH).unwrap();
+        (state, operator, delegator, validator)
+    }
+
+    fn pending(
+        state: &AppState,
+        delegator: &AccAddress,
+        validator: &ValAddress,
+    ) -> Vec<(String, String)> {
+        pending_rewards(state, delegator, validator)
+            .unwrap()
+            .into_iter()
+            .map(|coin| (coin.denom, coin.amount))
+            .collect()
+    }
+
+    #[test]
+    fn test_emission_halves() {
+        let mut params = DistributionParams {
+            block_reward: "1000".to_string(),
+            halving_interval: 10,
+        };
+        let rewards = [0, 9, 10, 25, 10 * 200].map(|height| emission(&params, height).unwrap());
+        assert_eq!(rewards, [1000, 1000, 500, 250, 0]);
+        params.halving_interval = 0;
+        assert_eq!(emission(&params, u64::MAX).unwrap(), 1000);
+    }
+
+    #[test]
+    fn test_block_rewards_split_by_power() {
+        let params = DistributionParams {
+            block_reward: "1000".to_string(),
+            halving_interval: 0,
+        };
+        let powers: imbl::HashMap<ValAddress, u64> = [
+            ("a".to_string(), 1),
+            ("b".to_string(), 2),
+            ("c".to_string(), 0),
+        ]
+        .into_iter()
+        .collect();
+
+        // The rounding remainder goes to the largest validator
+        let rewards = block_rewards(&params, 1, &powers).unwrap();
+        assert_eq!(rewards.total_rewards, "1000");
+        let expected = [
+            ("a".to_string(), "333".to_string()),
+            ("b".to_string(), "667".to_string()),
+        ];
+        assert_eq!(rewards.validator_rewards, expected.into());
+        assert_eq!(
+            block_rewards(&params, 1, &imbl::HashMap::new())
+                .unwrap()
+                .total_rewards,
+            "0"
+        );
+    }
+
+    #[test]
+    fn test_rewards_and_commission_are_withdrawn() {
+        let (mut state, operator, delegator, validator) = setup();
+        let native = state.params.native_token.clone();
+        allocate_block_rewards(&mut state, 1).unwrap();
+
+        // 100 is commission; the other 900 is shared by the two equal stakes
+        let sender = operator.account_address();
+        assert_eq!(
+            pending(&state, &delegator, &validator),
+            vec![(native.clone(), "450".to_string())]
+        );
+        assert_eq!(
+            pending(&state, &sender, &validator),
+            vec![(native.clone(), "450".to_string())]
+        );
+
+        let events = withdraw_rewards(&mut state, 2, &delegator, &validator).unwrap();
+        assert!(
+            matches!(&events[..], [Event::RewardsWithdrawn { amount, .. }] if amount[0].amount == "450")
+        );
+        assert!(pending(&state, &delegator, &validator).is_empty());
+        let events = withdraw_commission(&mut state, &sender).unwrap();
+        assert!(
+            matches!(&events[..], [Event::CommissionWithdrawn { amount, .. }] if amount[0].amount == "100")
+        );
+        check_invariants(&state).unwrap();
+
+        let code = |error: DistributionError| error.code();
+        assert_eq!(
+            code(validate_withdraw_commission(&state, &sender).unwrap_err()),
+            codes::DISTRIBUTION_NO_COMMISSION
+        );
+        assert_eq!(
+            code(validate_withdraw_commission(&state, &delegator).unwrap_err()),
+            codes::DISTRIBUTION_UNKNOWN_VALIDATOR
+        );
+        let stranger = KeyPair::generate().account_address();
+        assert_eq!(
+            code(validate_withdraw_rewards(&state, &stranger, &validator).unwrap_err()),
+            codes::DISTRIBUTION_NO_DELEGATION
+        );
+        let unknown = operator_of(&stranger).unwrap();
+        assert_eq!(
+            code(validate_withdraw_rewards(&state, &delegator, &unknown).unwrap_err()),
+            codes::DISTRIBUTION_UNKNOWN_VALIDATOR
+        );
+    }
+
+    #[test]
+    fn test_delegation_changes_withdraw_rewards_first() {
+        let (mut state, _, delegator, validator) = setup();
+        allocate_block_rewards(&mut state, 1).unwrap();
+
+        let events = staking::delegate(
+            &mut state,
+            2,
+            &delegator,
+            &validator,
+            &(10 * OMC).to_string(),
+        )
+        .unwrap();
+        assert!(
+            matches!(&events[0], Event::RewardsWithdrawn { amount, .. } if amount[0].amount == "450")
+        );
+        // Twice the stake of the operator now earns twice as much
+        allocate_block_rewards(&mut state, 2).unwrap();
+        assert_eq!(pending(&state, &delegator, &validator)[0].1, "600");
+        check_invariants(&state).unwrap();
+    }
+
+    #[test]
+    fn test_slashes_reduce_rewards_after_them_only() {
+        let (mut state, _, delegator, validator) = setup();
+        allocate_block_rewards(&mut state, 1).unwrap();
+        // Half of the 20 OMC at stake is burned
+        staking::slash(&mut state, 2, &validator, 2, 20, Decimal::new(5, 1)).unwrap();
+        assert_eq!(state.validators[&validator].tokens, (10 * OMC).to_string());
+
+        // The delegation still earns half of the rewards, now on 5 OMC
+        allocate_block_rewards(&mut state, 3).unwrap();
+        assert_eq!(pending(&state, &delegator, &validator)[0].1, "900");
+        withdraw_rewards(&mut state, 4, &delegator, &validator).unwrap();
+        assert_eq!(
+            state.distribution.starting_info[&delegator][&validator].stake,
+            (5 * OMC).to_string()
+        );
+        check_invariants(&state).unwrap();
+    }
+}
@@ -0,0 +1,366 @@
+//! Events Module
+//!
+//! This module defines the typed events emitted while executing
+//! transactions and ending blocks. Events of a successful transaction are
+//! stored with its result; events of `end_block` are stored per height.
+
+use crate::governance::{ProposalId, ProposalStatus, TallyResult, VoteOption};
+use crate::tx::TxHash;
+use crate::types::*;
+use serde::{Deserialize, Serialize};
+
+/// Why an order left the book without filling completely
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CancelReason {
+    /// Cancelled by its owner
+    User,
+
+    /// Immediate-or-cancel, fill-or-kill or market order remainder
+    TimeInForce,
+
+    /// The market was closed
+    MarketClosed,
+
+    /// A good-till-date order reached its date
+    Expired,
+}
+
+/// Why a validator was slashed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Infraction {
+    /// Missed too many blocks of the signing window
+    Downtime,
+
+    /// Signed two different blocks at the same height
+    DoubleSign,
+}
+
+/// Which side of a fill an order was on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Liquidity {
+    /// The order was resting on the book
+    Maker,
+
+    /// The order matched on arrival, or cleared in a batch auction
+    Taker,
+}
+
+/// Something that happened while executing a block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Event {
+    OrderPlaced {
+        order_id: OrderId,
+        trader: AccAddress,
+        market: MarketId,
+        side: OrderSide,
+        order_type: OrderType,
+        quantity: Uint128,
+        price: Option<Price>,
+    },
+
+    OrderCancelled {
+        order_id: OrderId,
+        trader: AccAddress,
+        market: MarketId,
+        reason: CancelReason,
+    },
+
+    TradeExecuted {
+        trade_id: TradeId,
+        market: MarketId,
+        buyer: AccAddress,
+        seller: AccAddress,
+        buy_order_id: OrderId,
+        sell_order_id: OrderId,
+        quantity: Uint128,
+        price: Price,
+    },
+
+    OptionExercised {
+        option_id: OptionId,
+        holder: AccAddress,
+        quantity: Uint128,
+        settlement_amount: Uint128,
+    },
+
+    OptionExpired {
+        option_id: OptionId,
+    },
+
+    CollateralPosted {
+        account: AccAddress,
+        asset: AssetId,
+        amount: Uint128,
+    },
+
+    PenaltyApplied {
+        account: AccAddress,
+        asset: AssetId,
+        amount: Uint128,
+        reason: String,
+    },
+
+    Liquidated {
+        account: AccAddress,
+        market: MarketId,
+        quantity: Int128,
+        price: Price,
+    },
+
+    Transfer {
+        from: AccAddress,
+        to: AccAddress,
+        asset: AssetId,
+        amount: Uint128,
+    },
+
+    Deposited {
+        deposit_id: String,
+        recipient: AccAddress,
+        asset: AssetId,
+        amount: Uint128,
+    },
+
+    WithdrawalRequested {
+        withdrawal_id: u64,
+        sender: AccAddress,
+        asset: AssetId,
+        amount: Uint128,
+        destination: String,
+    },
+
+    OraclePriceUpdated {
+        asset: AssetId,
+        price: Price,
+        twap: Price,
+        sources: u32,
+
+        /// Feeders whose submissions deviated too far from the median
+        outliers: Vec<AccAddress>,
+    },
+
+    ValidatorCreated {
+        validator: ValAddress,
+        operator: AccAddress,
+    },
+
+    Delegated {
+        delegator: AccAddress,
+        validator: ValAddress,
+        amount: Uint128,
+        shares: Uint128,
+    },
+
+    UnbondingStarted {
+        delegator: AccAddress,
+        validator: ValAddress,
+        amount: Uint128,
+        completion_time: Timestamp,
+    },
+
+    UnbondingCompleted {
+        delegator: AccAddress,
+        validator: ValAddress,
+        amount: Uint128,
+    },
+
+    Redelegated {
+        delegator: AccAddress,
+        source: ValAddress,
+        destination: ValAddress,
+        amount: Uint128,
+    },
+
+    CommissionChanged {
+        validator: ValAddress,
+        rate: Price,
+    },
+
+    ValidatorPowerChanged {
+        validator: ValAddress,
+        power: i64,
+    },
+
+    ValidatorSlashed {
+        validator: ValAddress,
+        infraction: Infraction,
+
+        /// Height the infraction was committed at
+        infraction_height: u64,
+
+        /// Tokens burned from the validator and its unbonding entries
+        amount: Uint128,
+    },
+
+    ValidatorJailed {
+        validator: ValAddress,
+
+        /// Empty when the validator is tombstoned and can never unjail
+        jailed_until: Option<Timestamp>,
+    },
+
+    ValidatorUnjailed {
+        validator: ValAddress,
+    },
+
+    RewardsAllocated {
+        validator: ValAddress,
+        asset: AssetId,
+        amount: Uint128,
+
+        /// Part of `amount` kept by the validator, a decimal
+        commission: Price,
+    },
+
+    RewardsWithdrawn {
+        delegator: AccAddress,
+        validator: ValAddress,
+        amount: Vec<Coin>,
+    },
+
+    CommissionWithdrawn {
+        validator: ValAddress,
+        operator: AccAddress,
+        amount: Vec<Coin>,
+    },
+
+    FeeCharged {
+        trade_id: TradeId,
+        account: AccAddress,
+        asset: AssetId,
+        amount: Uint128,
+        liquidity: Liquidity,
+    },
+
+    /// A maker rebate paid out of the taker's fee
+    FeeRebated {
+        trade_id: TradeId,
+        account: AccAddress,
+        asset: AssetId,
+        amount: Uint128,
+    },
+
+    FeeDistributed {
+        asset: AssetId,
+        to_liquidity_providers: Uint128,
+        to_stakers: Uint128,
+        burned: Uint128,
+        to_treasury: Uint128,
+    },
+
+    ProposalSubmitted {
+        proposal_id: ProposalId,
+        proposer: AccAddress,
+    },
+
+    ProposalDeposited {
+        proposal_id: ProposalId,
+        depositor: AccAddress,
+        amount: Uint128,
+    },
+
+    VotingStarted {
+        proposal_id: ProposalId,
+        voting_end_time: Timestamp,
+    },
+
+    ProposalVoted {
+        proposal_id: ProposalId,
+        voter: AccAddress,
+        option: VoteOption,
+    },
+
+    ProposalTallied {
+        proposal_id: ProposalId,
+        status: ProposalStatus,
+
+        /// Empty when the deposit period ended without enough deposits
+        tally: Option<TallyResult>,
+    },
+
+    ProposalExecuted {
+        proposal_id: ProposalId,
+    },
+
+    ProposalFailed {
+        proposal_id: ProposalId,
+        reason: String,
+    },
+
+    MarketListed {
+        market: MarketId,
+    },
+
+    MarketStatusChanged {
+        market: MarketId,
+        from: MarketStatus,
+        to: MarketStatus,
+    },
+
+    MatchingModeChanged {
+        market: MarketId,
+        from: MatchingMode,
+        to: MatchingMode,
+    },
+}
+
+impl Event {
+    /// Event for an executed trade
+    pub fn trade(trade: &Trade) -> Self {
+        Event::TradeExecuted {
+            trade_id: trade.id,
+            market: trade.market_id.clone(),
+            buyer: trade.buyer.clone(),
+            seller: trade.seller.clone(),
+            buy_order_id: trade.buy_order_id,
+            sell_order_id: trade.sell_order_id,
+            quantity: trade.quantity.clone(),
+            price: trade.price.clone(),
+        }
+    }
+
+    /// Orders whose state the event changed
+    pub fn order_ids(&self) -> Vec<OrderId> {
+        match self {
+            Event::OrderPlaced { order_id, .. } | Event::OrderCancelled { order_id, .. } => {
+                vec![*order_id]
+            }
+            Event::TradeExecuted {
+                buy_order_id,
+                sell_order_id,
+                ..
+            } => vec![*buy_order_id, *sell_order_id],
+            _ => vec![],
+        }
+    }
+}
+
+/// Events of one transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxEvents {
+    pub hash: TxHash,
+    pub events: Vec<Event>,
+}
+
+/// Every event of a block, in execution order
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockEvents {
+    pub height: u64,
+
+    /// Events of the successful transactions
+    pub txs: Vec<TxEvents>,
+
+    /// Events emitted by `end_block`
+    pub end_block: Vec<Event>,
+}
+
+impl BlockEvents {
+    /// All events of the block in execution order
+    pub fn iter(&self) -> impl Iterator<Item = &Event> {
+        self.txs
+            .iter()
+            .flat_map(|tx| tx.events.iter())
+            .chain(self.end_block.iter())
+    }
+}
@@ -0,0 +1,831 @@
+//! Fees Module
+//!
+//! Every fill pays a fee on what each side receives: the buyer in the base
+//! asset, the seller in the quote asset. The resting order of a continuous
+//! market pays the maker rate and the incoming order the taker rate; both
+//! sides of a batch auction pay the taker rate. Rates come from the market's
+//! schedule in `params.trading_fees`, or the default schedule, at the volume
+//! tier the account's quote volume over the last `volume_window_days`
+//! reaches. Accounts staking the native token get a discount on positive
+//! rates.
+//!
+//! A negative maker rate is a rebate. It is paid in the asset the taker's
+//! fee was charged in, out of that fee, so a fill never pays out more than
+//! it collected.
+//!
+//! Trading fees, and premium fees once options settle on chain, collect in
+//! the fee collector account during the block. `end_block` splits each asset
+//! the collector holds by `params.trading_fees.fee_distribution`: the
+//! liquidity provider share goes to the liquidity rewards pool, the staker
+//! share is allocated to the active validators by voting power like the
+//! block reward, the burn share is removed from the supply and the treasury
+//! share goes to the treasury. Shares are rounded down and the treasury
+//! receives the remainder; it also receives the staker share when no
+//! validator is active.
+
+use crate::app::{AppState, TradingFees};
+use crate::bank::{self, BankError};
+use crate::distribution::{self, DistributionError, DISTRIBUTION_POOL};
+use crate::events::{Event, Liquidity};
+use crate::math::{notional, parse_amount, parse_decimal, parse_fraction, NumberError};
+use crate::staking::{self, StakingError};
+use crate::types::*;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+type Result<T> = std::result::Result<T, FeeError>;
+
+/// Module account fees accrue to until the end of the block
+pub const FEE_COLLECTOR: &str = "optimic1feecollector";
+
+/// Module account holding the liquidity provider share of fees
+pub const LIQUIDITY_REWARDS_POOL: &str = "optimic1lprewards";
+
+/// Protocol treasury account
+pub const TREASURY: &str = "optimic1treasury";
+
+/// Seconds in a day of the volume window
+const DAY: i64 = 24 * 60 * 60;
+
+/// Failures while charging or distributing fees
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum FeeError {
+    #[error(transparent)]
+    Bank(#[from] BankError),
+
+    #[error(transparent)]
+    Distribution(#[from] DistributionError),
+
+    #[error(transparent)]
+    Staking(#[from] StakingError),
+
+    #[error(transparent)]
+    Number(#[from] NumberError),
+}
+
+impl FeeError {
+    /// Stable result code, see [`crate::codes`]
+    pub fn code(&self) -> u32 {
+        match self {
+            FeeError::Bank(error) => error.code(),
+            FeeError::Distribution(error) => error.code(),
+            FeeError::Staking(error) => error.code(),
+            FeeError::Number(error) => error.code(),
+        }
+    }
+}
+
+/// Maker and taker rates of a market
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeSchedule {
+    /// Rate of the resting side of a fill; negative rates are rebates
+    pub maker_rate: Price,
+
+    /// Rate of the incoming side of a fill
+    pub taker_rate: Price,
+
+    /// Lower rates for accounts with more volume, by ascending `min_volume`
+    #[serde(default)]
+    pub tiers: Vec<VolumeTier>,
+}
+
+impl Default for FeeSchedule {
+    fn default() -> Self {
+        Self {
+            maker_rate: "0.0002".to_string(), // 0.02%
+            taker_rate: "0.0005".to_string(), // 0.05%
+            tiers: vec![
+                VolumeTier {
+                    min_volume: "1000000000000".to_string(),
+                    maker_rate: "0".to_string(),
+                    taker_rate: "0.0004".to_string(),
+                },
+                VolumeTier {
+                    min_volume: "10000000000000".to_string(),
+                    maker_rate: "-0.0001".to_string(), // 0.01% rebate
+                    taker_rate: "0.0003".to_string(),
+                },
+            ],
+        }
+    }
+}
+
+/// Rates of accounts whose volume reaches `min_volume` quote units
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VolumeTier {
+    pub min_volume: Uint128,
+    pub maker_rate: Price,
+    pub taker_rate: Price,
+}
+
+/// Discount on positive rates for accounts staking at least `min_stake`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StakingDiscount {
+    pub min_stake: Uint128,
+
+    /// Fraction of the fee waived
+    pub discount: Price,
+}
+
+/// Default fee discounts for stakers of the native token
+pub fn default_staking_discounts() -> Vec<StakingDiscount> {
+    vec![
+        StakingDiscount {
+            min_stake: "1000000000".to_string(),
+            discount: "0.1".to_string(), // 10%
+        },
+        StakingDiscount {
+            min_stake: "10000000000".to_string(),
+            discount: "0.25".to_string(), // 25%
+        },
+    ]
+}
+
+/// Default number of days of volume counted for volume tiers
+pub fn default_volume_window_days() -> u64 {
+    30
+}
+
+/// Fee state
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FeeState {
+    /// Trading volume of every account over the volume window
+    pub volumes: imbl::HashMap<AccAddress, TradingVolume>,
+}
+
+/// Quote volume an account traded, by quote asset and day since the epoch
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TradingVolume {
+    pub daily: BTreeMap<AssetId, BTreeMap<i64, Uint128>>,
+}
+
+/// Validate the fee schedules and discounts, collecting every problem
+pub fn validate_params(fees: &TradingFees, errors: &mut Vec<String>) {
+    let schedules = std::iter::once(("default_schedule".to_string(), &fees.default_schedule))
+        .chain(
+            fees.market_schedules
+                .iter()
+                .map(|(market, schedule)| (format!("market_schedules.{}", market), schedule)),
+        );
+    for (name, schedule) in schedules {
+        validate_rates(&name, &schedule.maker_rate, &schedule.taker_rate, errors);
+        let mut previous = None;
+        for (i, tier) in schedule.tiers.iter().enumerate() {
+            let name = format!("{}.tiers.{}", name, i);
+            validate_rates(&name, &tier.maker_rate, &tier.taker_rate, errors);
+            match parse_amount(&tier.min_volume) {
+                Ok(volume) if previous.is_some_and(|previous| volume <= previous) => {
+                    errors.push(format!(
+                        "params.trading_fees.{}.min_volume must increase",
+                        name
+                    ));
+                }
+                Ok(volume) => previous = Some(volume),
+                Err(e) => errors.push(format!("params.trading_fees.{}.min_volume: {}", name, e)),
+            }
+        }
+    }
+
+    if fees.volume_window_days == 0 {
+        errors.push("params.trading_fees.volume_window_days must be positive".to_string());
+    }
+    let mut previous = None;
+    for (i, discount) in fees.staking_discounts.iter().enumerate() {
+        if let Err(e) = parse_fraction(&discount.discount) {
+            errors.push(format!(
+                "params.trading_fees.staking_discounts.{}.discount: {}",
+                i, e
+            ));
+        }
+        match parse_amount(&discount.min_stake) {
+            Ok(stake) if previous.is_some_and(|previous| stake <= previous) => {
+                errors.push(format!(
+                    "params.trading_fees.staking_discounts.{}.min_stake must increase",
+                    i
+                ));
+            }
+            Ok(stake) => previous = Some(stake),
+            Err(e) => errors.push(format!(
+                "params.trading_fees.staking_discounts.{}.min_stake: {}",
+                i, e
+            )),
+        }
+    }
+}
+
+fn validate_rates(name: &str, maker_rate: &Price, taker_rate: &Price, errors: &mut Vec<String>) {
+    let taker = match parse_fraction(taker_rate) {
+        Ok(taker) => taker,
+        Err(e) => {
+            errors.push(format!("params.trading_fees.{}.taker_rate: {}", name, e));
+            return;
+        }
+    };
+    match parse_decimal(maker_rate) {
+        Ok(maker) if maker > Decimal::ONE || maker < -taker => errors.push(format!(
+            "params.trading_fees.{}.maker_rate must be at most 1 and the rebate at most the taker rate",
+            name
+        )),
+        Ok(_) => {}
+        Err(e) => errors.push(format!("params.trading_fees.{}.maker_rate: {}", name, e)),
+    }
+}
+
+/// Rate `account` pays as maker or taker in `market` at `time`
+///
+/// The rate is that of the highest volume tier the account reaches, less the
+/// staking discount if it is positive.
+pub fn fee_rate(
+    state: &AppState,
+    market: &Market,
+    account: &AccAddress,
+    liquidity: Liquidity,
+    time: Timestamp,
+) -> Result<Decimal> {
+    let fees = &state.params.trading_fees;
+    let schedule = fees
+        .market_schedules
+        .get(&market.id)
+        .unwrap_or(&fees.default_schedule);
+    let volume = volume(state, account, &market.quote_asset, time)?;
+    let (mut maker_rate, mut taker_rate) = (&schedule.maker_rate, &schedule.taker_rate);
+    for tier in &schedule.tiers {
+        if parse_amount(&tier.min_volume)? <= volume {
+            (maker_rate, taker_rate) = (&tier.maker_rate, &tier.taker_rate);
+        }
+    }
+    let rate = parse_decimal(match liquidity {
+        Liquidity::Maker => maker_rate,
+        Liquidity::Taker => taker_rate,
+    })?;
+    if rate <= Decimal::ZERO {
+        return Ok(rate);
+    }
+    Ok(rate * (Decimal::ONE - staking_discount(state, account)?))
+}
+
+/// Quote volume `account` traded in `quote` over the volume window
+pub fn volume(
+    state: &AppState,
+    account: &AccAddress,
+    quote: &AssetId,
+    time: Timestamp,
+) -> Result<u128> {
+    let Some(daily) = state
+        .fees
+        .volumes
+        .get(account)
+        .and_then(|volume| volume.daily.get(quote))
+    else {
+        return Ok(0);
+    };
+    let first_day = window_start(state, time);
+    let mut total: u128 = 0;
+    for amount in daily.range(first_day..).map(|(_, amount)| amount) {
+        total = total
+            .checked_add(parse_amount(amount)?)
+            .ok_or_else(|| NumberError::Overflow(format!("volume of {}", account)))?;
+    }
+    Ok(total)
+}
+
+/// Native tokens `account` has delegated, valued at the current exchange rates
+pub fn staked(state: &AppState, account: &AccAddress) -> Result<u128> {
+    let mut total: u128 = 0;
+    for delegation in state
+        .staking
+        .delegations
+        .get(account)
+        .into_iter()
+        .flat_map(|delegations| delegations.values())
+    {
+        total = total
+            .checked_add(staking::delegation_tokens(state, delegation)?)
+            .ok_or_else(|| NumberError::Overflow(format!("stake of {}", account)))?;
+    }
+    Ok(total)
+}
+
+/// Fraction of positive fees waived for what `account` stakes
+fn staking_discount(state: &AppState, account: &AccAddress) -> Result<Decimal> {
+    let discounts = &state.params.trading_fees.staking_discounts;
+    if discounts.is_empty() {
+        return Ok(Decimal::ZERO);
+    }
+    let stake = staked(state, account)?;
+    let mut discount = Decimal::ZERO;
+    for tier in discounts {
+        if parse_amount(&tier.min_stake)? <= stake {
+            discount = parse_fraction(&tier.discount)?;
+        }
+    }
+    Ok(discount)
+}
+
+/// Add a fill's quote value to an account's volume, dropping days that left the window
+fn record_volume(
+    state: &mut AppState,
+    account: &AccAddress,
+    quote: &AssetId,
+    value: u128,
+    time: Timestamp,
+) -> Result<()> {
+    let first_day = window_start(state, time);
+    let daily = state
+        .fees
+        .volumes
+        .entry(account.clone())
+        .or_default()
+        .daily
+        .entry(quote.clone())
+        .or_default();
+    daily.retain(|day, _| *day >= first_day);
+    let today = daily.entry(day(time)).or_insert_with(|| "0".to_string());
+    *today = parse_amount(today)?
+        .checked_add(value)
+        .ok_or_else(|| NumberError::Overflow(format!("volume of {}", account)))?
+        .to_string();
+    Ok(())
+}
+
+/// First day of the volume window ending on the day of `time`
+fn window_start(state: &AppState, time: Timestamp) -> i64 {
+    let days = i64::try_from(state.params.trading_fees.volume_window_days).unwrap_or(i64::MAX);
+    day(time).saturating_sub(days.saturating_sub(1))
+}
+
+fn day(time: Timestamp) -> i64 {
+    time.timestamp().div_euclid(DAY)
+}
+
+/// Charge both sides of a settled trade their fee on what they received,
+/// pay the maker's rebate and count the trade towards both volumes
+pub fn charge_trade(state: &mut AppState, market: &Market, trade: &Trade) -> Result<Vec<Event>> {
+    let quantity = parse_amount(&trade.quantity)?;
+    let value = notional(quantity, parse_decimal(&trade.price)?)?;
+    let (buyer, seller) = match market.matching_mode {
+        MatchingMode::BatchAuction => (Liquidity::Taker, Liquidity::Taker),
+        MatchingMode::Continuous if trade.buy_order_id > trade.sell_order_id => {
+            (Liquidity::Taker, Liquidity::Maker)
+        }
+        MatchingMode::Continuous => (Liquidity::Maker, Liquidity::Taker),
+    };
+    let sides = [
+        (&trade.buyer, &market.base_asset, quantity, buyer),
+        (&trade.seller, &market.quote_asset, value, seller),
+    ];
+    let mut rates = Vec::new();
+    for (account, _, _, liquidity) in &sides {
+        rates.push(fee_rate(
+            state,
+            market,
+            account,
+            *liquidity,
+            trade.timestamp,
+        )?);
+    }
+
+    let mut events = Vec::new();
+    let mut rebates = Vec::new();
+    let mut taker_fee = None;
+    for ((payer, asset, received, liquidity), rate) in sides.into_iter().zip(rates) {
+        if rate.is_sign_negative() {
+            rebates.push((payer, -rate));
+            continue;
+        }
+        let fee = notional(received, rate)?;
+        if liquidity == Liquidity::Taker {
+            taker_fee = Some((asset, received, fee));
+        }
+        if fee == 0 {
+            continue;
+        }
+        collect(state, payer, asset, fee)?;
+        events.push(Event::FeeCharged {
+            trade_id: trade.id,
+            account: payer.clone(),
+            asset: asset.clone(),
+            amount: fee.to_string(),
+            liquidity,
+        });
+    }
+
+    // Rebates are paid out of the taker's fee, in its asset
+    if let Some((asset, received, fee)) = taker_fee {
+        for (maker, rate) in rebates {
+            let rebate = notional(received, rate)?.min(fee);
+            if rebate == 0 {
+                continue;
+            }
+            bank::transfer(state, &FEE_COLLECTOR.to_string(), maker, asset, rebate)?;
+            events.push(Event::FeeRebated {
+                trade_id: trade.id,
+                account: maker.clone(),
+                asset: asset.clone(),
+                amount: rebate.to_string(),
+            });
+        }
+    }
+
+    record_volume(
+        state,
+        &trade.buyer,
+        &market.quote_asset,
+        value,
+        trade.timestamp,
+    )?;
+    record_volume(
+        state,
+        &trade.seller,
+        &market.quote_asset,
+        value,
+        trade.timestamp,
+    )?;
+    Ok(events)
+}
+
+/// Move a fee from the payer's available balance to the fee collector
+pub fn collect(
+    state: &mut AppState,
+    payer: &AccAddress,
+    asset: &AssetId,
+    amount: u128,
+) -> Result<()> {
+    bank::transfer(state, payer, &FEE_COLLECTOR.to_string(), asset, amount)?;
+    Ok(())
+}
+
+/// Split everything the fee collector holds by the fee distribution
+pub fn distribute(state: &mut AppState) -> Result<Vec<Event>> {
+    let split = state.params.trading_fees.fee_distribution.clone();
+    let to_liquidity_providers = parse_fraction(&split.to_liquidity_providers)?;
+    let to_stakers = parse_fraction(&split.to_stakers)?;
+    let to_burn = parse_fraction(&split.to_burn)?;
+    let stakers_active = distribution::has_active_validators(state);
+
+    let collector = FEE_COLLECTOR.to_string();
+    let mut collected = Vec::new();
+    for (asset, amount) in state
+        .accounts
+        .get(&collector)
+        .into_iter()
+        .flat_map(|account| &account.balances)
+    {
+        match parse_amount(amount)? {
+            0 => {}
+            amount => collected.push((asset.clone(), amount)),
+        }
+    }
+    collected.sort();
+
+    let mut events = Vec::new();
+    for (asset, total) in collected {
+        let liquidity = notional(total, to_liquidity_providers)?;
+        let stakers = if stakers_active {
+            notional(total, to_stakers)?
+        } else {
+            0
+        };
+        let burned = notional(total, to_burn)?;
+        let treasury = total - liquidity - stakers - burned;
+
+        for (recipient, amount) in [
+            (LIQUIDITY_REWARDS_POOL, liquidity),
+            (DISTRIBUTION_POOL, stakers),
+            (TREASURY, treasury),
+        ] {
+            if amount > 0 {
+                bank::transfer(state, &collector, &recipient.to_string(), &asset, amount)?;
+            }
+        }
+        let allocations = match stakers {
+            0 => Vec::new(),
+            stakers => distribution::allocate_by_power(state, &asset, stakers)?,
+        };
+        if burned > 0 {
+            bank::burn(state, &collector, &asset, burned)?;
+        }
+
+        events.push(Event::FeeDistributed {
+            asset,
+            to_liquidity_providers: liquidity.to_string(),
+            to_stakers: stakers.to_string(),
+            burned: burned.to_string(),
+            to_treasury: treasury.to_string(),
+        });
+        events.extend(allocations);
+    }
+    Ok(events)
+}
+
+/// Fees `account` was charged in `events`, by asset
+pub fn charged(events: &[Event], account: &AccAddress) -> Result<BTreeMap<AssetId, Uint128>> {
+    let mut fees: BTreeMap<AssetId, u128> = BTreeMap::new();
+    for event in events {
+        if let Event::FeeCharged {
+            account: payer,
+            asset,
+            amount,
+            ..
+        } = event
+        {
+            if payer == account {
+                let total = fees.entry(asset.clone()).or_default();
+                *total = total
+                    .checked_add(parse_amount(amount)?)
+                    .ok_or_else(|| NumberError::Overflow(format!("fees in {}", asset)))?;
+            }
+        }
+    }
+    Ok(fees
+        .into_iter()
+        .map(|(asset, amount)| (asset, amount.to_string()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::test_state;
+    use crate::keys::KeyPair;
+
+    /// Default fee parameters, an ETH-USD market and two funded traders
+    fn setup() -> (AppState, Market, [AccAddress; 2]) {
+        let mut state = test_state(&["ETH", "USD", "OMC"]);
+        let traders = [
+            KeyPair::generate().account_address(),
+            KeyPair::generate().account_address(),
+        ];
+        for asset in ["ETH", "USD", "OMC"].map(String::from) {
+            for trader in &traders {
+                bank::mint(&mut state, trader, &asset, 100_000_000_000).unwrap();
+            }
+        }
+        let market = Market {
+            id: "ETH-USD".to_string(),
+            base_asset: "ETH".to_string(),
+            quote_asset: "USD".to_string(),
+            min_order_size: "1".to_string(),
+            lot_size: "1".to_string(),
+            tick_size: "0.01".to_string(),
+            market_type: MarketType::Spot,
+            status: MarketStatus::Active,
+            matching_mode: MatchingMode::Continuous,
+        };
+        (state, market, traders)
+    }
+
+    fn at(days: i64) -> Timestamp {
+        Timestamp::UNIX_EPOCH + chrono::TimeDelta::seconds(days * DAY)
+    }
+
+    /// 1 ETH at 100 USD, bought by the newer order
+    fn trade(traders: &[AccAddress; 2], time: Timestamp) -> Trade {
+        Trade {
+            id: 1,
+            market_id: "ETH-USD".to_string(),
+            buyer: traders[0].clone(),
+            seller: traders[1].clone(),
+            quantity: "1000000".to_string(),
+            price: "100".to_string(),
+            timestamp: time,
+            buy_order_id: 2,
+            sell_order_id: 1,
+        }
+    }
+
+    fn rate(
+        state: &AppState,
+        market: &Market,
+        account: &AccAddress,
+        liquidity: Liquidity,
+        days: i64,
+    ) -> String {
+        fee_rate(state, market, account, liquidity, at(days))
+            .unwrap()
+            .normalize()
+            .to_string()
+    }
+
+    #[test]
+    fn test_volume_tier_boundaries() {
+        let (mut state, market, traders) = setup();
+        let usd = "USD".to_string();
+        let rates = |state: &AppState| {
+            (
+                rate(state, &market, &traders[0], Liquidity::Maker, 0),
+                rate(state, &market, &traders[0], Liquidity::Taker, 0),
+            )
+        };
+
+        record_volume(&mut state, &traders[0], &usd, 999_999_999_999, at(0)).unwrap();
+        assert_eq!(rates(&state), ("0.0002".to_string(), "0.0005".to_string()));
+        record_volume(&mut state, &traders[0], &usd, 1, at(0)).unwrap();
+        assert_eq!(rates(&state), ("0".to_string(), "0.0004".to_string()));
+        record_volume(&mut state, &traders[0], &usd, 9_000_000_000_000, at(0)).unwrap();
+        assert_eq!(rates(&state), ("-0.0001".to_string(), "0.0003".to_string()));
+
+        // Volume counts for volume_window_days days, including the current one
+        assert_eq!(
+            volume(&state, &traders[0], &usd, at(29)).unwrap(),
+            10_000_000_000_000
+        );
+        assert_eq!(volume(&state, &traders[0], &usd, at(30)).unwrap(), 0);
+        assert_eq!(
+            rate(&state, &market, &traders[0], Liquidity::Taker, 30),
+            "0.0005"
+        );
+        // Other quote assets have their own volume
+        assert_eq!(
+            volume(&state, &traders[0], &"EUR".to_string(), at(0)).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_staking_discount_boundaries() {
+        let (mut state, market, traders) = setup();
+        let key = KeyPair::generate();
+        let operator = key.account_address();
+        bank::mint(&mut state, &operator, &"OMC".to_string(), 1_000_000_000).unwrap();
+        let commission = Commission {
+            rate: "0".to_string(),
+            max_rate: "0".to_string(),
+            max_change_rate: "0".to_string(),
+            update_time: None,
+        };
+        let stake = "1000000000".to_string();
+        staking::create_validator(
+            &mut state,
+            1,
+            at(0),
+            &operator,
+            &key.public_key_hex(),
+            &commission,
+            &stake,
+        )
+        .unwrap();
+        let validator = crate::keys::operator_of(&operator).unwrap();
+
+        // Exactly the first min_stake earns its discount; rebates are not reduced
+        assert_eq!(
+            rate(&state, &market, &operator, Liquidity::Taker, 0),
+            "0.00045"
+        );
+        staking::delegate(
+            &mut state,
+            1,
+            &traders[0],
+            &validator,
+            &"999999999".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            rate(&state, &market, &traders[0], Liquidity::Taker, 0),
+            "0.0005"
+        );
+        staking::delegate(
+            &mut state,
+            1,
+            &traders[0],
+            &validator,
+            &"9000000001".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            rate(&state, &market, &traders[0], Liquidity::Taker, 0),
+            "0.000375"
+        );
+        record_volume(
+            &mut state,
+            &traders[0],
+            &"USD".to_string(),
+            10_000_000_000_000,
+            at(0),
+        )
+        .unwrap();
+        assert_eq!(
+            rate(&state, &market, &traders[0], Liquidity::Maker, 0),
+            "-0.0001"
+        );
+    }
+
+    #[test]
+    fn test_negative_maker_rate_pays_a_rebate() {
+        let (mut state, market, traders) = setup();
+        let usd = "USD".to_string();
+        let balance = |state: &AppState, account: &AccAddress| {
+            parse_amount(&state.accounts[account].balances["ETH"]).unwrap()
+        };
+
+        // One quote unit short of the rebate tier the maker fee is zero
+        record_volume(&mut state, &traders[1], &usd, 9_999_999_999_999, at(0)).unwrap();
+        let events = charge_trade(&mut state, &market, &trade(&traders, at(0))).unwrap();
+        assert!(!events
+            .iter()
+            .any(|event| matches!(event, Event::FeeRebated { .. })));
+
+        // At the threshold the maker is paid 0.01% of the 1 ETH the taker bought
+        let before = balance(&state, &traders[1]);
+        let events = charge_trade(&mut state, &market, &trade(&traders, at(1))).unwrap();
+        assert!(events.iter().any(|event| matches!(
+            event,
+            Event::FeeRebated { account, amount, .. } if account == &traders[1] && amount == "100"
+        )));
+        assert_eq!(balance(&state, &traders[1]), before + 100);
+        bank::check_invariants(&state).unwrap();
+    }
+
+    #[test]
+    fn test_charge_trade_pays_rebates_out_of_taker_fee() {
+        let (mut state, market, traders) = setup();
+        state.params.trading_fees.staking_discounts.clear();
+        record_volume(
+            &mut state,
+            &traders[1],
+            &"USD".to_string(),
+            10_000_000_000_000,
+            at(0),
+        )
+        .unwrap();
+
+        // The buyer takes at 0.05% of its 1 ETH; the selling maker gets 0.01% of it back
+        let events = charge_trade(&mut state, &market, &trade(&traders, at(0))).unwrap();
+        assert!(matches!(
+            &events[..],
+            [
+                Event::FeeCharged { account, asset, amount, liquidity: Liquidity::Taker, .. },
+                Event::FeeRebated { account: maker, asset: rebate_asset, amount: rebate, .. },
+            ] if account == &traders[0] && asset == "ETH" && amount == "500"
+                && maker == &traders[1] && rebate_asset == "ETH" && rebate == "100"
+        ));
+        assert_eq!(state.accounts[FEE_COLLECTOR].balances["ETH"], "400");
+        assert_eq!(
+            volume(&state, &traders[0], &"USD".to_string(), at(0)).unwrap(),
+            100_000_000
+        );
+        assert_eq!(charged(&events, &traders[0]).unwrap()["ETH"], "500");
+
+        // A rebate never exceeds the taker's fee
+        let schedule = &mut state.params.trading_fees.default_schedule;
+        schedule.taker_rate = "0.00005".to_string();
+        schedule.tiers[1].maker_rate = "-0.0003".to_string();
+        let events = charge_trade(&mut state, &market, &trade(&traders, at(0))).unwrap();
+        assert!(matches!(&events[1], Event::FeeRebated { amount, .. } if amount == "50"));
+        bank::check_invariants(&state).unwrap();
+    }
+
+    #[test]
+    fn test_distribute_rounds_down_and_gives_treasury_the_rest() {
+        let (mut state, _, traders) = setup();
+        let usd = "USD".to_string();
+        collect(&mut state, &traders[0], &usd, 1001).unwrap();
+        let supply = bank::supply_of(&state, &usd).unwrap();
+
+        // Without active validators the staker share goes to the treasury too
+        let events = distribute(&mut state).unwrap();
+        assert!(matches!(
+            &events[..],
+            [Event::FeeDistributed { to_liquidity_providers, to_stakers, burned, to_treasury, .. }]
+                if (to_liquidity_providers.as_str(), to_stakers.as_str(), burned.as_str(), to_treasury.as_str())
+                    == ("400", "0", "200", "401")
+        ));
+        assert_eq!(state.accounts[TREASURY].balances["USD"], "401");
+        assert_eq!(bank::supply_of(&state, &usd).unwrap(), supply - 200);
+        assert!(distribute(&mut state).unwrap().is_empty());
+        bank::check_invariants(&state).unwrap();
+    }
+
+    #[test]
+    fn test_params_validation() {
+        let (state, _, _) = setup();
+        let mut fees = state.params.trading_fees.clone();
+        fees.default_schedule.tiers[1].min_volume =
+            fees.default_schedule.tiers[0].min_volume.clone();
+        fees.default_schedule.tiers[0].maker_rate = "-0.001".to_string();
+        fees.volume_window_days = 0;
+        fees.staking_discounts[1].discount = "1.5".to_string();
+        fees.staking_discounts[1].min_stake = "1".to_string();
+
+        let mut errors = Vec::new();
+        validate_params(&fees, &mut errors);
+        assert_eq!(errors.len(), 5, "{:?}", errors);
+        assert!(errors
+            .iter()
+            .any(|e| e.contains("default_schedule.tiers.1.min_volume must increase")));
+        assert!(errors
+            .iter()
+            .any(|e| e.contains("tiers.0.maker_rate must be at most 1 and the rebate at most")));
+        assert!(errors
+            .iter()
+            .any(|e| e.contains("volume_window_days must be positive")));
+        assert!(errors
+            .iter()
+            .any(|e| e.contains("staking_discounts.1.discount")));
+        assert!(errors
+            .iter()
+            .any(|e| e.contains("staking_discounts.1.min_stake must increase")));
+    }
+}
@@ -0,0 +1,1012 @@
+//! Governance Module
+//!
+//! Stakers change the chain through proposals: parameter changes, market
+//! listings and status changes, spends from the treasury and plain text. A proposal waits in
+//! its deposit period until deposits reach `min_deposit`, then stakers vote
+//! on it for `voting_period` seconds.
+//!
+//! Votes are weighted by bonded tokens. A delegation to an active validator
+//! votes the way its delegator voted; if the delegator did not vote, it
+//! follows the validator's operator. A proposal passes when the votes cast
+//! reach `quorum` of the bonded tokens, `NoWithVeto` is at most
+//! `veto_threshold` of the votes cast and `Yes` exceeds `threshold` of the
+//! votes that did not abstain. Passed proposals are executed at once; a proposal whose
+//! content no longer applies is marked failed and changes nothing.
+//!
+//! Deposits are refunded when the proposal ends, unless it was vetoed; then
+//! they are burned. Deposits of a proposal that never reached `min_deposit`
+//! are refunded too.
+
+use crate::app::{self, AppState, ChainParams};
+use crate::bank::{self, BankError};
+use crate::codes;
+use crate::events::Event;
+use crate::keys::{is_account_address, operator_of};
+use crate::markets;
+use crate::math::{parse_amount, parse_fraction, to_decimal, NumberError};
+use crate::portfolio::{self, PortfolioError};
+use crate::staking::{self, StakingError};
+use crate::types::*;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use thiserror::Error;
+
+type Result<T> = std::result::Result<T, GovernanceError>;
+
+/// Module account holding proposal deposits
+pub const GOVERNANCE_ACCOUNT: &str = "optimic1gov";
+
+/// Proposal identifier
+pub type ProposalId = u64;
+
+/// Reasons a governance operation is rejected
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum GovernanceError {
+    #[error("unknown proposal {0}")]
+    UnknownProposal(ProposalId),
+
+    #[error("proposal {0} is not in its deposit period")]
+    NotAcceptingDeposits(ProposalId),
+
+    #[error("proposal {0} is not in its voting period")]
+    NotVoting(ProposalId),
+
+    #[error("invalid proposal: {0}")]
+    InvalidProposal(String),
+
+    #[error("deposit must be positive")]
+    ZeroDeposit,
+
+    #[error(transparent)]
+    Bank(#[from] BankError),
+
+    #[error(transparent)]
+    Portfolio(#[from] PortfolioError),
+
+    #[error(transparent)]
+    Staking(#[from] StakingError),
+
+    #[error(transparent)]
+    Number(#[from] NumberError),
+}
+
+impl GovernanceError {
+    /// Stable result code, see [`crate::codes`]
+    pub fn code(&self) -> u32 {
+        match self {
+            GovernanceError::UnknownProposal(_) => codes::GOVERNANCE_UNKNOWN_PROPOSAL,
+            GovernanceError::NotAcceptingDeposits(_) => codes::GOVERNANCE_NOT_ACCEPTING_DEPOSITS,
+            GovernanceError::NotVoting(_) => codes::GOVERNANCE_NOT_VOTING,
+            GovernanceError::InvalidProposal(_) => codes::GOVERNANCE_INVALID_PROPOSAL,
+            GovernanceError::ZeroDeposit => codes::GOVERNANCE_ZERO_DEPOSIT,
+            GovernanceError::Bank(error) => error.code(),
+            GovernanceError::Portfolio(error) => error.code(),
+            GovernanceError::Staking(error) => error.code(),
+            GovernanceError::Number(error) => error.code(),
+        }
+    }
+}
+
+/// Governance parameters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GovernanceParams {
+    /// Native tokens deposited before voting starts
+    pub min_deposit: Uint128,
+
+    /// Seconds a proposal may wait for its deposit
+    pub max_deposit_period: u64,
+
+    /// Seconds a proposal is open for votes
+    pub voting_period: u64,
+
+    /// Fraction of bonded tokens that must vote
+    pub quorum: Price,
+
+    /// Fraction of non-abstaining votes `Yes` must exceed
+    pub threshold: Price,
+
+    /// Fraction of votes `NoWithVeto` must exceed to veto
+    pub veto_threshold: Price,
+}
+
+impl Default for GovernanceParams {
+    fn default() -> Self {
+        Self {
+            min_deposit: "10000000000".to_string(), // 10,000 OMC
+            max_deposit_period: 2 * 24 * 60 * 60,   // 2 days
+            voting_period: 2 * 24 * 60 * 60,        // 2 days
+            quorum: "0.334".to_string(),            // 33.4%
+            threshold: "0.5".to_string(),           // 50%
+            veto_threshold: "0.334".to_string(),    // 33.4%
+        }
+    }
+}
+
+/// Governance state
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GovernanceState {
+    pub proposals: imbl::OrdMap<ProposalId, Proposal>,
+
+    /// Deposits of open proposals by depositor
+    pub deposits: imbl::OrdMap<ProposalId, imbl::OrdMap<AccAddress, Uint128>>,
+
+    /// Votes of proposals in their voting period
+    pub votes: imbl::OrdMap<ProposalId, imbl::OrdMap<AccAddress, VoteOption>>,
+
+    /// Last assigned proposal ID
+    pub last_proposal_id: ProposalId,
+}
+
+/// What a proposal does when it passes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ProposalContent {
+    /// Signal only; nothing is executed
+    Text,
+
+    /// Set chain parameters
+    ParamChange { changes: Vec<ParamChange> },
+
+    /// List a new market
+    MarketListing { market: Market },
+
+    /// Suspend, resume or close a market
+    MarketStatusChange {
+        market: MarketId,
+        status: MarketStatus,
+    },
+
+    /// Switch a market between continuous matching and batch auctions
+    MatchingModeChange {
+        market: MarketId,
+        mode: MatchingMode,
+    },
+
+    /// Pay coins out of the treasury
+    TreasurySpend {
+        recipient: AccAddress,
+        amount: Vec<Coin>,
+    },
+}
+
+/// A new value for one parameter
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParamChange {
+    /// Dotted path into the chain parameters, such as `trading_fees.default_schedule.taker_rate`
+    pub key: String,
+
+    pub value: serde_json::Value,
+}
+
+/// A governance proposal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Proposal {
+    pub id: ProposalId,
+    pub proposer: AccAddress,
+    pub title: String,
+    pub description: String,
+    pub content: ProposalContent,
+    pub status: ProposalStatus,
+    pub submit_time: Timestamp,
+    pub deposit_end_time: Timestamp,
+    pub voting_start_time: Option<Timestamp>,
+    pub voting_end_time: Option<Timestamp>,
+    pub total_deposit: Uint128,
+    pub final_tally: Option<TallyResult>,
+
+    /// Why a passed proposal could not be executed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure: Option<String>,
+}
+
+/// Proposal status
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProposalStatus {
+    DepositPeriod,
+    VotingPeriod,
+    Passed,
+    Rejected,
+
+    /// Passed, but its content could not be executed
+    Failed,
+}
+
+/// A vote on a proposal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum VoteOption {
+    Yes,
+    No,
+    Abstain,
+    NoWithVeto,
+}
+
+/// Bonded tokens behind each vote option
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TallyResult {
+    pub yes: Uint128,
+    pub no: Uint128,
+    pub abstain: Uint128,
+    pub no_with_veto: Uint128,
+}
+
+/// Validate governance parameters, collecting every problem
+pub fn validate_params(params: &GovernanceParams, errors: &mut Vec<String>) {
+    if let Err(e) = parse_amount(&params.min_deposit) {
+        errors.push(format!("params.governance.min_deposit: {}", e));
+    }
+    if params.voting_period == 0 {
+        errors.push("params.governance.voting_period must be positive".to_string());
+    }
+    for (name, value) in [
+        ("quorum", &params.quorum),
+        ("threshold", &params.threshold),
+        ("veto_threshold", &params.veto_threshold),
+    ] {
+        if let Err(e) = parse_fraction(value) {
+            errors.push(format!("params.governance.{}: {}", name, e));
+        }
+    }
+}
+
+/// Check a new proposal and the proposer's initial deposit
+pub fn validate_submit(
+    state: &AppState,
+    proposer: &AccAddress,
+    title: &str,
+    content: &ProposalContent,
+    initial_deposit: &Uint128,
+) -> Result<()> {
+    if title.trim().is_empty() {
+        return Err(GovernanceError::InvalidProposal(
+            "title must not be empty".to_string(),
+        ));
+    }
+    validate_content(state, content)?;
+    let amount = parse_amount(initial_deposit)?;
+    portfolio::ensure_available(state, proposer, &state.params.native_token, amount)?;
+    Ok(())
+}
+
+/// Submit a proposal, opening its deposit period
+pub fn submit_proposal(
+    state: &mut AppState,
+    time: Timestamp,
+    proposer: &AccAddress,
+    title: &str,
+    description: &str,
+    content: ProposalContent,
+    initial_deposit: &Uint128,
+) -> Result<Vec<Event>> {
+    validate_submit(state, proposer, title, &content, initial_deposit)?;
+    let governance = &mut state.governance;
+    governance.last_proposal_id += 1;
+    let id = governance.last_proposal_id;
+    governance.proposals.insert(
+        id,
+        Proposal {
+            id,
+            proposer: proposer.clone(),
+            title: title.to_string(),
+            description: description.to_string(),
+            content,
+            status: ProposalStatus::DepositPeriod,
+            submit_time: time,
+            deposit_end_time: time + seconds(state.params.governance.max_deposit_period),
+            voting_start_time: None,
+            voting_end_time: None,
+            total_deposit: "0".to_string(),
+            final_tally: None,
+            failure: None,
+        },
+    );
+
+    let mut events = vec![Event::ProposalSubmitted {
+        proposal_id: id,
+        proposer: proposer.clone(),
+    }];
+    if parse_amount(initial_deposit)? > 0 {
+        events.extend(deposit(state, time, proposer, id, initial_deposit)?);
+    } else {
+        events.extend(start_voting_if_funded(state, time, id)?);
+    }
+    Ok(events)
+}
+
+/// Check a deposit to a proposal in its deposit period
+pub fn validate_deposit(
+    state: &AppState,
+    depositor: &AccAddress,
+    id: ProposalId,
+    amount: &Uint128,
+) -> Result<()> {
+    let proposal = find(state, id)?;
+    if proposal.status != ProposalStatus::DepositPeriod {
+        return Err(GovernanceError::NotAcceptingDeposits(id));
+    }
+    let amount = match parse_amount(amount)? {
+        0 => return Err(GovernanceError::ZeroDeposit),
+        amount => amount,
+    };
+    portfolio::ensure_available(state, depositor, &state.params.native_token, amount)?;
+    Ok(())
+}
+
+/// Deposit native tokens to a proposal, starting its vote once `min_deposit` is reached
+pub fn deposit(
+    state: &mut AppState,
+    time: Timestamp,
+    depositor: &AccAddress,
+    id: ProposalId,
+    amount: &Uint128,
+) -> Result<Vec<Event>> {
+    validate_deposit(state, depositor, id, amount)?;
+    let value = parse_amount(amount)?;
+    let native = state.params.native_token.clone();
+    bank::transfer(
+        state,
+        depositor,
+        &GOVERNANCE_ACCOUNT.to_string(),
+        &native,
+        value,
+    )?;
+
+    let deposited = state
+        .governance
+        .deposits
+        .entry(id)
+        .or_default()
+        .entry(depositor.clone())
+        .or_insert_with(|| "0".to_string());
+    *deposited = add(deposited, value)?;
+    let proposal = state
+        .governance
+        .proposals
+        .get_mut(&id)
+        .expect("proposal exists");
+    proposal.total_deposit = add(&proposal.total_deposit, value)?;
+
+    let mut events = vec![Event::ProposalDeposited {
+        proposal_id: id,
+        depositor: depositor.clone(),
+        amount: amount.clone(),
+    }];
+    events.extend(start_voting_if_funded(state, time, id)?);
+    Ok(events)
+}
+
+/// Check a vote on a proposal in its voting period
+pub fn validate_vote(state: &AppState, id: ProposalId) -> Result<()> {
+    if find(state, id)?.status != ProposalStatus::VotingPeriod {
+        return Err(GovernanceError::NotVoting(id));
+    }
+    Ok(())
+}
+
+/// Record a vote, replacing the voter's earlier vote
+pub fn vote(
+    state: &mut AppState,
+    voter: &AccAddress,
+    id: ProposalId,
+    option: VoteOption,
+) -> Result<Vec<Event>> {
+    validate_vote(state, id)?;
+    state
+        .governance
+        .votes
+        .entry(id)
+        .or_default()
+        .insert(voter.clone(), option);
+    Ok(vec![Event::ProposalVoted {
+        proposal_id: id,
+        voter: voter.clone(),
+        option,
+    }])
+}
+
+/// Close expired deposit periods and tally finished votes, returning the
+/// events and the proposals that passed and must be executed
+pub fn end_block(state: &mut AppState, time: Timestamp) -> Result<(Vec<Event>, Vec<ProposalId>)> {
+    let mut events = Vec::new();
+    let mut passed = Vec::new();
+    let ended: Vec<_> = state
+        .governance
+        .proposals
+        .values()
+        .filter(|proposal| match proposal.status {
+            ProposalStatus::DepositPeriod => proposal.deposit_end_time <= time,
+            ProposalStatus::VotingPeriod => proposal.voting_end_time.is_some_and(|end| end <= time),
+            _ => false,
+        })
+        .map(|proposal| (proposal.id, proposal.status))
+        .collect();
+
+    for (id, status) in ended {
+        let (status, tally, burn) = match status {
+            ProposalStatus::DepositPeriod => (ProposalStatus::Rejected, None, false),
+            _ => {
+                let (status, tally, vetoed) = decide(state, id)?;
+                (status, Some(tally), vetoed)
+            }
+        };
+        settle_deposits(state, id, burn)?;
+        state.governance.votes.remove(&id);
+        let proposal = state
+            .governance
+            .proposals
+            .get_mut(&id)
+            .expect("proposal exists");
+        proposal.status = status;
+        proposal.final_tally = tally.clone();
+        if status == ProposalStatus::Passed {
+            passed.push(id);
+        }
+        events.push(Event::ProposalTallied {
+            proposal_id: id,
+            status,
+            tally,
+        });
+    }
+    Ok((events, passed))
+}
+
+/// Mark a passed proposal whose content could not be executed as failed
+pub fn fail(state: &mut AppState, id: ProposalId, reason: String) -> Event {
+    if let Some(proposal) = state.governance.proposals.get_mut(&id) {
+        proposal.status = ProposalStatus::Failed;
+        proposal.failure = Some(reason.clone());
+    }
+    Event::ProposalFailed {
+        proposal_id: id,
+        reason,
+    }
+}
+
+/// Votes cast so far, with delegations following their validator's operator
+/// unless the delegator voted
+pub fn tally(state: &AppState, id: ProposalId) -> Result<TallyResult> {
+    Ok(count_votes(state, id)?.0)
+}
+
+/// Chain parameters with `changes` applied, rejected unless they validate
+pub fn apply_param_changes(params: &ChainParams, changes: &[ParamChange]) -> Result<ChainParams> {
+    let invalid = |message: String| GovernanceError::InvalidProposal(message);
+    let mut value = serde_json::to_value(params).map_err(|e| invalid(e.to_string()))?;
+    for change in changes {
+        let pointer: String = change
+            .key
+            .split('.')
+            .map(|segment| format!("/{}", segment.replace('~', "~0").replace('/', "~1")))
+            .collect();
+        let target = value
+            .pointer_mut(&pointer)
+            .ok_or_else(|| invalid(format!("unknown parameter '{}'", change.key)))?;
+        *target = change.value.clone();
+    }
+    let params: ChainParams = serde_json::from_value(value).map_err(|e| invalid(e.to_string()))?;
+    let mut errors = Vec::new();
+    app::validate_params(&params, &mut errors);
+    if !errors.is_empty() {
+        return Err(invalid(errors.join("; ")));
+    }
+    Ok(params)
+}
+
+/// Check that a proposal's content could be executed right now
+fn validate_content(state: &AppState, content: &ProposalContent) -> Result<()> {
+    let invalid = |message: String| Err(GovernanceError::InvalidProposal(message));
+    match content {
+        ProposalContent::Text => Ok(()),
+        ProposalContent::ParamChange { changes } => {
+            if changes.is_empty() {
+                return invalid("no parameter changes".to_string());
+            }
+            apply_param_changes(&state.params, changes).map(|_| ())
+        }
+        ProposalContent::MarketListing { market } => markets::validate_listing(state, market)
+            .map_err(|e| GovernanceError::InvalidProposal(e.to_string())),
+        ProposalContent::MarketStatusChange { market, status } => {
+            markets::validate_status_change(state, market, *status)
+                .map(|_| ())
+                .map_err(|e| GovernanceError::InvalidProposal(e.to_string()))
+        }
+        ProposalContent::MatchingModeChange { market, mode } => {
+            markets::validate_mode_change(state, market, *mode)
+                .map(|_| ())
+                .map_err(|e| GovernanceError::InvalidProposal(e.to_string()))
+        }
+        ProposalContent::TreasurySpend { recipient, amount } => {
+            if !is_account_address(recipient) {
+                return invalid(format!("invalid recipient '{}'", recipient));
+            }
+            if amount.is_empty() {
+                return invalid("no coins to spend".to_string());
+            }
+            for coin in amount {
+                if !state.assets.contains_key(&coin.denom) {
+                    return invalid(format!("unknown asset {}", coin.denom));
+                }
+                if parse_amount(&coin.amount)? == 0 {
+                    return invalid(format!("amount of {} must be positive", coin.denom));
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Open the voting period once deposits reach `min_deposit`
+fn start_voting_if_funded(
+    state: &mut AppState,
+    time: Timestamp,
+    id: ProposalId,
+) -> Result<Vec<Event>> {
+    let params = state.params.governance.clone();
+    let proposal = state
+        .governance
+        .proposals
+        .get_mut(&id)
+        .expect("proposal exists");
+    if proposal.status != ProposalStatus::DepositPeriod
+        || parse_amount(&proposal.total_deposit)? < parse_amount(&params.min_deposit)?
+    {
+        return Ok(Vec::new());
+    }
+    let voting_end_time = time + seconds(params.voting_period);
+    proposal.status = ProposalStatus::VotingPeriod;
+    proposal.voting_start_time = Some(time);
+    proposal.voting_end_time = Some(voting_end_time);
+    Ok(vec![Event::VotingStarted {
+        proposal_id: id,
+        voting_end_time,
+    }])
+}
+
+/// Outcome of a finished vote: its status, the tally and whether it was vetoed
+fn decide(state: &AppState, id: ProposalId) -> Result<(ProposalStatus, TallyResult, bool)> {
+    let params = &state.params.governance;
+    let (tally, bonded) = count_votes(state, id)?;
+    let [yes, no, abstain, veto] = [&tally.yes, &tally.no, &tally.abstain, &tally.no_with_veto]
+        .map(|amount| parse_amount(amount).map_err(Into::into).and_then(decimal));
+    let (yes, no, abstain, veto) = (yes?, no?, abstain?, veto?);
+    let voted = yes + no + abstain + veto;
+
+    if bonded.is_zero() || voted / bonded < parse_fraction(&params.quorum)? {
+        return Ok((ProposalStatus::Rejected, tally, false));
+    }
+    if voted == abstain {
+        return Ok((ProposalStatus::Rejected, tally, false));
+    }
+    if veto / voted > parse_fraction(&params.veto_threshold)? {
+        return Ok((ProposalStatus::Rejected, tally, true));
+    }
+    if yes / (voted - abstain) > parse_fraction(&params.threshold)? {
+        return Ok((ProposalStatus::Passed, tally, false));
+    }
+    Ok((ProposalStatus::Rejected, tally, false))
+}
+
+/// Tally the votes of a proposal and the bonded tokens of the active validators
+fn count_votes(state: &AppState, id: ProposalId) -> Result<(TallyResult, Decimal)> {
+    struct Voter {
+        tokens: u128,
+        delegated_votes: u128,
+        option: Option<VoteOption>,
+    }
+
+    let mut validators = HashMap::new();
+    let mut bonded: u128 = 0;
+    for (address, _) in state
+        .staking
+        .last_powers
+        .iter()
+        .filter(|(_, power)| **power > 0)
+    {
+        let Some(validator) = state.validators.get(address) else {
+            continue;
+        };
+        let tokens = parse_amount(&validator.tokens)?;
+        bonded = bonded
+            .checked_add(tokens)
+            .ok_or_else(|| NumberError::Overflow("bonded tokens".to_string()))?;
+        validators.insert(
+            address.clone(),
+            Voter {
+                tokens,
+                delegated_votes: 0,
+                option: None,
+            },
+        );
+    }
+
+    let mut totals: BTreeMap<VoteOption, u128> = BTreeMap::new();
+    let mut count = |option: VoteOption, tokens: u128| -> Result<()> {
+        let total = totals.entry(option).or_default();
+        *total = total
+            .checked_add(tokens)
+            .ok_or_else(|| NumberError::Overflow("tally".to_string()))?;
+        Ok(())
+    };
+    let empty = imbl::OrdMap::new();
+    for (voter, option) in state.governance.votes.get(&id).unwrap_or(&empty) {
+        if let Some(validator) =
+            operator_of(voter).and_then(|operator| validators.get_mut(&operator))
+        {
+            validator.option = Some(*option);
+        }
+        for (address, delegation) in state.staking.delegations.get(voter).into_iter().flatten() {
+            if let Some(validator) = validators.get_mut(address) {
+                let tokens = staking::delegation_tokens(state, delegation)?;
+                validator.delegated_votes += tokens;
+                count(*option, tokens)?;
+            }
+        }
+    }
+    for validator in validators.values() {
+        if let Some(option) = validator.option {
+            count(
+                option,
+                validator.tokens.saturating_sub(validator.delegated_votes),
+            )?;
+        }
+    }
+
+    let total = |option| totals.get(&option).copied().unwrap_or(0).to_string();
+    let tally = TallyResult {
+        yes: total(VoteOption::Yes),
+        no: total(VoteOption::No),
+        abstain: total(VoteOption::Abstain),
+        no_with_veto: total(VoteOption::NoWithVeto),
+    };
+    Ok((tally, decimal(bonded)?))
+}
+
+/// Refund or burn the deposits of a finished proposal
+fn settle_deposits(state: &mut AppState, id: ProposalId, burn: bool) -> Result<()> {
+    let deposits = state.governance.deposits.remove(&id).unwrap_or_default();
+    let native = state.params.native_token.clone();
+    let account = GOVERNANCE_ACCOUNT.to_string();
+    for (depositor, amount) in deposits {
+        let amount = parse_amount(&amount)?;
+        if burn {
+            bank::burn(state, &account, &native, amount)?;
+        } else {
+            bank::transfer(state, &account, &depositor, &native, amount)?;
+        }
+    }
+    Ok(())
+}
+
+fn find(state: &AppState, id: ProposalId) -> Result<&Proposal> {
+    state
+        .governance
+        .proposals
+        .get(&id)
+        .ok_or(GovernanceError::UnknownProposal(id))
+}
+
+fn add(amount: &Uint128, value: u128) -> Result<Uint128> {
+    Ok(parse_amount(amount)?
+        .checked_add(value)
+        .ok_or_else(|| NumberError::Overflow("deposit".to_string()))?
+        .to_string())
+}
+
+fn decimal(amount: u128) -> Result<Decimal> {
+    let amount = i128::try_from(amount).map_err(|_| NumberError::Overflow(amount.to_string()))?;
+    Ok(to_decimal(amount)?)
+}
+
+fn seconds(seconds: u64) -> chrono::TimeDelta {
+    chrono::TimeDelta::seconds(i64::try_from(seconds).unwrap_or(i64::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::test_state;
+    use crate::keys::KeyPair;
+    use chrono::TimeDelta;
+
+    const OMC: u128 = 1_000_000;
+
+    /// One active validator with 60 OMC of its own and 40 OMC delegated;
+    /// proposals need 100 OMC and vote for 100 seconds
+    fn setup() -> (AppState, AccAddress, AccAddress) {
+        let mut state = test_state(&["OMC"]);
+        state.params.governance = GovernanceParams {
+            min_deposit: (100 * OMC).to_string(),
+            max_deposit_period: 100,
+            voting_period: 100,
+            ..GovernanceParams::default()
+        };
+        let native = state.params.native_token.clone();
+        let (key, delegator) = (KeyPair::generate(), KeyPair::generate().account_address());
+        let operator = key.account_address();
+        for account in [&operator, &delegator] {
+            bank::mint(&mut state, account, &native, 1000 * OMC).unwrap();
+        }
+
+        let commission = Commission {
+            rate: "0.1".to_string(),
+            max_rate: "0.2".to_string(),
+            max_change_rate: "0.01".to_string(),
+            update_time: None,
+        };
+        let stake = (60 * OMC).to_string();
+        staking::create_validator(
+            &mut state,
+            1,
+            at(0),
+            &operator,
+            &key.public_key_hex(),
+            &commission,
+            &stake,
+        )
+        .unwrap();
+        let validator = operator_of(&operator).unwrap();
+        staking::delegate(
+            &mut state,
+            1,
+            &delegator,
+            &validator,
+            &(40 * OMC).to_string(),
+        )
+        .unwrap();
+        staking::end_block(&mut state, at(0)).unwrap();
+        (state, operator, delegator)
+    }
+
+    fn at(seconds: i64) -> Timestamp {
+        Timestamp::UNIX_EPOCH + TimeDelta::seconds(seconds)
+    }
+
+    /// A funded text proposal in its voting period
+    fn voting(state: &mut AppState, proposer: &AccAddress) -> ProposalId {
+        let deposit = (100 * OMC).to_string();
+        submit_proposal(
+            state,
+            at(0),
+            proposer,
+            "Signal",
+            "",
+            ProposalContent::Text,
+            &deposit,
+        )
+        .unwrap();
+        state.governance.last_proposal_id
+    }
+
+    fn outcome(
+        state: &mut AppState,
+        votes: &[(&AccAddress, VoteOption)],
+    ) -> (ProposalStatus, bool) {
+        let id = voting(state, &votes[0].0.clone());
+        for (voter, option) in votes {
+            vote(state, voter, id, *option).unwrap();
+        }
+        let (status, _, vetoed) = decide(state, id).unwrap();
+        (status, vetoed)
+    }
+
+    #[test]
+    fn test_proposal_failure_codes() {
+        let (mut state, operator, _) = setup();
+        let code = |state: &AppState, title: &str, content: &ProposalContent, deposit: u128| {
+            validate_submit(state, &operator, title, content, &deposit.to_string())
+                .unwrap_err()
+                .code()
+        };
+        let text = ProposalContent::Text;
+
+        assert_eq!(
+            code(&state, " ", &text, 0),
+            codes::GOVERNANCE_INVALID_PROPOSAL
+        );
+        assert_eq!(
+            code(&state, "Signal", &text, 1000 * OMC),
+            codes::PORTFOLIO_INSUFFICIENT_FUNDS
+        );
+        let no_changes = ProposalContent::ParamChange { changes: vec![] };
+        assert_eq!(
+            code(&state, "Params", &no_changes, 0),
+            codes::GOVERNANCE_INVALID_PROPOSAL
+        );
+        let unknown = ProposalContent::ParamChange {
+            changes: vec![ParamChange {
+                key: "governance.nonexistent".to_string(),
+                value: serde_json::json!(1),
+            }],
+        };
+        assert_eq!(
+            code(&state, "Params", &unknown, 0),
+            codes::GOVERNANCE_INVALID_PROPOSAL
+        );
+        let spend = ProposalContent::TreasurySpend {
+            recipient: "optimic1nobody".to_string(),
+            amount: vec![],
+        };
+        assert_eq!(
+            code(&state, "Spend", &spend, 0),
+            codes::GOVERNANCE_INVALID_PROPOSAL
+        );
+        let closing = ProposalContent::MarketStatusChange {
+            market: "ETH-USD".to_string(),
+            status: MarketStatus::Closed,
+        };
+        assert_eq!(
+            code(&state, "Close", &closing, 0),
+            codes::GOVERNANCE_INVALID_PROPOSAL
+        );
+
+        submit_proposal(
+            &mut state,
+            at(0),
+            &operator,
+            "Signal",
+            "",
+            text.clone(),
+            &"0".to_string(),
+        )
+        .unwrap();
+        let err = validate_deposit(&state, &operator, 1, &"0".to_string()).unwrap_err();
+        assert_eq!(err.code(), codes::GOVERNANCE_ZERO_DEPOSIT);
+        assert_eq!(
+            validate_vote(&state, 1).unwrap_err().code(),
+            codes::GOVERNANCE_NOT_VOTING
+        );
+        assert_eq!(
+            validate_vote(&state, 9).unwrap_err().code(),
+            codes::GOVERNANCE_UNKNOWN_PROPOSAL
+        );
+        let events = deposit(&mut state, at(1), &operator, 1, &(100 * OMC).to_string()).unwrap();
+        assert!(matches!(
+            events[1],
+            Event::VotingStarted { proposal_id: 1, .. }
+        ));
+        let err = validate_deposit(&state, &operator, 1, &"1".to_string()).unwrap_err();
+        assert_eq!(err.code(), codes::GOVERNANCE_NOT_ACCEPTING_DEPOSITS);
+    }
+
+    #[test]
+    fn test_delegators_override_their_validator() {
+        let (mut state, operator, delegator) = setup();
+        let id = voting(&mut state, &operator);
+
+        vote(&mut state, &operator, id, VoteOption::Yes).unwrap();
+        let expected = |yes: u128, no: u128| TallyResult {
+            yes: (yes * OMC).to_string(),
+            no: (no * OMC).to_string(),
+            abstain: "0".to_string(),
+            no_with_veto: "0".to_string(),
+        };
+        assert_eq!(tally(&state, id).unwrap(), expected(100, 0));
+        vote(&mut state, &delegator, id, VoteOption::No).unwrap();
+        assert_eq!(tally(&state, id).unwrap(), expected(60, 40));
+    }
+
+    #[test]
+    fn test_tally_quorum_threshold_and_veto_boundaries() {
+        let (mut state, operator, delegator) = setup();
+        let (yes, no, abstain, veto) = (
+            VoteOption::Yes,
+            VoteOption::No,
+            VoteOption::Abstain,
+            VoteOption::NoWithVeto,
+        );
+        let (passed, rejected) = (ProposalStatus::Passed, ProposalStatus::Rejected);
+
+        // 40 of 100 bonded tokens vote: quorum is met at exactly 40%
+        state.params.governance.quorum = "0.4".to_string();
+        assert_eq!(outcome(&mut state, &[(&delegator, yes)]), (passed, false));
+        state.params.governance.quorum = "0.41".to_string();
+        assert_eq!(outcome(&mut state, &[(&delegator, yes)]), (rejected, false));
+        state.params.governance.quorum = "0.334".to_string();
+
+        // Yes must exceed the threshold of the votes that did not abstain
+        assert_eq!(
+            outcome(&mut state, &[(&operator, abstain), (&delegator, yes)]),
+            (passed, false)
+        );
+        assert_eq!(
+            outcome(&mut state, &[(&operator, abstain), (&delegator, abstain)]),
+            (rejected, false)
+        );
+        state.params.governance.threshold = "0.6".to_string();
+        assert_eq!(
+            outcome(&mut state, &[(&operator, yes), (&delegator, no)]),
+            (rejected, false)
+        );
+        state.params.governance.threshold = "0.5".to_string();
+        assert_eq!(
+            outcome(&mut state, &[(&operator, yes), (&delegator, no)]),
+            (passed, false)
+        );
+
+        // A veto must exceed its threshold
+        assert_eq!(
+            outcome(&mut state, &[(&operator, yes), (&delegator, veto)]),
+            (rejected, true)
+        );
+        state.params.governance.veto_threshold = "0.4".to_string();
+        assert_eq!(
+            outcome(&mut state, &[(&operator, yes), (&delegator, veto)]),
+            (passed, false)
+        );
+    }
+
+    #[test]
+    fn test_deposits_are_refunded_unless_vetoed() {
+        let (mut state, operator, delegator) = setup();
+        let native = state.params.native_token.clone();
+        let balance = |state: &AppState, account: &AccAddress| {
+            portfolio::balance(state, account, &native).unwrap().total
+        };
+        let supply = bank::supply_of(&state, &native).unwrap();
+
+        // An underfunded proposal is rejected at the end of its deposit period
+        submit_proposal(
+            &mut state,
+            at(0),
+            &delegator,
+            "Signal",
+            "",
+            ProposalContent::Text,
+            &"5".to_string(),
+        )
+        .unwrap();
+        assert!(end_block(&mut state, at(99)).unwrap().0.is_empty());
+        let (events, passed) = end_block(&mut state, at(100)).unwrap();
+        assert!(passed.is_empty());
+        assert!(matches!(
+            events[..],
+            [Event::ProposalTallied {
+                proposal_id: 1,
+                status: ProposalStatus::Rejected,
+                tally: None
+            }]
+        ));
+        assert_eq!(balance(&state, &delegator), (960 * OMC).to_string());
+
+        let id = voting(&mut state, &operator);
+        vote(&mut state, &delegator, id, VoteOption::NoWithVeto).unwrap();
+        let (_, passed) = end_block(&mut state, at(100)).unwrap();
+        assert!(passed.is_empty());
+        assert_eq!(
+            state.governance.proposals[&id].status,
+            ProposalStatus::Rejected
+        );
+        assert!(!state.governance.votes.contains_key(&id));
+        assert_eq!(balance(&state, &operator), (840 * OMC).to_string());
+        assert_eq!(
+            bank::supply_of(&state, &native).unwrap(),
+            supply - 100 * OMC
+        );
+        bank::check_invariants(&state).unwrap();
+    }
+
+    #[test]
+    fn test_param_changes_must_validate() {
+        let (state, _, _) = setup();
+        let change = |key: &str, value: serde_json::Value| {
+            apply_param_changes(
+                &state.params,
+                &[ParamChange {
+                    key: key.to_string(),
+                    value,
+                }],
+            )
+        };
+
+        let params = change(
+            "trading_fees.default_schedule.taker_rate",
+            serde_json::json!("0.001"),
+        )
+        .unwrap();
+        assert_eq!(params.trading_fees.default_schedule.taker_rate, "0.001");
+        let err = change("governance.quorum", serde_json::json!("1.5")).unwrap_err();
+        assert!(err.to_string().contains("params.governance.quorum"));
+        let err = change("governance.voting_period", serde_json::json!("soon")).unwrap_err();
+        assert_eq!(err.code(), codes::GOVERNANCE_INVALID_PROPOSAL);
+    }
+}
@@ -0,0 +1,225 @@
+//! Node Home Initialization
+//!
+//! This module scaffolds a node home directory (configuration, genesis and
+//! keys) and provides helpers for editing the genesis before launch.
+
+use crate::app::{ChainParams, GenesisData};
+use crate::config::{NodeConfig, StorageBackend};
+use crate::keys::KeyPair;
+use crate::math::parse_amount;
+use crate::types::*;
+use anyhow::{anyhow, Context, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Node configuration file name
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// Genesis file name
+pub const GENESIS_FILE: &str = "genesis.json";
+
+/// Validator consensus key file name
+pub const VALIDATOR_KEY_FILE: &str = "priv_validator_key.json";
+
+/// Node (peer identity) key file name
+pub const NODE_KEY_FILE: &str = "node_key.json";
+
+/// Data directory name
+pub const DATA_DIR: &str = "data";
+
+/// Self-bonded tokens of the genesis validator
+const GENESIS_VALIDATOR_TOKENS: &str = "100000000";
+
+/// Result of initializing a home directory
+#[derive(Debug, Clone)]
+pub struct InitSummary {
+    pub home: PathBuf,
+    pub chain_id: String,
+    pub validator_address: ValAddress,
+    pub node_id: String,
+}
+
+/// Create a node home directory with configuration, genesis and keys
+///
+/// Refuses to touch an existing home unless `force` is set, in which case
+/// keys and genesis are regenerated and the data directory is wiped.
+pub fn init_home(home: &Path, chain_id: &str, force: bool) -> Result<InitSummary> {
+    if chain_id.trim().is_empty() {
+        return Err(anyhow!("chain_id must not be empty"));
+    }
+
+    let existing: Vec<_> = [
+        CONFIG_FILE,
+        GENESIS_FILE,
+        VALIDATOR_KEY_FILE,
+        NODE_KEY_FILE,
+        DATA_DIR,
+    ]
+    .iter()
+    .map(|name| home.join(name))
+    .filter(|path| path.exists())
+    .collect();
+    if !existing.is_empty() {
+        if !force {
+            return Err(anyhow!(
+                "{} is already initialized ({} exists); use --force to overwrite",
+                home.display(),
+                existing[0].display()
+            ));
+        }
+        let data_dir = home.join(DATA_DIR);
+        if data_dir.exists() {
+            std::fs::remove_dir_all(&data_dir)
+                .with_context(|| format!("failed to remove {}", data_dir.display()))?;
+        }
+    }
+
+    std::fs::create_dir_all(home.join(DATA_DIR))
+        .with_context(|| format!("failed to create {}", home.display()))?;
+
+    // Keys
+    let validator_key = KeyPair::generate();
+    let validator_address = validator_key.validator_address();
+    validator_key.write_file(home.join(VALIDATOR_KEY_FILE), &validator_address)?;
+
+    let node_key = KeyPair::generate();
+    let node_id = node_key.public_key_hex()[..40].to_string();
+    node_key.write_file(home.join(NODE_KEY_FILE), &node_id)?;
+
+    // Configuration
+    let mut config = NodeConfig::default();
+    config.node.chain_id = chain_id.to_string();
+    config.node.data_dir = DATA_DIR.to_string();
+    config.node.genesis_path = GENESIS_FILE.to_string();
+    config.storage.backend = StorageBackend::File;
+    let contents = format!("# Optimic Node Configuration\n\n{}", config.to_toml()?);
+    std::fs::write(home.join(CONFIG_FILE), contents)
+        .with_context(|| format!("failed to write {}", CONFIG_FILE))?;
+
+    // Genesis
+    let genesis = default_genesis(chain_id, &validator_key);
+    genesis.validate()?;
+    genesis.to_file(home.join(GENESIS_FILE))?;
+
+    info!("Initialized {} for chain {}", home.display(), chain_id);
+
+    Ok(InitSummary {
+        home: home.to_path_buf(),
+        chain_id: chain_id.to_string(),
+        validator_address,
+        node_id,
+    })
+}
+
+/// Build a genesis with a single bonded validator and default parameters
+pub fn default_genesis(chain_id: &str, validator_key: &KeyPair) -> GenesisData {
+    let validator = Validator {
+        operator_address: validator_key.validator_address(),
+        consensus_pubkey: validator_key.public_key_hex(),
+        jailed: false,
+        status: BondStatus::Bonded,
+        tokens: GENESIS_VALIDATOR_TOKENS.to_string(),
+        delegator_shares: GENESIS_VALIDATOR_TOKENS.to_string(),
+        commission: Commission {
+            rate: "0.05".to_string(),
+            max_rate: "0.20".to_string(),
+            max_change_rate: "0.01".to_string(),
+            update_time: None,
+        },
+    };
+
+    let params = ChainParams::default();
+    let native = AssetMetadata {
+        id: params.native_token.clone(),
+        display: params.native_token.clone(),
+        decimals: 6,
+        description: "Optimic native staking and fee token".to_string(),
+    };
+
+    GenesisData {
+        chain_id: chain_id.to_string(),
+        initial_height: 0,
+        params,
+        assets: vec![native],
+        accounts: vec![],
+        validators: vec![validator],
+        markets: vec![],
+        app_state: None,
+    }
+}
+
+/// Add a funded account to the genesis in `home`
+///
+/// `coins` is a comma separated list such as `1000000OMC,500ETH`.
+pub fn add_genesis_account(home: &Path, address: &str, coins: &str) -> Result<()> {
+    let path = home.join(GENESIS_FILE);
+    let mut genesis = GenesisData::from_file(&path)?;
+
+    if genesis
+        .accounts
+        .iter()
+        .any(|account| account.address == address)
+    {
+        return Err(anyhow!("account {} already exists in genesis", address));
+    }
+
+    let account_number = genesis
+        .accounts
+        .iter()
+        .map(|account| account.account_number + 1)
+        .max()
+        .unwrap_or(0);
+    genesis.accounts.push(Account {
+        address: address.to_string(),
+        public_key: None,
+        account_number,
+        sequence: 0,
+        balances: parse_coins(coins)?,
+    });
+
+    genesis.validate()?;
+    genesis.to_file(&path)?;
+    info!("Added genesis account {}", address);
+    Ok(())
+}
+
+/// Add a market to the genesis in `home`
+pub fn add_genesis_market(home: &Path, market: Market) -> Result<()> {
+    let path = home.join(GENESIS_FILE);
+    let mut genesis = GenesisData::from_file(&path)?;
+
+    let market_id = market.id.clone();
+    genesis.markets.push(market);
+
+    genesis.validate()?;
+    genesis.to_file(&path)?;
+    info!("Added genesis market {}", market_id);
+    Ok(())
+}
+
+/// Parse a coin list such as `1000000OMC,500ETH`
+pub fn parse_coins(coins: &str) -> Result<HashMap<AssetId, Uint128>> {
+    let mut balances = HashMap::new();
+    for coin in coins
+        .split(',')
+        .map(str::trim)
+        .filter(|coin| !coin.is_empty())
+    {
+        let split = coin
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(|| anyhow!("coin '{}' is missing a denomination", coin))?;
+        let (amount, denom) = coin.split_at(split);
+        parse_amount(amount).with_context(|| format!("invalid coin '{}'", coin))?;
+        if balances
+            .insert(denom.to_string(), amount.to_string())
+            .is_some()
+        {
+            return Err(anyhow!("denomination {} listed twice", denom));
+        }
+    }
+    if balances.is_empty() {
+        return Err(anyhow!("no coins given"));
+    }
+    Ok(balances)
+}
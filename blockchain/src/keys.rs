@@ -0,0 +1,173 @@
+//! Key Management Module
+//!
+//! This module handles ed25519 key generation, key files and the derivation
+//! of account and validator addresses from public keys.
+
+use crate::types::{AccAddress, ValAddress};
+use anyhow::{anyhow, Context, Result};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::Path;
+
+/// Prefix for account addresses
+pub const ACCOUNT_PREFIX: &str = "optimic1";
+
+/// Prefix for validator operator addresses
+pub const VALIDATOR_PREFIX: &str = "optimicval1";
+
+/// Number of public key hash bytes encoded in an address
+const ADDRESS_LENGTH: usize = 20;
+
+/// An ed25519 key pair
+#[derive(Clone)]
+pub struct KeyPair {
+    signing_key: SigningKey,
+}
+
+/// On-disk representation of a key pair
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyFile {
+    #[serde(rename = "type")]
+    pub key_type: String,
+    pub address: String,
+    pub public_key: String,
+    pub private_key: String,
+}
+
+impl KeyPair {
+    /// Generate a new random key pair
+    pub fn generate() -> Self {
+        let mut seed = [0u8; 32];
+        OsRng.fill_bytes(&mut seed);
+        Self {
+            signing_key: SigningKey::from_bytes(&seed),
+        }
+    }
+
+    /// Restore a key pair from a hex-encoded 32-byte secret
+    pub fn from_secret_hex(secret: &str) -> Result<Self> {
+        let bytes: [u8; 32] = hex::decode(secret)?
+            .try_into()
+            .map_err(|_| anyhow!("private key must be 32 bytes"))?;
+        Ok(Self {
+            signing_key: SigningKey::from_bytes(&bytes),
+        })
+    }
+
+    /// Hex-encoded public key
+    pub fn public_key_hex(&self) -> String {
+        hex::encode(self.signing_key.verifying_key().as_bytes())
+    }
+
+    /// Hex-encoded private key
+    pub fn secret_hex(&self) -> String {
+        hex::encode(self.signing_key.to_bytes())
+    }
+
+    /// Account address controlled by this key
+    pub fn account_address(&self) -> AccAddress {
+        derive_address(ACCOUNT_PREFIX, self.signing_key.verifying_key().as_bytes())
+    }
+
+    /// Validator operator address controlled by this key
+    pub fn validator_address(&self) -> ValAddress {
+        derive_address(
+            VALIDATOR_PREFIX,
+            self.signing_key.verifying_key().as_bytes(),
+        )
+    }
+
+    /// Sign a message, returning the hex-encoded signature
+    pub fn sign_hex(&self, message: &[u8]) -> String {
+        hex::encode(self.signing_key.sign(message).to_bytes())
+    }
+
+    /// Write the key pair to a JSON key file labelled with `address`
+    pub fn write_file(&self, path: impl AsRef<Path>, address: &str) -> Result<()> {
+        let path = path.as_ref();
+        let file = KeyFile {
+            key_type: "ed25519".to_string(),
+            address: address.to_string(),
+            public_key: self.public_key_hex(),
+            private_key: self.secret_hex(),
+        };
+        let contents = serde_json::to_string_pretty(&file)?;
+        write_private(path, contents.as_bytes())
+            .with_context(|| format!("failed to write key file {}", path.display()))
+    }
+
+    /// Read a key pair from a JSON key file
+    pub fn read_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read key file {}", path.display()))?;
+        let file: KeyFile = serde_json::from_str(&contents)
+            .with_context(|| format!("failed to parse key file {}", path.display()))?;
+        Self::from_secret_hex(&file.private_key)
+    }
+}
+
+/// Write a file only its owner can read, since it holds a private key
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // The mode only applies to new files; tighten an existing one too
+        if path.exists() {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    options.open(path)?.write_all(contents)
+}
+
+/// Derive an address as `prefix` followed by the hex-encoded truncated
+/// SHA-256 hash of the public key
+pub fn derive_address(prefix: &str, public_key: &[u8]) -> String {
+    let hash = Sha256::digest(public_key);
+    format!("{}{}", prefix, hex::encode(&hash[..ADDRESS_LENGTH]))
+}
+
+/// Validator operator address controlled by the key of `account`
+pub fn operator_of(account: &AccAddress) -> Option<ValAddress> {
+    account
+        .strip_prefix(ACCOUNT_PREFIX)
+        .map(|hash| format!("{}{}", VALIDATOR_PREFIX, hash))
+}
+
+/// Account address controlled by the key of `operator`
+pub fn account_of(operator: &ValAddress) -> Option<AccAddress> {
+    operator
+        .strip_prefix(VALIDATOR_PREFIX)
+        .map(|hash| format!("{}{}", ACCOUNT_PREFIX, hash))
+}
+
+/// Whether `address` is a well-formed account address
+pub fn is_account_address(address: &str) -> bool {
+    address.strip_prefix(ACCOUNT_PREFIX).is_some_and(|hash| {
+        hash.len() == ADDRESS_LENGTH * 2
+            && hash
+                .bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+    })
+}
+
+/// Verify a hex-encoded ed25519 signature against a hex-encoded public key
+pub fn verify_signature(public_key: &str, message: &[u8], signature: &str) -> Result<()> {
+    let key_bytes: [u8; 32] = hex::decode(public_key)?
+        .try_into()
+        .map_err(|_| anyhow!("public key must be 32 bytes"))?;
+    let sig_bytes: [u8; 64] = hex::decode(signature)?
+        .try_into()
+        .map_err(|_| anyhow!("signature must be 64 bytes"))?;
+
+    let key = VerifyingKey::from_bytes(&key_bytes)?;
+    key.verify(message, &Signature::from_bytes(&sig_bytes))
+        .map_err(|_| anyhow!("signature verification failed"))
+}
@@ -1,33 +1,52 @@
 //! Optimic Core - High-Performance Layer 1 Blockchain for Options Trading
-//! 
+//!
 //! This crate implements the core blockchain functionality for the Optimic protocol,
 //! including consensus, state management, trading engine, and options infrastructure.
 
 pub mod abci;
 pub mod app;
+pub mod bank;
+pub mod bridge;
+pub mod codes;
+pub mod collateral;
+pub mod config;
 pub mod consensus;
+pub mod distribution;
+pub mod events;
+pub mod fees;
+pub mod governance;
+pub mod init;
+pub mod keys;
+pub mod markets;
+pub mod math;
+pub mod mempool;
+pub mod node;
+pub mod options;
+pub mod oracle;
+pub mod portfolio;
+pub mod query;
+pub mod rpc;
+pub mod slashing;
+pub mod staking;
 pub mod state;
 pub mod storage;
-pub mod types;
+pub mod stream;
 pub mod trading;
-pub mod options;
-pub mod collateral;
+pub mod tx;
+pub mod types;
 
 // Re-export core types for external use
 pub use app::OptimicApp;
 pub use types::*;
 
 use anyhow::Result;
-use tracing::{info, warn};
-
-/// Initialize the Optimic blockchain node
-pub async fn init_node() -> Result<()> {
-    info!("Initializing Optimic blockchain node...");
-    
-    // TODO: Implement node initialization logic
-    warn!("Node initialization not yet implemented");
-    
-    Ok(())
+use std::path::Path;
+use tracing::info;
+
+/// Initialize the Optimic blockchain node home directory
+pub fn init_node(home: &Path, chain_id: &str, force: bool) -> Result<init::InitSummary> {
+    info!("Initializing Optimic blockchain node in {}", home.display());
+    init::init_home(home, chain_id, force)
 }
 
 /// Version information
@@ -38,8 +57,35 @@ pub const NAME: &str = env!("CARGO_PKG_NAME");
 mod tests {
     use super::*;
 
-    #[tokio::test]
-    async fn test_node_init() {
-        assert!(init_node().await.is_ok());
+    #[test]
+    fn test_node_init() {
+        let home = tempfile::tempdir().unwrap();
+        let summary = init_node(home.path(), "optimic-test", false).unwrap();
+        assert!(summary
+            .validator_address
+            .starts_with(keys::VALIDATOR_PREFIX));
+
+        // An initialized home is never overwritten by accident
+        assert!(init_node(home.path(), "optimic-test", false).is_err());
+        assert!(init_node(home.path(), "optimic-test", true).is_ok());
+
+        // Private keys are readable by their owner only
+        #[cfg(unix)]
+        for file in [init::VALIDATOR_KEY_FILE, init::NODE_KEY_FILE] {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(home.path().join(file))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600, "{} has mode {:o}", file, mode);
+        }
+
+        init::add_genesis_account(home.path(), "optimic1alice", "1000OMC,5ETH").unwrap();
+        assert!(init::add_genesis_account(home.path(), "optimic1alice", "1OMC").is_err());
+
+        let config = config::NodeConfig::from_file(home.path().join(init::CONFIG_FILE)).unwrap();
+        let genesis = app::GenesisData::from_file(&config.node.genesis_path).unwrap();
+        assert_eq!(genesis.chain_id, "optimic-test");
+        assert_eq!(genesis.accounts[0].balances["ETH"], "5");
     }
 }
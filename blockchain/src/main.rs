@@ -1,8 +1,9 @@
 //! Optimic Core Library Test
-//! 
+//!
 //! This is a simple test binary to verify the blockchain core functionality
 
-use optimic_core::{init_node, VERSION, NAME};
+use optimic_core::keys::KeyPair;
+use optimic_core::{NAME, VERSION};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -14,10 +15,11 @@ async fn main() -> anyhow::Result<()> {
     println!("🚀 {} v{}", NAME, VERSION);
     println!("Initializing Optimic blockchain core...");
 
-    // Test node initialization
-    init_node().await?;
+    // Check key generation in memory; nothing is written to disk
+    let key = KeyPair::generate();
+    println!("Validator: {}", key.validator_address());
 
     println!("✅ Optimic core initialized successfully!");
-    
+
     Ok(())
 }
@@ -0,0 +1,450 @@
+//! Markets Module
+//!
+//! This module controls the lifecycle of markets. New markets are listed
+//! either by the market admin named in the chain parameters or by a
+//! governance proposal; both can suspend, resume and close a market the
+//! same way.
+//!
+//! The same two paths switch a market between continuous matching and
+//! batch auctions; leaving batch mode clears the pending auction first.
+//!
+//! A suspended market is cancel-only: its resting orders stay on the book
+//! and can be cancelled, but no order is accepted or matched until it is
+//! resumed. Closing a market is final; every order left on its book is
+//! cancelled and the balances it locked are released. This halts a broken
+//! market without halting the chain.
+//!
+//! New orders must respect the market's grid: prices on the tick size,
+//! quantities of at least the minimum order size and in whole lots. Limit
+//! prices must also lie within `price_band` of the mark price, so a
+//! mistyped price cannot sweep the book; market and stop orders are held
+//! to the same band when they match.
+
+use crate::app::{self, AppState};
+use crate::keys::is_account_address;
+use crate::math::{parse_amount, parse_decimal, parse_fraction};
+use crate::portfolio;
+use crate::trading::{PriceBand, TradingError};
+use crate::types::*;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+type Result<T> = std::result::Result<T, TradingError>;
+
+/// Market lifecycle and order entry parameters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketParams {
+    /// Account allowed to list markets and change their status besides
+    /// governance; none leaves it to governance alone
+    #[serde(default)]
+    pub admin: Option<AccAddress>,
+
+    /// Largest relative distance of a limit price from the mark price;
+    /// zero turns the check off
+    #[serde(default = "default_price_band")]
+    pub price_band: Price,
+}
+
+impl Default for MarketParams {
+    fn default() -> Self {
+        Self {
+            admin: None,
+            price_band: default_price_band(),
+        }
+    }
+}
+
+fn default_price_band() -> Price {
+    "0.1".to_string() // 10%
+}
+
+/// Validate market parameters, collecting every problem
+pub fn validate_params(params: &MarketParams, errors: &mut Vec<String>) {
+    if let Some(admin) = &params.admin {
+        if !is_account_address(admin) {
+            errors.push(format!("params.markets.admin: invalid address '{}'", admin));
+        }
+    }
+    if let Err(e) = parse_fraction(&params.price_band) {
+        errors.push(format!("params.markets.price_band: {}", e));
+    }
+}
+
+/// Check that the sender is the market admin
+pub fn authorize(state: &AppState, sender: &AccAddress) -> Result<()> {
+    match &state.params.markets.admin {
+        Some(admin) if admin == sender => Ok(()),
+        _ => Err(TradingError::NotMarketAdmin(sender.clone())),
+    }
+}
+
+/// Check that a market can be listed
+pub fn validate_listing(state: &AppState, market: &Market) -> Result<()> {
+    if state.markets.contains_key(&market.id) {
+        return Err(TradingError::MarketExists(market.id.clone()));
+    }
+    for asset in [&market.base_asset, &market.quote_asset] {
+        if !state.assets.contains_key(asset) {
+            return Err(TradingError::InvalidMarket(format!(
+                "unknown asset {}",
+                asset
+            )));
+        }
+    }
+    if market.status != MarketStatus::Active {
+        return Err(TradingError::InvalidMarket(format!(
+            "market '{}' must be listed {}",
+            market.id,
+            MarketStatus::Active
+        )));
+    }
+    let mut errors = Vec::new();
+    app::validate_markets(std::iter::once(market), &mut errors);
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(TradingError::InvalidMarket(errors.join("; "))),
+    }
+}
+
+/// Check that a market can move to `status`, returning its current status
+///
+/// Active and suspended markets switch between each other and can close;
+/// a closed market stays closed.
+pub fn validate_status_change(
+    state: &AppState,
+    market_id: &MarketId,
+    status: MarketStatus,
+) -> Result<MarketStatus> {
+    let market = state
+        .markets
+        .get(market_id)
+        .ok_or_else(|| TradingError::UnknownMarket(market_id.clone()))?;
+    let allowed = matches!(
+        (market.status, status),
+        (MarketStatus::Active, MarketStatus::Suspended)
+            | (MarketStatus::Suspended, MarketStatus::Active)
+            | (
+                MarketStatus::Active | MarketStatus::Suspended,
+                MarketStatus::Closed
+            )
+    );
+    if !allowed {
+        return Err(TradingError::InvalidStatusChange {
+            market: market_id.clone(),
+            from: market.status,
+            to: status,
+        });
+    }
+    Ok(market.status)
+}
+
+/// Check that a market can switch to matching `mode`, returning its current mode
+///
+/// Only active markets switch, so the auction run when leaving batch mode
+/// clears like any other.
+pub fn validate_mode_change(
+    state: &AppState,
+    market_id: &MarketId,
+    mode: MatchingMode,
+) -> Result<MatchingMode> {
+    let market = state
+        .markets
+        .get(market_id)
+        .ok_or_else(|| TradingError::UnknownMarket(market_id.clone()))?;
+    if market.status != MarketStatus::Active {
+        return Err(TradingError::MarketNotActive {
+            market: market_id.clone(),
+            status: market.status,
+        });
+    }
+    if market.matching_mode == mode {
+        return Err(TradingError::InvalidMarket(format!(
+            "market '{}' already uses {} matching",
+            market_id, mode
+        )));
+    }
+    Ok(market.matching_mode)
+}
+
+/// Check a new order against the market's tick size, minimum and lot size
+/// and price band
+///
+/// Market orders carry no price, so only their quantity is checked; the
+/// matcher holds them and stop orders to the band instead. The band is
+/// skipped while the market has no mark price.
+pub fn validate_order(
+    state: &AppState,
+    market: &Market,
+    order_type: &OrderType,
+    quantity: u128,
+    price: &Option<Price>,
+) -> Result<()> {
+    let min_order_size = parse_amount(&market.min_order_size)?;
+    if quantity < min_order_size {
+        return Err(TradingError::BelowMinSize {
+            quantity: quantity.to_string(),
+            min_order_size: market.min_order_size.clone(),
+        });
+    }
+    let lot_size = parse_amount(&market.lot_size)?;
+    if lot_size > 0 && !quantity.is_multiple_of(lot_size) {
+        return Err(TradingError::OffLot {
+            quantity: quantity.to_string(),
+            lot_size: market.lot_size.clone(),
+        });
+    }
+
+    let (OrderType::Limit | OrderType::StopLimit, Some(price)) = (order_type, price) else {
+        return Ok(());
+    };
+    let limit = parse_decimal(price)?;
+    let tick_size = parse_decimal(&market.tick_size)?;
+    if tick_size > Decimal::ZERO && !(limit % tick_size).is_zero() {
+        return Err(TradingError::OffTick {
+            price: price.clone(),
+            tick_size: market.tick_size.clone(),
+        });
+    }
+
+    let Some(PriceBand { low, high }) = price_band(state, market)? else {
+        return Ok(());
+    };
+    if limit < low || limit > high {
+        return Err(TradingError::OutsidePriceBand {
+            price: price.clone(),
+            low: low.normalize().to_string(),
+            high: high.normalize().to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Prices within `price_band` of the market's mark price
+///
+/// `None` while the market has no mark price or the band is turned off.
+pub fn price_band(state: &AppState, market: &Market) -> Result<Option<PriceBand>> {
+    let band = parse_fraction(&state.params.markets.price_band)?;
+    let Some(mark) = portfolio::mark_price(state, market)?.filter(|_| band > Decimal::ZERO) else {
+        return Ok(None);
+    };
+    Ok(Some(PriceBand {
+        low: mark * (Decimal::ONE - band),
+        high: mark * (Decimal::ONE + band),
+    }))
+}
+
+/// Price bands of every market that has one
+pub fn price_bands(state: &AppState) -> Result<BTreeMap<MarketId, PriceBand>> {
+    let mut bands = BTreeMap::new();
+    for market in state.markets.values() {
+        if let Some(band) = price_band(state, market)? {
+            bands.insert(market.id.clone(), band);
+        }
+    }
+    Ok(bands)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::test_state;
+    use crate::codes;
+    use crate::keys::KeyPair;
+
+    fn market() -> Market {
+        Market {
+            id: "ETH-USD".to_string(),
+            base_asset: "ETH".to_string(),
+            quote_asset: "USD".to_string(),
+            min_order_size: "10".to_string(),
+            lot_size: "5".to_string(),
+            tick_size: "0.5".to_string(),
+            market_type: MarketType::Spot,
+            status: MarketStatus::Active,
+            matching_mode: MatchingMode::Continuous,
+        }
+    }
+
+    #[test]
+    fn test_only_the_admin_is_authorized() {
+        let mut state = test_state(&["ETH", "USD"]);
+        let (admin, other) = (
+            KeyPair::generate().account_address(),
+            KeyPair::generate().account_address(),
+        );
+
+        assert_eq!(
+            authorize(&state, &admin).unwrap_err().code(),
+            codes::TRADING_NOT_MARKET_ADMIN
+        );
+        state.params.markets.admin = Some(admin.clone());
+        authorize(&state, &admin).unwrap();
+        assert_eq!(
+            authorize(&state, &other).unwrap_err().code(),
+            codes::TRADING_NOT_MARKET_ADMIN
+        );
+    }
+
+    #[test]
+    fn test_listing_failure_codes() {
+        let mut state = test_state(&["ETH", "USD"]);
+        let code =
+            |state: &AppState, market: &Market| validate_listing(state, market).unwrap_err().code();
+
+        validate_listing(&state, &market()).unwrap();
+        let unknown_asset = Market {
+            quote_asset: "EUR".to_string(),
+            ..market()
+        };
+        assert_eq!(code(&state, &unknown_asset), codes::TRADING_INVALID_MARKET);
+        let suspended = Market {
+            status: MarketStatus::Suspended,
+            ..market()
+        };
+        assert_eq!(code(&state, &suspended), codes::TRADING_INVALID_MARKET);
+        let off_lot_minimum = Market {
+            min_order_size: "12".to_string(),
+            ..market()
+        };
+        assert_eq!(
+            code(&state, &off_lot_minimum),
+            codes::TRADING_INVALID_MARKET
+        );
+        let zero_tick = Market {
+            tick_size: "0".to_string(),
+            ..market()
+        };
+        assert_eq!(code(&state, &zero_tick), codes::TRADING_INVALID_MARKET);
+
+        state.markets.insert("ETH-USD".to_string(), market());
+        assert_eq!(code(&state, &market()), codes::TRADING_MARKET_EXISTS);
+    }
+
+    #[test]
+    fn test_status_changes() {
+        use MarketStatus::*;
+
+        let mut state = test_state(&["ETH", "USD"]);
+        let err = validate_status_change(&state, &"ETH-USD".to_string(), Suspended).unwrap_err();
+        assert_eq!(err.code(), codes::TRADING_UNKNOWN_MARKET);
+
+        let allowed = [
+            (Active, Suspended),
+            (Suspended, Active),
+            (Active, Closed),
+            (Suspended, Closed),
+        ];
+        for from in [Active, Suspended, Closed] {
+            state.markets.insert(
+                "ETH-USD".to_string(),
+                Market {
+                    status: from,
+                    ..market()
+                },
+            );
+            for to in [Active, Suspended, Closed] {
+                match validate_status_change(&state, &"ETH-USD".to_string(), to) {
+                    Ok(current) => assert!(current == from && allowed.contains(&(from, to))),
+                    Err(e) => {
+                        assert!(!allowed.contains(&(from, to)));
+                        assert_eq!(e.code(), codes::TRADING_INVALID_STATUS_CHANGE);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_order_size_and_tick() {
+        let state = test_state(&["ETH", "USD"]);
+        let check = |order_type: OrderType, quantity: u128, price: Option<&str>| {
+            validate_order(
+                &state,
+                &market(),
+                &order_type,
+                quantity,
+                &price.map(str::to_string),
+            )
+            .map_err(|e| e.code())
+        };
+
+        assert_eq!(check(OrderType::Limit, 10, Some("100.5")), Ok(()));
+        assert_eq!(
+            check(OrderType::Limit, 5, Some("100")),
+            Err(codes::TRADING_BELOW_MIN_SIZE)
+        );
+        assert_eq!(
+            check(OrderType::Limit, 12, Some("100")),
+            Err(codes::TRADING_OFF_LOT)
+        );
+        assert_eq!(
+            check(OrderType::Limit, 15, Some("100.25")),
+            Err(codes::TRADING_OFF_TICK)
+        );
+        assert_eq!(
+            check(OrderType::StopLimit, 15, Some("100.25")),
+            Err(codes::TRADING_OFF_TICK)
+        );
+
+        // Only limit prices are held to the grid
+        assert_eq!(check(OrderType::Market, 15, None), Ok(()));
+        assert_eq!(
+            check(OrderType::Market, 12, None),
+            Err(codes::TRADING_OFF_LOT)
+        );
+        assert_eq!(check(OrderType::Stop, 15, Some("100.25")), Ok(()));
+    }
+
+    #[test]
+    fn test_price_band_boundaries() {
+        let mut state = test_state(&["ETH", "USD"]);
+        let check = |state: &AppState, price: &str| {
+            validate_order(
+                state,
+                &market(),
+                &OrderType::Limit,
+                10,
+                &Some(price.to_string()),
+            )
+            .map_err(|e| e.code())
+        };
+
+        // Without a mark price any price on the grid is accepted
+        assert_eq!(check(&state, "1000"), Ok(()));
+
+        state
+            .mark_prices
+            .insert("ETH-USD".to_string(), "100".to_string());
+        for price in ["90", "100", "110"] {
+            assert_eq!(check(&state, price), Ok(()));
+        }
+        for price in ["89.5", "110.5"] {
+            assert_eq!(check(&state, price), Err(codes::TRADING_OUTSIDE_PRICE_BAND));
+        }
+        validate_order(&state, &market(), &OrderType::Market, 10, &None).unwrap();
+
+        state.params.markets.price_band = "0".to_string();
+        assert_eq!(check(&state, "1000"), Ok(()));
+    }
+
+    #[test]
+    fn test_params_validation() {
+        let mut params = MarketParams::default();
+        let mut errors = Vec::new();
+        validate_params(&params, &mut errors);
+        assert!(errors.is_empty());
+
+        params.admin = Some("nobody".to_string());
+        validate_params(&params, &mut errors);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("params.markets.admin"));
+
+        params.admin = None;
+        params.price_band = "1.5".to_string();
+        errors.clear();
+        validate_params(&params, &mut errors);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("params.markets.price_band"));
+    }
+}
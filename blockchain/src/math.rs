@@ -0,0 +1,164 @@
+//! Numeric Helpers
+//!
+//! Amounts and prices are carried as strings in the protocol types to avoid
+//! floating point precision issues. This module parses them into exact
+//! integer and decimal values for arithmetic.
+
+use crate::codes;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// A string that is not a valid number of the expected kind
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum NumberError {
+    #[error("invalid amount '{0}'")]
+    InvalidAmount(String),
+
+    #[error("amount '{0}' out of range")]
+    AmountOutOfRange(String),
+
+    #[error("invalid decimal '{0}'")]
+    InvalidDecimal(String),
+
+    #[error("fraction '{0}' must be between 0 and 1")]
+    FractionOutOfRange(String),
+
+    #[error("invalid signed integer '{0}'")]
+    InvalidSigned(String),
+
+    #[error("arithmetic overflow in {0}")]
+    Overflow(String),
+}
+
+impl NumberError {
+    /// Stable result code, see [`crate::codes`]
+    pub fn code(&self) -> u32 {
+        codes::INVALID_NUMBER
+    }
+}
+
+/// Parse a token amount (an unsigned integer in base units)
+pub fn parse_amount(value: &str) -> Result<u128, NumberError> {
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(NumberError::InvalidAmount(value.to_string()));
+    }
+    value
+        .parse::<u128>()
+        .map_err(|_| NumberError::AmountOutOfRange(value.to_string()))
+}
+
+/// Parse a decimal value such as a price, rate or ratio
+pub fn parse_decimal(value: &str) -> Result<Decimal, NumberError> {
+    Decimal::from_str(value.trim()).map_err(|_| NumberError::InvalidDecimal(value.to_string()))
+}
+
+/// Parse a fraction that must lie within [0, 1]
+pub fn parse_fraction(value: &str) -> Result<Decimal, NumberError> {
+    let fraction = parse_decimal(value)?;
+    if fraction < Decimal::ZERO || fraction > Decimal::ONE {
+        return Err(NumberError::FractionOutOfRange(value.to_string()));
+    }
+    Ok(fraction)
+}
+
+/// Parse a signed integer such as a position size or P&L
+pub fn parse_signed(value: &str) -> Result<i128, NumberError> {
+    value
+        .parse::<i128>()
+        .map_err(|_| NumberError::InvalidSigned(value.to_string()))
+}
+
+/// Convert an integer amount to a decimal
+pub fn to_decimal(value: impl Into<i128>) -> Result<Decimal, NumberError> {
+    let value = value.into();
+    Decimal::try_from_i128_with_scale(value, 0)
+        .map_err(|_| NumberError::Overflow(value.to_string()))
+}
+
+/// Round a decimal down to an integer
+pub fn floor_signed(value: Decimal) -> Result<i128, NumberError> {
+    value
+        .floor()
+        .to_i128()
+        .ok_or_else(|| NumberError::Overflow(value.to_string()))
+}
+
+/// `a * b / c` rounded down, exact even when `a * b` does not fit in a u128
+pub fn mul_div(a: u128, b: u128, c: u128) -> Result<u128, NumberError> {
+    let overflow = || NumberError::Overflow(format!("{} * {} / {}", a, b, c));
+    if c == 0 {
+        return Err(overflow());
+    }
+    if let Some(product) = a.checked_mul(b) {
+        return Ok(product / c);
+    }
+
+    // 256-bit product as (hi, lo) from 64-bit halves
+    const LOW: u128 = u64::MAX as u128;
+    let (a1, a0, b1, b0) = (a >> 64, a & LOW, b >> 64, b & LOW);
+    let (mid, mid_carry) = (a1 * b0).overflowing_add(a0 * b1);
+    let (lo, lo_carry) = (a0 * b0).overflowing_add(mid << 64);
+    let hi = a1 * b1 + (mid >> 64) + ((mid_carry as u128) << 64) + lo_carry as u128;
+    if hi >= c {
+        return Err(overflow());
+    }
+
+    // Long division, one bit of `lo` at a time; the remainder stays below `c`
+    let (mut remainder, mut quotient) = (hi, 0u128);
+    for bit in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((lo >> bit) & 1);
+        quotient <<= 1;
+        if carry == 1 || remainder >= c {
+            remainder = remainder.wrapping_sub(c);
+            quotient |= 1;
+        }
+    }
+    Ok(quotient)
+}
+
+/// Quote value of `quantity` base units at `price`, rounded down
+pub fn notional(quantity: u128, price: Decimal) -> Result<u128, NumberError> {
+    exact_notional(quantity, price).and_then(|value| to_amount(value.floor(), quantity, price))
+}
+
+/// Quote value of `quantity` base units at `price`, rounded up
+///
+/// What a buyer pays, so that no fill, however small, is free.
+pub fn notional_ceil(quantity: u128, price: Decimal) -> Result<u128, NumberError> {
+    exact_notional(quantity, price).and_then(|value| to_amount(value.ceil(), quantity, price))
+}
+
+/// Quote value of a fill of `quantity` at `price` for an order whose earlier
+/// fills are worth exactly `filled`, and the new exact total
+///
+/// Rounding up applies to the running total, so all fills of an order
+/// together cost their exact value rounded up once: no more than a lock of
+/// [`notional_ceil`] at the limit price, with every whole quote unit going
+/// to the fill that completes it.
+pub fn fill_value(
+    filled: Decimal,
+    quantity: u128,
+    price: Decimal,
+) -> Result<(u128, Decimal), NumberError> {
+    let total = filled
+        .checked_add(exact_notional(quantity, price)?)
+        .ok_or_else(|| NumberError::Overflow(format!("{} + {} * {}", filled, quantity, price)))?;
+    let paid = to_amount(filled.ceil(), quantity, price)?;
+    let owed = to_amount(total.ceil(), quantity, price)?;
+    Ok((owed - paid, total))
+}
+
+fn exact_notional(quantity: u128, price: Decimal) -> Result<Decimal, NumberError> {
+    let overflow = || NumberError::Overflow(format!("{} * {}", quantity, price));
+    let value = i128::try_from(quantity).map_err(|_| overflow())?;
+    to_decimal(value)?.checked_mul(price).ok_or_else(overflow)
+}
+
+fn to_amount(value: Decimal, quantity: u128, price: Decimal) -> Result<u128, NumberError> {
+    value
+        .to_u128()
+        .ok_or_else(|| NumberError::Overflow(format!("{} * {}", quantity, price)))
+}
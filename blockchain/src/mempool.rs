@@ -0,0 +1,466 @@
+//! Mempool Module
+//!
+//! This module holds transactions that passed `check_tx` until they are
+//! included in a block. Transactions from one sender are kept in sequence
+//! order, cancellations are proposed ahead of new orders, and the pool is
+//! bounded in both count and bytes.
+
+use crate::app::OptimicApp;
+use crate::tx::{tx_hash, TxError, TxHash};
+use crate::types::AccAddress;
+use anyhow::Result;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use tracing::debug;
+
+/// Number of full blocks worth of bytes the mempool holds
+const MAX_BLOCKS_OF_BYTES: u64 = 16;
+
+/// Mempool limits
+#[derive(Debug, Clone)]
+pub struct MempoolConfig {
+    /// Maximum number of transactions held
+    pub max_txs: usize,
+
+    /// Maximum total size of held transactions in bytes
+    pub max_bytes: u64,
+
+    /// Maximum size of a single transaction in bytes
+    pub max_tx_bytes: u64,
+}
+
+impl MempoolConfig {
+    /// Derive byte limits from the chain's maximum block size
+    pub fn new(max_txs: usize, max_block_size: u64) -> Self {
+        Self {
+            max_txs,
+            max_bytes: max_block_size.saturating_mul(MAX_BLOCKS_OF_BYTES),
+            max_tx_bytes: max_block_size,
+        }
+    }
+}
+
+/// Block inclusion priority
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TxPriority {
+    /// Transactions that add to or trade against the book
+    Normal,
+    /// Transactions that only cancel orders
+    Cancel,
+}
+
+/// A transaction waiting in the mempool
+#[derive(Debug, Clone)]
+struct MempoolTx {
+    bytes: Vec<u8>,
+    sender: AccAddress,
+    sequence: u64,
+    priority: TxPriority,
+    timeout_height: u64,
+    arrival: u64,
+}
+
+impl MempoolTx {
+    fn expired_at(&self, height: u64) -> bool {
+        crate::tx::timed_out(self.timeout_height, height)
+    }
+}
+
+/// Transaction mempool
+#[derive(Debug)]
+pub struct Mempool {
+    config: MempoolConfig,
+    txs: HashMap<TxHash, MempoolTx>,
+    by_sender: HashMap<AccAddress, BTreeMap<u64, TxHash>>,
+    total_bytes: u64,
+    next_arrival: u64,
+}
+
+impl Mempool {
+    /// Create an empty mempool
+    pub fn new(config: MempoolConfig) -> Self {
+        Self {
+            config,
+            txs: HashMap::new(),
+            by_sender: HashMap::new(),
+            total_bytes: 0,
+            next_arrival: 0,
+        }
+    }
+
+    /// Number of pending transactions
+    pub fn len(&self) -> usize {
+        self.txs.len()
+    }
+
+    /// Whether the mempool is empty
+    pub fn is_empty(&self) -> bool {
+        self.txs.is_empty()
+    }
+
+    /// Total size of pending transactions in bytes
+    pub fn size_bytes(&self) -> u64 {
+        self.total_bytes
+    }
+
+    /// Whether a transaction is pending
+    pub fn contains(&self, hash: &TxHash) -> bool {
+        self.txs.contains_key(hash)
+    }
+
+    /// Run `check_tx` and add the transaction to the pool
+    ///
+    /// The sequence must directly follow the sender's last pending
+    /// transaction (or the account sequence when none is pending). When the
+    /// pool is full, a cancellation may evict the newest pending order.
+    pub fn add_tx(&mut self, tx_bytes: Vec<u8>, app: &OptimicApp) -> Result<TxHash> {
+        let size = tx_bytes.len() as u64;
+        if size > self.config.max_tx_bytes {
+            return Err(TxError::TooLarge {
+                size,
+                limit: self.config.max_tx_bytes,
+            }
+            .into());
+        }
+
+        let hash = tx_hash(&tx_bytes);
+        if self.txs.contains_key(&hash) {
+            return Err(TxError::Duplicate(hash).into());
+        }
+
+        let tx = app.check_tx(&tx_bytes)?;
+        let sender = tx.body.sender.clone();
+        let expected = match self
+            .by_sender
+            .get(&sender)
+            .and_then(|queue| queue.keys().next_back())
+        {
+            Some(last) => last + 1,
+            None => app
+                .state
+                .accounts
+                .get(&sender)
+                .map(|a| a.sequence)
+                .unwrap_or(0),
+        };
+        if tx.body.sequence != expected {
+            return Err(TxError::SequenceMismatch {
+                sequence: tx.body.sequence,
+                expected,
+            }
+            .into());
+        }
+
+        let priority = if tx.is_cancel_only() {
+            TxPriority::Cancel
+        } else {
+            TxPriority::Normal
+        };
+        self.make_room(size, priority)?;
+
+        let entry = MempoolTx {
+            bytes: tx_bytes,
+            sender: sender.clone(),
+            sequence: tx.body.sequence,
+            priority,
+            timeout_height: tx.body.timeout_height,
+            arrival: self.next_arrival,
+        };
+        self.next_arrival += 1;
+        self.total_bytes += size;
+        self.by_sender
+            .entry(sender)
+            .or_default()
+            .insert(entry.sequence, hash.clone());
+        self.txs.insert(hash.clone(), entry);
+
+        debug!(
+            "Added transaction {} to mempool ({} pending)",
+            hash,
+            self.txs.len()
+        );
+        Ok(hash)
+    }
+
+    /// Select transactions for the next block, up to `max_bytes`
+    ///
+    /// Each sender's transactions are taken in sequence order, starting from
+    /// the account sequence. Across senders, cancellations go first and ties
+    /// are broken by arrival order.
+    pub fn reap(&self, app: &OptimicApp, max_bytes: u64) -> Vec<Vec<u8>> {
+        let height = app.height + 1;
+        let mut heads = BinaryHeap::new();
+        let mut cursors: HashMap<&AccAddress, std::collections::btree_map::Range<'_, u64, TxHash>> =
+            HashMap::new();
+
+        for (sender, queue) in &self.by_sender {
+            let sequence = app
+                .state
+                .accounts
+                .get(sender)
+                .map(|a| a.sequence)
+                .unwrap_or(0);
+            let mut cursor = queue.range(sequence..);
+            if let Some((&first, hash)) = cursor.next() {
+                if first == sequence {
+                    let tx = &self.txs[hash];
+                    heads.push((tx.priority, Reverse(tx.arrival), hash));
+                    cursors.insert(sender, cursor);
+                }
+            }
+        }
+
+        let mut block = Vec::new();
+        let mut block_bytes = 0u64;
+        while let Some((_, _, hash)) = heads.pop() {
+            let tx = &self.txs[hash];
+            let size = tx.bytes.len() as u64;
+            if tx.expired_at(height) || block_bytes + size > max_bytes {
+                // Later transactions of this sender would leave a sequence gap
+                continue;
+            }
+            block_bytes += size;
+            block.push(tx.bytes.clone());
+
+            if let Some((_, next)) = cursors.get_mut(&tx.sender).and_then(|cursor| cursor.next()) {
+                let next_tx = &self.txs[next];
+                heads.push((next_tx.priority, Reverse(next_tx.arrival), next));
+            }
+        }
+
+        block
+    }
+
+    /// Remove committed transactions and re-check everything left
+    ///
+    /// Called after each commit with the block's transactions. Pending
+    /// transactions that are now stale, expired or invalid are evicted along
+    /// with any later transactions from the same sender.
+    pub fn update(&mut self, app: &OptimicApp, committed: &[Vec<u8>]) {
+        for tx_bytes in committed {
+            self.remove(&tx_hash(tx_bytes));
+        }
+
+        let next_height = app.height + 1;
+        let mut evicted = Vec::new();
+        for (sender, queue) in &self.by_sender {
+            let mut expected = app
+                .state
+                .accounts
+                .get(sender)
+                .map(|a| a.sequence)
+                .unwrap_or(0);
+            let mut broken = false;
+            for (&sequence, hash) in queue {
+                let tx = &self.txs[hash];
+                let valid = !broken
+                    && sequence == expected
+                    && !tx.expired_at(next_height)
+                    && app.check_tx(&tx.bytes).is_ok();
+                if valid {
+                    expected += 1;
+                } else {
+                    // Anything after an evicted transaction has a sequence gap
+                    broken = broken || sequence >= expected;
+                    evicted.push(hash.clone());
+                }
+            }
+        }
+
+        if !evicted.is_empty() {
+            debug!("Evicting {} transactions from mempool", evicted.len());
+        }
+        for hash in evicted {
+            self.remove(&hash);
+        }
+    }
+
+    /// Evict lower-priority transactions until `size` more bytes fit
+    fn make_room(&mut self, size: u64, priority: TxPriority) -> Result<()> {
+        while self.txs.len() + 1 > self.config.max_txs
+            || self.total_bytes + size > self.config.max_bytes
+        {
+            // Only the newest transaction of a sender can go without
+            // leaving a sequence gap behind it
+            let victim = self
+                .by_sender
+                .values()
+                .filter_map(|queue| queue.values().next_back())
+                .map(|hash| (hash, &self.txs[hash]))
+                .filter(|(_, tx)| tx.priority < priority)
+                .min_by_key(|(_, tx)| (tx.priority, Reverse(tx.arrival)))
+                .map(|(hash, _)| hash.clone());
+
+            match victim {
+                Some(hash) => {
+                    debug!("Evicting transaction {} to make room", hash);
+                    self.remove(&hash);
+                }
+                None => return Err(TxError::MempoolFull.into()),
+            }
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, hash: &TxHash) {
+        if let Some(tx) = self.txs.remove(hash) {
+            self.total_bytes -= tx.bytes.len() as u64;
+            if let Some(queue) = self.by_sender.get_mut(&tx.sender) {
+                queue.remove(&tx.sequence);
+                if queue.is_empty() {
+                    self.by_sender.remove(&tx.sender);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{test_app, ChainParams, GenesisData};
+    use crate::keys::KeyPair;
+    use crate::tx::{Msg, Tx, TxBody};
+    use crate::types::*;
+
+    fn setup(keys: &[&KeyPair]) -> OptimicApp {
+        let mut app = test_app();
+        let accounts = keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| Account {
+                address: key.account_address(),
+                public_key: None,
+                account_number: i as u64,
+                sequence: 0,
+                balances: [("USD".to_string(), "1000000".to_string())].into(),
+            })
+            .collect();
+        let market = Market {
+            id: "ETH-USD".to_string(),
+            base_asset: "ETH".to_string(),
+            quote_asset: "USD".to_string(),
+            min_order_size: "1".to_string(),
+            lot_size: "1".to_string(),
+            tick_size: "0.01".to_string(),
+            market_type: MarketType::Spot,
+            status: MarketStatus::Active,
+            matching_mode: MatchingMode::Continuous,
+        };
+        app.init_genesis(GenesisData {
+            chain_id: "optimic-test".to_string(),
+            initial_height: 0,
+            params: ChainParams::default(),
+            assets: vec![],
+            accounts,
+            validators: vec![],
+            markets: vec![market],
+            app_state: None,
+        })
+        .unwrap();
+        app
+    }
+
+    fn tx(key: &KeyPair, sequence: u64, timeout_height: u64, msg: Msg) -> Vec<u8> {
+        let body = TxBody {
+            chain_id: "optimic-test".to_string(),
+            sender: key.account_address(),
+            sequence,
+            timeout_height,
+            msgs: vec![msg],
+            memo: String::new(),
+        };
+        Tx::sign(body, key).unwrap().encode().unwrap()
+    }
+
+    fn order() -> Msg {
+        Msg::PlaceOrder {
+            market: "ETH-USD".to_string(),
+            side: OrderSide::Buy,
+            order_type: OrderType::Limit,
+            quantity: "10".to_string(),
+            price: Some("100".to_string()),
+            time_in_force: TimeInForce::GTC,
+        }
+    }
+
+    #[test]
+    fn test_sender_sequence_ordering() {
+        let key = KeyPair::generate();
+        let app = setup(&[&key]);
+        let mut mempool = Mempool::new(MempoolConfig::new(100, 1024 * 1024));
+
+        assert!(mempool.add_tx(tx(&key, 1, 0, order()), &app).is_err());
+        let txs: Vec<_> = (0..3).map(|seq| tx(&key, seq, 0, order())).collect();
+        for tx_bytes in &txs {
+            mempool.add_tx(tx_bytes.clone(), &app).unwrap();
+        }
+        assert_eq!(mempool.reap(&app, u64::MAX), txs);
+    }
+
+    #[test]
+    fn test_cancels_jump_ahead_of_orders() {
+        let spammer = KeyPair::generate();
+        let maker = KeyPair::generate();
+        let mut app = setup(&[&spammer, &maker]);
+        let mut mempool = Mempool::new(MempoolConfig::new(3, 1024 * 1024));
+
+        // The maker's resting order
+        app.deliver_tx(&tx(&maker, 0, 0, order())).unwrap();
+        let order_id = app.state.next_order_id - 1;
+
+        for seq in 0..3 {
+            mempool.add_tx(tx(&spammer, seq, 0, order()), &app).unwrap();
+        }
+        // A full pool rejects more orders but makes room for a cancel
+        let spam = tx(&maker, 1, 0, order());
+        assert!(mempool.add_tx(spam, &app).is_err());
+        let cancel = tx(&maker, 1, 0, Msg::CancelOrder { order_id });
+        mempool.add_tx(cancel.clone(), &app).unwrap();
+
+        assert_eq!(mempool.len(), 3);
+        assert_eq!(mempool.reap(&app, u64::MAX)[0], cancel);
+    }
+
+    #[test]
+    fn test_timeout_height_is_inclusive() {
+        let key = KeyPair::generate();
+        let mut app = setup(&[&key]);
+        let mut mempool = Mempool::new(MempoolConfig::new(100, 1024 * 1024));
+
+        // Valid for block 1 exactly: admitted, reaped and delivered there
+        let last_chance = tx(&key, 0, 1, order());
+        mempool.add_tx(last_chance.clone(), &app).unwrap();
+        assert_eq!(mempool.reap(&app, u64::MAX), vec![last_chance.clone()]);
+        app.begin_block(1, Timestamp::UNIX_EPOCH).unwrap();
+        app.deliver_tx(&last_chance).unwrap();
+
+        // Too late for block 2
+        let error = mempool.add_tx(tx(&key, 1, 1, order()), &app).unwrap_err();
+        assert_eq!(
+            crate::codes::result_code(&error),
+            crate::codes::TX_TIMED_OUT
+        );
+    }
+
+    #[test]
+    fn test_update_evicts_committed_and_expired() {
+        let key = KeyPair::generate();
+        let mut app = setup(&[&key]);
+        let mut mempool = Mempool::new(MempoolConfig::new(100, 1024 * 1024));
+
+        let first = tx(&key, 0, 0, order());
+        mempool.add_tx(first.clone(), &app).unwrap();
+        mempool.add_tx(tx(&key, 1, 1, order()), &app).unwrap();
+        mempool.add_tx(tx(&key, 2, 0, order()), &app).unwrap();
+
+        // Block 1 only includes the first transaction
+        app.begin_block(1, Timestamp::UNIX_EPOCH).unwrap();
+        app.deliver_tx(&first).unwrap();
+        mempool.update(&app, &[first]);
+
+        // The second timed out at height 1, which strands the third
+        assert!(mempool.is_empty());
+        assert_eq!(mempool.size_bytes(), 0);
+    }
+}
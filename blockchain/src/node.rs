@@ -0,0 +1,295 @@
+//! Node Runtime
+//!
+//! This module wires the node configuration, storage backend and application
+//! together and drives a single-validator block production loop alongside
+//! the RPC server.
+
+use crate::abci::{BlockHeader, BlockResult, OptimicABCI};
+use crate::app::{AppConfig, OptimicApp};
+use crate::config::NodeConfig;
+use crate::consensus::{CommitInfo, VoteInfo};
+use crate::mempool::{Mempool, MempoolConfig};
+use crate::rpc;
+use crate::storage::open_storage;
+use crate::stream::Streams;
+use crate::tx::TxHash;
+use anyhow::{Context, Result};
+use chrono::Utc;
+use std::future::Future;
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, oneshot};
+use tracing::{error, info, warn};
+
+/// A running Optimic node
+pub struct Node {
+    config: NodeConfig,
+    abci: OptimicABCI,
+    mempool: Mempool,
+
+    /// Committed blocks, fanned out to subscribers
+    blocks: broadcast::Sender<Arc<BlockResult>>,
+
+    /// Real-time updates for WebSocket clients
+    streams: Streams,
+}
+
+/// A node shared between block production and the RPC server
+pub type SharedNode = Arc<RwLock<Node>>;
+
+/// Number of committed blocks buffered for slow subscribers
+const BLOCK_CHANNEL_CAPACITY: usize = 64;
+
+impl Node {
+    /// Open storage, load genesis and restore any committed state
+    pub fn new(config: NodeConfig) -> Result<Self> {
+        let storage = open_storage(config.storage.backend, &config.node.data_dir)?;
+
+        let mut app = OptimicApp::new(AppConfig {
+            chain_id: config.node.chain_id.clone(),
+            genesis_path: config.node.genesis_path.clone(),
+            data_dir: config.node.data_dir.clone(),
+        });
+
+        // Genesis is always loaded so that a malformed file fails at boot,
+        // even when committed state is restored afterwards
+        app.load_genesis()?;
+        check_config_against_genesis(&config, &app);
+
+        let mut abci =
+            OptimicABCI::with_storage(app, storage).with_keep_recent(config.storage.keep_recent);
+        if abci.load_committed_state()? {
+            info!("Resuming from committed height {}", abci.height());
+        }
+
+        let mempool = Mempool::new(MempoolConfig::new(
+            config.mempool.size,
+            abci.app().state.params.max_block_size,
+        ));
+
+        let (blocks, _) = broadcast::channel(BLOCK_CHANNEL_CAPACITY);
+        let streams = Streams::new(abci.app());
+        Ok(Self {
+            config,
+            abci,
+            mempool,
+            blocks,
+            streams,
+        })
+    }
+
+    /// Node configuration
+    pub fn config(&self) -> &NodeConfig {
+        &self.config
+    }
+
+    /// Receive every block committed from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<BlockResult>> {
+        self.blocks.subscribe()
+    }
+
+    /// Real-time update streams
+    pub fn streams(&self) -> &Streams {
+        &self.streams
+    }
+
+    /// Access the ABCI application
+    pub fn abci(&self) -> &OptimicABCI {
+        &self.abci
+    }
+
+    /// Access the mempool
+    pub fn mempool(&self) -> &Mempool {
+        &self.mempool
+    }
+
+    /// Check a transaction and queue it in the mempool
+    pub fn submit_tx(&mut self, tx_bytes: Vec<u8>) -> Result<TxHash> {
+        self.mempool.add_tx(tx_bytes, self.abci.app())
+    }
+
+    /// Produce and commit the next block from the mempool
+    pub fn produce_block(&mut self) -> Result<Arc<BlockResult>> {
+        let height = self.abci.height() + 1;
+        let max_block_size = self.abci.app().state.params.max_block_size;
+        let transactions = self.mempool.reap(self.abci.app(), max_block_size);
+
+        // This node is the only signer; it signed the blocks it proposed
+        let proposer = self.abci.app().proposer();
+        let votes = proposer
+            .iter()
+            .map(|validator| VoteInfo {
+                validator: validator.clone(),
+                signed: true,
+            })
+            .collect();
+        let header = BlockHeader {
+            height,
+            time: Utc::now(),
+            proposer: proposer.unwrap_or_default(),
+            prev_hash: String::new(),
+            commit_info: CommitInfo {
+                votes,
+                evidence: vec![],
+            },
+        };
+        let result = Arc::new(self.abci.process_block(header, transactions.clone())?);
+        self.mempool.update(self.abci.app(), &transactions);
+        self.streams.publish(self.abci.app(), &result);
+
+        info!(
+            "Committed block {} with {} txs app_hash={} ({} pending)",
+            height,
+            transactions.len(),
+            result.block.app_hash,
+            self.mempool.len()
+        );
+
+        // Nobody listening is not an error
+        let _ = self.blocks.send(result.clone());
+        Ok(result)
+    }
+
+    /// Serve RPC and produce a block every `block_time` seconds until
+    /// `shutdown` resolves
+    pub async fn run(self, shutdown: impl Future<Output = ()>) -> Result<()> {
+        let block_time = Duration::from_secs(self.config.consensus.block_time.max(1));
+        let mut ticker = tokio::time::interval(block_time);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        tokio::pin!(shutdown);
+
+        let rpc_addr = self.config.network.rpc_listen_addr.clone();
+        let listener = TcpListener::bind(&rpc_addr)
+            .await
+            .with_context(|| format!("failed to bind RPC server to {}", rpc_addr))?;
+        info!("RPC server listening on {}", listener.local_addr()?);
+
+        info!(
+            "Producing blocks every {:?} on chain {}",
+            block_time, self.config.node.chain_id
+        );
+
+        let node: SharedNode = Arc::new(RwLock::new(self));
+        let (stop_rpc, rpc_stopped) = oneshot::channel::<()>();
+        let rpc = tokio::spawn(rpc::serve(listener, node.clone(), async {
+            let _ = rpc_stopped.await;
+        }));
+
+        let outcome = loop {
+            tokio::select! {
+                _ = &mut shutdown => {
+                    info!("Shutdown requested, stopping at height {}", read(&node).abci.height());
+                    break Ok(());
+                }
+                _ = ticker.tick() => {
+                    if let Err(e) = write(&node).produce_block() {
+                        error!("Block production failed: {}", e);
+                        break Err(e);
+                    }
+                }
+            }
+        };
+
+        let _ = stop_rpc.send(());
+        rpc.await??;
+        outcome
+    }
+}
+
+/// Lock a shared node for reading
+pub fn read(node: &SharedNode) -> RwLockReadGuard<'_, Node> {
+    node.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Lock a shared node for writing
+pub fn write(node: &SharedNode) -> RwLockWriteGuard<'_, Node> {
+    node.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Warn about node settings that disagree with the consensus parameters
+fn check_config_against_genesis(config: &NodeConfig, app: &OptimicApp) {
+    let params = &app.state.params;
+    if config.consensus.block_time != params.block_time {
+        warn!(
+            "config block_time {}s differs from genesis block_time {}s; using the config value for block production",
+            config.consensus.block_time, params.block_time
+        );
+    }
+    if config.consensus.max_block_size != params.max_block_size {
+        warn!("config max_block_size differs from genesis; genesis parameters take precedence");
+    }
+
+    let collateral = &params.collateral_params;
+    if config.collateral.buyer_min_ratio != collateral.buyer_min_collateral_ratio
+        || config.collateral.seller_min_ratio != collateral.seller_min_collateral_ratio
+        || config.collateral.liquidation_threshold != collateral.liquidation_threshold
+    {
+        warn!("config [collateral] differs from genesis; genesis parameters take precedence");
+    }
+
+    let fees = &params.trading_fees;
+    if config.fees.premium_fee_rate != fees.premium_fee_rate
+        || config.fees.penalty_fee_rate != fees.penalty_fee_rate
+    {
+        warn!("config [fees] differs from genesis; genesis parameters take precedence");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StorageBackend;
+
+    #[test]
+    fn test_blocks_survive_restart_with_file_storage() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = NodeConfig::default();
+        config.node.genesis_path = concat!(env!("CARGO_MANIFEST_DIR"), "/genesis.json").to_string();
+        config.node.data_dir = dir.path().to_string_lossy().into_owned();
+        config.storage.backend = StorageBackend::File;
+        config.storage.keep_recent = 1;
+
+        let mut node = Node::new(config.clone()).unwrap();
+        node.produce_block().unwrap();
+        let app_hash = node.produce_block().unwrap().block.app_hash.clone();
+        assert_eq!(node.abci().height(), 2);
+
+        let restarted = Node::new(config).unwrap();
+        assert_eq!(restarted.abci().height(), 2);
+        assert_eq!(
+            hex::encode(restarted.abci().app().clone().commit().unwrap()),
+            app_hash
+        );
+        let block = restarted.abci().block(2).unwrap().unwrap();
+        assert_eq!(
+            block.prev_hash,
+            restarted.abci().block(1).unwrap().unwrap().hash
+        );
+        assert_eq!(
+            restarted.abci().block_by_hash(&block.hash).unwrap(),
+            Some(block)
+        );
+
+        // Only the last `keep_recent` heights stay queryable
+        assert!(restarted.abci().query("/params?height=1", &[]).is_ok());
+        let pruned = restarted.abci().query("/params?height=0", &[]).unwrap_err();
+        assert_eq!(pruned.code(), crate::codes::QUERY_HEIGHT_UNAVAILABLE);
+
+        // and exportable, with the state committed at that height
+        let exported = restarted.abci().export_genesis(1).unwrap();
+        assert_eq!(exported.initial_height, 2);
+        let mut app = OptimicApp::new(restarted.abci().app().config.clone());
+        app.init_genesis(exported).unwrap();
+        assert_eq!(
+            hex::encode(app.commit().unwrap()),
+            restarted.abci().block(1).unwrap().unwrap().app_hash
+        );
+        assert_eq!(
+            restarted.abci().export_genesis(2).unwrap().initial_height,
+            3
+        );
+        assert!(restarted.abci().export_genesis(0).is_err());
+        assert!(restarted.abci().export_genesis(3).is_err());
+    }
+}
@@ -1,17 +1,59 @@
 //! Options Module
-//! 
+//!
 //! This module implements options trading functionality including
 //! contract creation, pricing, Greeks calculation, and settlement.
 
+use crate::app::AppState;
+use crate::codes;
+use crate::oracle::{self, OracleError};
 use crate::types::*;
-use anyhow::Result;
+use rust_decimal::prelude::ToPrimitive;
+use thiserror::Error;
 use tracing::warn;
 
+type Result<T> = std::result::Result<T, OptionsError>;
+
+/// Reasons an options operation is rejected
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum OptionsError {
+    #[error("unknown option {0}")]
+    UnknownOption(OptionId),
+
+    #[error("option {0} already exists")]
+    OptionExists(OptionId),
+
+    #[error("option {0} has expired")]
+    Expired(OptionId),
+
+    #[error("invalid quantity {quantity} for option {option_id}")]
+    InvalidQuantity { option_id: OptionId, quantity: u32 },
+
+    #[error("invalid pricing parameters: {0}")]
+    InvalidParameters(String),
+
+    #[error(transparent)]
+    Oracle(#[from] OracleError),
+}
+
+impl OptionsError {
+    /// Stable result code, see [`crate::codes`]
+    pub fn code(&self) -> u32 {
+        match self {
+            OptionsError::UnknownOption(_) => codes::OPTIONS_UNKNOWN_OPTION,
+            OptionsError::OptionExists(_) => codes::OPTIONS_OPTION_EXISTS,
+            OptionsError::Expired(_) => codes::OPTIONS_EXPIRED,
+            OptionsError::InvalidQuantity { .. } => codes::OPTIONS_INVALID_QUANTITY,
+            OptionsError::InvalidParameters(_) => codes::OPTIONS_INVALID_PARAMETERS,
+            OptionsError::Oracle(error) => error.code(),
+        }
+    }
+}
+
 /// Options manager
 pub struct OptionsManager {
     /// All option contracts
     contracts: std::collections::HashMap<OptionId, OptionContract>,
-    
+
     /// Options chains by underlying asset
     chains: std::collections::HashMap<AssetId, OptionsChain>,
 }
@@ -73,6 +115,41 @@ impl OptionsManager {
         })
     }
 
+    /// Price an option at the underlying's oracle price
+    pub fn option_price_at_mark(
+        &self,
+        state: &AppState,
+        option_id: &OptionId,
+        volatility: f64,
+        risk_free_rate: f64,
+    ) -> Result<f64> {
+        let spot_price = self.underlying_mark(state, option_id)?;
+        self.calculate_option_price(option_id, spot_price, volatility, risk_free_rate)
+    }
+
+    /// Greeks of an option at the underlying's oracle price
+    pub fn greeks_at_mark(
+        &self,
+        state: &AppState,
+        option_id: &OptionId,
+        volatility: f64,
+        risk_free_rate: f64,
+    ) -> Result<Greeks> {
+        let spot_price = self.underlying_mark(state, option_id)?;
+        self.calculate_greeks(option_id, spot_price, volatility, risk_free_rate)
+    }
+
+    fn underlying_mark(&self, state: &AppState, option_id: &OptionId) -> Result<f64> {
+        let option = self
+            .contracts
+            .get(option_id)
+            .ok_or_else(|| OptionsError::UnknownOption(option_id.clone()))?;
+        let mark = oracle::mark_price(state, &option.underlying_asset)?;
+        mark.to_f64().ok_or_else(|| {
+            OptionsError::InvalidParameters(format!("spot price {} out of range", mark))
+        })
+    }
+
     /// Exercise an option
     pub fn exercise_option(&mut self, option_id: &OptionId, quantity: u32) -> Result<()> {
         warn!("Option exercise not yet implemented");
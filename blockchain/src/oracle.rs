@@ -0,0 +1,596 @@
+//! Oracle Module
+//!
+//! This module aggregates the prices that feeders submit for each asset.
+//! Feeders are the accounts registered in the oracle parameters and, when
+//! enabled, the operators of bonded validators weighted by their tokens.
+//! At the end of every block the fresh submissions of an asset are reduced
+//! to their weighted median; submissions further from it than the allowed
+//! deviation are discarded as outliers before the median is taken again.
+//! The result and its time-weighted average are the marks used for
+//! collateral, option pricing and liquidations.
+//!
+//! Prices are quoted like market prices: base units of the oracle's quote
+//! asset per base unit of the priced asset.
+
+use crate::app::AppState;
+use crate::codes;
+use crate::events::Event;
+use crate::keys::{is_account_address, operator_of};
+use crate::math::{parse_amount, parse_decimal, parse_fraction, to_decimal, NumberError};
+use crate::types::*;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use thiserror::Error;
+
+type Result<T> = std::result::Result<T, OracleError>;
+
+/// Reasons a price submission or lookup fails
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum OracleError {
+    #[error("{0} is not a price feeder")]
+    NotFeeder(AccAddress),
+
+    #[error("the oracle does not price {0}")]
+    UnknownAsset(AssetId),
+
+    #[error("price of {0} must be positive")]
+    InvalidPrice(AssetId),
+
+    #[error("{0} is submitted twice")]
+    DuplicateAsset(AssetId),
+
+    #[error("no prices submitted")]
+    NoPrices,
+
+    #[error("no oracle price for {0}")]
+    NoPrice(AssetId),
+
+    #[error("oracle price of {asset} is from height {height}, older than {max_age} blocks")]
+    StalePrice {
+        asset: AssetId,
+        height: u64,
+        max_age: u64,
+    },
+
+    #[error(transparent)]
+    Number(#[from] NumberError),
+}
+
+impl OracleError {
+    /// Stable result code, see [`crate::codes`]
+    pub fn code(&self) -> u32 {
+        match self {
+            OracleError::NotFeeder(_) => codes::ORACLE_NOT_FEEDER,
+            OracleError::UnknownAsset(_) => codes::ORACLE_UNKNOWN_ASSET,
+            OracleError::InvalidPrice(_) => codes::ORACLE_INVALID_PRICE,
+            OracleError::DuplicateAsset(_) => codes::ORACLE_DUPLICATE_ASSET,
+            OracleError::NoPrices => codes::ORACLE_NO_PRICES,
+            OracleError::NoPrice(_) => codes::ORACLE_NO_PRICE,
+            OracleError::StalePrice { .. } => codes::ORACLE_STALE_PRICE,
+            OracleError::Number(error) => error.code(),
+        }
+    }
+}
+
+/// Oracle parameters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OracleParams {
+    /// Asset prices are quoted in; its own price is always 1
+    pub quote_asset: AssetId,
+
+    /// Assets the oracle prices
+    pub assets: Vec<AssetId>,
+
+    /// Registered feeders
+    pub feeders: Vec<Feeder>,
+
+    /// Whether operators of bonded validators feed prices, weighted by tokens
+    pub validator_feeders: bool,
+
+    /// Submissions and prices this many blocks old are stale
+    pub max_age_blocks: u64,
+
+    /// Largest relative distance from the median a submission may have
+    pub max_deviation: String,
+
+    /// Submissions an update needs after outliers are removed
+    pub min_sources: u32,
+
+    /// Number of blocks the TWAP averages over
+    pub twap_window_blocks: u64,
+}
+
+impl Default for OracleParams {
+    fn default() -> Self {
+        Self {
+            quote_asset: "USD".to_string(),
+            assets: vec![],
+            feeders: vec![],
+            validator_feeders: true,
+            max_age_blocks: 10,
+            max_deviation: "0.1".to_string(), // 10%
+            min_sources: 1,
+            twap_window_blocks: 60,
+        }
+    }
+}
+
+/// A registered price feeder
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Feeder {
+    pub address: AccAddress,
+    pub weight: Uint128,
+}
+
+/// Price of one asset in a submission
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssetPrice {
+    pub asset: AssetId,
+    pub price: Price,
+}
+
+/// Oracle state
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OracleState {
+    /// Height of the last aggregation
+    pub height: u64,
+
+    /// Latest submission of every feeder, by asset
+    pub submissions: imbl::HashMap<AssetId, HashMap<AccAddress, Submission>>,
+
+    /// Aggregated prices by asset
+    pub prices: imbl::HashMap<AssetId, OraclePrice>,
+}
+
+/// A feeder's latest price for an asset
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Submission {
+    pub price: Price,
+    pub height: u64,
+}
+
+/// Aggregated price of an asset
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OraclePrice {
+    pub asset: AssetId,
+
+    /// Weighted median of the last update
+    pub price: Price,
+
+    /// Time-weighted average over the TWAP window
+    pub twap: Price,
+
+    /// Height of the last update
+    pub height: u64,
+
+    /// Submissions the last update was taken from
+    pub sources: u32,
+
+    /// Updates within the TWAP window, oldest first
+    pub samples: Vec<PriceSample>,
+}
+
+/// Price of an asset from a given height on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceSample {
+    pub height: u64,
+    pub price: Price,
+}
+
+/// Validate oracle parameters, collecting every problem
+pub fn validate_params(params: &OracleParams, errors: &mut Vec<String>) {
+    if params.quote_asset.trim().is_empty() {
+        errors.push("params.oracle.quote_asset must not be empty".to_string());
+    }
+    let mut assets = HashSet::new();
+    for asset in &params.assets {
+        if asset == &params.quote_asset {
+            errors.push(format!(
+                "params.oracle.assets: '{}' is the quote asset",
+                asset
+            ));
+        }
+        if !assets.insert(asset) {
+            errors.push(format!("params.oracle.assets: duplicate asset '{}'", asset));
+        }
+    }
+    let mut feeders = HashSet::new();
+    for feeder in &params.feeders {
+        if !is_account_address(&feeder.address) {
+            errors.push(format!(
+                "params.oracle.feeders: invalid address '{}'",
+                feeder.address
+            ));
+        }
+        if !feeders.insert(&feeder.address) {
+            errors.push(format!(
+                "params.oracle.feeders: duplicate feeder '{}'",
+                feeder.address
+            ));
+        }
+        match parse_amount(&feeder.weight) {
+            Ok(0) => errors.push(format!(
+                "params.oracle.feeders.{}.weight must be positive",
+                feeder.address
+            )),
+            Ok(_) => {}
+            Err(e) => errors.push(format!(
+                "params.oracle.feeders.{}.weight: {}",
+                feeder.address, e
+            )),
+        }
+    }
+    if params.max_age_blocks == 0 {
+        errors.push("params.oracle.max_age_blocks must be positive".to_string());
+    }
+    match parse_fraction(&params.max_deviation) {
+        Ok(deviation) if deviation > Decimal::ZERO => {}
+        Ok(_) => errors.push("params.oracle.max_deviation must be positive".to_string()),
+        Err(e) => errors.push(format!("params.oracle.max_deviation: {}", e)),
+    }
+    if params.min_sources == 0 {
+        errors.push("params.oracle.min_sources must be positive".to_string());
+    }
+    if params.twap_window_blocks == 0 {
+        errors.push("params.oracle.twap_window_blocks must be positive".to_string());
+    }
+}
+
+/// Check that `sender` may submit `prices`
+pub fn validate_submission(
+    state: &AppState,
+    sender: &AccAddress,
+    prices: &[AssetPrice],
+) -> Result<()> {
+    if prices.is_empty() {
+        return Err(OracleError::NoPrices);
+    }
+    if feeder_weight(state, sender)? == 0 {
+        return Err(OracleError::NotFeeder(sender.clone()));
+    }
+    let mut assets = HashSet::new();
+    for submitted in prices {
+        if !state.params.oracle.assets.contains(&submitted.asset) {
+            return Err(OracleError::UnknownAsset(submitted.asset.clone()));
+        }
+        if !assets.insert(&submitted.asset) {
+            return Err(OracleError::DuplicateAsset(submitted.asset.clone()));
+        }
+        if parse_decimal(&submitted.price)? <= Decimal::ZERO {
+            return Err(OracleError::InvalidPrice(submitted.asset.clone()));
+        }
+    }
+    Ok(())
+}
+
+/// Record `sender`'s prices, replacing its earlier submissions
+pub fn submit(
+    state: &mut AppState,
+    height: u64,
+    sender: &AccAddress,
+    prices: &[AssetPrice],
+) -> Result<()> {
+    validate_submission(state, sender, prices)?;
+    for submitted in prices {
+        state
+            .oracle
+            .submissions
+            .entry(submitted.asset.clone())
+            .or_default()
+            .insert(
+                sender.clone(),
+                Submission {
+                    price: submitted.price.clone(),
+                    height,
+                },
+            );
+    }
+    Ok(())
+}
+
+/// Aggregate the fresh submissions of every priced asset at `height`
+pub fn aggregate(state: &mut AppState, height: u64) -> Result<Vec<Event>> {
+    let params = state.params.oracle.clone();
+    let max_deviation = parse_fraction(&params.max_deviation)?;
+    let is_fresh = |submitted: u64| height.saturating_sub(submitted) < params.max_age_blocks;
+    state.oracle.height = height;
+
+    let mut events = Vec::new();
+    for asset in &params.assets {
+        let Some(submissions) = state.oracle.submissions.get_mut(asset) else {
+            continue;
+        };
+        submissions.retain(|_, submission| is_fresh(submission.height));
+
+        let mut quotes = Vec::new();
+        for (feeder, submission) in state.oracle.submissions[asset].iter() {
+            let weight = feeder_weight(state, feeder)?;
+            if weight > 0 {
+                quotes.push((parse_decimal(&submission.price)?, weight, feeder.clone()));
+            }
+        }
+        quotes.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.2.cmp(&b.2)));
+        let Some(median) = weighted_median(&quotes) else {
+            continue;
+        };
+
+        let (accepted, outliers): (Vec<_>, Vec<_>) = quotes
+            .into_iter()
+            .partition(|(price, _, _)| (*price - median).abs() <= median * max_deviation);
+        if accepted.len() < params.min_sources as usize {
+            continue;
+        }
+        let Some(price) = weighted_median(&accepted) else {
+            continue;
+        };
+
+        let oracle_price =
+            state
+                .oracle
+                .prices
+                .entry(asset.clone())
+                .or_insert_with(|| OraclePrice {
+                    asset: asset.clone(),
+                    price: String::new(),
+                    twap: String::new(),
+                    height,
+                    sources: 0,
+                    samples: vec![],
+                });
+        oracle_price.samples.retain(|sample| sample.height < height);
+        oracle_price.samples.push(PriceSample {
+            height,
+            price: price.to_string(),
+        });
+        let window_start = (height + 1).saturating_sub(params.twap_window_blocks);
+        while oracle_price.samples.len() > 1 && oracle_price.samples[1].height <= window_start {
+            oracle_price.samples.remove(0);
+        }
+        let twap = time_weighted(&oracle_price.samples, window_start, height + 1)?;
+
+        oracle_price.price = price.to_string();
+        oracle_price.twap = twap.to_string();
+        oracle_price.height = height;
+        oracle_price.sources = accepted.len() as u32;
+
+        events.push(Event::OraclePriceUpdated {
+            asset: asset.clone(),
+            price: oracle_price.price.clone(),
+            twap: oracle_price.twap.clone(),
+            sources: oracle_price.sources,
+            outliers: outliers.into_iter().map(|(_, _, feeder)| feeder).collect(),
+        });
+    }
+    Ok(events)
+}
+
+/// Current oracle price of `asset`, failing if it is missing or stale
+pub fn mark_price(state: &AppState, asset: &AssetId) -> Result<Decimal> {
+    let params = &state.params.oracle;
+    if asset == &params.quote_asset {
+        return Ok(Decimal::ONE);
+    }
+    let price = state
+        .oracle
+        .prices
+        .get(asset)
+        .ok_or_else(|| OracleError::NoPrice(asset.clone()))?;
+    if state.oracle.height.saturating_sub(price.height) >= params.max_age_blocks {
+        return Err(OracleError::StalePrice {
+            asset: asset.clone(),
+            height: price.height,
+            max_age: params.max_age_blocks,
+        });
+    }
+    Ok(parse_decimal(&price.price)?)
+}
+
+/// Oracle price of a market's base asset in its quote asset, if both are priced
+pub fn market_price(state: &AppState, market: &Market) -> Option<Decimal> {
+    let base = mark_price(state, &market.base_asset).ok()?;
+    let quote = mark_price(state, &market.quote_asset).ok()?;
+    base.checked_div(quote)
+}
+
+/// Weight of `address` as a feeder: its registered weight, or its
+/// validator's tokens when validators feed prices
+fn feeder_weight(state: &AppState, address: &AccAddress) -> Result<u128> {
+    let params = &state.params.oracle;
+    if let Some(feeder) = params
+        .feeders
+        .iter()
+        .find(|feeder| &feeder.address == address)
+    {
+        return Ok(parse_amount(&feeder.weight)?);
+    }
+    if !params.validator_feeders {
+        return Ok(0);
+    }
+    let Some(operator) = operator_of(address) else {
+        return Ok(0);
+    };
+    match state.validators.get(&operator) {
+        Some(validator) if matches!(validator.status, BondStatus::Bonded) && !validator.jailed => {
+            Ok(parse_amount(&validator.tokens)?)
+        }
+        _ => Ok(0),
+    }
+}
+
+/// Weighted median of quotes sorted by price
+fn weighted_median(quotes: &[(Decimal, u128, AccAddress)]) -> Option<Decimal> {
+    let total: u128 = quotes.iter().map(|(_, weight, _)| weight).sum();
+    let mut cumulative = 0u128;
+    for (price, weight, _) in quotes {
+        cumulative += weight;
+        if cumulative * 2 >= total {
+            return Some(*price);
+        }
+    }
+    None
+}
+
+/// Average of `samples` over the blocks in `[start, end)`
+fn time_weighted(samples: &[PriceSample], start: u64, end: u64) -> Result<Decimal> {
+    let mut sum = Decimal::ZERO;
+    let mut blocks = 0u64;
+    for (i, sample) in samples.iter().enumerate() {
+        let until = samples.get(i + 1).map_or(end, |next| next.height);
+        let duration = until.saturating_sub(sample.height.max(start));
+        sum += parse_decimal(&sample.price)? * to_decimal(duration)?;
+        blocks += duration;
+    }
+    if blocks == 0 {
+        return Ok(Decimal::ZERO);
+    }
+    Ok((sum / to_decimal(blocks)?).normalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::test_state;
+    use crate::keys::KeyPair;
+
+    /// Oracle pricing ETH from four registered feeders, the third of which
+    /// has double weight
+    fn setup() -> (AppState, Vec<AccAddress>) {
+        let mut state = test_state(&[]);
+        let feeders: Vec<_> = (0..4)
+            .map(|_| KeyPair::generate().account_address())
+            .collect();
+        state.params.oracle = OracleParams {
+            assets: vec!["ETH".to_string()],
+            feeders: feeders
+                .iter()
+                .zip(["1", "1", "2", "1"])
+                .map(|(address, weight)| Feeder {
+                    address: address.clone(),
+                    weight: weight.to_string(),
+                })
+                .collect(),
+            validator_feeders: false,
+            ..OracleParams::default()
+        };
+        (state, feeders)
+    }
+
+    fn eth(price: &str) -> Vec<AssetPrice> {
+        vec![AssetPrice {
+            asset: "ETH".to_string(),
+            price: price.to_string(),
+        }]
+    }
+
+    #[test]
+    fn test_submission_failure_codes() {
+        let (state, feeders) = setup();
+        let code = |sender: &AccAddress, prices: &[AssetPrice]| {
+            validate_submission(&state, sender, prices)
+                .unwrap_err()
+                .code()
+        };
+
+        assert_eq!(code(&feeders[0], &[]), codes::ORACLE_NO_PRICES);
+        let outsider = KeyPair::generate().account_address();
+        assert_eq!(code(&outsider, &eth("100")), codes::ORACLE_NOT_FEEDER);
+        let btc = AssetPrice {
+            asset: "BTC".to_string(),
+            price: "100".to_string(),
+        };
+        assert_eq!(code(&feeders[0], &[btc]), codes::ORACLE_UNKNOWN_ASSET);
+        assert_eq!(
+            code(&feeders[0], &[eth("1"), eth("2")].concat()),
+            codes::ORACLE_DUPLICATE_ASSET
+        );
+        assert_eq!(code(&feeders[0], &eth("0")), codes::ORACLE_INVALID_PRICE);
+        assert_eq!(code(&feeders[0], &eth("-1")), codes::ORACLE_INVALID_PRICE);
+        assert_eq!(code(&feeders[0], &eth("abc")), codes::INVALID_NUMBER);
+    }
+
+    #[test]
+    fn test_aggregate_takes_weighted_median_without_outliers() {
+        let (mut state, feeders) = setup();
+        for (feeder, price) in feeders.iter().zip(["100", "102", "101", "150"]) {
+            submit(&mut state, 1, feeder, &eth(price)).unwrap();
+        }
+
+        let events = aggregate(&mut state, 1).unwrap();
+        assert!(matches!(
+            &events[..],
+            [Event::OraclePriceUpdated { price, sources: 3, outliers, .. }]
+                if price == "101" && outliers == &[feeders[3].clone()]
+        ));
+        assert_eq!(
+            mark_price(&state, &"ETH".to_string()).unwrap(),
+            Decimal::from(101)
+        );
+
+        // Too few sources left after dropping outliers keep the last price
+        state.params.oracle.min_sources = 4;
+        submit(&mut state, 2, &feeders[0], &eth("99")).unwrap();
+        assert!(aggregate(&mut state, 2).unwrap().is_empty());
+        assert_eq!(state.oracle.prices["ETH"].height, 1);
+    }
+
+    #[test]
+    fn test_prices_go_stale() {
+        let (mut state, feeders) = setup();
+        let eth_id = "ETH".to_string();
+        assert_eq!(
+            mark_price(&state, &"USD".to_string()).unwrap(),
+            Decimal::ONE
+        );
+        assert_eq!(
+            mark_price(&state, &eth_id).unwrap_err().code(),
+            codes::ORACLE_NO_PRICE
+        );
+
+        submit(&mut state, 1, &feeders[0], &eth("100")).unwrap();
+        aggregate(&mut state, 1).unwrap();
+
+        // A price is usable for max_age_blocks heights, counting its own
+        state.oracle.height = 10;
+        assert_eq!(mark_price(&state, &eth_id).unwrap(), Decimal::from(100));
+        // By then the submission aged out too, so nothing refreshes the price
+        assert!(aggregate(&mut state, 11).unwrap().is_empty());
+        assert!(state.oracle.submissions["ETH"].is_empty());
+        let err = mark_price(&state, &eth_id).unwrap_err();
+        assert_eq!(err.code(), codes::ORACLE_STALE_PRICE);
+        assert!(err
+            .to_string()
+            .contains("from height 1, older than 10 blocks"));
+
+        // Markets are priced only while both of their assets are
+        let market = Market {
+            id: "ETH-USD".to_string(),
+            base_asset: eth_id.clone(),
+            quote_asset: "USD".to_string(),
+            min_order_size: "1".to_string(),
+            lot_size: "1".to_string(),
+            tick_size: "0.01".to_string(),
+            market_type: MarketType::Spot,
+            status: MarketStatus::Active,
+            matching_mode: MatchingMode::Continuous,
+        };
+        assert_eq!(market_price(&state, &market), None);
+        submit(&mut state, 12, &feeders[0], &eth("120")).unwrap();
+        aggregate(&mut state, 12).unwrap();
+        assert_eq!(market_price(&state, &market), Some(Decimal::from(120)));
+    }
+
+    #[test]
+    fn test_twap_averages_over_window() {
+        let (mut state, feeders) = setup();
+        state.params.oracle.twap_window_blocks = 2;
+        for (height, price) in [(1, "100"), (3, "110"), (5, "130")] {
+            submit(&mut state, height, &feeders[0], &eth(price)).unwrap();
+            aggregate(&mut state, height).unwrap();
+        }
+        // Heights 4 and 5 are in the window: one block at 110, one at 130
+        let price = &state.oracle.prices["ETH"];
+        assert_eq!((price.price.as_str(), price.twap.as_str()), ("130", "120"));
+        assert_eq!(price.samples.len(), 2);
+    }
+}
@@ -0,0 +1,618 @@
+//! Portfolio Module
+//!
+//! This module keeps account balances, the funds locked by open orders and
+//! the per-market positions of each trader in sync.
+//!
+//! `Account.balances` holds the total of each asset; the trader's
+//! `Portfolio` splits that total into `available` and `locked`. A buy order
+//! locks quote funds for its unfilled quantity at its limit price, a sell
+//! order locks the base quantity. Fills spend from the lock and credit the
+//! counterparty; whatever is left is released when the order is cancelled,
+//! expires or fills. Every balance change goes through this module so that
+//! `total = available + locked` always holds.
+//!
+//! Fills also move the trader's position in the market: the average price
+//! follows average-cost accounting and reductions realize P&L in quote
+//! units. Positions are marked to the last trade price in `end_block`.
+
+use crate::app::AppState;
+use crate::codes;
+use crate::math::{
+    fill_value, floor_signed, notional_ceil, parse_amount, parse_decimal, parse_signed, to_decimal,
+    NumberError,
+};
+use crate::oracle;
+use crate::types::*;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use thiserror::Error;
+
+type Result<T> = std::result::Result<T, PortfolioError>;
+
+/// Balance and portfolio accounting failures
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PortfolioError {
+    #[error(
+        "insufficient {asset} balance for {account}: required {required}, available {available}"
+    )]
+    InsufficientFunds {
+        account: AccAddress,
+        asset: AssetId,
+        required: Uint128,
+        available: Uint128,
+    },
+
+    #[error("unknown account {0}")]
+    UnknownAccount(AccAddress),
+
+    #[error("balance invariant violated: {0}")]
+    Invariant(String),
+
+    #[error(transparent)]
+    Number(#[from] NumberError),
+}
+
+impl PortfolioError {
+    /// Stable result code, see [`crate::codes`]
+    pub fn code(&self) -> u32 {
+        match self {
+            PortfolioError::InsufficientFunds { .. } => codes::PORTFOLIO_INSUFFICIENT_FUNDS,
+            PortfolioError::UnknownAccount(_) => codes::PORTFOLIO_UNKNOWN_ACCOUNT,
+            PortfolioError::Invariant(_) => codes::PORTFOLIO_INVARIANT,
+            PortfolioError::Number(error) => error.code(),
+        }
+    }
+}
+
+impl Portfolio {
+    /// Empty portfolio of `owner`
+    pub fn new(owner: AccAddress) -> Self {
+        Self {
+            owner,
+            balances: HashMap::new(),
+            positions: HashMap::new(),
+            orders: Vec::new(),
+            unrealized_pnl: "0".to_string(),
+            realized_pnl: "0".to_string(),
+        }
+    }
+}
+
+/// Portfolio of `owner` with a balance entry for every asset it holds
+pub fn view(state: &AppState, owner: &AccAddress) -> Result<Portfolio> {
+    let mut portfolio = state
+        .portfolios
+        .get(owner)
+        .cloned()
+        .unwrap_or_else(|| Portfolio::new(owner.clone()));
+    if let Some(account) = state.accounts.get(owner) {
+        for asset in account.balances.keys() {
+            if !portfolio.balances.contains_key(asset) {
+                portfolio
+                    .balances
+                    .insert(asset.clone(), balance(state, owner, asset)?);
+            }
+        }
+    }
+    Ok(portfolio)
+}
+
+/// Current balance of one asset
+pub fn balance(state: &AppState, owner: &AccAddress, asset: &AssetId) -> Result<Balance> {
+    let (total, locked) = amounts(state, owner, asset)?;
+    let available = total.checked_sub(locked).ok_or_else(|| {
+        PortfolioError::Invariant(format!(
+            "{} has {} {} locked but only {} in total",
+            owner, locked, asset, total
+        ))
+    })?;
+    Ok(Balance {
+        total: total.to_string(),
+        available: available.to_string(),
+        locked: locked.to_string(),
+    })
+}
+
+/// Amount of an asset that is not locked
+pub fn available(state: &AppState, owner: &AccAddress, asset: &AssetId) -> Result<u128> {
+    Ok(parse_amount(&balance(state, owner, asset)?.available)?)
+}
+
+/// Fail unless `owner` has `amount` of `asset` available
+pub fn ensure_available(
+    state: &AppState,
+    owner: &AccAddress,
+    asset: &AssetId,
+    amount: u128,
+) -> Result<()> {
+    let available = available(state, owner, asset)?;
+    if available < amount {
+        return Err(PortfolioError::InsufficientFunds {
+            account: owner.clone(),
+            asset: asset.clone(),
+            required: amount.to_string(),
+            available: available.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Move `amount` of `asset` from available to locked
+pub fn lock(state: &mut AppState, owner: &AccAddress, asset: &AssetId, amount: u128) -> Result<()> {
+    ensure_available(state, owner, asset, amount)?;
+    let (total, locked) = amounts(state, owner, asset)?;
+    write(state, owner, asset, total, locked + amount)
+}
+
+/// Move `amount` of `asset` from locked back to available
+pub fn unlock(
+    state: &mut AppState,
+    owner: &AccAddress,
+    asset: &AssetId,
+    amount: u128,
+) -> Result<()> {
+    let (total, locked) = amounts(state, owner, asset)?;
+    let locked = locked.checked_sub(amount).ok_or_else(|| {
+        PortfolioError::Invariant(format!(
+            "cannot unlock {} {} of {}, only {} locked",
+            amount, asset, owner, locked
+        ))
+    })?;
+    write(state, owner, asset, total, locked)
+}
+
+/// Add `amount` of `asset` to the available balance of `owner`
+pub fn credit(
+    state: &mut AppState,
+    owner: &AccAddress,
+    asset: &AssetId,
+    amount: u128,
+) -> Result<()> {
+    let (total, locked) = amounts(state, owner, asset)?;
+    write(state, owner, asset, total + amount, locked)
+}
+
+/// Remove `amount` of `asset` from the available balance of `owner`
+pub fn debit(
+    state: &mut AppState,
+    owner: &AccAddress,
+    asset: &AssetId,
+    amount: u128,
+) -> Result<()> {
+    ensure_available(state, owner, asset, amount)?;
+    let (total, locked) = amounts(state, owner, asset)?;
+    write(state, owner, asset, total - amount, locked)
+}
+
+/// Remove `amount` of `asset` from the locked balance of `owner`
+fn spend_locked(
+    state: &mut AppState,
+    owner: &AccAddress,
+    asset: &AssetId,
+    amount: u128,
+) -> Result<()> {
+    let (total, locked) = amounts(state, owner, asset)?;
+    if locked < amount {
+        return Err(PortfolioError::Invariant(format!(
+            "cannot spend {} {} of {}, only {} locked",
+            amount, asset, owner, locked
+        )));
+    }
+    write(state, owner, asset, total - amount, locked - amount)
+}
+
+/// Asset and amount a limit order locks when placed
+///
+/// Buys lock the quote value of `quantity` at `price`, rounded up, sells
+/// lock the base quantity. Fills never cost a buyer more than the limit,
+/// so the lock covers the exact value of every fill.
+pub fn order_lock(
+    market: &Market,
+    side: &OrderSide,
+    quantity: u128,
+    price: &Price,
+) -> Result<(AssetId, u128)> {
+    match side {
+        OrderSide::Buy => Ok((
+            market.quote_asset.clone(),
+            notional_ceil(quantity, parse_decimal(price)?)?,
+        )),
+        OrderSide::Sell => Ok((market.base_asset.clone(), quantity)),
+    }
+}
+
+/// Lock funds for a new order and record it on the order
+pub fn lock_order(
+    state: &mut AppState,
+    order: &mut Order,
+    asset: &AssetId,
+    amount: u128,
+) -> Result<()> {
+    lock(state, &order.trader, asset, amount)?;
+    order.locked = amount.to_string();
+    state
+        .portfolios
+        .entry(order.trader.clone())
+        .or_insert_with(|| Portfolio::new(order.trader.clone()))
+        .orders
+        .push(order.id);
+    Ok(())
+}
+
+/// Settle a trade: spend both orders' locks and credit the counterparties
+///
+/// The buyer pays the exact value of its order's fills rounded up once, see
+/// [`fill_value`], so the order never pays more than it locked and each
+/// seller receives the whole quote units its fill completes. Whatever the
+/// order still locks when it closes is released.
+pub fn settle_trade(state: &mut AppState, trade: &Trade) -> Result<()> {
+    let market = state.markets.get(&trade.market_id).ok_or_else(|| {
+        PortfolioError::Invariant(format!(
+            "trade {} references unknown market {}",
+            trade.id, trade.market_id
+        ))
+    })?;
+    let (base, quote) = (market.base_asset.clone(), market.quote_asset.clone());
+    let (quantity, price) = (parse_amount(&trade.quantity)?, parse_decimal(&trade.price)?);
+    let value = record_fill(state, trade.buy_order_id, quantity, price)?;
+    record_fill(state, trade.sell_order_id, quantity, price)?;
+
+    spend_order_lock(state, trade.buy_order_id, &quote, value)?;
+    credit(state, &trade.buyer, &base, quantity)?;
+    spend_order_lock(state, trade.sell_order_id, &base, quantity)?;
+    credit(state, &trade.seller, &quote, value)?;
+
+    let delta =
+        i128::try_from(quantity).map_err(|_| NumberError::Overflow(trade.quantity.clone()))?;
+    adjust_position(state, &trade.buyer, trade, delta)?;
+    adjust_position(state, &trade.seller, trade, -delta)?;
+    state
+        .mark_prices
+        .insert(trade.market_id.clone(), trade.price.clone());
+    Ok(())
+}
+
+/// Add a fill to an order's exact filled value, returning what it costs
+/// the order in whole quote units
+fn record_fill(
+    state: &mut AppState,
+    order_id: OrderId,
+    quantity: u128,
+    price: Decimal,
+) -> Result<u128> {
+    let order = state
+        .orders
+        .get_mut(&order_id)
+        .ok_or_else(|| PortfolioError::Invariant(format!("unknown order {}", order_id)))?;
+    let (value, filled) = fill_value(parse_decimal(&order.filled_value)?, quantity, price)?;
+    order.filled_value = filled.normalize().to_string();
+    Ok(value)
+}
+
+/// Release whatever a closed order still has locked
+pub fn release(state: &mut AppState, order_id: OrderId) -> Result<()> {
+    let Some(order) = state.orders.get(&order_id) else {
+        return Ok(());
+    };
+    if order.is_open() {
+        return Ok(());
+    }
+    let (trader, asset) = (order.trader.clone(), locked_asset(state, order)?);
+    let locked = parse_amount(&order.locked)?;
+    if locked > 0 {
+        unlock(state, &trader, &asset, locked)?;
+    }
+    if let Some(order) = state.orders.get_mut(&order_id) {
+        order.locked = "0".to_string();
+    }
+    if let Some(portfolio) = state.portfolios.get_mut(&trader) {
+        portfolio.orders.retain(|id| *id != order_id);
+    }
+    Ok(())
+}
+
+/// Verify that every balance is consistent
+///
+/// For every account and asset the portfolio total matches the account
+/// balance, `total = available + locked`, and the locked amount equals
+/// what the trader's open orders hold.
+pub fn check_invariants(state: &AppState) -> Result<()> {
+    let mut order_locks: HashMap<(AccAddress, AssetId), u128> = HashMap::new();
+    for order in state.orders.values().filter(|order| order.is_open()) {
+        *order_locks
+            .entry((order.trader.clone(), locked_asset(state, order)?))
+            .or_default() += parse_amount(&order.locked)?;
+    }
+
+    for (owner, portfolio) in &state.portfolios {
+        for (asset, entry) in &portfolio.balances {
+            let (total, available, locked) = (
+                parse_amount(&entry.total)?,
+                parse_amount(&entry.available)?,
+                parse_amount(&entry.locked)?,
+            );
+            if available.checked_add(locked) != Some(total) {
+                return Err(PortfolioError::Invariant(format!(
+                    "{} {}: total {} != available {} + locked {}",
+                    owner, asset, total, available, locked
+                )));
+            }
+            let account_total = account_total(state, owner, asset)?;
+            if account_total != total {
+                return Err(PortfolioError::Invariant(format!(
+                    "{} {}: portfolio total {} != account balance {}",
+                    owner, asset, total, account_total
+                )));
+            }
+        }
+    }
+
+    for ((owner, asset), expected) in &order_locks {
+        let (_, locked) = amounts(state, owner, asset)?;
+        if locked != *expected {
+            return Err(PortfolioError::Invariant(format!(
+                "{} {}: {} locked but open orders hold {}",
+                owner, asset, locked, expected
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Asset an order locks: quote for buys, base for sells
+fn locked_asset(state: &AppState, order: &Order) -> Result<AssetId> {
+    let market = state.markets.get(&order.market).ok_or_else(|| {
+        PortfolioError::Invariant(format!(
+            "order {} references unknown market {}",
+            order.id, order.market
+        ))
+    })?;
+    Ok(match order.side {
+        OrderSide::Buy => market.quote_asset.clone(),
+        OrderSide::Sell => market.base_asset.clone(),
+    })
+}
+
+/// Spend part of an order's lock
+fn spend_order_lock(
+    state: &mut AppState,
+    order_id: OrderId,
+    asset: &AssetId,
+    amount: u128,
+) -> Result<()> {
+    let order = state
+        .orders
+        .get_mut(&order_id)
+        .ok_or_else(|| PortfolioError::Invariant(format!("unknown order {}", order_id)))?;
+    let locked = parse_amount(&order.locked)?;
+    let remaining = locked.checked_sub(amount).ok_or_else(|| {
+        PortfolioError::Invariant(format!(
+            "order {} spends {} but only {} is locked",
+            order_id, amount, locked
+        ))
+    })?;
+    order.locked = remaining.to_string();
+    let trader = order.trader.clone();
+    spend_locked(state, &trader, asset, amount)
+}
+
+/// Apply a signed fill to the trader's position in the trade's market
+fn adjust_position(
+    state: &mut AppState,
+    owner: &AccAddress,
+    trade: &Trade,
+    delta: i128,
+) -> Result<()> {
+    let price = parse_decimal(&trade.price)?;
+    let portfolio = state
+        .portfolios
+        .entry(owner.clone())
+        .or_insert_with(|| Portfolio::new(owner.clone()));
+    let position = portfolio
+        .positions
+        .entry(trade.market_id.clone())
+        .or_insert_with(|| Position {
+            market_id: trade.market_id.clone(),
+            quantity: "0".to_string(),
+            average_price: "0".to_string(),
+            unrealized_pnl: "0".to_string(),
+            realized_pnl: "0".to_string(),
+            last_update: trade.timestamp,
+        });
+    let realized = floor_signed(apply_fill(position, delta, price)?)?;
+    position.realized_pnl = add_signed(&position.realized_pnl, realized)?;
+    position.last_update = trade.timestamp;
+    portfolio.realized_pnl = add_signed(&portfolio.realized_pnl, realized)?;
+    Ok(())
+}
+
+/// Apply a signed fill at `price` to a position, returning the realized P&L
+///
+/// Increases move the average price to the quantity-weighted mean.
+/// Reductions realize `(price - average) * closed` on the closed quantity,
+/// with the sign flipped for shorts; a flip opens the remainder at `price`.
+fn apply_fill(position: &mut Position, delta: i128, price: Decimal) -> Result<Decimal> {
+    let overflow = || {
+        NumberError::Overflow(format!(
+            "fill of {} at {} on {}",
+            delta, price, position.market_id
+        ))
+    };
+    let quantity = parse_signed(&position.quantity)?;
+    let mut average = parse_decimal(&position.average_price)?;
+    let updated = quantity.checked_add(delta).ok_or_else(overflow)?;
+    let mut realized = Decimal::ZERO;
+
+    if quantity == 0 || quantity.signum() == delta.signum() {
+        let (held, added) = (to_decimal(quantity.abs())?, to_decimal(delta.abs())?);
+        average = average
+            .checked_mul(held)
+            .and_then(|cost| cost.checked_add(price.checked_mul(added)?))
+            .and_then(|cost| cost.checked_div(held + added))
+            .ok_or_else(overflow)?;
+    } else {
+        let closed = to_decimal(quantity.abs().min(delta.abs()))?;
+        realized = (price - average)
+            .checked_mul(closed)
+            .map(|pnl| if quantity < 0 { -pnl } else { pnl })
+            .ok_or_else(overflow)?;
+        if updated == 0 {
+            average = Decimal::ZERO;
+        } else if updated.signum() != quantity.signum() {
+            average = price;
+        }
+    }
+
+    position.quantity = updated.to_string();
+    position.average_price = average.normalize().to_string();
+    Ok(realized)
+}
+
+/// Mark every position to its market's mark price
+///
+/// The mark is the oracle price of the market when both of its assets are
+/// priced, and the last trade price otherwise. Sets each position's
+/// unrealized P&L, rounded down, and the portfolio total across positions.
+pub fn mark_to_market(state: &mut AppState) -> Result<()> {
+    let mut marks = HashMap::new();
+    for market in state.markets.values() {
+        if let Some(mark) = mark_price(state, market)? {
+            marks.insert(market.id.clone(), mark);
+        }
+    }
+
+    for (_, portfolio) in state.portfolios.iter_mut() {
+        let mut total = 0i128;
+        for position in portfolio.positions.values_mut() {
+            if let Some(mark) = marks.get(&position.market_id) {
+                let quantity = to_decimal(parse_signed(&position.quantity)?)?;
+                let pnl = (*mark - parse_decimal(&position.average_price)?)
+                    .checked_mul(quantity)
+                    .ok_or_else(|| {
+                        NumberError::Overflow(format!("marking {}", position.market_id))
+                    })?;
+                position.unrealized_pnl = floor_signed(pnl)?.to_string();
+            }
+            total += parse_signed(&position.unrealized_pnl)?;
+        }
+        portfolio.unrealized_pnl = total.to_string();
+    }
+    Ok(())
+}
+
+/// Mark price of a market: the oracle price, else the last trade price
+pub fn mark_price(
+    state: &AppState,
+    market: &Market,
+) -> std::result::Result<Option<Decimal>, NumberError> {
+    match (
+        oracle::market_price(state, market),
+        state.mark_prices.get(&market.id),
+    ) {
+        (Some(price), _) => Ok(Some(price)),
+        (None, Some(price)) => Ok(Some(parse_decimal(price)?)),
+        (None, None) => Ok(None),
+    }
+}
+
+fn add_signed(value: &Int128, delta: i128) -> Result<Int128> {
+    let sum = parse_signed(value)?
+        .checked_add(delta)
+        .ok_or_else(|| NumberError::Overflow(format!("{} + {}", value, delta)))?;
+    Ok(sum.to_string())
+}
+
+fn account_total(state: &AppState, owner: &AccAddress, asset: &AssetId) -> Result<u128> {
+    match state
+        .accounts
+        .get(owner)
+        .and_then(|account| account.balances.get(asset))
+    {
+        Some(total) => Ok(parse_amount(total)?),
+        None => Ok(0),
+    }
+}
+
+/// Total and locked amount of an asset
+fn amounts(state: &AppState, owner: &AccAddress, asset: &AssetId) -> Result<(u128, u128)> {
+    let locked = match state
+        .portfolios
+        .get(owner)
+        .and_then(|p| p.balances.get(asset))
+    {
+        Some(entry) => parse_amount(&entry.locked)?,
+        None => 0,
+    };
+    Ok((account_total(state, owner, asset)?, locked))
+}
+
+/// Store a new total and locked amount in both the account and portfolio
+fn write(
+    state: &mut AppState,
+    owner: &AccAddress,
+    asset: &AssetId,
+    total: u128,
+    locked: u128,
+) -> Result<()> {
+    let account = state
+        .accounts
+        .get_mut(owner)
+        .ok_or_else(|| PortfolioError::UnknownAccount(owner.clone()))?;
+    account.balances.insert(asset.clone(), total.to_string());
+    state
+        .portfolios
+        .entry(owner.clone())
+        .or_insert_with(|| Portfolio::new(owner.clone()))
+        .balances
+        .insert(
+            asset.clone(),
+            Balance {
+                total: total.to_string(),
+                available: (total - locked).to_string(),
+                locked: locked.to_string(),
+            },
+        );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_average_cost_and_realized_pnl() {
+        let mut position = Position {
+            market_id: "ETH-USD".to_string(),
+            quantity: "0".to_string(),
+            average_price: "0".to_string(),
+            unrealized_pnl: "0".to_string(),
+            realized_pnl: "0".to_string(),
+            last_update: Timestamp::UNIX_EPOCH,
+        };
+        let mut fill = |delta: i128, price: i64| {
+            apply_fill(&mut position, delta, Decimal::from(price)).unwrap()
+        };
+
+        // Increases average the cost
+        assert_eq!(fill(10, 100), Decimal::ZERO);
+        assert_eq!(fill(30, 120), Decimal::ZERO);
+        // Reductions realize against the average and keep it
+        assert_eq!(fill(-20, 130), Decimal::from(300));
+        // A flip closes the long and opens a short at the fill price
+        assert_eq!(fill(-30, 110), Decimal::from(-100));
+        assert_eq!(
+            (position.quantity.as_str(), position.average_price.as_str()),
+            ("-10", "110")
+        );
+
+        let mut fill = |delta: i128, price: i64| {
+            apply_fill(&mut position, delta, Decimal::from(price)).unwrap()
+        };
+        // Covering a short below its average is a gain
+        assert_eq!(fill(10, 100), Decimal::from(100));
+        assert_eq!(
+            (position.quantity.as_str(), position.average_price.as_str()),
+            ("0", "0")
+        );
+    }
+}
@@ -0,0 +1,732 @@
+//! Query Module
+//!
+//! This module routes `OptimicApp::query` paths to typed handlers. Paths
+//! look like `/orderbook/ETH-USD?depth=20`; list results are paginated with
+//! `offset` and `limit`, and `height` selects an earlier committed state
+//! where the node still keeps it.
+
+use crate::app::OptimicApp;
+use crate::codes;
+use crate::distribution::{self, DecCoins};
+use crate::events::Liquidity;
+use crate::fees;
+use crate::governance::{self, Proposal, VoteOption};
+use crate::math::parse_decimal;
+use crate::portfolio;
+use crate::staking::{self, Delegation};
+use crate::types::*;
+use chrono::NaiveDate;
+use serde::Serialize;
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Default page size for list queries
+pub const DEFAULT_LIMIT: usize = 50;
+
+/// Maximum page size for list queries
+pub const MAX_LIMIT: usize = 500;
+
+/// Default number of price levels per side in order book queries
+pub const DEFAULT_DEPTH: usize = 50;
+
+/// Query failures, each with a stable result code
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum QueryError {
+    #[error("unknown query path '{0}'")]
+    UnknownPath(String),
+
+    #[error("invalid parameter '{name}': {reason}")]
+    InvalidParam { name: String, reason: String },
+
+    #[error("{kind} '{id}' not found")]
+    NotFound { kind: &'static str, id: String },
+
+    #[error("state at height {requested} is not available (latest height is {latest})")]
+    HeightUnavailable { requested: u64, latest: u64 },
+
+    #[error("internal error: {0}")]
+    Internal(String),
+}
+
+impl QueryError {
+    /// Stable result code, see [`crate::codes`]
+    pub fn code(&self) -> u32 {
+        match self {
+            QueryError::UnknownPath(_) => codes::QUERY_UNKNOWN_PATH,
+            QueryError::InvalidParam { .. } => codes::QUERY_INVALID_PARAM,
+            QueryError::NotFound { .. } => codes::QUERY_NOT_FOUND,
+            QueryError::HeightUnavailable { .. } => codes::QUERY_HEIGHT_UNAVAILABLE,
+            QueryError::Internal(_) => codes::QUERY_INTERNAL,
+        }
+    }
+
+    fn invalid(name: &str, reason: impl Into<String>) -> Self {
+        QueryError::InvalidParam {
+            name: name.to_string(),
+            reason: reason.into(),
+        }
+    }
+}
+
+/// Envelope returned by every query
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryResponse<T> {
+    pub height: u64,
+    pub result: T,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pagination: Option<PageInfo>,
+}
+
+/// Pagination details of a list result
+#[derive(Debug, Clone, Serialize)]
+pub struct PageInfo {
+    pub offset: usize,
+    pub limit: usize,
+    pub total: usize,
+}
+
+/// Balance of one asset
+#[derive(Debug, Clone, Serialize)]
+pub struct AssetBalance {
+    pub asset: AssetId,
+
+    /// Total balance
+    pub amount: Uint128,
+    pub available: Uint128,
+
+    /// Held by open orders
+    pub locked: Uint128,
+}
+
+/// Whether a bridge deposit was minted
+#[derive(Debug, Clone, Serialize)]
+pub struct DepositStatus {
+    pub id: String,
+    pub processed: bool,
+}
+
+/// A delegation with the tokens its shares are currently worth
+#[derive(Debug, Clone, Serialize)]
+pub struct DelegationBalance {
+    pub delegator: AccAddress,
+    pub validator: ValAddress,
+    pub shares: Uint128,
+    pub balance: Uint128,
+}
+
+/// Rewards a delegation has earned and not withdrawn
+#[derive(Debug, Clone, Serialize)]
+pub struct DelegationRewards {
+    pub validator: ValAddress,
+    pub rewards: Vec<Coin>,
+}
+
+/// Commission a validator has not withdrawn, as decimals
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidatorCommission {
+    pub validator: ValAddress,
+    pub commission: DecCoins,
+}
+
+/// Fee rates an account currently pays in a market
+#[derive(Debug, Clone, Serialize)]
+pub struct FeeRates {
+    pub account: AccAddress,
+    pub market: MarketId,
+    pub maker_rate: Price,
+    pub taker_rate: Price,
+
+    /// Quote volume over the volume window
+    pub volume: Uint128,
+
+    /// Native tokens staked
+    pub staked: Uint128,
+}
+
+/// A vote on a proposal
+#[derive(Debug, Clone, Serialize)]
+pub struct ProposalVote {
+    pub voter: AccAddress,
+    pub option: VoteOption,
+}
+
+/// A parsed query path
+#[derive(Debug, Clone)]
+pub struct QueryRequest {
+    pub segments: Vec<String>,
+    pub params: HashMap<String, String>,
+}
+
+impl QueryRequest {
+    /// Split a path such as `/orders/alice?limit=10` into segments and parameters
+    pub fn parse(path: &str) -> Result<Self, QueryError> {
+        let (route, query) = path.split_once('?').unwrap_or((path, ""));
+        let segments = route
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        let mut params = HashMap::new();
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            if params.insert(key.to_string(), value.to_string()).is_some() {
+                return Err(QueryError::invalid(key, "given more than once"));
+            }
+        }
+
+        Ok(Self { segments, params })
+    }
+
+    /// Requested state height, if any
+    pub fn height(&self) -> Result<Option<u64>, QueryError> {
+        self.params
+            .get("height")
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| QueryError::invalid("height", "must be a block height"))
+            })
+            .transpose()
+    }
+
+    fn usize_param(&self, name: &str, default: usize, max: usize) -> Result<usize, QueryError> {
+        match self.params.get(name) {
+            None => Ok(default),
+            Some(value) => match value.parse::<usize>() {
+                Ok(n) if n <= max => Ok(n),
+                Ok(_) => Err(QueryError::invalid(
+                    name,
+                    format!("must be at most {}", max),
+                )),
+                Err(_) => Err(QueryError::invalid(name, "must be a non-negative integer")),
+            },
+        }
+    }
+
+    fn page(&self) -> Result<(usize, usize), QueryError> {
+        let offset = self.usize_param("offset", 0, usize::MAX)?;
+        let limit = self.usize_param("limit", DEFAULT_LIMIT, MAX_LIMIT)?;
+        if limit == 0 {
+            return Err(QueryError::invalid("limit", "must be positive"));
+        }
+        Ok((offset, limit))
+    }
+}
+
+/// Route a query against the application's current state
+pub fn handle(app: &OptimicApp, path: &str) -> Result<Vec<u8>, QueryError> {
+    let request = QueryRequest::parse(path)?;
+    if let Some(requested) = request.height()? {
+        if requested != app.height {
+            return Err(QueryError::HeightUnavailable {
+                requested,
+                latest: app.height,
+            });
+        }
+    }
+
+    let state = &app.state;
+    let segments: Vec<&str> = request.segments.iter().map(String::as_str).collect();
+    match segments.as_slice() {
+        ["account", address] => single(app, find(&state.accounts, "account", address)?),
+        ["assets"] => {
+            let mut assets: Vec<_> = state.assets.values().cloned().collect();
+            assets.sort_by(|a, b| a.id.cmp(&b.id));
+            paginated(app, &request, assets)
+        }
+        ["asset", id] => single(app, find(&state.assets, "asset", id)?),
+        ["supply"] => {
+            let mut supply: Vec<_> = state
+                .supply
+                .iter()
+                .map(|(denom, amount)| Coin {
+                    denom: denom.clone(),
+                    amount: amount.clone(),
+                })
+                .collect();
+            supply.sort_by(|a, b| a.denom.cmp(&b.denom));
+            paginated(app, &request, supply)
+        }
+        ["supply", asset] => {
+            find(&state.assets, "asset", asset)?;
+            let amount = state
+                .supply
+                .get(*asset)
+                .cloned()
+                .unwrap_or_else(|| "0".to_string());
+            single(
+                app,
+                Coin {
+                    denom: asset.to_string(),
+                    amount,
+                },
+            )
+        }
+        ["balances", address] => {
+            let account = find(&state.accounts, "account", address)?;
+            let mut balances = Vec::new();
+            for asset in account.balances.keys() {
+                let balance =
+                    portfolio::balance(state, &account.address, asset).map_err(internal)?;
+                balances.push(AssetBalance {
+                    asset: asset.clone(),
+                    amount: balance.total,
+                    available: balance.available,
+                    locked: balance.locked,
+                });
+            }
+            balances.sort_by(|a, b| a.asset.cmp(&b.asset));
+            paginated(app, &request, balances)
+        }
+        ["markets"] => {
+            let mut markets: Vec<_> = state.markets.values().cloned().collect();
+            markets.sort_by(|a, b| a.id.cmp(&b.id));
+            paginated(app, &request, markets)
+        }
+        ["market", id] => single(app, find(&state.markets, "market", id)?),
+        ["fees", account, market] => {
+            let market = find(&state.markets, "market", market)?;
+            let rate = |liquidity| {
+                fees::fee_rate(
+                    state,
+                    market,
+                    &account.to_string(),
+                    liquidity,
+                    app.block_time,
+                )
+            };
+            single(
+                app,
+                FeeRates {
+                    account: account.to_string(),
+                    market: market.id.clone(),
+                    maker_rate: rate(Liquidity::Maker)
+                        .map_err(internal)?
+                        .normalize()
+                        .to_string(),
+                    taker_rate: rate(Liquidity::Taker)
+                        .map_err(internal)?
+                        .normalize()
+                        .to_string(),
+                    volume: fees::volume(
+                        state,
+                        &account.to_string(),
+                        &market.quote_asset,
+                        app.block_time,
+                    )
+                    .map_err(internal)?
+                    .to_string(),
+                    staked: fees::staked(state, &account.to_string())
+                        .map_err(internal)?
+                        .to_string(),
+                },
+            )
+        }
+        ["orderbook", market] => {
+            let depth = request.usize_param("depth", DEFAULT_DEPTH, MAX_LIMIT)?;
+            let book = app
+                .trading
+                .get_order_book(&market.to_string())
+                .ok_or_else(|| not_found("market", market))?;
+            single(app, book.snapshot(depth))
+        }
+        ["orders", trader] => {
+            let open_only = match request.params.get("status").map(String::as_str) {
+                None | Some("all") => false,
+                Some("open") => true,
+                Some(_) => return Err(QueryError::invalid("status", "must be 'open' or 'all'")),
+            };
+            let mut orders: Vec<_> = state
+                .orders
+                .values()
+                .filter(|order| &order.trader == trader && (!open_only || order.is_open()))
+                .cloned()
+                .collect();
+            orders.sort_by_key(|order| std::cmp::Reverse(order.id));
+            paginated(app, &request, orders)
+        }
+        ["option", id] => single(app, find(&state.options, "option", id)?),
+        ["chain", underlying, expiry] => {
+            let expiry = Expiry::parse(expiry)?;
+            let mut contracts: Vec<_> = state
+                .options
+                .values()
+                .filter(|option| {
+                    &option.underlying_asset == underlying && expiry.matches(&option.expiry_date)
+                })
+                .cloned()
+                .collect();
+            contracts.sort_by(|a, b| {
+                let strike =
+                    |o: &OptionContract| parse_decimal(&o.strike_price).unwrap_or_default();
+                strike(a).cmp(&strike(b)).then_with(|| {
+                    matches!(a.option_type, OptionType::Put)
+                        .cmp(&matches!(b.option_type, OptionType::Put))
+                })
+            });
+            paginated(app, &request, contracts)
+        }
+        ["portfolio", address] => {
+            let account = find(&state.accounts, "account", address)?;
+            single(
+                app,
+                portfolio::view(state, &account.address).map_err(internal)?,
+            )
+        }
+        ["params"] => single(app, &state.params),
+        ["oracle", "prices"] => {
+            let mut prices: Vec<_> = state.oracle.prices.values().cloned().collect();
+            prices.sort_by(|a, b| a.asset.cmp(&b.asset));
+            paginated(app, &request, prices)
+        }
+        ["oracle", "price", asset] => {
+            single(app, find(&state.oracle.prices, "oracle price", asset)?)
+        }
+        ["bridge", "deposit", source_chain, nonce] => {
+            let nonce: u64 = nonce
+                .parse()
+                .map_err(|_| QueryError::invalid("nonce", "must be a non-negative integer"))?;
+            let id = format!("{}:{}", source_chain, nonce);
+            single(
+                app,
+                DepositStatus {
+                    processed: state.bridge.deposits.contains(&id),
+                    id,
+                },
+            )
+        }
+        ["bridge", "withdrawals"] => {
+            let sender = request.params.get("sender");
+            let mut withdrawals: Vec<_> = state
+                .bridge
+                .withdrawals
+                .values()
+                .filter(|withdrawal| sender.is_none_or(|sender| &withdrawal.sender == sender))
+                .cloned()
+                .collect();
+            withdrawals.sort_by_key(|withdrawal| withdrawal.id);
+            paginated(app, &request, withdrawals)
+        }
+        ["bridge", "withdrawal", id] => {
+            let withdrawal = id
+                .parse()
+                .ok()
+                .and_then(|id: u64| state.bridge.withdrawals.get(&id))
+                .ok_or_else(|| not_found("withdrawal", id))?;
+            single(app, withdrawal)
+        }
+        ["validators"] => {
+            let mut validators: Vec<_> = state.validators.values().cloned().collect();
+            validators.sort_by(|a, b| {
+                let tokens = |v: &Validator| v.tokens.parse::<u128>().unwrap_or(0);
+                tokens(b)
+                    .cmp(&tokens(a))
+                    .then_with(|| a.operator_address.cmp(&b.operator_address))
+            });
+            paginated(app, &request, validators)
+        }
+        ["validator", address] => single(app, find(&state.validators, "validator", address)?),
+        ["validator", address, "delegations"] => {
+            find(&state.validators, "validator", address)?;
+            let delegations: Vec<_> = state
+                .staking
+                .delegations
+                .values()
+                .filter_map(|delegations| delegations.get(*address))
+                .collect();
+            paginated(app, &request, delegation_balances(app, delegations)?)
+        }
+        ["delegations", delegator] => {
+            let delegations: Vec<_> = state
+                .staking
+                .delegations
+                .get(*delegator)
+                .map(|delegations| delegations.values().collect())
+                .unwrap_or_default();
+            paginated(app, &request, delegation_balances(app, delegations)?)
+        }
+        ["rewards", delegator] => {
+            let mut validators: Vec<_> = state
+                .distribution
+                .starting_info
+                .get(*delegator)
+                .map(|delegations| delegations.keys().collect())
+                .unwrap_or_default();
+            validators.sort();
+            let rewards = validators
+                .into_iter()
+                .map(|validator| {
+                    Ok(DelegationRewards {
+                        validator: validator.clone(),
+                        rewards: distribution::pending_rewards(
+                            state,
+                            &delegator.to_string(),
+                            validator,
+                        )
+                        .map_err(internal)?,
+                    })
+                })
+                .collect::<Result<Vec<_>, QueryError>>()?;
+            paginated(app, &request, rewards)
+        }
+        ["commission", validator] => {
+            find(&state.validators, "validator", validator)?;
+            let commission = state
+                .distribution
+                .validators
+                .get(*validator)
+                .map(|rewards| rewards.commission.clone())
+                .unwrap_or_default();
+            single(
+                app,
+                ValidatorCommission {
+                    validator: validator.to_string(),
+                    commission,
+                },
+            )
+        }
+        ["signing_info", validator] => single(
+            app,
+            find(&state.slashing.signing_info, "signing info", validator)?,
+        ),
+        ["proposals"] => paginated(
+            app,
+            &request,
+            state.governance.proposals.values().cloned().collect(),
+        ),
+        ["proposal", id] => single(app, find_proposal(app, id)?),
+        ["proposal", id, "votes"] => {
+            let proposal = find_proposal(app, id)?;
+            let votes = state
+                .governance
+                .votes
+                .get(&proposal.id)
+                .into_iter()
+                .flatten()
+                .map(|(voter, option)| ProposalVote {
+                    voter: voter.clone(),
+                    option: *option,
+                })
+                .collect();
+            paginated(app, &request, votes)
+        }
+        ["proposal", id, "tally"] => {
+            let proposal = find_proposal(app, id)?;
+            match &proposal.final_tally {
+                Some(tally) => single(app, tally),
+                None => single(
+                    app,
+                    governance::tally(state, proposal.id).map_err(internal)?,
+                ),
+            }
+        }
+        ["unbonding", delegator] => {
+            let entries: Vec<_> = state
+                .staking
+                .unbonding
+                .iter()
+                .filter(|entry| &entry.delegator == delegator)
+                .cloned()
+                .collect();
+            paginated(app, &request, entries)
+        }
+        _ => Err(QueryError::UnknownPath(path.to_string())),
+    }
+}
+
+/// Value the delegations, sorted by delegator and validator
+fn delegation_balances(
+    app: &OptimicApp,
+    delegations: Vec<&Delegation>,
+) -> Result<Vec<DelegationBalance>, QueryError> {
+    let mut balances = delegations
+        .into_iter()
+        .map(|delegation| {
+            Ok(DelegationBalance {
+                delegator: delegation.delegator.clone(),
+                validator: delegation.validator.clone(),
+                shares: delegation.shares.clone(),
+                balance: staking::delegation_tokens(&app.state, delegation)
+                    .map_err(internal)?
+                    .to_string(),
+            })
+        })
+        .collect::<Result<Vec<_>, QueryError>>()?;
+    balances.sort_by(|a, b| (&a.delegator, &a.validator).cmp(&(&b.delegator, &b.validator)));
+    Ok(balances)
+}
+
+fn internal(error: impl std::fmt::Display) -> QueryError {
+    QueryError::Internal(error.to_string())
+}
+
+fn not_found(kind: &'static str, id: &str) -> QueryError {
+    QueryError::NotFound {
+        kind,
+        id: id.to_string(),
+    }
+}
+
+fn find<'a, K, V>(
+    map: &'a imbl::HashMap<K, V>,
+    kind: &'static str,
+    id: &str,
+) -> Result<&'a V, QueryError>
+where
+    K: std::borrow::Borrow<str> + std::hash::Hash + Eq,
+{
+    map.get(id).ok_or_else(|| not_found(kind, id))
+}
+
+fn find_proposal<'a>(app: &'a OptimicApp, id: &str) -> Result<&'a Proposal, QueryError> {
+    id.parse()
+        .ok()
+        .and_then(|id: u64| app.state.governance.proposals.get(&id))
+        .ok_or_else(|| not_found("proposal", id))
+}
+
+fn encode<T: Serialize>(response: &QueryResponse<T>) -> Result<Vec<u8>, QueryError> {
+    serde_json::to_vec(response).map_err(internal)
+}
+
+fn single<T: Serialize>(app: &OptimicApp, result: T) -> Result<Vec<u8>, QueryError> {
+    encode(&QueryResponse {
+        height: app.height,
+        result,
+        pagination: None,
+    })
+}
+
+fn paginated<T: Serialize>(
+    app: &OptimicApp,
+    request: &QueryRequest,
+    items: Vec<T>,
+) -> Result<Vec<u8>, QueryError> {
+    let (offset, limit) = request.page()?;
+    let total = items.len();
+    let page: Vec<T> = items.into_iter().skip(offset).take(limit).collect();
+    encode(&QueryResponse {
+        height: app.height,
+        result: page,
+        pagination: Some(PageInfo {
+            offset,
+            limit,
+            total,
+        }),
+    })
+}
+
+/// Expiry filter of an option chain query
+enum Expiry {
+    Date(NaiveDate),
+    Exact(Timestamp),
+}
+
+impl Expiry {
+    /// Parse a date (`2025-12-26`) or RFC 3339 timestamp
+    fn parse(value: &str) -> Result<Self, QueryError> {
+        if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+            return Ok(Expiry::Date(date));
+        }
+        value.parse().map(Expiry::Exact).map_err(|_| {
+            QueryError::invalid(
+                "expiry",
+                "must be a date (YYYY-MM-DD) or RFC 3339 timestamp",
+            )
+        })
+    }
+
+    fn matches(&self, expiry: &Timestamp) -> bool {
+        match self {
+            Expiry::Date(date) => expiry.date_naive() == *date,
+            Expiry::Exact(exact) => expiry == exact,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::AppConfig;
+    use serde_json::Value;
+
+    fn app_with_orders() -> OptimicApp {
+        let mut app = OptimicApp::new(AppConfig {
+            chain_id: "optimic-1".to_string(),
+            genesis_path: concat!(env!("CARGO_MANIFEST_DIR"), "/genesis.json").to_string(),
+            data_dir: "./data".to_string(),
+        });
+        app.load_genesis().unwrap();
+        app.height = 7;
+
+        let time = Timestamp::from_timestamp(0, 0).unwrap();
+        for (id, side, price) in [
+            (1, OrderSide::Buy, "99"),
+            (2, OrderSide::Buy, "100"),
+            (3, OrderSide::Sell, "101"),
+        ] {
+            let order = Order {
+                id,
+                trader: "optimic1genesis".to_string(),
+                market: "ETH-USD".to_string(),
+                side,
+                order_type: OrderType::Limit,
+                quantity: "1000000".to_string(),
+                price: Some(price.to_string()),
+                filled_quantity: "0".to_string(),
+                status: OrderStatus::Submitted,
+                created_at: time,
+                updated_at: time,
+                time_in_force: TimeInForce::GTC,
+                locked: "0".to_string(),
+                filled_value: "0".to_string(),
+            };
+            let mut next_trade_id = 0;
+            app.trading
+                .place_order(order, &mut app.state.orders, &mut next_trade_id, None)
+                .unwrap();
+        }
+        app
+    }
+
+    fn query(app: &OptimicApp, path: &str) -> Value {
+        serde_json::from_slice(&handle(app, path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_orderbook_depth_and_order_pagination() {
+        let app = app_with_orders();
+
+        let book = query(&app, "/orderbook/ETH-USD?depth=1");
+        assert_eq!(book["height"], 7);
+        assert_eq!(book["result"]["bids"].as_array().unwrap().len(), 1);
+        assert_eq!(book["result"]["bids"][0]["price"], "100");
+        assert_eq!(book["result"]["asks"][0]["price"], "101");
+
+        let orders = query(&app, "/orders/optimic1genesis?limit=2&offset=1");
+        assert_eq!(orders["pagination"]["total"], 3);
+        let ids: Vec<_> = orders["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|o| o["id"].clone())
+            .collect();
+        assert_eq!(ids, vec![2, 1]);
+    }
+
+    #[test]
+    fn test_query_error_codes() {
+        let app = app_with_orders();
+        let code = |path: &str| handle(&app, path).unwrap_err().code();
+
+        assert_eq!(code("/nope"), codes::QUERY_UNKNOWN_PATH);
+        assert_eq!(
+            code("/orders/optimic1genesis?limit=0"),
+            codes::QUERY_INVALID_PARAM
+        );
+        assert_eq!(code("/market/DOGE-USD"), codes::QUERY_NOT_FOUND);
+        assert_eq!(code("/params?height=3"), codes::QUERY_HEIGHT_UNAVAILABLE);
+        assert!(handle(&app, "/params?height=7").is_ok());
+    }
+}
@@ -0,0 +1,766 @@
+//! RPC Server
+//!
+//! This module serves the node over HTTP on `rpc_listen_addr`. Every
+//! endpoint is available both as a REST route and as a JSON-RPC 2.0 method
+//! posted to `/`; the OpenAPI description is served at `/openapi.json`.
+//! Real-time updates are pushed over a WebSocket at `/ws`.
+
+use crate::abci::{Block, TxResult};
+use crate::codes;
+use crate::events::BlockEvents;
+use crate::node::{read, write, SharedNode};
+use crate::query::QueryError;
+use crate::stream::{StreamMessage, StreamRequest, Subscriptions};
+use crate::tx::{tx_hash, TxHash};
+use crate::types::Timestamp;
+use anyhow::Result;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, RawQuery, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::future::Future;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, info};
+
+/// How long `commit` broadcasts wait for the transaction to be included
+pub const COMMIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// OpenAPI description of the REST routes
+const OPENAPI: &str = include_str!("openapi.json");
+
+// JSON-RPC 2.0 error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+const TIMEOUT: i64 = -32000;
+
+/// An RPC failure, returned as a JSON-RPC error object or REST error body
+#[derive(Debug, Clone, Serialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<ErrorData>,
+    #[serde(skip)]
+    status: StatusCode,
+}
+
+/// Details of an RPC failure caused by a typed error
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorData {
+    /// Result code, see [`crate::codes`]
+    pub code: u32,
+}
+
+impl RpcError {
+    fn new(status: StatusCode, code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+            status,
+        }
+    }
+
+    fn with_result_code(mut self, code: u32) -> Self {
+        self.data = Some(ErrorData { code });
+        self
+    }
+
+    fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, INVALID_PARAMS, message)
+    }
+
+    fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, INVALID_PARAMS, message)
+    }
+
+    fn internal(error: impl std::fmt::Display) -> Self {
+        Self::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            INTERNAL_ERROR,
+            error.to_string(),
+        )
+    }
+}
+
+impl From<QueryError> for RpcError {
+    fn from(error: QueryError) -> Self {
+        let (status, code) = match error {
+            QueryError::UnknownPath(_) | QueryError::NotFound { .. } => {
+                (StatusCode::NOT_FOUND, INVALID_PARAMS)
+            }
+            QueryError::InvalidParam { .. } | QueryError::HeightUnavailable { .. } => {
+                (StatusCode::BAD_REQUEST, INVALID_PARAMS)
+            }
+            QueryError::Internal(_) => (StatusCode::INTERNAL_SERVER_ERROR, INTERNAL_ERROR),
+        };
+        Self::new(status, code, error.to_string()).with_result_code(error.code())
+    }
+}
+
+impl IntoResponse for RpcError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self)).into_response()
+    }
+}
+
+/// How long a broadcast waits before returning
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BroadcastMode {
+    /// Return immediately, before the transaction is checked
+    Async,
+
+    /// Return once the transaction passed the mempool check
+    #[default]
+    Sync,
+
+    /// Return once the transaction was committed in a block
+    Commit,
+}
+
+/// Transaction broadcast request
+#[derive(Debug, Clone, Deserialize)]
+pub struct BroadcastRequest {
+    /// Hex-encoded transaction bytes
+    pub tx: String,
+
+    #[serde(default)]
+    pub mode: BroadcastMode,
+}
+
+/// Outcome of a broadcast
+#[derive(Debug, Clone, Serialize)]
+pub struct BroadcastResponse {
+    pub hash: TxHash,
+
+    /// Mempool check result (always 0 in async mode)
+    pub code: u32,
+    pub log: String,
+
+    /// Execution result, in commit mode when the check passed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deliver_tx: Option<TxResult>,
+}
+
+/// Node status
+#[derive(Debug, Clone, Serialize)]
+pub struct Status {
+    pub chain_id: String,
+    pub version: String,
+    pub latest_block_height: u64,
+    pub latest_block_hash: Option<String>,
+    pub latest_app_hash: Option<String>,
+    pub latest_block_time: Option<Timestamp>,
+    pub mempool_txs: usize,
+    pub mempool_bytes: u64,
+}
+
+/// Build the RPC router for `node`
+pub fn router(node: SharedNode) -> Router {
+    Router::new()
+        .route("/", post(json_rpc))
+        .route("/health", get(|| async { Json(json!({})) }))
+        .route("/status", get(rest_status))
+        .route("/openapi.json", get(openapi))
+        .route("/txs", post(rest_broadcast))
+        .route("/txs/:hash", get(rest_tx))
+        .route("/blocks/latest", get(rest_latest_block))
+        .route("/blocks/:height", get(rest_block))
+        .route("/blocks/:height/events", get(rest_block_events))
+        .route("/blocks/:height/results", get(rest_block_results))
+        .route("/blocks/hash/:hash", get(rest_block_by_hash))
+        .route("/query/*path", get(rest_query))
+        .route("/ws", get(websocket))
+        .with_state(node)
+}
+
+/// Serve RPC on `listener` until `shutdown` resolves
+pub async fn serve(
+    listener: TcpListener,
+    node: SharedNode,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<()> {
+    axum::serve(listener, router(node))
+        .with_graceful_shutdown(shutdown)
+        .await?;
+    info!("RPC server stopped");
+    Ok(())
+}
+
+async fn openapi() -> Response {
+    (
+        [(axum::http::header::CONTENT_TYPE, "application/json")],
+        OPENAPI,
+    )
+        .into_response()
+}
+
+async fn rest_status(State(node): State<SharedNode>) -> Result<Json<Status>, RpcError> {
+    status(&node).map(Json)
+}
+
+async fn rest_broadcast(
+    State(node): State<SharedNode>,
+    Json(request): Json<BroadcastRequest>,
+) -> Result<Json<BroadcastResponse>, RpcError> {
+    broadcast(&node, request).await.map(Json)
+}
+
+async fn rest_tx(
+    State(node): State<SharedNode>,
+    Path(hash): Path<String>,
+) -> Result<Json<TxResult>, RpcError> {
+    tx(&node, &hash).map(Json)
+}
+
+async fn rest_latest_block(State(node): State<SharedNode>) -> Result<Json<Block>, RpcError> {
+    block(&node, None).map(Json)
+}
+
+async fn rest_block(
+    State(node): State<SharedNode>,
+    Path(height): Path<String>,
+) -> Result<Json<Block>, RpcError> {
+    let height = height
+        .parse()
+        .map_err(|_| RpcError::invalid_params(format!("invalid height '{}'", height)))?;
+    block(&node, Some(height)).map(Json)
+}
+
+async fn rest_block_events(
+    State(node): State<SharedNode>,
+    Path(height): Path<String>,
+) -> Result<Json<BlockEvents>, RpcError> {
+    let height = height
+        .parse()
+        .map_err(|_| RpcError::invalid_params(format!("invalid height '{}'", height)))?;
+    block_events(&node, height).map(Json)
+}
+
+async fn rest_block_results(
+    State(node): State<SharedNode>,
+    Path(height): Path<String>,
+) -> Result<Json<Vec<TxResult>>, RpcError> {
+    let height = height
+        .parse()
+        .map_err(|_| RpcError::invalid_params(format!("invalid height '{}'", height)))?;
+    block_results(&node, height).map(Json)
+}
+
+async fn rest_block_by_hash(
+    State(node): State<SharedNode>,
+    Path(hash): Path<String>,
+) -> Result<Json<Block>, RpcError> {
+    block_by_hash(&node, &hash).map(Json)
+}
+
+async fn rest_query(
+    State(node): State<SharedNode>,
+    Path(path): Path<String>,
+    RawQuery(params): RawQuery,
+) -> Result<Response, RpcError> {
+    let path = match params {
+        Some(params) => format!("/{}?{}", path, params),
+        None => format!("/{}", path),
+    };
+    let bytes = query(&node, &path)?;
+    Ok((
+        [(axum::http::header::CONTENT_TYPE, "application/json")],
+        bytes,
+    )
+        .into_response())
+}
+
+async fn websocket(State(node): State<SharedNode>, upgrade: WebSocketUpgrade) -> Response {
+    upgrade.on_upgrade(move |socket| stream_updates(socket, node))
+}
+
+/// Push subscribed updates to a WebSocket client until it disconnects
+async fn stream_updates(mut socket: WebSocket, node: SharedNode) {
+    let mut updates = read(&node).streams().subscribe();
+    let mut subscriptions = Subscriptions::default();
+
+    loop {
+        let outgoing = tokio::select! {
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Text(text))) => handle_stream_request(&node, &mut subscriptions, &text),
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+            update = updates.recv() => match update {
+                Ok(batch) => {
+                    let node = read(&node);
+                    to_messages(subscriptions.filter(&batch, node.streams()))
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    debug!("WebSocket client lagged by {} blocks", skipped);
+                    let node = read(&node);
+                    let mut messages = vec![json!({ "type": "lagged", "skipped_blocks": skipped })];
+                    messages.extend(to_messages(subscriptions.resync(node.streams())));
+                    messages
+                }
+                Err(RecvError::Closed) => break,
+            },
+        };
+
+        for message in outgoing {
+            if socket
+                .send(Message::Text(message.to_string()))
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+    }
+}
+
+fn handle_stream_request(
+    node: &SharedNode,
+    subscriptions: &mut Subscriptions,
+    text: &str,
+) -> Vec<Value> {
+    let request = match serde_json::from_str::<StreamRequest>(text) {
+        Ok(request) => request,
+        Err(e) => return vec![json!({ "type": "error", "message": e.to_string() })],
+    };
+    let node = read(node);
+
+    match request {
+        StreamRequest::Subscribe(channel) => {
+            match subscriptions.subscribe(channel.clone(), node.abci().app(), node.streams()) {
+                Ok(snapshot) => {
+                    let mut messages =
+                        vec![json!({ "type": "subscribed", "subscription": channel })];
+                    messages.extend(to_messages(snapshot));
+                    messages
+                }
+                Err(message) => vec![json!({ "type": "error", "message": message })],
+            }
+        }
+        StreamRequest::Unsubscribe(channel) => {
+            subscriptions.unsubscribe(&channel);
+            vec![json!({ "type": "unsubscribed", "subscription": channel })]
+        }
+        StreamRequest::Snapshot { market } => match subscriptions.snapshot(&market, node.streams())
+        {
+            Some(snapshot) => to_messages(vec![snapshot]),
+            None => {
+                vec![json!({ "type": "error", "message": format!("unknown market '{}'", market) })]
+            }
+        },
+    }
+}
+
+fn to_messages(messages: Vec<StreamMessage>) -> Vec<Value> {
+    messages
+        .into_iter()
+        .filter_map(|message| serde_json::to_value(message).ok())
+        .collect()
+}
+
+/// JSON-RPC 2.0 request
+#[derive(Debug, Deserialize)]
+struct JsonRpcRequest {
+    jsonrpc: String,
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+async fn json_rpc(State(node): State<SharedNode>, body: String) -> Json<Value> {
+    let request: JsonRpcRequest = match serde_json::from_str(&body) {
+        Ok(request) => request,
+        Err(e) => {
+            let error = RpcError::new(StatusCode::BAD_REQUEST, PARSE_ERROR, e.to_string());
+            return Json(json!({ "jsonrpc": "2.0", "id": Value::Null, "error": error }));
+        }
+    };
+    debug!("JSON-RPC {}", request.method);
+
+    let result = dispatch(&node, &request).await;
+    Json(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": request.id, "result": result }),
+        Err(error) => json!({ "jsonrpc": "2.0", "id": request.id, "error": error }),
+    })
+}
+
+async fn dispatch(node: &SharedNode, request: &JsonRpcRequest) -> Result<Value, RpcError> {
+    if request.jsonrpc != "2.0" {
+        return Err(RpcError::new(
+            StatusCode::BAD_REQUEST,
+            INVALID_REQUEST,
+            "jsonrpc must be \"2.0\"",
+        ));
+    }
+
+    let param = |name: &str| request.params.get(name).and_then(Value::as_str);
+    let required = |name: &str| {
+        param(name).ok_or_else(|| RpcError::invalid_params(format!("missing parameter '{}'", name)))
+    };
+    let broadcast_with = |mode| async move {
+        let tx = required("tx")?.to_string();
+        broadcast(node, BroadcastRequest { tx, mode }).await
+    };
+
+    let result = match request.method.as_str() {
+        "health" => json!({}),
+        "status" => to_value(status(node)?)?,
+        "broadcast_tx_async" => to_value(broadcast_with(BroadcastMode::Async).await?)?,
+        "broadcast_tx_sync" => to_value(broadcast_with(BroadcastMode::Sync).await?)?,
+        "broadcast_tx_commit" => to_value(broadcast_with(BroadcastMode::Commit).await?)?,
+        "abci_query" => {
+            serde_json::from_slice(&query(node, required("path")?)?).map_err(RpcError::internal)?
+        }
+        "block" => to_value(block(node, height_param(&request.params)?)?)?,
+        "block_events" => {
+            let height =
+                height_param(&request.params)?.unwrap_or_else(|| read(node).abci().height());
+            to_value(block_events(node, height)?)?
+        }
+        "block_results" => {
+            let height =
+                height_param(&request.params)?.unwrap_or_else(|| read(node).abci().height());
+            to_value(block_results(node, height)?)?
+        }
+        "block_by_hash" => to_value(block_by_hash(node, required("hash")?)?)?,
+        "tx" => to_value(tx(node, required("hash")?)?)?,
+        method => {
+            return Err(RpcError::new(
+                StatusCode::NOT_FOUND,
+                METHOD_NOT_FOUND,
+                format!("unknown method '{}'", method),
+            ))
+        }
+    };
+    Ok(result)
+}
+
+/// Optional `height` parameter, given as a number or string
+fn height_param(params: &Value) -> Result<Option<u64>, RpcError> {
+    match params.get("height") {
+        None | Some(Value::Null) => Ok(None),
+        Some(height) => height
+            .as_u64()
+            .or_else(|| height.as_str().and_then(|h| h.parse().ok()))
+            .map(Some)
+            .ok_or_else(|| RpcError::invalid_params("height must be a block height")),
+    }
+}
+
+fn to_value(value: impl Serialize) -> Result<Value, RpcError> {
+    serde_json::to_value(value).map_err(RpcError::internal)
+}
+
+fn status(node: &SharedNode) -> Result<Status, RpcError> {
+    let node = read(node);
+    let height = node.abci().height();
+    let latest = node.abci().block(height).map_err(RpcError::internal)?;
+    Ok(Status {
+        chain_id: node.config().node.chain_id.clone(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        latest_block_height: height,
+        latest_block_hash: latest.as_ref().map(|block| block.hash.clone()),
+        latest_app_hash: latest.as_ref().map(|block| block.app_hash.clone()),
+        latest_block_time: latest.map(|block| block.time),
+        mempool_txs: node.mempool().len(),
+        mempool_bytes: node.mempool().size_bytes(),
+    })
+}
+
+fn query(node: &SharedNode, path: &str) -> Result<Vec<u8>, RpcError> {
+    Ok(read(node).abci().query(path, &[])?)
+}
+
+fn block(node: &SharedNode, height: Option<u64>) -> Result<Block, RpcError> {
+    let node = read(node);
+    let height = height.unwrap_or_else(|| node.abci().height());
+    node.abci()
+        .block(height)
+        .map_err(RpcError::internal)?
+        .ok_or_else(|| RpcError::not_found(format!("block {} not found", height)))
+}
+
+fn block_events(node: &SharedNode, height: u64) -> Result<BlockEvents, RpcError> {
+    read(node)
+        .abci()
+        .block_events(height)
+        .map_err(RpcError::internal)?
+        .ok_or_else(|| RpcError::not_found(format!("block {} not found", height)))
+}
+
+fn block_results(node: &SharedNode, height: u64) -> Result<Vec<TxResult>, RpcError> {
+    read(node)
+        .abci()
+        .block_results(height)
+        .map_err(RpcError::internal)?
+        .ok_or_else(|| RpcError::not_found(format!("block {} not found", height)))
+}
+
+fn block_by_hash(node: &SharedNode, hash: &str) -> Result<Block, RpcError> {
+    read(node)
+        .abci()
+        .block_by_hash(&hash.to_lowercase())
+        .map_err(RpcError::internal)?
+        .ok_or_else(|| RpcError::not_found(format!("block {} not found", hash)))
+}
+
+fn tx(node: &SharedNode, hash: &str) -> Result<TxResult, RpcError> {
+    read(node)
+        .abci()
+        .tx(&hash.to_lowercase())
+        .map_err(RpcError::internal)?
+        .ok_or_else(|| RpcError::not_found(format!("transaction {} not found", hash)))
+}
+
+async fn broadcast(
+    node: &SharedNode,
+    request: BroadcastRequest,
+) -> Result<BroadcastResponse, RpcError> {
+    let tx_bytes = hex::decode(request.tx.trim())
+        .map_err(|e| RpcError::invalid_params(format!("tx must be hex encoded: {}", e)))?;
+    let hash = tx_hash(&tx_bytes);
+
+    if request.mode == BroadcastMode::Async {
+        let node = node.clone();
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = write(&node).submit_tx(tx_bytes) {
+                debug!("Async broadcast rejected: {}", e);
+            }
+        });
+        return Ok(BroadcastResponse {
+            hash,
+            code: codes::OK,
+            log: String::new(),
+            deliver_tx: None,
+        });
+    }
+
+    // Subscribe before submitting so the including block cannot be missed
+    let (mut blocks, checked) = {
+        let mut node = write(node);
+        (node.subscribe(), node.submit_tx(tx_bytes))
+    };
+    let mut response = match checked {
+        Ok(_) => BroadcastResponse {
+            hash,
+            code: codes::OK,
+            log: String::new(),
+            deliver_tx: None,
+        },
+        Err(e) => BroadcastResponse {
+            hash,
+            code: codes::result_code(&e),
+            log: e.to_string(),
+            deliver_tx: None,
+        },
+    };
+    if request.mode == BroadcastMode::Sync || response.code != codes::OK {
+        return Ok(response);
+    }
+
+    let included = tokio::time::timeout(COMMIT_TIMEOUT, async {
+        loop {
+            match blocks.recv().await {
+                Ok(result) => {
+                    if let Some(tx) = result.tx_results.iter().find(|tx| tx.hash == response.hash) {
+                        return Ok(tx.clone());
+                    }
+                }
+                // Fell behind; the transaction may be in a skipped block
+                Err(RecvError::Lagged(_)) => {
+                    if let Ok(tx) = tx(node, &response.hash) {
+                        return Ok(tx);
+                    }
+                }
+                Err(RecvError::Closed) => return Err(RpcError::internal("node stopped")),
+            }
+        }
+    })
+    .await
+    .map_err(|_| {
+        RpcError::new(
+            StatusCode::GATEWAY_TIMEOUT,
+            TIMEOUT,
+            format!(
+                "timed out waiting for transaction {} to be committed",
+                response.hash
+            ),
+        )
+    })??;
+
+    response.deliver_tx = Some(included);
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NodeConfig;
+    use crate::init::{add_genesis_account, add_genesis_market, init_home, CONFIG_FILE};
+    use crate::keys::KeyPair;
+    use crate::node::Node;
+    use crate::tx::{Msg, Tx, TxBody};
+    use crate::types::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use std::sync::{Arc, RwLock};
+    use tower::ServiceExt;
+
+    fn setup(home: &std::path::Path, key: &KeyPair) -> SharedNode {
+        init_home(home, "optimic-test", false).unwrap();
+        add_genesis_account(home, &key.account_address(), "1000000USD,1000ETH").unwrap();
+        add_genesis_market(
+            home,
+            Market {
+                id: "ETH-USD".to_string(),
+                base_asset: "ETH".to_string(),
+                quote_asset: "USD".to_string(),
+                min_order_size: "1".to_string(),
+                lot_size: "1".to_string(),
+                tick_size: "0.01".to_string(),
+                market_type: MarketType::Spot,
+                status: MarketStatus::Active,
+                matching_mode: MatchingMode::Continuous,
+            },
+        )
+        .unwrap();
+        let config = NodeConfig::from_file(home.join(CONFIG_FILE)).unwrap();
+        Arc::new(RwLock::new(Node::new(config).unwrap()))
+    }
+
+    fn order_tx(key: &KeyPair, sequence: u64) -> String {
+        let body = TxBody {
+            chain_id: "optimic-test".to_string(),
+            sender: key.account_address(),
+            sequence,
+            timeout_height: 0,
+            msgs: vec![Msg::PlaceOrder {
+                market: "ETH-USD".to_string(),
+                side: OrderSide::Buy,
+                order_type: OrderType::Limit,
+                quantity: "10".to_string(),
+                price: Some("100".to_string()),
+                time_in_force: TimeInForce::GTC,
+            }],
+            memo: String::new(),
+        };
+        hex::encode(Tx::sign(body, key).unwrap().encode().unwrap())
+    }
+
+    async fn call(
+        node: &SharedNode,
+        method: &str,
+        uri: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))
+            .unwrap();
+        let response = router(node.clone()).oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_and_lookup() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = KeyPair::generate();
+        let node = setup(dir.path(), &key);
+
+        assert_eq!(call(&node, "GET", "/health", None).await.0, StatusCode::OK);
+        let (status, error) = call(&node, "POST", "/txs", Some(json!({ "tx": "zz" }))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error["code"], INVALID_PARAMS);
+        let (status, error) = call(&node, "GET", "/query/market/DOGE-USD", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(error["data"]["code"], codes::QUERY_NOT_FOUND);
+
+        let (_, early) = call(
+            &node,
+            "POST",
+            "/txs",
+            Some(json!({ "tx": order_tx(&key, 1) })),
+        )
+        .await;
+        assert_eq!(early["code"], codes::TX_SEQUENCE_MISMATCH);
+        let (_, synced) = call(
+            &node,
+            "POST",
+            "/txs",
+            Some(json!({ "tx": order_tx(&key, 0) })),
+        )
+        .await;
+        assert_eq!(synced["code"], codes::OK);
+        let hash = synced["hash"].as_str().unwrap().to_string();
+        write(&node).produce_block().unwrap();
+
+        let (_, tx) = call(&node, "GET", &format!("/txs/{}", hash), None).await;
+        assert_eq!(
+            (tx["height"].as_u64(), tx["code"].as_u64()),
+            (Some(1), Some(0))
+        );
+        assert_eq!(tx["events"][0]["type"], "OrderPlaced");
+        assert!(tx["gas_used"].as_u64().unwrap() > 0);
+        let (_, results) = call(&node, "GET", "/blocks/1/results", None).await;
+        assert_eq!(results[0]["hash"], hash);
+        let (_, events) = call(&node, "GET", "/blocks/1/events", None).await;
+        assert_eq!(events["txs"][0]["hash"], hash);
+
+        let rpc =
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "block", "params": { "height": 1 } });
+        let (_, block) = call(&node, "POST", "/", Some(rpc)).await;
+        assert_eq!(block["result"]["txs"][0], hash);
+
+        let addr = key.account_address();
+        let (_, orders) = call(
+            &node,
+            "GET",
+            &format!("/query/orders/{}?status=open", addr),
+            None,
+        )
+        .await;
+        assert_eq!(orders["pagination"]["total"], 1);
+        let (status, _) = call(&node, "GET", "/query/market/DOGE-USD", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_commit_waits_for_block() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = KeyPair::generate();
+        let node = setup(dir.path(), &key);
+
+        let request = json!({ "tx": order_tx(&key, 0), "mode": "commit" });
+        let pending = tokio::spawn({
+            let node = node.clone();
+            async move { call(&node, "POST", "/txs", Some(request)).await }
+        });
+        while read(&node).mempool().is_empty() {
+            tokio::task::yield_now().await;
+        }
+        write(&node).produce_block().unwrap();
+
+        let (_, committed) = pending.await.unwrap();
+        assert_eq!(committed["deliver_tx"]["height"], 1);
+        assert_eq!(committed["deliver_tx"]["code"], codes::OK);
+    }
+}
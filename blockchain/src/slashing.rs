@@ -0,0 +1,540 @@
+//! Slashing Module
+//!
+//! This module punishes validators for downtime and double-signing.
+//! Consensus reports which active validators signed the previous block;
+//! a validator that misses too many blocks of the signing window is slashed
+//! and jailed for a while. Double-sign evidence slashes harder and
+//! tombstones the validator, which jails it for good.
+//!
+//! Slashing burns part of the validator's bonded tokens, so every
+//! delegation loses value, and part of the unbonding entries started after
+//! the infraction. Redelegations move stake at once and are not tracked, so
+//! stake redelegated away after an infraction is not slashed.
+
+use crate::app::AppState;
+use crate::codes;
+use crate::consensus::{CommitInfo, Evidence};
+use crate::events::{Event, Infraction};
+use crate::keys::operator_of;
+use crate::math::{parse_fraction, NumberError};
+use crate::staking::{self, StakingError};
+use crate::types::*;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use thiserror::Error;
+
+type Result<T> = std::result::Result<T, SlashingError>;
+
+/// Blocks between an infraction and the block that reports it; votes of
+/// height `h` arrive with block `h + 1`, and power changes apply one block late
+const VALIDATOR_UPDATE_DELAY: u64 = 2;
+
+/// Reasons a slashing operation is rejected
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SlashingError {
+    #[error("unknown validator {0}")]
+    UnknownValidator(ValAddress),
+
+    #[error("validator {0} is not jailed")]
+    NotJailed(ValAddress),
+
+    #[error("validator {0} is tombstoned and cannot be unjailed")]
+    Tombstoned(ValAddress),
+
+    #[error("validator {validator} is jailed until {jailed_until}")]
+    StillJailed {
+        validator: ValAddress,
+        jailed_until: Timestamp,
+    },
+
+    #[error(transparent)]
+    Staking(#[from] StakingError),
+
+    #[error(transparent)]
+    Number(#[from] NumberError),
+}
+
+impl SlashingError {
+    /// Stable result code, see [`crate::codes`]
+    pub fn code(&self) -> u32 {
+        match self {
+            SlashingError::UnknownValidator(_) => codes::SLASHING_UNKNOWN_VALIDATOR,
+            SlashingError::NotJailed(_) => codes::SLASHING_NOT_JAILED,
+            SlashingError::Tombstoned(_) => codes::SLASHING_TOMBSTONED,
+            SlashingError::StillJailed { .. } => codes::SLASHING_STILL_JAILED,
+            SlashingError::Staking(error) => error.code(),
+            SlashingError::Number(error) => error.code(),
+        }
+    }
+}
+
+/// Slashing parameters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlashingParams {
+    /// Blocks over which signatures are counted
+    pub signed_blocks_window: u64,
+
+    /// Fraction of the window a validator must sign
+    pub min_signed_per_window: Price,
+
+    /// Seconds a validator stays jailed for downtime
+    pub downtime_jail_duration: u64,
+
+    /// Fraction of stake burned for downtime
+    pub slash_fraction_downtime: Price,
+
+    /// Fraction of stake burned for double-signing
+    pub slash_fraction_double_sign: Price,
+
+    /// Blocks after which double-sign evidence is ignored
+    pub max_evidence_age: u64,
+}
+
+impl Default for SlashingParams {
+    fn default() -> Self {
+        Self {
+            signed_blocks_window: 10_000,
+            min_signed_per_window: "0.5".to_string(), // 50%
+            downtime_jail_duration: 10 * 60,          // 10 minutes
+            slash_fraction_downtime: "0.0001".to_string(), // 0.01%
+            slash_fraction_double_sign: "0.05".to_string(), // 5%
+            max_evidence_age: 21 * 24 * 60 * 60,      // 21 days of 1 second blocks
+        }
+    }
+}
+
+/// Slashing state
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SlashingState {
+    /// Signing records by validator
+    pub signing_info: imbl::HashMap<ValAddress, SigningInfo>,
+}
+
+/// Signing record of a validator over the sliding window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SigningInfo {
+    /// Height tracking started at, or restarted at after a downtime slash
+    pub start_height: u64,
+
+    /// Blocks counted since `start_height`
+    pub index_offset: u64,
+
+    /// Window positions (`index_offset % window`) of the missed blocks
+    pub missed_blocks: BTreeSet<u64>,
+
+    /// Time an unjail is accepted from
+    pub jailed_until: Option<Timestamp>,
+
+    /// Slashed for double-signing; can never unjail
+    pub tombstoned: bool,
+}
+
+impl SigningInfo {
+    fn new(start_height: u64) -> Self {
+        Self {
+            start_height,
+            index_offset: 0,
+            missed_blocks: BTreeSet::new(),
+            jailed_until: None,
+            tombstoned: false,
+        }
+    }
+}
+
+/// Validate slashing parameters, collecting every problem
+pub fn validate_params(params: &SlashingParams, errors: &mut Vec<String>) {
+    if params.signed_blocks_window == 0 {
+        errors.push("params.slashing.signed_blocks_window must be positive".to_string());
+    }
+    for (name, value) in [
+        ("min_signed_per_window", &params.min_signed_per_window),
+        ("slash_fraction_downtime", &params.slash_fraction_downtime),
+        (
+            "slash_fraction_double_sign",
+            &params.slash_fraction_double_sign,
+        ),
+    ] {
+        if let Err(e) = parse_fraction(value) {
+            errors.push(format!("params.slashing.{}: {}", name, e));
+        }
+    }
+}
+
+/// Handle double-sign evidence and the signatures of the previous block
+pub fn handle_commit(
+    state: &mut AppState,
+    height: u64,
+    time: Timestamp,
+    commit: &CommitInfo,
+) -> Result<Vec<Event>> {
+    let mut events = Vec::new();
+    for evidence in &commit.evidence {
+        events.extend(handle_double_sign(state, height, evidence)?);
+    }
+    for vote in &commit.votes {
+        events.extend(handle_signature(
+            state,
+            height,
+            time,
+            &vote.validator,
+            vote.signed,
+        )?);
+    }
+    Ok(events)
+}
+
+/// Check that the sender's validator can leave jail
+pub fn validate_unjail(state: &AppState, time: Timestamp, sender: &AccAddress) -> Result<()> {
+    let validator =
+        operator_of(sender).ok_or_else(|| SlashingError::UnknownValidator(sender.clone()))?;
+    let record = state
+        .validators
+        .get(&validator)
+        .ok_or_else(|| SlashingError::UnknownValidator(validator.clone()))?;
+    if !record.jailed {
+        return Err(SlashingError::NotJailed(validator));
+    }
+    let Some(info) = state.slashing.signing_info.get(&validator) else {
+        return Ok(());
+    };
+    if info.tombstoned {
+        return Err(SlashingError::Tombstoned(validator));
+    }
+    if let Some(jailed_until) = info.jailed_until.filter(|until| time < *until) {
+        return Err(SlashingError::StillJailed {
+            validator,
+            jailed_until,
+        });
+    }
+    Ok(())
+}
+
+/// Release the sender's validator from jail; it rejoins the active set at
+/// the end of the block if its stake is large enough
+pub fn unjail(state: &mut AppState, time: Timestamp, sender: &AccAddress) -> Result<Vec<Event>> {
+    validate_unjail(state, time, sender)?;
+    let validator = operator_of(sender).expect("sender operates a validator");
+    state
+        .validators
+        .get_mut(&validator)
+        .expect("validator exists")
+        .jailed = false;
+    Ok(vec![Event::ValidatorUnjailed { validator }])
+}
+
+/// Count a signature or a miss, slashing and jailing the validator when it
+/// misses too much of the window
+fn handle_signature(
+    state: &mut AppState,
+    height: u64,
+    time: Timestamp,
+    validator: &ValAddress,
+    signed: bool,
+) -> Result<Vec<Event>> {
+    if state
+        .validators
+        .get(validator)
+        .is_none_or(|record| record.jailed)
+    {
+        return Ok(Vec::new());
+    }
+    let params = state.params.slashing.clone();
+    let window = params.signed_blocks_window.max(1);
+    let info = state
+        .slashing
+        .signing_info
+        .entry(validator.clone())
+        .or_insert_with(|| SigningInfo::new(height));
+    let index = info.index_offset % window;
+    info.index_offset += 1;
+    if signed {
+        info.missed_blocks.remove(&index);
+    } else {
+        info.missed_blocks.insert(index);
+    }
+
+    let min_signed = (Decimal::from(window) * parse_fraction(&params.min_signed_per_window)?)
+        .floor()
+        .to_u64()
+        .unwrap_or(window);
+    let missed = info.missed_blocks.len() as u64;
+    if height <= info.start_height + window || missed <= window - min_signed {
+        return Ok(Vec::new());
+    }
+
+    // Restart the window so the validator is judged afresh after unjailing
+    let jailed_until = time
+        + chrono::TimeDelta::seconds(
+            i64::try_from(params.downtime_jail_duration).unwrap_or(i64::MAX),
+        );
+    *info = SigningInfo {
+        jailed_until: Some(jailed_until),
+        ..SigningInfo::new(height)
+    };
+    let fraction = parse_fraction(&params.slash_fraction_downtime)?;
+    let infraction_height = height.saturating_sub(VALIDATOR_UPDATE_DELAY);
+    let power = state
+        .staking
+        .last_powers
+        .get(validator)
+        .copied()
+        .unwrap_or(0);
+    let amount = staking::slash(state, height, validator, infraction_height, power, fraction)?;
+    state
+        .validators
+        .get_mut(validator)
+        .expect("validator exists")
+        .jailed = true;
+
+    Ok(vec![
+        Event::ValidatorSlashed {
+            validator: validator.clone(),
+            infraction: Infraction::Downtime,
+            infraction_height,
+            amount: amount.to_string(),
+        },
+        Event::ValidatorJailed {
+            validator: validator.clone(),
+            jailed_until: Some(jailed_until),
+        },
+    ])
+}
+
+/// Slash, jail and tombstone a validator that signed twice at one height
+fn handle_double_sign(
+    state: &mut AppState,
+    height: u64,
+    evidence: &Evidence,
+) -> Result<Vec<Event>> {
+    let params = state.params.slashing.clone();
+    let too_old = height.saturating_sub(evidence.height) > params.max_evidence_age;
+    let tombstoned = state
+        .slashing
+        .signing_info
+        .get(&evidence.validator)
+        .is_some_and(|info| info.tombstoned);
+    if too_old || tombstoned || !state.validators.contains_key(&evidence.validator) {
+        return Ok(Vec::new());
+    }
+
+    let fraction = parse_fraction(&params.slash_fraction_double_sign)?;
+    let power = u64::try_from(evidence.power).unwrap_or(0);
+    let amount = staking::slash(
+        state,
+        height,
+        &evidence.validator,
+        evidence.height,
+        power,
+        fraction,
+    )?;
+    state
+        .validators
+        .get_mut(&evidence.validator)
+        .expect("validator exists")
+        .jailed = true;
+    let info = state
+        .slashing
+        .signing_info
+        .entry(evidence.validator.clone())
+        .or_insert_with(|| SigningInfo::new(height));
+    info.tombstoned = true;
+    info.jailed_until = None;
+
+    Ok(vec![
+        Event::ValidatorSlashed {
+            validator: evidence.validator.clone(),
+            infraction: Infraction::DoubleSign,
+            infraction_height: evidence.height,
+            amount: amount.to_string(),
+        },
+        Event::ValidatorJailed {
+            validator: evidence.validator.clone(),
+            jailed_until: None,
+        },
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::test_state;
+    use crate::bank;
+    use crate::consensus::VoteInfo;
+    use crate::keys::KeyPair;
+    use chrono::TimeDelta;
+
+    const OMC: u128 = 1_000_000;
+
+    /// A validator with 100 OMC of its own stake and 100 OMC delegated,
+    /// judged over windows of 10 blocks
+    fn setup() -> (AppState, KeyPair, AccAddress, ValAddress) {
+        let mut state = test_state(&["OMC"]);
+        state.params.slashing = SlashingParams {
+            signed_blocks_window: 10,
+            min_signed_per_window: "0.5".to_string(),
+            downtime_jail_duration: 600,
+            slash_fraction_downtime: "0.01".to_string(),
+            slash_fraction_double_sign: "0.1".to_string(),
+            max_evidence_age: 100,
+        };
+        let native = state.params.native_token.clone();
+        let (operator, delegator) = (KeyPair::generate(), KeyPair::generate().account_address());
+        for account in [&operator.account_address(), &delegator] {
+            bank::mint(&mut state, account, &native, 1000 * OMC).unwrap();
+        }
+
+        let commission = Commission {
+            rate: "0.1".to_string(),
+            max_rate: "0.2".to_string(),
+            max_change_rate: "0.01".to_string(),
+            update_time: None,
+        };
+        let (sender, pubkey) = (operator.account_address(), operator.public_key_hex());
+        let stake = (100 * OMC).to_string();
+        staking::create_validator(
+            &mut state,
+            1,
+            Timestamp::UNIX_EPOCH,
+            &sender,
+            &pubkey,
+            &commission,
+            &stake,
+        )
+        .unwrap();
+        let validator = operator_of(&sender).unwrap();
+        staking::delegate(&mut state, 1, &delegator, &validator, &stake).unwrap();
+        staking::end_block(&mut state, Timestamp::UNIX_EPOCH).unwrap();
+        (state, operator, delegator, validator)
+    }
+
+    fn votes(validator: &ValAddress, signed: bool) -> CommitInfo {
+        CommitInfo {
+            votes: vec![VoteInfo {
+                validator: validator.clone(),
+                signed,
+            }],
+            evidence: vec![],
+        }
+    }
+
+    fn at(seconds: i64) -> Timestamp {
+        Timestamp::UNIX_EPOCH + TimeDelta::seconds(seconds)
+    }
+
+    #[test]
+    fn test_double_sign_slashes_unbonding_entries_after_infraction() {
+        let (mut state, operator, delegator, validator) = setup();
+        let undelegate = |state: &mut AppState, height: u64, amount: u128| {
+            staking::undelegate(
+                state,
+                height,
+                at(0),
+                &delegator,
+                &validator,
+                &amount.to_string(),
+            )
+            .unwrap();
+        };
+        undelegate(&mut state, 2, 20 * OMC);
+        undelegate(&mut state, 5, 50 * OMC);
+
+        // 10% of the 200 OMC at stake at height 3: 5 OMC out of the entry
+        // started after the infraction, the other 15 OMC from the bond
+        let evidence = CommitInfo {
+            votes: vec![],
+            evidence: vec![Evidence {
+                validator: validator.clone(),
+                height: 3,
+                power: 200,
+            }],
+        };
+        let events = handle_commit(&mut state, 6, at(6), &evidence).unwrap();
+        assert!(matches!(
+            &events[..],
+            [
+                Event::ValidatorSlashed { infraction: Infraction::DoubleSign, infraction_height: 3, amount, .. },
+                Event::ValidatorJailed { jailed_until: None, .. },
+            ] if amount == &(20 * OMC).to_string()
+        ));
+        let amounts: Vec<_> = state
+            .staking
+            .unbonding
+            .iter()
+            .map(|entry| entry.amount.clone())
+            .collect();
+        assert_eq!(amounts, [(20 * OMC).to_string(), (45 * OMC).to_string()]);
+        assert_eq!(state.validators[&validator].tokens, (115 * OMC).to_string());
+        staking::check_invariants(&state).unwrap();
+        bank::check_invariants(&state).unwrap();
+
+        // A tombstoned validator is not slashed again and stays jailed
+        assert!(handle_commit(&mut state, 7, at(7), &evidence)
+            .unwrap()
+            .is_empty());
+        let err = validate_unjail(&state, at(10_000), &operator.account_address()).unwrap_err();
+        assert_eq!(err.code(), codes::SLASHING_TOMBSTONED);
+    }
+
+    #[test]
+    fn test_old_evidence_is_ignored() {
+        let (mut state, _, _, validator) = setup();
+        let evidence = CommitInfo {
+            votes: vec![],
+            evidence: vec![Evidence {
+                validator: validator.clone(),
+                height: 1,
+                power: 200,
+            }],
+        };
+        assert!(handle_commit(&mut state, 102, at(102), &evidence)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            handle_commit(&mut state, 101, at(101), &evidence)
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_downtime_jails_once_window_is_missed() {
+        let (mut state, operator, _, validator) = setup();
+        let sender = operator.account_address();
+
+        let commit = |state: &mut AppState, height: u64, signed: bool| {
+            handle_commit(state, height, at(height as i64), &votes(&validator, signed)).unwrap()
+        };
+        // Signing every other block is enough
+        for height in 1..=30 {
+            assert!(commit(&mut state, height, height % 2 == 0).is_empty());
+        }
+        // A full window is counted before anyone is judged
+        state.slashing.signing_info.clear();
+        for height in 31..=41 {
+            assert!(commit(&mut state, height, false).is_empty());
+        }
+        let events = commit(&mut state, 42, false);
+        assert!(matches!(
+            &events[..],
+            [
+                Event::ValidatorSlashed { infraction: Infraction::Downtime, infraction_height: 40, amount, .. },
+                Event::ValidatorJailed { jailed_until: Some(_), .. },
+            ] if amount == &(2 * OMC).to_string()
+        ));
+        assert!(state.validators[&validator].jailed);
+        // Jailed validators are not counted
+        assert!(commit(&mut state, 43, false).is_empty());
+
+        let err = validate_unjail(&state, at(641), &sender).unwrap_err();
+        assert_eq!(err.code(), codes::SLASHING_STILL_JAILED);
+        unjail(&mut state, at(642), &sender).unwrap();
+        let err = validate_unjail(&state, at(643), &sender).unwrap_err();
+        assert_eq!(err.code(), codes::SLASHING_NOT_JAILED);
+        let stranger = KeyPair::generate().account_address();
+        let err = validate_unjail(&state, at(643), &stranger).unwrap_err();
+        assert_eq!(err.code(), codes::SLASHING_UNKNOWN_VALIDATOR);
+    }
+}
@@ -0,0 +1,1303 @@
+//! Staking Module
+//!
+//! This module bonds native tokens to validators. Delegators receive shares
+//! of a validator's bonded tokens, so anything that later changes the
+//! tokens (rewards, slashing) is shared pro rata. Bonded tokens are held by
+//! the bonded pool account; undelegated tokens wait in the unbonding pool
+//! for the unbonding period before they are returned.
+//!
+//! At the end of every block the top `max_validators` unjailed validators
+//! by bonded tokens form the active set. Validators that leave the set
+//! unbond over the same period, and every change of voting power is
+//! reported as a validator update.
+
+use crate::app::AppState;
+use crate::bank::{self, BankError};
+use crate::codes;
+use crate::consensus::ValidatorUpdate;
+use crate::distribution::{self, DistributionError};
+use crate::events::Event;
+use crate::keys::{account_of, operator_of};
+use crate::math::{notional, parse_amount, parse_fraction, NumberError};
+use crate::portfolio::{self, PortfolioError};
+use crate::types::*;
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use thiserror::Error;
+
+type Result<T> = std::result::Result<T, StakingError>;
+
+/// Module account holding the tokens bonded to validators
+pub const BONDED_POOL: &str = "optimic1bondedpool";
+
+/// Module account holding undelegated tokens until they are released
+pub const UNBONDING_POOL: &str = "optimic1unbondingpool";
+
+/// Seconds of block time between two commission changes
+pub const COMMISSION_CHANGE_INTERVAL: i64 = 24 * 60 * 60;
+
+/// Reasons a staking operation is rejected
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum StakingError {
+    #[error("unknown validator {0}")]
+    UnknownValidator(ValAddress),
+
+    #[error("validator {0} already exists")]
+    ValidatorExists(ValAddress),
+
+    #[error("{delegator} has no delegation to {validator}")]
+    NoDelegation {
+        delegator: AccAddress,
+        validator: ValAddress,
+    },
+
+    #[error(
+        "delegation of {delegator} to {validator} is worth {available}, less than {requested}"
+    )]
+    InsufficientDelegation {
+        delegator: AccAddress,
+        validator: ValAddress,
+        requested: Uint128,
+        available: Uint128,
+    },
+
+    #[error("{delegator} already has {max} unbonding entries with {validator}")]
+    TooManyUnbondingEntries {
+        delegator: AccAddress,
+        validator: ValAddress,
+        max: u32,
+    },
+
+    #[error("cannot redelegate from {0} to itself")]
+    SelfRedelegation(ValAddress),
+
+    #[error("staking amount must be positive")]
+    ZeroAmount,
+
+    #[error("invalid commission: {0}")]
+    InvalidCommission(String),
+
+    #[error("invalid consensus public key '{0}'")]
+    InvalidPubkey(String),
+
+    #[error("commission of {validator} cannot change again before {next_change}")]
+    CommissionChangeTooSoon {
+        validator: ValAddress,
+        next_change: Timestamp,
+    },
+
+    #[error("commission change of {change} exceeds the max_change_rate of {max_change_rate}")]
+    CommissionChangeTooLarge {
+        change: Price,
+        max_change_rate: Price,
+    },
+
+    #[error("validator {0} has shares but no tokens")]
+    InvalidExchangeRate(ValAddress),
+
+    #[error("staking invariant violated: {0}")]
+    Invariant(String),
+
+    #[error(transparent)]
+    Distribution(#[from] DistributionError),
+
+    #[error(transparent)]
+    Bank(#[from] BankError),
+
+    #[error(transparent)]
+    Portfolio(#[from] PortfolioError),
+
+    #[error(transparent)]
+    Number(#[from] NumberError),
+}
+
+impl StakingError {
+    /// Stable result code, see [`crate::codes`]
+    pub fn code(&self) -> u32 {
+        match self {
+            StakingError::UnknownValidator(_) => codes::STAKING_UNKNOWN_VALIDATOR,
+            StakingError::ValidatorExists(_) => codes::STAKING_VALIDATOR_EXISTS,
+            StakingError::NoDelegation { .. } => codes::STAKING_NO_DELEGATION,
+            StakingError::InsufficientDelegation { .. } => codes::STAKING_INSUFFICIENT_DELEGATION,
+            StakingError::TooManyUnbondingEntries { .. } => {
+                codes::STAKING_TOO_MANY_UNBONDING_ENTRIES
+            }
+            StakingError::SelfRedelegation(_) => codes::STAKING_SELF_REDELEGATION,
+            StakingError::ZeroAmount => codes::STAKING_ZERO_AMOUNT,
+            StakingError::InvalidCommission(_) => codes::STAKING_INVALID_COMMISSION,
+            StakingError::InvalidPubkey(_) => codes::STAKING_INVALID_PUBKEY,
+            StakingError::CommissionChangeTooSoon { .. } => {
+                codes::STAKING_COMMISSION_CHANGE_TOO_SOON
+            }
+            StakingError::CommissionChangeTooLarge { .. } => {
+                codes::STAKING_COMMISSION_CHANGE_TOO_LARGE
+            }
+            StakingError::InvalidExchangeRate(_) => codes::STAKING_INVALID_EXCHANGE_RATE,
+            StakingError::Invariant(_) => codes::STAKING_INVARIANT,
+            StakingError::Distribution(error) => error.code(),
+            StakingError::Bank(error) => error.code(),
+            StakingError::Portfolio(error) => error.code(),
+            StakingError::Number(error) => error.code(),
+        }
+    }
+}
+
+/// Staking parameters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StakingParams {
+    /// Size of the active validator set
+    pub max_validators: u32,
+
+    /// Seconds undelegated tokens wait before they are released
+    pub unbonding_time: u64,
+
+    /// Unbonding entries a delegator may have per validator
+    pub max_entries: u32,
+
+    /// Bonded tokens per unit of voting power
+    pub power_reduction: Uint128,
+}
+
+impl Default for StakingParams {
+    fn default() -> Self {
+        Self {
+            max_validators: 100,
+            unbonding_time: 21 * 24 * 60 * 60, // 21 days
+            max_entries: 7,
+            power_reduction: "1000000".to_string(), // 1 OMC
+        }
+    }
+}
+
+/// Staking state
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StakingState {
+    /// Delegations by delegator and validator
+    pub delegations: imbl::HashMap<AccAddress, HashMap<ValAddress, Delegation>>,
+
+    /// Undelegations waiting to be released
+    pub unbonding: imbl::Vector<UnbondingEntry>,
+
+    /// Id of the latest unbonding entry
+    pub last_unbonding_id: u64,
+
+    /// Validators that left the active set, by the time they finish unbonding
+    pub unbonding_validators: imbl::HashMap<ValAddress, Timestamp>,
+
+    /// Voting power of the active set as last reported
+    pub last_powers: imbl::HashMap<ValAddress, u64>,
+}
+
+/// Shares of a validator held by a delegator
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Delegation {
+    pub delegator: AccAddress,
+    pub validator: ValAddress,
+    pub shares: Uint128,
+}
+
+/// Tokens undelegated from a validator, released at `completion_time`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnbondingEntry {
+    pub id: u64,
+    pub delegator: AccAddress,
+    pub validator: ValAddress,
+
+    /// Tokens left to release, after any slashing
+    pub amount: Uint128,
+
+    /// Tokens undelegated
+    pub initial_balance: Uint128,
+
+    /// Height the undelegation was made at
+    pub creation_height: u64,
+
+    pub completion_time: Timestamp,
+}
+
+/// Validate staking parameters, collecting every problem
+pub fn validate_params(params: &StakingParams, errors: &mut Vec<String>) {
+    if params.max_validators == 0 {
+        errors.push("params.staking.max_validators must be positive".to_string());
+    }
+    if params.unbonding_time == 0 {
+        errors.push("params.staking.unbonding_time must be positive".to_string());
+    }
+    if params.max_entries == 0 {
+        errors.push("params.staking.max_entries must be positive".to_string());
+    }
+    match parse_amount(&params.power_reduction) {
+        Ok(0) => errors.push("params.staking.power_reduction must be positive".to_string()),
+        Ok(_) => {}
+        Err(e) => errors.push(format!("params.staking.power_reduction: {}", e)),
+    }
+}
+
+/// Check that a commission's rates are fractions with `rate <= max_rate`
+/// and `max_change_rate <= max_rate`
+pub fn validate_commission(commission: &Commission) -> Result<()> {
+    let invalid = |e: NumberError| StakingError::InvalidCommission(e.to_string());
+    let rate = parse_fraction(&commission.rate).map_err(invalid)?;
+    let max_rate = parse_fraction(&commission.max_rate).map_err(invalid)?;
+    let max_change_rate = parse_fraction(&commission.max_change_rate).map_err(invalid)?;
+    if rate > max_rate {
+        return Err(StakingError::InvalidCommission(
+            "rate exceeds max_rate".to_string(),
+        ));
+    }
+    if max_change_rate > max_rate {
+        return Err(StakingError::InvalidCommission(
+            "max_change_rate exceeds max_rate".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Back the tokens of genesis validators with self-delegations held in the
+/// bonded pool and compute the initial active set
+pub fn init_genesis(state: &mut AppState, time: Timestamp) -> Result<()> {
+    let mut operators: Vec<_> = state.validators.keys().cloned().collect();
+    operators.sort();
+    for operator in operators {
+        let delegated = state
+            .staking
+            .delegations
+            .values()
+            .any(|delegations| delegations.contains_key(&operator));
+        let validator = &state.validators[&operator];
+        let tokens = parse_amount(&validator.tokens)?;
+        if delegated || tokens == 0 {
+            continue;
+        }
+
+        let shares = match parse_amount(&validator.delegator_shares)? {
+            0 => tokens,
+            shares => shares,
+        };
+        let delegator = account_of(&operator).unwrap_or_else(|| operator.clone());
+        state
+            .validators
+            .get_mut(&operator)
+            .expect("validator exists")
+            .delegator_shares = shares.to_string();
+        state
+            .staking
+            .delegations
+            .entry(delegator.clone())
+            .or_default()
+            .insert(
+                operator.clone(),
+                Delegation {
+                    delegator: delegator.clone(),
+                    validator: operator.clone(),
+                    shares: shares.to_string(),
+                },
+            );
+        distribution::after_delegation_modified(state, 0, &delegator, &operator)?;
+        bank::ensure_account(state, &BONDED_POOL.to_string());
+        portfolio::credit(
+            state,
+            &BONDED_POOL.to_string(),
+            &state.params.native_token.clone(),
+            tokens,
+        )?;
+    }
+    apply_validator_set(state, time)?;
+    Ok(())
+}
+
+/// Check that `sender` can create a validator bonding `amount`
+pub fn validate_create_validator(
+    state: &AppState,
+    sender: &AccAddress,
+    consensus_pubkey: &str,
+    commission: &Commission,
+    amount: &Uint128,
+) -> Result<()> {
+    let operator = operator(sender)?;
+    if state.validators.contains_key(&operator) {
+        return Err(StakingError::ValidatorExists(operator));
+    }
+    if !hex::decode(consensus_pubkey).is_ok_and(|key| key.len() == 32) {
+        return Err(StakingError::InvalidPubkey(consensus_pubkey.to_string()));
+    }
+    validate_commission(commission)?;
+    let amount = positive(amount)?;
+    portfolio::ensure_available(state, sender, &state.params.native_token, amount)?;
+    Ok(())
+}
+
+/// Create the validator operated by `sender` with a self-delegation of `amount`
+pub fn create_validator(
+    state: &mut AppState,
+    height: u64,
+    time: Timestamp,
+    sender: &AccAddress,
+    consensus_pubkey: &str,
+    commission: &Commission,
+    amount: &Uint128,
+) -> Result<Vec<Event>> {
+    validate_create_validator(state, sender, consensus_pubkey, commission, amount)?;
+    let operator = operator(sender)?;
+    state.validators.insert(
+        operator.clone(),
+        Validator {
+            operator_address: operator.clone(),
+            consensus_pubkey: consensus_pubkey.to_string(),
+            jailed: false,
+            status: BondStatus::Unbonded,
+            tokens: "0".to_string(),
+            delegator_shares: "0".to_string(),
+            commission: Commission {
+                update_time: Some(time),
+                ..commission.clone()
+            },
+        },
+    );
+
+    let mut events = vec![Event::ValidatorCreated {
+        validator: operator.clone(),
+        operator: sender.clone(),
+    }];
+    events.extend(delegate(state, height, sender, &operator, amount)?);
+    Ok(events)
+}
+
+/// Check that the sender's validator can change its commission rate to
+/// `rate`: at most `max_rate`, by at most `max_change_rate`, and not within
+/// a day of the last change
+pub fn validate_edit_validator(
+    state: &AppState,
+    time: Timestamp,
+    sender: &AccAddress,
+    rate: &Price,
+) -> Result<()> {
+    let operator = operator(sender)?;
+    let commission = &find(state, &operator)?.commission;
+    let invalid = |e: NumberError| StakingError::InvalidCommission(e.to_string());
+    let rate = parse_fraction(rate).map_err(invalid)?;
+    if rate > parse_fraction(&commission.max_rate).map_err(invalid)? {
+        return Err(StakingError::InvalidCommission(
+            "rate exceeds max_rate".to_string(),
+        ));
+    }
+    if let Some(updated) = commission.update_time {
+        let next_change = updated + chrono::TimeDelta::seconds(COMMISSION_CHANGE_INTERVAL);
+        if time < next_change {
+            return Err(StakingError::CommissionChangeTooSoon {
+                validator: operator,
+                next_change,
+            });
+        }
+    }
+    let change = (rate - parse_fraction(&commission.rate).map_err(invalid)?).abs();
+    if change > parse_fraction(&commission.max_change_rate).map_err(invalid)? {
+        return Err(StakingError::CommissionChangeTooLarge {
+            change: change.normalize().to_string(),
+            max_change_rate: commission.max_change_rate.clone(),
+        });
+    }
+    Ok(())
+}
+
+/// Change the commission rate of the sender's validator
+pub fn edit_validator(
+    state: &mut AppState,
+    time: Timestamp,
+    sender: &AccAddress,
+    rate: &Price,
+) -> Result<Vec<Event>> {
+    validate_edit_validator(state, time, sender, rate)?;
+    let operator = operator(sender)?;
+    let commission = &mut state
+        .validators
+        .get_mut(&operator)
+        .expect("validator exists")
+        .commission;
+    commission.rate = rate.clone();
+    commission.update_time = Some(time);
+
+    Ok(vec![Event::CommissionChanged {
+        validator: operator,
+        rate: rate.clone(),
+    }])
+}
+
+/// Check that `delegator` can bond `amount` to `validator`
+pub fn validate_delegate(
+    state: &AppState,
+    delegator: &AccAddress,
+    validator: &ValAddress,
+    amount: &Uint128,
+) -> Result<()> {
+    let amount = positive(amount)?;
+    let validator = find(state, validator)?;
+    if parse_amount(&validator.tokens)? == 0 && parse_amount(&validator.delegator_shares)? > 0 {
+        return Err(StakingError::InvalidExchangeRate(
+            validator.operator_address.clone(),
+        ));
+    }
+    portfolio::ensure_available(state, delegator, &state.params.native_token, amount)?;
+    Ok(())
+}
+
+/// Bond `amount` of the native token from `delegator` to `validator`
+pub fn delegate(
+    state: &mut AppState,
+    height: u64,
+    delegator: &AccAddress,
+    validator: &ValAddress,
+    amount: &Uint128,
+) -> Result<Vec<Event>> {
+    validate_delegate(state, delegator, validator, amount)?;
+    let tokens = parse_amount(amount)?;
+    let native = state.params.native_token.clone();
+    let mut events = distribution::before_delegation_modified(state, delegator, validator)?;
+    bank::transfer(state, delegator, &BONDED_POOL.to_string(), &native, tokens)?;
+    let shares = add_tokens(state, delegator, validator, tokens)?;
+    distribution::after_delegation_modified(state, height, delegator, validator)?;
+
+    events.push(Event::Delegated {
+        delegator: delegator.clone(),
+        validator: validator.clone(),
+        amount: amount.clone(),
+        shares: shares.to_string(),
+    });
+    Ok(events)
+}
+
+/// Check that `delegator` can undelegate `amount` from `validator`
+pub fn validate_undelegate(
+    state: &AppState,
+    delegator: &AccAddress,
+    validator: &ValAddress,
+    amount: &Uint128,
+) -> Result<()> {
+    shares_to_remove(state, delegator, validator, amount)?;
+    let entries = state
+        .staking
+        .unbonding
+        .iter()
+        .filter(|entry| &entry.delegator == delegator && &entry.validator == validator)
+        .count();
+    if entries >= state.params.staking.max_entries as usize {
+        return Err(StakingError::TooManyUnbondingEntries {
+            delegator: delegator.clone(),
+            validator: validator.clone(),
+            max: state.params.staking.max_entries,
+        });
+    }
+    Ok(())
+}
+
+/// Undelegate `amount` from `validator`, releasing it after the unbonding period
+pub fn undelegate(
+    state: &mut AppState,
+    height: u64,
+    time: Timestamp,
+    delegator: &AccAddress,
+    validator: &ValAddress,
+    amount: &Uint128,
+) -> Result<Vec<Event>> {
+    validate_undelegate(state, delegator, validator, amount)?;
+    let shares = shares_to_remove(state, delegator, validator, amount)?;
+    let mut events = distribution::before_delegation_modified(state, delegator, validator)?;
+    let tokens = remove_shares(state, delegator, validator, shares)?;
+    distribution::after_delegation_modified(state, height, delegator, validator)?;
+    let native = state.params.native_token.clone();
+    bank::transfer(
+        state,
+        &BONDED_POOL.to_string(),
+        &UNBONDING_POOL.to_string(),
+        &native,
+        tokens,
+    )?;
+
+    let completion_time = unbonding_completion(state, time)?;
+    let id = state.staking.last_unbonding_id + 1;
+    state.staking.last_unbonding_id = id;
+    state.staking.unbonding.push_back(UnbondingEntry {
+        id,
+        delegator: delegator.clone(),
+        validator: validator.clone(),
+        amount: tokens.to_string(),
+        initial_balance: tokens.to_string(),
+        creation_height: height,
+        completion_time,
+    });
+
+    events.push(Event::UnbondingStarted {
+        delegator: delegator.clone(),
+        validator: validator.clone(),
+        amount: tokens.to_string(),
+        completion_time,
+    });
+    Ok(events)
+}
+
+/// Check that `delegator` can move `amount` from `source` to `destination`
+pub fn validate_redelegate(
+    state: &AppState,
+    delegator: &AccAddress,
+    source: &ValAddress,
+    destination: &ValAddress,
+    amount: &Uint128,
+) -> Result<()> {
+    if source == destination {
+        return Err(StakingError::SelfRedelegation(source.clone()));
+    }
+    shares_to_remove(state, delegator, source, amount)?;
+    let destination = find(state, destination)?;
+    if parse_amount(&destination.tokens)? == 0 && parse_amount(&destination.delegator_shares)? > 0 {
+        return Err(StakingError::InvalidExchangeRate(
+            destination.operator_address.clone(),
+        ));
+    }
+    Ok(())
+}
+
+/// Move `amount` of bonded tokens from `source` to `destination` at once
+pub fn redelegate(
+    state: &mut AppState,
+    height: u64,
+    delegator: &AccAddress,
+    source: &ValAddress,
+    destination: &ValAddress,
+    amount: &Uint128,
+) -> Result<Vec<Event>> {
+    validate_redelegate(state, delegator, source, destination, amount)?;
+    let shares = shares_to_remove(state, delegator, source, amount)?;
+    let mut events = distribution::before_delegation_modified(state, delegator, source)?;
+    events.extend(distribution::before_delegation_modified(
+        state,
+        delegator,
+        destination,
+    )?);
+    let tokens = remove_shares(state, delegator, source, shares)?;
+    add_tokens(state, delegator, destination, tokens)?;
+    distribution::after_delegation_modified(state, height, delegator, source)?;
+    distribution::after_delegation_modified(state, height, delegator, destination)?;
+
+    events.push(Event::Redelegated {
+        delegator: delegator.clone(),
+        source: source.clone(),
+        destination: destination.clone(),
+        amount: tokens.to_string(),
+    });
+    Ok(events)
+}
+
+/// Release matured unbondings, finish validator unbonding and recompute
+/// the active set, returning the events and the validator updates
+pub fn end_block(
+    state: &mut AppState,
+    time: Timestamp,
+) -> Result<(Vec<Event>, Vec<ValidatorUpdate>)> {
+    let mut events = Vec::new();
+    let native = state.params.native_token.clone();
+    let (matured, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut state.staking.unbonding)
+        .into_iter()
+        .partition(|entry| entry.completion_time <= time);
+    state.staking.unbonding = pending.into();
+    for entry in matured {
+        let amount = parse_amount(&entry.amount)?;
+        if amount > 0 {
+            bank::transfer(
+                state,
+                &UNBONDING_POOL.to_string(),
+                &entry.delegator,
+                &native,
+                amount,
+            )?;
+        }
+        events.push(Event::UnbondingCompleted {
+            delegator: entry.delegator,
+            validator: entry.validator,
+            amount: entry.amount,
+        });
+    }
+
+    let unbonded: Vec<_> = state
+        .staking
+        .unbonding_validators
+        .iter()
+        .filter(|(_, completion)| **completion <= time)
+        .map(|(operator, _)| operator.clone())
+        .collect();
+    for operator in unbonded {
+        state.staking.unbonding_validators.remove(&operator);
+        if let Some(validator) = state.validators.get_mut(&operator) {
+            validator.status = BondStatus::Unbonded;
+        }
+    }
+
+    let updates = apply_validator_set(state, time)?;
+    events.extend(updates.iter().map(|update| Event::ValidatorPowerChanged {
+        validator: update.address.clone(),
+        power: update.power,
+    }));
+    Ok((events, updates))
+}
+
+/// Burn `fraction` of the stake a validator had at `infraction_height`
+///
+/// The stake is taken as `power` at the infraction. Unbonding entries
+/// started at or after the infraction are slashed first, each by
+/// `fraction` of its initial balance; the rest comes out of the
+/// validator's bonded tokens, lowering the value of every delegation.
+/// Returns the tokens burned.
+pub fn slash(
+    state: &mut AppState,
+    height: u64,
+    validator: &ValAddress,
+    infraction_height: u64,
+    power: u64,
+    fraction: Decimal,
+) -> Result<u128> {
+    let reduction = parse_amount(&state.params.staking.power_reduction)?;
+    let stake = (power as u128)
+        .checked_mul(reduction)
+        .ok_or_else(|| NumberError::Overflow(format!("stake of {}", validator)))?;
+    let mut remaining = notional(stake, fraction)?;
+    let native = state.params.native_token.clone();
+
+    let mut from_unbonding = 0u128;
+    for entry in
+        state.staking.unbonding.iter_mut().filter(|entry| {
+            &entry.validator == validator && entry.creation_height >= infraction_height
+        })
+    {
+        let amount = parse_amount(&entry.amount)?;
+        let slashed = notional(parse_amount(&entry.initial_balance)?, fraction)?.min(amount);
+        entry.amount = (amount - slashed).to_string();
+        from_unbonding += slashed;
+        remaining = remaining.saturating_sub(slashed);
+    }
+    if from_unbonding > 0 {
+        bank::burn(state, &UNBONDING_POOL.to_string(), &native, from_unbonding)?;
+    }
+
+    let tokens = parse_amount(&find(state, validator)?.tokens)?;
+    let from_tokens = remaining.min(tokens);
+    if from_tokens > 0 {
+        let effective = Decimal::from_u128(from_tokens)
+            .zip(Decimal::from_u128(tokens))
+            .and_then(|(burned, tokens)| burned.checked_div(tokens))
+            .ok_or_else(|| NumberError::Overflow(format!("slash fraction of {}", validator)))?;
+        distribution::before_validator_slashed(state, height, validator, effective)?;
+        let record = state
+            .validators
+            .get_mut(validator)
+            .expect("validator exists");
+        record.tokens = (tokens - from_tokens).to_string();
+        bank::burn(state, &BONDED_POOL.to_string(), &native, from_tokens)?;
+    }
+    Ok(from_unbonding + from_tokens)
+}
+
+/// Verify that the pools hold exactly the bonded and unbonding tokens and
+/// that delegation shares add up to each validator's shares
+pub fn check_invariants(state: &AppState) -> Result<()> {
+    let native = &state.params.native_token;
+    let mut bonded = 0u128;
+    let mut shares: HashMap<&ValAddress, u128> = HashMap::new();
+    for validator in state.validators.values() {
+        bonded += parse_amount(&validator.tokens)?;
+        shares.insert(&validator.operator_address, 0);
+    }
+    for delegation in state
+        .staking
+        .delegations
+        .values()
+        .flat_map(|delegations| delegations.values())
+    {
+        let total = shares.get_mut(&delegation.validator).ok_or_else(|| {
+            StakingError::Invariant(format!(
+                "delegation to unknown validator {}",
+                delegation.validator
+            ))
+        })?;
+        *total += parse_amount(&delegation.shares)?;
+    }
+    for (operator, total) in shares {
+        let expected = parse_amount(&state.validators[operator].delegator_shares)?;
+        if total != expected {
+            return Err(StakingError::Invariant(format!(
+                "delegations to {} hold {} shares, validator has {}",
+                operator, total, expected
+            )));
+        }
+    }
+
+    let unbonding = state
+        .staking
+        .unbonding
+        .iter()
+        .map(|entry| parse_amount(&entry.amount))
+        .sum::<std::result::Result<u128, _>>()?;
+    for (pool, expected) in [(BONDED_POOL, bonded), (UNBONDING_POOL, unbonding)] {
+        let held = parse_amount(&portfolio::balance(state, &pool.to_string(), native)?.total)?;
+        if held != expected {
+            return Err(StakingError::Invariant(format!(
+                "{} holds {} {}, expected {}",
+                pool, held, native, expected
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Voting power of a validator's bonded tokens
+pub fn power(state: &AppState, validator: &Validator) -> Result<u64> {
+    let reduction = parse_amount(&state.params.staking.power_reduction)?.max(1);
+    Ok(u64::try_from(parse_amount(&validator.tokens)? / reduction).unwrap_or(u64::MAX))
+}
+
+/// Tokens a delegation's shares are worth
+pub fn delegation_tokens(state: &AppState, delegation: &Delegation) -> Result<u128> {
+    let validator = find(state, &delegation.validator)?;
+    tokens_for(validator, parse_amount(&delegation.shares)?)
+}
+
+/// Bond the active set, start unbonding validators that left it and
+/// return the changes in voting power
+fn apply_validator_set(state: &mut AppState, time: Timestamp) -> Result<Vec<ValidatorUpdate>> {
+    let mut candidates = Vec::new();
+    for validator in state
+        .validators
+        .values()
+        .filter(|validator| !validator.jailed)
+    {
+        let power = power(state, validator)?;
+        if power > 0 {
+            candidates.push((
+                parse_amount(&validator.tokens)?,
+                validator.operator_address.clone(),
+                power,
+            ));
+        }
+    }
+    candidates.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    candidates.truncate(state.params.staking.max_validators as usize);
+    let active: HashMap<ValAddress, u64> = candidates
+        .into_iter()
+        .map(|(_, operator, power)| (operator, power))
+        .collect();
+
+    let completion = unbonding_completion(state, time)?;
+    for (operator, validator) in state.validators.iter_mut() {
+        if active.contains_key(operator) {
+            validator.status = BondStatus::Bonded;
+            state.staking.unbonding_validators.remove(operator);
+        } else if matches!(validator.status, BondStatus::Bonded) {
+            validator.status = BondStatus::Unbonding;
+            state
+                .staking
+                .unbonding_validators
+                .insert(operator.clone(), completion);
+        }
+    }
+
+    let mut updates: Vec<_> = active
+        .iter()
+        .filter(|(operator, power)| state.staking.last_powers.get(*operator) != Some(power))
+        .map(|(operator, power)| ValidatorUpdate {
+            address: operator.clone(),
+            power: i64::try_from(*power).unwrap_or(i64::MAX),
+        })
+        .chain(
+            state
+                .staking
+                .last_powers
+                .keys()
+                .filter(|operator| !active.contains_key(*operator))
+                .map(|operator| ValidatorUpdate {
+                    address: operator.clone(),
+                    power: 0,
+                }),
+        )
+        .collect();
+    updates.sort_by(|a, b| a.address.cmp(&b.address));
+    state.staking.last_powers = active.into();
+    Ok(updates)
+}
+
+fn operator(sender: &AccAddress) -> Result<ValAddress> {
+    operator_of(sender).ok_or_else(|| StakingError::UnknownValidator(sender.clone()))
+}
+
+fn find<'a>(state: &'a AppState, validator: &ValAddress) -> Result<&'a Validator> {
+    state
+        .validators
+        .get(validator)
+        .ok_or_else(|| StakingError::UnknownValidator(validator.clone()))
+}
+
+fn positive(amount: &Uint128) -> Result<u128> {
+    match parse_amount(amount)? {
+        0 => Err(StakingError::ZeroAmount),
+        amount => Ok(amount),
+    }
+}
+
+fn unbonding_completion(state: &AppState, time: Timestamp) -> Result<Timestamp> {
+    let seconds = i64::try_from(state.params.staking.unbonding_time).unwrap_or(i64::MAX);
+    chrono::TimeDelta::try_seconds(seconds)
+        .and_then(|period| time.checked_add_signed(period))
+        .ok_or_else(|| NumberError::Overflow("unbonding completion time".to_string()).into())
+}
+
+/// `amount * numerator / denominator`, rounded down or up
+fn mul_div(amount: u128, numerator: u128, denominator: u128, round_up: bool) -> Result<u128> {
+    let product = amount
+        .checked_mul(numerator)
+        .ok_or_else(|| NumberError::Overflow(format!("{} * {}", amount, numerator)))?;
+    let quotient = product / denominator;
+    Ok(if round_up && product % denominator != 0 {
+        quotient + 1
+    } else {
+        quotient
+    })
+}
+
+/// Tokens `shares` of a validator are worth, rounded down
+fn tokens_for(validator: &Validator, shares: u128) -> Result<u128> {
+    let total_shares = parse_amount(&validator.delegator_shares)?;
+    if total_shares == 0 {
+        return Ok(0);
+    }
+    mul_div(
+        shares,
+        parse_amount(&validator.tokens)?,
+        total_shares,
+        false,
+    )
+}
+
+/// Shares that undelegating `amount` removes, rounded up
+fn shares_to_remove(
+    state: &AppState,
+    delegator: &AccAddress,
+    validator: &ValAddress,
+    amount: &Uint128,
+) -> Result<u128> {
+    let amount = positive(amount)?;
+    let no_delegation = || StakingError::NoDelegation {
+        delegator: delegator.clone(),
+        validator: validator.clone(),
+    };
+    let delegation = state
+        .staking
+        .delegations
+        .get(delegator)
+        .and_then(|delegations| delegations.get(validator))
+        .ok_or_else(no_delegation)?;
+    let held = parse_amount(&delegation.shares)?;
+    let record = find(state, validator)?;
+    let (tokens, total_shares) = (
+        parse_amount(&record.tokens)?,
+        parse_amount(&record.delegator_shares)?,
+    );
+    let available = tokens_for(record, held)?;
+    if amount > available || tokens == 0 {
+        return Err(StakingError::InsufficientDelegation {
+            delegator: delegator.clone(),
+            validator: validator.clone(),
+            requested: amount.to_string(),
+            available: available.to_string(),
+        });
+    }
+    Ok(mul_div(amount, total_shares, tokens, true)?.min(held))
+}
+
+/// Remove `shares` from a delegation, returning the tokens they were worth
+fn remove_shares(
+    state: &mut AppState,
+    delegator: &AccAddress,
+    validator: &ValAddress,
+    shares: u128,
+) -> Result<u128> {
+    let record = find(state, validator)?;
+    let total_shares = parse_amount(&record.delegator_shares)?;
+    let total_tokens = parse_amount(&record.tokens)?;
+    let tokens = if shares == total_shares {
+        total_tokens
+    } else {
+        tokens_for(record, shares)?
+    };
+
+    let delegations = state
+        .staking
+        .delegations
+        .get_mut(delegator)
+        .expect("delegation exists");
+    let delegation = delegations.get_mut(validator).expect("delegation exists");
+    let remaining = parse_amount(&delegation.shares)? - shares;
+    if remaining == 0 {
+        delegations.remove(validator);
+        if delegations.is_empty() {
+            state.staking.delegations.remove(delegator);
+        }
+    } else {
+        delegation.shares = remaining.to_string();
+    }
+
+    let record = state
+        .validators
+        .get_mut(validator)
+        .expect("validator exists");
+    record.tokens = (total_tokens - tokens).to_string();
+    record.delegator_shares = (total_shares - shares).to_string();
+    Ok(tokens)
+}
+
+/// Add `tokens` to a validator on behalf of `delegator`, returning the shares issued
+fn add_tokens(
+    state: &mut AppState,
+    delegator: &AccAddress,
+    validator: &ValAddress,
+    tokens: u128,
+) -> Result<u128> {
+    let record = find(state, validator)?;
+    let total_tokens = parse_amount(&record.tokens)?;
+    let total_shares = parse_amount(&record.delegator_shares)?;
+    let shares = if total_shares == 0 {
+        tokens
+    } else {
+        mul_div(tokens, total_shares, total_tokens, false)?
+    };
+    let overflow = || NumberError::Overflow(format!("stake of {}", validator));
+
+    let record = state
+        .validators
+        .get_mut(validator)
+        .expect("validator exists");
+    record.tokens = total_tokens
+        .checked_add(tokens)
+        .ok_or_else(overflow)?
+        .to_string();
+    record.delegator_shares = total_shares
+        .checked_add(shares)
+        .ok_or_else(overflow)?
+        .to_string();
+
+    let delegation = state
+        .staking
+        .delegations
+        .entry(delegator.clone())
+        .or_default()
+        .entry(validator.clone())
+        .or_insert_with(|| Delegation {
+            delegator: delegator.clone(),
+            validator: validator.clone(),
+            shares: "0".to_string(),
+        });
+    delegation.shares = parse_amount(&delegation.shares)?
+        .checked_add(shares)
+        .ok_or_else(overflow)?
+        .to_string();
+    Ok(shares)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::test_state;
+    use crate::keys::KeyPair;
+    use chrono::TimeDelta;
+
+    const OMC: u128 = 1_000_000;
+
+    /// Three accounts holding 1000 OMC each, with unbonding over 100 seconds
+    fn setup() -> (AppState, [KeyPair; 3]) {
+        let mut state = test_state(&["OMC"]);
+        state.params.staking.unbonding_time = 100;
+        let native = state.params.native_token.clone();
+        let keys = [
+            KeyPair::generate(),
+            KeyPair::generate(),
+            KeyPair::generate(),
+        ];
+        for key in &keys {
+            bank::mint(&mut state, &key.account_address(), &native, 1000 * OMC).unwrap();
+        }
+        (state, keys)
+    }
+
+    fn commission(rate: &str, max_rate: &str, max_change_rate: &str) -> Commission {
+        Commission {
+            rate: rate.to_string(),
+            max_rate: max_rate.to_string(),
+            max_change_rate: max_change_rate.to_string(),
+            update_time: None,
+        }
+    }
+
+    fn at(seconds: i64) -> Timestamp {
+        Timestamp::UNIX_EPOCH + TimeDelta::seconds(seconds)
+    }
+
+    fn create(state: &mut AppState, key: &KeyPair, amount: u128) -> ValAddress {
+        let sender = key.account_address();
+        let pubkey = key.public_key_hex();
+        create_validator(
+            state,
+            1,
+            at(0),
+            &sender,
+            &pubkey,
+            &commission("0.1", "0.2", "0.05"),
+            &amount.to_string(),
+        )
+        .unwrap();
+        operator_of(&sender).unwrap()
+    }
+
+    #[test]
+    fn test_create_and_edit_validator_failure_codes() {
+        let (mut state, keys) = setup();
+        let sender = keys[0].account_address();
+        let pubkey = keys[0].public_key_hex();
+        let code = |state: &AppState, pubkey: &str, commission: &Commission, amount: &str| {
+            validate_create_validator(state, &sender, pubkey, commission, &amount.to_string())
+                .unwrap_err()
+                .code()
+        };
+        let valid = commission("0.1", "0.2", "0.05");
+
+        assert_eq!(
+            code(&state, "abcd", &valid, "1"),
+            codes::STAKING_INVALID_PUBKEY
+        );
+        let high = commission("0.3", "0.2", "0.05");
+        assert_eq!(
+            code(&state, &pubkey, &high, "1"),
+            codes::STAKING_INVALID_COMMISSION
+        );
+        let fast = commission("0.1", "0.2", "0.5");
+        assert_eq!(
+            code(&state, &pubkey, &fast, "1"),
+            codes::STAKING_INVALID_COMMISSION
+        );
+        assert_eq!(
+            code(&state, &pubkey, &valid, "0"),
+            codes::STAKING_ZERO_AMOUNT
+        );
+        assert_eq!(
+            code(&state, &pubkey, &valid, &(1001 * OMC).to_string()),
+            codes::PORTFOLIO_INSUFFICIENT_FUNDS
+        );
+        create(&mut state, &keys[0], 10 * OMC);
+        assert_eq!(
+            code(&state, &pubkey, &valid, "1"),
+            codes::STAKING_VALIDATOR_EXISTS
+        );
+
+        // Commission changes are capped by max_rate and max_change_rate, once a day
+        let edit = |state: &AppState, seconds: i64, rate: &str| {
+            validate_edit_validator(state, at(seconds), &sender, &rate.to_string())
+        };
+        let day = COMMISSION_CHANGE_INTERVAL;
+        assert_eq!(
+            edit(&state, day - 1, "0.12").unwrap_err().code(),
+            codes::STAKING_COMMISSION_CHANGE_TOO_SOON
+        );
+        assert_eq!(
+            edit(&state, day, "0.16").unwrap_err().code(),
+            codes::STAKING_COMMISSION_CHANGE_TOO_LARGE
+        );
+        assert_eq!(
+            edit(&state, day, "0.25").unwrap_err().code(),
+            codes::STAKING_INVALID_COMMISSION
+        );
+        edit_validator(&mut state, at(day), &sender, &"0.15".to_string()).unwrap();
+        assert_eq!(
+            edit(&state, 2 * day - 1, "0.1").unwrap_err().code(),
+            codes::STAKING_COMMISSION_CHANGE_TOO_SOON
+        );
+
+        let stranger = KeyPair::generate().account_address();
+        let err = validate_edit_validator(&state, at(2 * day), &stranger, &"0.1".to_string())
+            .unwrap_err();
+        assert_eq!(err.code(), codes::STAKING_UNKNOWN_VALIDATOR);
+    }
+
+    #[test]
+    fn test_commission_change_boundaries() {
+        let (mut state, keys) = setup();
+        let sender = keys[0].account_address();
+        let operator = create(&mut state, &keys[0], 10 * OMC);
+        let edit = |state: &mut AppState, seconds: i64, rate: &str| {
+            edit_validator(state, at(seconds), &sender, &rate.to_string()).map_err(|e| e.code())
+        };
+        let day = COMMISSION_CHANGE_INTERVAL;
+
+        // A change of exactly max_change_rate is allowed once a day has passed
+        let events = edit(&mut state, day, "0.15").unwrap();
+        assert!(matches!(
+            &events[..],
+            [Event::CommissionChanged { rate, .. }] if rate == "0.15"
+        ));
+        let commission = &state.validators[&operator].commission;
+        assert_eq!(commission.rate, "0.15");
+        assert_eq!(commission.update_time, Some(at(day)));
+
+        // A second edit must wait a full day from the first
+        assert_eq!(
+            edit(&mut state, 2 * day - 1, "0.2"),
+            Err(codes::STAKING_COMMISSION_CHANGE_TOO_SOON)
+        );
+        assert_eq!(
+            edit(&mut state, 2 * day, "0.2001"),
+            Err(codes::STAKING_INVALID_COMMISSION)
+        );
+        edit(&mut state, 2 * day, "0.2").unwrap();
+
+        // Cuts are limited the same way as raises
+        assert_eq!(
+            edit(&mut state, 3 * day, "0.1499"),
+            Err(codes::STAKING_COMMISSION_CHANGE_TOO_LARGE)
+        );
+        edit(&mut state, 3 * day, "0.15").unwrap();
+        assert_eq!(state.validators[&operator].commission.rate, "0.15");
+    }
+
+    #[test]
+    fn test_delegation_failure_codes() {
+        let (mut state, keys) = setup();
+        let [first, second] = [
+            create(&mut state, &keys[0], 10 * OMC),
+            create(&mut state, &keys[1], 10 * OMC),
+        ];
+        let delegator = keys[2].account_address();
+        let amount = |amount: u128| amount.to_string();
+
+        let unknown = operator_of(&delegator).unwrap();
+        let err = validate_delegate(&state, &delegator, &unknown, &amount(OMC)).unwrap_err();
+        assert_eq!(err.code(), codes::STAKING_UNKNOWN_VALIDATOR);
+        let err = validate_undelegate(&state, &delegator, &first, &amount(1)).unwrap_err();
+        assert_eq!(err.code(), codes::STAKING_NO_DELEGATION);
+
+        delegate(&mut state, 1, &delegator, &first, &amount(5 * OMC)).unwrap();
+        let err =
+            validate_undelegate(&state, &delegator, &first, &amount(5 * OMC + 1)).unwrap_err();
+        assert_eq!(err.code(), codes::STAKING_INSUFFICIENT_DELEGATION);
+        let err = validate_undelegate(&state, &delegator, &first, &amount(0)).unwrap_err();
+        assert_eq!(err.code(), codes::STAKING_ZERO_AMOUNT);
+        let err = validate_redelegate(&state, &delegator, &first, &first, &amount(1)).unwrap_err();
+        assert_eq!(err.code(), codes::STAKING_SELF_REDELEGATION);
+
+        // Unbonding entries are capped per delegator and validator
+        state.params.staking.max_entries = 2;
+        for _ in 0..2 {
+            undelegate(&mut state, 1, at(0), &delegator, &first, &amount(OMC)).unwrap();
+        }
+        let err = validate_undelegate(&state, &delegator, &first, &amount(OMC)).unwrap_err();
+        assert_eq!(err.code(), codes::STAKING_TOO_MANY_UNBONDING_ENTRIES);
+        redelegate(&mut state, 1, &delegator, &first, &second, &amount(OMC)).unwrap();
+        validate_undelegate(&state, &delegator, &second, &amount(OMC)).unwrap();
+
+        // A validator whose tokens were all slashed away takes no new stake
+        let validator = state.validators.get_mut(&second).unwrap();
+        validator.tokens = "0".to_string();
+        let err = validate_delegate(&state, &delegator, &second, &amount(OMC)).unwrap_err();
+        assert_eq!(err.code(), codes::STAKING_INVALID_EXCHANGE_RATE);
+        let err =
+            validate_redelegate(&state, &delegator, &first, &second, &amount(OMC)).unwrap_err();
+        assert_eq!(err.code(), codes::STAKING_INVALID_EXCHANGE_RATE);
+    }
+
+    #[test]
+    fn test_undelegations_are_released_after_unbonding_time() {
+        let (mut state, keys) = setup();
+        let validator = create(&mut state, &keys[0], 10 * OMC);
+        let delegator = keys[1].account_address();
+        let native = state.params.native_token.clone();
+        let balance = |state: &AppState| {
+            portfolio::balance(state, &delegator, &native)
+                .unwrap()
+                .total
+        };
+
+        delegate(
+            &mut state,
+            1,
+            &delegator,
+            &validator,
+            &(4 * OMC).to_string(),
+        )
+        .unwrap();
+        undelegate(
+            &mut state,
+            2,
+            at(10),
+            &delegator,
+            &validator,
+            &(4 * OMC).to_string(),
+        )
+        .unwrap();
+        // Undelegating everything removes the delegation
+        assert!(!state.staking.delegations.contains_key(&delegator));
+        assert_eq!(balance(&state), (996 * OMC).to_string());
+        check_invariants(&state).unwrap();
+
+        end_block(&mut state, at(109)).unwrap();
+        assert_eq!(state.staking.unbonding.len(), 1);
+        let (events, _) = end_block(&mut state, at(110)).unwrap();
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, Event::UnbondingCompleted { .. })));
+        assert!(state.staking.unbonding.is_empty());
+        assert_eq!(balance(&state), (1000 * OMC).to_string());
+        check_invariants(&state).unwrap();
+    }
+
+    #[test]
+    fn test_active_set_reports_power_changes() {
+        let (mut state, keys) = setup();
+        state.params.staking.max_validators = 1;
+        let first = create(&mut state, &keys[0], 10 * OMC);
+        let second = create(&mut state, &keys[1], 5 * OMC);
+
+        let (_, updates) = end_block(&mut state, at(0)).unwrap();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(
+            (updates[0].address.clone(), updates[0].power),
+            (first.clone(), 10)
+        );
+        assert!(end_block(&mut state, at(1)).unwrap().1.is_empty());
+
+        // Outgrowing the active validator swaps them; the one leaving unbonds
+        delegate(
+            &mut state,
+            1,
+            &keys[2].account_address(),
+            &second,
+            &(6 * OMC).to_string(),
+        )
+        .unwrap();
+        let (_, updates) = end_block(&mut state, at(2)).unwrap();
+        let powers: Vec<_> = updates
+            .iter()
+            .map(|update| (update.address.clone(), update.power))
+            .collect();
+        let mut expected = vec![(first.clone(), 0), (second.clone(), 11)];
+        expected.sort();
+        assert_eq!(powers, expected);
+        assert!(matches!(
+            state.validators[&first].status,
+            BondStatus::Unbonding
+        ));
+        end_block(&mut state, at(102)).unwrap();
+        assert!(matches!(
+            state.validators[&first].status,
+            BondStatus::Unbonded
+        ));
+        assert!(matches!(
+            state.validators[&second].status,
+            BondStatus::Bonded
+        ));
+    }
+}
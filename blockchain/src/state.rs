@@ -1,19 +1,49 @@
 //! State Management Module
-//! 
+//!
 //! This module handles all blockchain state operations including
 //! storage, retrieval, and state transitions.
 
+use crate::codes;
 use crate::types::*;
-use anyhow::{anyhow, Result};
-use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use tracing::{info, warn, error};
+use thiserror::Error;
+use tracing::info;
+
+type Result<T> = std::result::Result<T, StateError>;
+
+/// State and storage failures
+#[derive(Debug, Error)]
+pub enum StateError {
+    #[error("storage error: {0}")]
+    Storage(String),
+
+    #[error("failed to encode or decode state: {0}")]
+    Codec(#[from] serde_json::Error),
+
+    #[error("corrupt state: {0}")]
+    Corrupt(String),
+
+    #[error("storage backend unavailable: {0}")]
+    BackendUnavailable(String),
+}
+
+impl StateError {
+    /// Stable result code, see [`crate::codes`]
+    pub fn code(&self) -> u32 {
+        match self {
+            StateError::Storage(_) => codes::STATE_STORAGE,
+            StateError::Codec(_) => codes::STATE_CODEC,
+            StateError::Corrupt(_) => codes::STATE_CORRUPT,
+            StateError::BackendUnavailable(_) => codes::STATE_BACKEND_UNAVAILABLE,
+        }
+    }
+}
 
 /// State manager for blockchain data
 pub struct StateManager {
     /// In-memory state cache
     cache: StateCache,
-    
+
     /// Persistent storage backend
     storage: Box<dyn StateStorage>,
 }
@@ -74,15 +104,15 @@ impl StateManager {
     /// Set account
     pub fn set_account(&mut self, account: Account) -> Result<()> {
         let address = account.address.clone();
-        
+
         // Update cache
         self.cache.accounts.insert(address.clone(), account.clone());
-        
+
         // Update storage
         let key = format!("account:{}", address);
         let value = serde_json::to_vec(&account)?;
         self.storage.set(key.as_bytes(), value)?;
-        
+
         info!("Account updated: {}", address);
         Ok(())
     }
@@ -107,15 +137,15 @@ impl StateManager {
     /// Set order
     pub fn set_order(&mut self, order: Order) -> Result<()> {
         let order_id = order.id;
-        
+
         // Update cache
         self.cache.orders.insert(order_id, order.clone());
-        
+
         // Update storage
         let key = format!("order:{}", order_id);
         let value = serde_json::to_vec(&order)?;
         self.storage.set(key.as_bytes(), value)?;
-        
+
         info!("Order updated: {}", order_id);
         Ok(())
     }
@@ -140,15 +170,15 @@ impl StateManager {
     /// Set market
     pub fn set_market(&mut self, market: Market) -> Result<()> {
         let market_id = market.id.clone();
-        
+
         // Update cache
         self.cache.markets.insert(market_id.clone(), market.clone());
-        
+
         // Update storage
         let key = format!("market:{}", market_id);
         let value = serde_json::to_vec(&market)?;
         self.storage.set(key.as_bytes(), value)?;
-        
+
         info!("Market updated: {}", market_id);
         Ok(())
     }
@@ -173,15 +203,17 @@ impl StateManager {
     /// Set portfolio
     pub fn set_portfolio(&mut self, portfolio: Portfolio) -> Result<()> {
         let address = portfolio.owner.clone();
-        
+
         // Update cache
-        self.cache.portfolios.insert(address.clone(), portfolio.clone());
-        
+        self.cache
+            .portfolios
+            .insert(address.clone(), portfolio.clone());
+
         // Update storage
         let key = format!("portfolio:{}", address);
         let value = serde_json::to_vec(&portfolio)?;
         self.storage.set(key.as_bytes(), value)?;
-        
+
         info!("Portfolio updated: {}", address);
         Ok(())
     }
@@ -228,19 +260,19 @@ impl StateStorage for MemoryStorage {
 
     fn commit(&mut self) -> Result<Vec<u8>> {
         // Calculate a simple hash of all data
-        use sha2::{Sha256, Digest};
+        use sha2::{Digest, Sha256};
         let mut hasher = Sha256::new();
-        
+
         let mut keys: Vec<_> = self.data.keys().collect();
         keys.sort();
-        
+
         for key in keys {
             if let Some(value) = self.data.get(key) {
                 hasher.update(key);
                 hasher.update(value);
             }
         }
-        
+
         Ok(hasher.finalize().to_vec())
     }
 }
@@ -1,8 +1,284 @@
 //! Storage Module
-//! 
+//!
 //! This module provides persistent storage implementations for the Optimic blockchain.
 
-use anyhow::Result;
+use crate::config::StorageBackend;
+use crate::state::{MemoryStorage, StateError, StateStorage};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+type Result<T> = std::result::Result<T, StateError>;
+
+/// Open the configured storage backend inside `data_dir`
+pub fn open_storage(backend: StorageBackend, data_dir: &str) -> Result<Box<dyn StateStorage>> {
+    info!("Opening {:?} storage in {}", backend, data_dir);
+    match backend {
+        StorageBackend::Memory => Ok(Box::new(MemoryStorage::new())),
+        StorageBackend::File => Ok(Box::new(FileStorage::open(Path::new(data_dir).join("state.db"))?)),
+        StorageBackend::RocksDB => Err(StateError::BackendUnavailable(
+            "the rocksdb storage backend is not available in this build; use \"memory\" or \"file\"".to_string(),
+        )),
+    }
+}
+
+/// Log size, beyond twice the live data, that triggers a compaction
+const COMPACTION_SLACK: u64 = 1 << 20;
+
+/// Changes made by one commit, one JSON line in the log
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Batch {
+    /// Hex-encoded keys and their new values
+    set: BTreeMap<String, String>,
+
+    /// Hex-encoded deleted keys
+    delete: Vec<String>,
+}
+
+/// A line of the storage file
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Record {
+    Batch(Batch),
+
+    /// Whole-file snapshot written by earlier versions
+    Snapshot(BTreeMap<String, String>),
+}
+
+/// File-backed storage that appends the changes of every commit to a log
+///
+/// Each commit writes one line holding only the keys set or deleted since
+/// the previous commit, so its cost does not grow with the data already
+/// stored. The log is rewritten as a single batch once it grows past twice
+/// the live data. A line torn by a crash is dropped when the file is opened.
+pub struct FileStorage {
+    path: PathBuf,
+    file: File,
+    data: BTreeMap<Vec<u8>, Vec<u8>>,
+
+    /// Changes since the last commit; `None` marks a deletion
+    pending: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+
+    /// Bytes in the log file
+    log_bytes: u64,
+
+    /// Encoded size of the live data, see [`encoded_len`]
+    live_bytes: u64,
+
+    /// XOR of the hashes of all entries, see [`entry_hash`]
+    digest: [u8; 32],
+}
+
+impl FileStorage {
+    /// Open (or create) a storage file, replaying its log
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| {
+                StateError::Storage(format!(
+                    "failed to create data directory {}: {}",
+                    dir.display(),
+                    e
+                ))
+            })?;
+        }
+        let contents = match std::fs::read(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                return Err(StateError::Storage(format!(
+                    "failed to read storage file {}: {}",
+                    path.display(),
+                    e
+                )))
+            }
+        };
+
+        let corrupt = |e: &dyn std::fmt::Display| {
+            StateError::Corrupt(format!("storage file {}: {}", path.display(), e))
+        };
+        let mut data = BTreeMap::new();
+        let mut valid = 0;
+        for line in contents.split_inclusive(|byte| *byte == b'\n') {
+            let record = match serde_json::from_slice::<Record>(line) {
+                Ok(record) => record,
+                // Only the last line can be torn by a crash mid-append
+                Err(_) if !line.ends_with(b"\n") => break,
+                Err(e) => return Err(corrupt(&e)),
+            };
+            let batch = match record {
+                Record::Batch(batch) => batch,
+                Record::Snapshot(set) => Batch {
+                    set,
+                    delete: Vec::new(),
+                },
+            };
+            for (key, value) in batch.set {
+                data.insert(
+                    hex::decode(key).map_err(|e| corrupt(&e))?,
+                    hex::decode(value).map_err(|e| corrupt(&e))?,
+                );
+            }
+            for key in batch.delete {
+                data.remove(&hex::decode(key).map_err(|e| corrupt(&e))?);
+            }
+            valid += line.len();
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| {
+                StateError::Storage(format!(
+                    "failed to open storage file {}: {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+        file.set_len(valid as u64).map_err(|e| {
+            StateError::Storage(format!(
+                "failed to truncate storage file {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+
+        let (mut digest, mut live_bytes) = ([0u8; 32], 0);
+        for (key, value) in &data {
+            xor(&mut digest, &entry_hash(key, value));
+            live_bytes += encoded_len(key, value);
+        }
+        Ok(Self {
+            path,
+            file,
+            data,
+            pending: BTreeMap::new(),
+            log_bytes: valid as u64,
+            live_bytes,
+            digest,
+        })
+    }
+
+    /// Rewrite the log as one batch of the live data
+    fn compact(&mut self) -> Result<()> {
+        let batch = Batch {
+            set: self
+                .data
+                .iter()
+                .map(|(key, value)| (hex::encode(key), hex::encode(value)))
+                .collect(),
+            delete: Vec::new(),
+        };
+        let mut line = serde_json::to_vec(&batch)?;
+        line.push(b'\n');
+
+        // Write to a temporary file first so a crash never leaves a torn log
+        let tmp = self.path.with_extension("tmp");
+        let storage_error = |action: &str, path: &Path, e: std::io::Error| {
+            StateError::Storage(format!(
+                "failed to {} storage file {}: {}",
+                action,
+                path.display(),
+                e
+            ))
+        };
+        std::fs::write(&tmp, &line).map_err(|e| storage_error("write", &tmp, e))?;
+        std::fs::rename(&tmp, &self.path).map_err(|e| storage_error("replace", &self.path, e))?;
+        self.file = OpenOptions::new()
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| storage_error("open", &self.path, e))?;
+        self.log_bytes = line.len() as u64;
+        Ok(())
+    }
+}
+
+impl StateStorage for FileStorage {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.data.get(key).cloned())
+    }
+
+    fn set(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        if let Some(old) = self.data.get(key) {
+            xor(&mut self.digest, &entry_hash(key, old));
+            self.live_bytes -= encoded_len(key, old);
+        }
+        xor(&mut self.digest, &entry_hash(key, &value));
+        self.live_bytes += encoded_len(key, &value);
+        self.pending.insert(key.to_vec(), Some(value.clone()));
+        self.data.insert(key.to_vec(), value);
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<()> {
+        if let Some(old) = self.data.remove(key) {
+            xor(&mut self.digest, &entry_hash(key, &old));
+            self.live_bytes -= encoded_len(key, &old);
+            self.pending.insert(key.to_vec(), None);
+        }
+        Ok(())
+    }
+
+    /// Append the pending changes and return the digest of all entries
+    fn commit(&mut self) -> Result<Vec<u8>> {
+        if !self.pending.is_empty() {
+            let mut batch = Batch::default();
+            for (key, value) in std::mem::take(&mut self.pending) {
+                match value {
+                    Some(value) => {
+                        batch.set.insert(hex::encode(key), hex::encode(value));
+                    }
+                    None => batch.delete.push(hex::encode(key)),
+                }
+            }
+            let mut line = serde_json::to_vec(&batch)?;
+            line.push(b'\n');
+            self.file
+                .write_all(&line)
+                .and_then(|()| self.file.sync_data())
+                .map_err(|e| {
+                    StateError::Storage(format!(
+                        "failed to append to storage file {}: {}",
+                        self.path.display(),
+                        e
+                    ))
+                })?;
+            self.log_bytes += line.len() as u64;
+
+            if self.log_bytes > 2 * self.live_bytes + COMPACTION_SLACK {
+                self.compact()?;
+            }
+        }
+        Ok(self.digest.to_vec())
+    }
+}
+
+/// Hash of one entry; the storage digest XORs these so that it is updated
+/// per entry instead of rehashing everything on every commit
+fn entry_hash(key: &[u8], value: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update((key.len() as u64).to_be_bytes());
+    hasher.update(key);
+    hasher.update(value);
+    hasher.finalize().into()
+}
+
+/// Size of an entry in a batch: both parts hex-encoded, quoted and separated
+fn encoded_len(key: &[u8], value: &[u8]) -> u64 {
+    2 * (key.len() + value.len()) as u64 + 6
+}
+
+fn xor(digest: &mut [u8; 32], hash: &[u8; 32]) {
+    for (byte, other) in digest.iter_mut().zip(hash) {
+        *byte ^= other;
+    }
+}
 
 /// RocksDB storage implementation
 pub struct RocksDBStorage {
@@ -29,3 +305,77 @@ impl RedisCache {
         Ok(Self {})
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_storage_appends_and_replays_commits() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.db");
+        let mut storage = FileStorage::open(&path).unwrap();
+        storage.set(b"a", b"1".to_vec()).unwrap();
+        storage.set(b"b", b"2".to_vec()).unwrap();
+        storage.commit().unwrap();
+        let size = std::fs::metadata(&path).unwrap().len();
+
+        // The second commit only appends its own change
+        storage.delete(b"a").unwrap();
+        storage.set(b"c", b"3".to_vec()).unwrap();
+        let digest = storage.commit().unwrap();
+        let appended = std::fs::metadata(&path).unwrap().len() - size;
+        assert!(
+            appended < size + 20,
+            "appended {} bytes after a {} byte commit",
+            appended,
+            size
+        );
+
+        // A line torn by a crash is dropped on the next open
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"set\":{\"64\":").unwrap();
+        let mut reopened = FileStorage::open(&path).unwrap();
+        assert_eq!(reopened.get(b"a").unwrap(), None);
+        assert_eq!(reopened.get(b"b").unwrap(), Some(b"2".to_vec()));
+        assert_eq!(reopened.get(b"c").unwrap(), Some(b"3".to_vec()));
+        assert_eq!(reopened.commit().unwrap(), digest);
+        reopened.set(b"d", b"4".to_vec()).unwrap();
+        reopened.commit().unwrap();
+        assert_eq!(
+            FileStorage::open(&path).unwrap().get(b"d").unwrap(),
+            Some(b"4".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_file_storage_digest_depends_on_contents_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut first = FileStorage::open(dir.path().join("first.db")).unwrap();
+        first.set(b"a", b"1".to_vec()).unwrap();
+        first.commit().unwrap();
+        first.set(b"a", b"2".to_vec()).unwrap();
+        first.set(b"b", b"3".to_vec()).unwrap();
+
+        let mut second = FileStorage::open(dir.path().join("second.db")).unwrap();
+        second.set(b"b", b"3".to_vec()).unwrap();
+        second.set(b"a", b"2".to_vec()).unwrap();
+        assert_eq!(first.commit().unwrap(), second.commit().unwrap());
+    }
+
+    #[test]
+    fn test_file_storage_compacts_overwritten_values() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.db");
+        let mut storage = FileStorage::open(&path).unwrap();
+        for i in 0..100u8 {
+            storage.set(b"state", vec![i; 32 * 1024]).unwrap();
+            storage.commit().unwrap();
+        }
+        assert!(std::fs::metadata(&path).unwrap().len() < 3 * COMPACTION_SLACK);
+        assert_eq!(
+            FileStorage::open(&path).unwrap().get(b"state").unwrap(),
+            Some(vec![99; 32 * 1024])
+        );
+    }
+}
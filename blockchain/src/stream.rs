@@ -0,0 +1,643 @@
+//! Streaming Module
+//!
+//! This module turns committed blocks into real-time updates for WebSocket
+//! clients: level 2 book deltas, trades, per-account order updates and
+//! fills, and new blocks.
+//!
+//! Book deltas carry a per-market `sequence` and the `prev_sequence` they
+//! apply on top of. A client starts from a snapshot and applies deltas in
+//! order; whenever `prev_sequence` does not match the last sequence it
+//! applied, it has missed an update and must request a new snapshot.
+
+use crate::abci::{Block, BlockResult};
+use crate::app::OptimicApp;
+use crate::events::Event;
+use crate::math::parse_decimal;
+use crate::trading::{BookLevel, BookSnapshot};
+use crate::types::*;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+/// Number of blocks of updates buffered for slow subscribers
+const STREAM_CHANNEL_CAPACITY: usize = 256;
+
+/// A channel a client can subscribe to
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "channel", rename_all = "lowercase")]
+pub enum Channel {
+    /// Level 2 book of a market
+    Book { market: MarketId },
+
+    /// Trades of a market
+    Trades { market: MarketId },
+
+    /// Order updates and fills of an account
+    Account { address: AccAddress },
+
+    /// Newly committed blocks
+    Blocks,
+}
+
+/// Level 2 book update
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum BookUpdate {
+    /// Full book as of `sequence`
+    Snapshot {
+        market: MarketId,
+        sequence: u64,
+        bids: Vec<BookLevel>,
+        asks: Vec<BookLevel>,
+    },
+
+    /// Levels changed since `prev_sequence`; a quantity of "0" removes the level
+    Delta {
+        market: MarketId,
+        sequence: u64,
+        prev_sequence: u64,
+        height: u64,
+        bids: Vec<BookLevel>,
+        asks: Vec<BookLevel>,
+    },
+}
+
+/// Update on an account's stream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum AccountUpdate {
+    /// An order was placed, filled, cancelled or expired
+    Order { order: Order },
+
+    /// One of the account's orders traded
+    Fill { trade: Trade, side: OrderSide },
+
+    /// A position of the account was liquidated
+    Liquidation { event: Event },
+
+    /// The account was charged a penalty
+    Penalty { event: Event },
+
+    /// The account sent or received a transfer
+    Transfer { event: Event },
+
+    /// A deposit was minted to the account or it requested a withdrawal
+    Bridge { event: Event },
+
+    /// The account paid a trading fee or received a maker rebate
+    Fee { event: Event },
+
+    /// The account deposited to or voted on a proposal
+    Governance { event: Event },
+
+    /// The account delegated, undelegated, redelegated or withdrew rewards,
+    /// or an unbonding finished
+    Staking { event: Event },
+}
+
+/// A message pushed to subscribers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "channel", rename_all = "lowercase")]
+pub enum StreamMessage {
+    Book(BookUpdate),
+    Trades {
+        market: MarketId,
+        height: u64,
+        trade: Trade,
+    },
+    Account {
+        address: AccAddress,
+        height: u64,
+        update: AccountUpdate,
+    },
+    Blocks {
+        block: Block,
+    },
+}
+
+impl StreamMessage {
+    /// Channel the message is published on
+    pub fn channel(&self) -> Channel {
+        match self {
+            StreamMessage::Book(
+                BookUpdate::Snapshot { market, .. } | BookUpdate::Delta { market, .. },
+            ) => Channel::Book {
+                market: market.clone(),
+            },
+            StreamMessage::Trades { market, .. } => Channel::Trades {
+                market: market.clone(),
+            },
+            StreamMessage::Account { address, .. } => Channel::Account {
+                address: address.clone(),
+            },
+            StreamMessage::Blocks { .. } => Channel::Blocks,
+        }
+    }
+}
+
+/// Last published state of one market's book
+#[derive(Debug, Clone)]
+struct BookFeed {
+    sequence: u64,
+    book: BookSnapshot,
+}
+
+/// Publishes the updates of every committed block
+#[derive(Debug)]
+pub struct Streams {
+    books: HashMap<MarketId, BookFeed>,
+    sender: broadcast::Sender<Arc<Vec<StreamMessage>>>,
+}
+
+impl Streams {
+    /// Start streaming from the application's current state
+    pub fn new(app: &OptimicApp) -> Self {
+        let (sender, _) = broadcast::channel(STREAM_CHANNEL_CAPACITY);
+        let mut streams = Self {
+            books: HashMap::new(),
+            sender,
+        };
+        for market in app.state.markets.keys() {
+            let book = full_book(app, market);
+            streams
+                .books
+                .insert(market.clone(), BookFeed { sequence: 0, book });
+        }
+        streams
+    }
+
+    /// Receive the updates of every block committed from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<Vec<StreamMessage>>> {
+        self.sender.subscribe()
+    }
+
+    /// Current snapshot of a market's book
+    pub fn snapshot(&self, market: &MarketId) -> Option<BookUpdate> {
+        self.books.get(market).map(|feed| BookUpdate::Snapshot {
+            market: market.clone(),
+            sequence: feed.sequence,
+            bids: feed.book.bids.clone(),
+            asks: feed.book.asks.clone(),
+        })
+    }
+
+    /// Publish the updates of a committed block
+    pub fn publish(&mut self, app: &OptimicApp, result: &BlockResult) {
+        let height = result.block.height;
+        let mut messages = Vec::new();
+
+        // Book deltas
+        let mut markets: Vec<_> = app.state.markets.keys().collect();
+        markets.sort();
+        for market in markets {
+            let book = full_book(app, market);
+            let feed = self
+                .books
+                .entry(market.clone())
+                .or_insert_with(|| BookFeed {
+                    sequence: 0,
+                    book: BookSnapshot {
+                        market_id: market.clone(),
+                        bids: vec![],
+                        asks: vec![],
+                    },
+                });
+            let bids = diff_levels(&feed.book.bids, &book.bids);
+            let asks = diff_levels(&feed.book.asks, &book.asks);
+            if bids.is_empty() && asks.is_empty() {
+                continue;
+            }
+            let prev_sequence = feed.sequence;
+            feed.sequence += 1;
+            feed.book = book;
+            messages.push(StreamMessage::Book(BookUpdate::Delta {
+                market: market.clone(),
+                sequence: feed.sequence,
+                prev_sequence,
+                height,
+                bids,
+                asks,
+            }));
+        }
+
+        // Trades, fills and account events, in execution order
+        let events = result.events();
+        let mut touched = Vec::new();
+        let mut seen = HashSet::new();
+        for event in events.iter() {
+            match event {
+                Event::TradeExecuted { trade_id, .. } => {
+                    let Some(trade) = app.state.trades.get(trade_id) else {
+                        continue;
+                    };
+                    messages.push(StreamMessage::Trades {
+                        market: trade.market_id.clone(),
+                        height,
+                        trade: trade.clone(),
+                    });
+                    for (address, side) in [
+                        (&trade.buyer, OrderSide::Buy),
+                        (&trade.seller, OrderSide::Sell),
+                    ] {
+                        messages.push(StreamMessage::Account {
+                            address: address.clone(),
+                            height,
+                            update: AccountUpdate::Fill {
+                                trade: trade.clone(),
+                                side,
+                            },
+                        });
+                    }
+                }
+                Event::Liquidated { account, .. } => messages.push(StreamMessage::Account {
+                    address: account.clone(),
+                    height,
+                    update: AccountUpdate::Liquidation {
+                        event: event.clone(),
+                    },
+                }),
+                Event::PenaltyApplied { account, .. } => messages.push(StreamMessage::Account {
+                    address: account.clone(),
+                    height,
+                    update: AccountUpdate::Penalty {
+                        event: event.clone(),
+                    },
+                }),
+                Event::Transfer { from, to, .. } => {
+                    for address in [from, to] {
+                        messages.push(StreamMessage::Account {
+                            address: address.clone(),
+                            height,
+                            update: AccountUpdate::Transfer {
+                                event: event.clone(),
+                            },
+                        });
+                    }
+                }
+                Event::Deposited {
+                    recipient: address, ..
+                }
+                | Event::WithdrawalRequested {
+                    sender: address, ..
+                } => messages.push(StreamMessage::Account {
+                    address: address.clone(),
+                    height,
+                    update: AccountUpdate::Bridge {
+                        event: event.clone(),
+                    },
+                }),
+                Event::FeeCharged { account, .. } | Event::FeeRebated { account, .. } => messages
+                    .push(StreamMessage::Account {
+                        address: account.clone(),
+                        height,
+                        update: AccountUpdate::Fee {
+                            event: event.clone(),
+                        },
+                    }),
+                Event::ProposalDeposited {
+                    depositor: address, ..
+                }
+                | Event::ProposalVoted { voter: address, .. } => {
+                    messages.push(StreamMessage::Account {
+                        address: address.clone(),
+                        height,
+                        update: AccountUpdate::Governance {
+                            event: event.clone(),
+                        },
+                    })
+                }
+                Event::Delegated { delegator, .. }
+                | Event::UnbondingStarted { delegator, .. }
+                | Event::UnbondingCompleted { delegator, .. }
+                | Event::Redelegated { delegator, .. }
+                | Event::RewardsWithdrawn { delegator, .. }
+                | Event::CommissionWithdrawn {
+                    operator: delegator,
+                    ..
+                } => messages.push(StreamMessage::Account {
+                    address: delegator.clone(),
+                    height,
+                    update: AccountUpdate::Staking {
+                        event: event.clone(),
+                    },
+                }),
+                _ => {}
+            }
+            touched.extend(event.order_ids().into_iter().filter(|id| seen.insert(*id)));
+        }
+
+        // Final state of every order the block touched
+        for order in touched.iter().filter_map(|id| app.state.orders.get(id)) {
+            messages.push(StreamMessage::Account {
+                address: order.trader.clone(),
+                height,
+                update: AccountUpdate::Order {
+                    order: order.clone(),
+                },
+            });
+        }
+
+        messages.push(StreamMessage::Blocks {
+            block: result.block.clone(),
+        });
+
+        // Nobody listening is not an error
+        let _ = self.sender.send(Arc::new(messages));
+    }
+}
+
+/// Request sent by a WebSocket client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum StreamRequest {
+    Subscribe(Channel),
+    Unsubscribe(Channel),
+
+    /// Ask for a fresh book snapshot after detecting a gap
+    Snapshot {
+        market: MarketId,
+    },
+}
+
+/// Channels of one client and the last book sequence sent per market
+#[derive(Debug, Default)]
+pub struct Subscriptions {
+    channels: HashSet<Channel>,
+    sequences: HashMap<MarketId, u64>,
+}
+
+impl Subscriptions {
+    /// Subscribe to `channel`, returning the initial book snapshot if any
+    pub fn subscribe(
+        &mut self,
+        channel: Channel,
+        app: &OptimicApp,
+        streams: &Streams,
+    ) -> Result<Vec<StreamMessage>, String> {
+        match &channel {
+            Channel::Book { market } | Channel::Trades { market }
+                if !app.state.markets.contains_key(market) =>
+            {
+                return Err(format!("unknown market '{}'", market));
+            }
+            Channel::Account { address } if address.is_empty() => {
+                return Err("address must not be empty".to_string())
+            }
+            _ => {}
+        }
+
+        let snapshot = match &channel {
+            Channel::Book { market } => self.snapshot(market, streams),
+            _ => None,
+        };
+        self.channels.insert(channel);
+        Ok(snapshot.into_iter().collect())
+    }
+
+    /// Drop a subscription
+    pub fn unsubscribe(&mut self, channel: &Channel) {
+        if let Channel::Book { market } = channel {
+            self.sequences.remove(market);
+        }
+        self.channels.remove(channel);
+    }
+
+    /// Current snapshot of a market's book; later deltas continue from it
+    pub fn snapshot(&mut self, market: &MarketId, streams: &Streams) -> Option<StreamMessage> {
+        let snapshot = streams.snapshot(market)?;
+        if let BookUpdate::Snapshot { sequence, .. } = &snapshot {
+            self.sequences.insert(market.clone(), *sequence);
+        }
+        Some(StreamMessage::Book(snapshot))
+    }
+
+    /// Fresh snapshots of every subscribed book, after updates were lost
+    pub fn resync(&mut self, streams: &Streams) -> Vec<StreamMessage> {
+        let mut markets: Vec<_> = self
+            .channels
+            .iter()
+            .filter_map(|channel| match channel {
+                Channel::Book { market } => Some(market.clone()),
+                _ => None,
+            })
+            .collect();
+        markets.sort();
+        markets
+            .iter()
+            .filter_map(|market| self.snapshot(market, streams))
+            .collect()
+    }
+
+    /// Messages of a published block this client should receive
+    ///
+    /// Deltas already covered by a snapshot are dropped; a delta that does
+    /// not follow the last one sent is replaced by a fresh snapshot.
+    pub fn filter(&mut self, batch: &[StreamMessage], streams: &Streams) -> Vec<StreamMessage> {
+        let mut out = Vec::new();
+        for message in batch {
+            if !self.channels.contains(&message.channel()) {
+                continue;
+            }
+            if let StreamMessage::Book(BookUpdate::Delta {
+                market,
+                sequence,
+                prev_sequence,
+                ..
+            }) = message
+            {
+                let last = self.sequences.get(market).copied().unwrap_or(0);
+                if *sequence <= last {
+                    continue;
+                }
+                if *prev_sequence != last {
+                    out.extend(self.snapshot(market, streams));
+                    continue;
+                }
+                self.sequences.insert(market.clone(), *sequence);
+            }
+            out.push(message.clone());
+        }
+        out
+    }
+}
+
+fn full_book(app: &OptimicApp, market: &MarketId) -> BookSnapshot {
+    app.trading
+        .get_order_book(market)
+        .map(|book| book.snapshot(usize::MAX))
+        .unwrap_or_else(|| BookSnapshot {
+            market_id: market.clone(),
+            bids: vec![],
+            asks: vec![],
+        })
+}
+
+/// Levels that differ between two views of one side of a book
+fn diff_levels(old: &[BookLevel], new: &[BookLevel]) -> Vec<BookLevel> {
+    let index = |levels: &[BookLevel]| -> BTreeMap<Decimal, BookLevel> {
+        levels
+            .iter()
+            .map(|level| {
+                (
+                    parse_decimal(&level.price).unwrap_or_default(),
+                    level.clone(),
+                )
+            })
+            .collect()
+    };
+    let old = index(old);
+    let new = index(new);
+
+    let mut changes: Vec<BookLevel> = new
+        .iter()
+        .filter(|(price, level)| old.get(price) != Some(level))
+        .map(|(_, level)| level.clone())
+        .collect();
+    changes.extend(
+        old.iter()
+            .filter(|(price, _)| !new.contains_key(price))
+            .map(|(_, level)| BookLevel {
+                price: level.price.clone(),
+                quantity: "0".to_string(),
+                orders: 0,
+            }),
+    );
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::AppConfig;
+
+    fn app() -> OptimicApp {
+        let mut app = OptimicApp::new(AppConfig {
+            chain_id: "optimic-1".to_string(),
+            genesis_path: concat!(env!("CARGO_MANIFEST_DIR"), "/genesis.json").to_string(),
+            data_dir: "./data".to_string(),
+        });
+        app.load_genesis().unwrap();
+        app
+    }
+
+    /// Rest a bid at `price` and publish the block
+    fn publish_bid(app: &mut OptimicApp, streams: &mut Streams, id: OrderId, price: &str) {
+        let time = Timestamp::from_timestamp(id as i64, 0).unwrap();
+        let order = Order {
+            id,
+            trader: "optimic1genesis".to_string(),
+            market: "ETH-USD".to_string(),
+            side: OrderSide::Buy,
+            order_type: OrderType::Limit,
+            quantity: "1000000".to_string(),
+            price: Some(price.to_string()),
+            filled_quantity: "0".to_string(),
+            status: OrderStatus::Submitted,
+            created_at: time,
+            updated_at: time,
+            time_in_force: TimeInForce::GTC,
+            locked: "0".to_string(),
+            filled_value: "0".to_string(),
+        };
+        app.trading
+            .place_order(
+                order,
+                &mut app.state.orders,
+                &mut app.state.next_trade_id,
+                None,
+            )
+            .unwrap();
+        let block = Block {
+            height: id,
+            hash: String::new(),
+            time,
+            proposer: String::new(),
+            prev_hash: String::new(),
+            app_hash: String::new(),
+            txs: vec![],
+        };
+        let placed = Event::OrderPlaced {
+            order_id: id,
+            trader: "optimic1genesis".to_string(),
+            market: "ETH-USD".to_string(),
+            side: OrderSide::Buy,
+            order_type: OrderType::Limit,
+            quantity: "1000000".to_string(),
+            price: Some(price.to_string()),
+        };
+        let result = BlockResult {
+            block,
+            tx_results: vec![],
+            end_block_events: vec![placed],
+        };
+        streams.publish(app, &result);
+    }
+
+    #[test]
+    fn test_snapshot_then_deltas_with_gap_resync() {
+        let mut app = app();
+        let mut streams = Streams::new(&app);
+        let mut updates = streams.subscribe();
+        let mut subscriptions = Subscriptions::default();
+
+        let book = Channel::Book {
+            market: "ETH-USD".to_string(),
+        };
+        let initial = subscriptions.subscribe(book, &app, &streams).unwrap();
+        assert!(matches!(
+            initial[..],
+            [StreamMessage::Book(BookUpdate::Snapshot {
+                sequence: 0,
+                ..
+            })]
+        ));
+        let orders = Channel::Account {
+            address: "optimic1genesis".to_string(),
+        };
+        subscriptions.subscribe(orders, &app, &streams).unwrap();
+
+        publish_bid(&mut app, &mut streams, 1, "100");
+        let received = subscriptions.filter(&updates.try_recv().unwrap(), &streams);
+        assert!(matches!(
+            &received[0],
+            StreamMessage::Book(BookUpdate::Delta { sequence: 1, prev_sequence: 0, bids, .. }) if bids[0].price == "100"
+        ));
+        assert!(
+            matches!(&received[1], StreamMessage::Account { update: AccountUpdate::Order { order }, .. } if order.id == 1)
+        );
+        assert_eq!(received.len(), 2);
+
+        // The client misses block 2; block 3's delta is replaced by a snapshot
+        publish_bid(&mut app, &mut streams, 2, "101");
+        updates.try_recv().unwrap();
+        publish_bid(&mut app, &mut streams, 3, "102");
+        let received = subscriptions.filter(&updates.try_recv().unwrap(), &streams);
+        assert!(matches!(
+            &received[0],
+            StreamMessage::Book(BookUpdate::Snapshot { sequence: 3, bids, .. }) if bids.len() == 3
+        ));
+    }
+
+    fn level(price: &str, quantity: &str) -> BookLevel {
+        BookLevel {
+            price: price.to_string(),
+            quantity: quantity.to_string(),
+            orders: 1,
+        }
+    }
+
+    #[test]
+    fn test_diff_levels_reports_changes_and_removals() {
+        let old = vec![level("100", "5"), level("99", "3"), level("98", "1")];
+        let new = vec![level("100", "5"), level("99", "4"), level("97", "2")];
+
+        let changes = diff_levels(&old, &new);
+        let summary: Vec<_> = changes
+            .iter()
+            .map(|l| (l.price.as_str(), l.quantity.as_str()))
+            .collect();
+        assert_eq!(summary, vec![("97", "2"), ("99", "4"), ("98", "0")]);
+    }
+}
@@ -1,22 +1,173 @@
 //! Trading Engine Module
-//! 
+//!
 //! This module implements the core trading functionality including
 //! order matching, trade execution, and market management.
+//!
+//! Markets match either continuously (price-time priority, in transaction
+//! order) or in a frequent batch auction where every order collected during
+//! a block clears at a single uniform price in `end_block`. Batch auctions
+//! take away the block proposer's ability to front-run by reordering
+//! transactions inside a block.
+//!
+//! Market and stop orders execute no further than the market's price band
+//! around the mark price, in either matching mode; what the band leaves of
+//! them is cancelled rather than rested.
+//!
+//! Market buys are not accepted in batch auction markets: the quote funds
+//! they need are unknown until the auction clears, so they cannot be locked
+//! up front (see [`crate::portfolio`]).
 
+use crate::codes;
+use crate::math::{mul_div, notional_ceil, parse_amount, parse_decimal, NumberError};
 use crate::types::*;
-use anyhow::Result;
-use tracing::warn;
+use imbl::{HashMap, OrdMap};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use thiserror::Error;
+use tracing::debug;
+
+type Result<T> = std::result::Result<T, TradingError>;
+
+/// Reasons an order or market operation is rejected
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TradingError {
+    #[error("unknown market {0}")]
+    UnknownMarket(MarketId),
+
+    #[error("market {0} already exists")]
+    MarketExists(MarketId),
+
+    #[error("unknown order {0}")]
+    UnknownOrder(OrderId),
+
+    #[error("order {order_id} is already {status}")]
+    OrderClosed { order_id: OrderId, status: String },
+
+    #[error("order {order_id} does not belong to {sender}")]
+    NotOrderOwner {
+        order_id: OrderId,
+        sender: AccAddress,
+    },
+
+    #[error("{0} orders are not supported yet")]
+    UnsupportedOrderType(OrderType),
+
+    #[error("fill-or-kill orders are not supported in batch auction markets")]
+    FillOrKillInBatchAuction,
+
+    #[error("market buy orders are not supported in batch auction markets")]
+    MarketBuyInBatchAuction,
+
+    #[error("order quantity must be positive")]
+    InvalidQuantity,
+
+    #[error("order price must be positive")]
+    InvalidPrice,
+
+    #[error("{0} orders require a price")]
+    MissingPrice(OrderType),
+
+    #[error("market {market} is {status}")]
+    MarketNotActive {
+        market: MarketId,
+        status: MarketStatus,
+    },
+
+    #[error("invalid market: {0}")]
+    InvalidMarket(String),
+
+    #[error("{0} is not the market admin")]
+    NotMarketAdmin(AccAddress),
+
+    #[error("market {market} cannot change from {from} to {to}")]
+    InvalidStatusChange {
+        market: MarketId,
+        from: MarketStatus,
+        to: MarketStatus,
+    },
+
+    #[error("price {price} is not a multiple of the tick size {tick_size}")]
+    OffTick { price: Price, tick_size: Price },
+
+    #[error("quantity {quantity} is below the minimum order size {min_order_size}")]
+    BelowMinSize {
+        quantity: Uint128,
+        min_order_size: Uint128,
+    },
+
+    #[error("quantity {quantity} is not a multiple of the lot size {lot_size}")]
+    OffLot {
+        quantity: Uint128,
+        lot_size: Uint128,
+    },
+
+    #[error("price {price} is outside the band {low} to {high} around the mark price")]
+    OutsidePriceBand {
+        price: Price,
+        low: Price,
+        high: Price,
+    },
+
+    #[error("order book inconsistent: {0}")]
+    BookInconsistent(String),
+
+    #[error(transparent)]
+    Number(#[from] NumberError),
+}
+
+impl TradingError {
+    /// Stable result code, see [`crate::codes`]
+    pub fn code(&self) -> u32 {
+        match self {
+            TradingError::UnknownMarket(_) => codes::TRADING_UNKNOWN_MARKET,
+            TradingError::MarketExists(_) => codes::TRADING_MARKET_EXISTS,
+            TradingError::UnknownOrder(_) => codes::TRADING_UNKNOWN_ORDER,
+            TradingError::OrderClosed { .. } => codes::TRADING_ORDER_CLOSED,
+            TradingError::NotOrderOwner { .. } => codes::TRADING_NOT_ORDER_OWNER,
+            TradingError::UnsupportedOrderType(_) => codes::TRADING_UNSUPPORTED_ORDER_TYPE,
+            TradingError::FillOrKillInBatchAuction => codes::TRADING_UNSUPPORTED_TIME_IN_FORCE,
+            TradingError::MarketBuyInBatchAuction => codes::TRADING_MARKET_BUY_IN_BATCH_AUCTION,
+            TradingError::InvalidQuantity => codes::TRADING_INVALID_QUANTITY,
+            TradingError::InvalidPrice => codes::TRADING_INVALID_PRICE,
+            TradingError::MissingPrice(_) => codes::TRADING_MISSING_PRICE,
+            TradingError::MarketNotActive { .. } => codes::TRADING_MARKET_NOT_ACTIVE,
+            TradingError::InvalidMarket(_) => codes::TRADING_INVALID_MARKET,
+            TradingError::NotMarketAdmin(_) => codes::TRADING_NOT_MARKET_ADMIN,
+            TradingError::InvalidStatusChange { .. } => codes::TRADING_INVALID_STATUS_CHANGE,
+            TradingError::OffTick { .. } => codes::TRADING_OFF_TICK,
+            TradingError::BelowMinSize { .. } => codes::TRADING_BELOW_MIN_SIZE,
+            TradingError::OffLot { .. } => codes::TRADING_OFF_LOT,
+            TradingError::OutsidePriceBand { .. } => codes::TRADING_OUTSIDE_PRICE_BAND,
+            TradingError::BookInconsistent(_) => codes::TRADING_BOOK_INCONSISTENT,
+            TradingError::Number(error) => error.code(),
+        }
+    }
+}
 
 /// Order book implementation
+#[derive(Debug, Clone)]
 pub struct OrderBook {
     /// Market identifier
     pub market_id: MarketId,
-    
-    /// Buy orders (bids) - sorted by price descending
-    pub bids: std::collections::BTreeMap<String, PriceLevel>,
-    
-    /// Sell orders (asks) - sorted by price ascending  
-    pub asks: std::collections::BTreeMap<String, PriceLevel>,
+
+    /// How orders in this market are matched
+    pub matching_mode: MatchingMode,
+
+    /// Only active markets accept and match orders
+    pub status: MarketStatus,
+
+    /// Buy orders (bids) - best price is the last key
+    pub bids: OrdMap<Decimal, PriceLevel>,
+
+    /// Sell orders (asks) - best price is the first key
+    pub asks: OrdMap<Decimal, PriceLevel>,
+
+    /// Market orders waiting for the next batch auction
+    pub unpriced: Vec<OrderId>,
+
+    /// Whether orders arrived since the last batch auction
+    pub auction_pending: bool,
 }
 
 /// Price level in the order book
@@ -28,52 +179,1250 @@ pub struct PriceLevel {
     pub orders: Vec<OrderId>,
 }
 
+/// Aggregated quantity at one price (level 2 view)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BookLevel {
+    pub price: Price,
+    pub quantity: Uint128,
+    pub orders: u32,
+}
+
+/// Level 2 view of an order book, best prices first
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BookSnapshot {
+    pub market_id: MarketId,
+    pub bids: Vec<BookLevel>,
+    pub asks: Vec<BookLevel>,
+}
+
+/// Prices between which market and stop orders may execute
+///
+/// Derived from the mark price and `params.markets.price_band` by
+/// [`crate::markets::price_band`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PriceBand {
+    pub low: Decimal,
+    pub high: Decimal,
+}
+
+/// Outcome of clearing batch auctions
+#[derive(Debug, Clone, Default)]
+pub struct AuctionResult {
+    pub trades: Vec<Trade>,
+
+    /// Market, immediate-or-cancel and band-limited stop orders whose
+    /// remainder was cancelled
+    pub expired: Vec<OrderId>,
+}
+
+impl AuctionResult {
+    fn extend(&mut self, other: AuctionResult) {
+        self.trades.extend(other.trades);
+        self.expired.extend(other.expired);
+    }
+}
+
 /// Trading engine
+#[derive(Debug, Clone, Default)]
 pub struct TradingEngine {
     /// Order books for each market
-    order_books: std::collections::HashMap<MarketId, OrderBook>,
-    
-    /// Next trade ID
-    next_trade_id: TradeId,
+    order_books: HashMap<MarketId, OrderBook>,
+}
+
+/// Quantity of an order that is still open
+pub fn remaining_quantity(order: &Order) -> Result<u128> {
+    let quantity = parse_amount(&order.quantity)?;
+    let filled = parse_amount(&order.filled_quantity)?;
+    Ok(quantity.saturating_sub(filled))
+}
+
+/// Limit price of an order, `None` for market orders
+fn limit_price(order: &Order) -> Result<Option<Decimal>> {
+    match (&order.order_type, &order.price) {
+        (OrderType::Market, _) => Ok(None),
+        (_, Some(price)) => Ok(Some(parse_decimal(price)?)),
+        (order_type, None) => Err(TradingError::MissingPrice(order_type.clone())),
+    }
+}
+
+/// Worst price `order` may execute at
+///
+/// Limit prices are held to the band on entry; market and stop orders are
+/// held to it here, when they match.
+fn execution_limit(order: &Order, band: Option<PriceBand>) -> Result<Option<Decimal>> {
+    let limit = limit_price(order)?;
+    let Some(band) =
+        band.filter(|_| matches!(order.order_type, OrderType::Market | OrderType::Stop))
+    else {
+        return Ok(limit);
+    };
+    Ok(Some(match order.side {
+        OrderSide::Buy => limit.map_or(band.high, |limit| limit.min(band.high)),
+        OrderSide::Sell => limit.map_or(band.low, |limit| limit.max(band.low)),
+    }))
+}
+
+impl PriceLevel {
+    fn new(price: Price) -> Self {
+        Self {
+            price,
+            total_quantity: "0".to_string(),
+            order_count: 0,
+            orders: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, order_id: OrderId, quantity: u128) -> Result<()> {
+        self.total_quantity = add(parse_amount(&self.total_quantity)?, quantity)?.to_string();
+        self.order_count += 1;
+        self.orders.push(order_id);
+        Ok(())
+    }
+
+    fn reduce(&mut self, quantity: u128) -> Result<()> {
+        self.total_quantity = parse_amount(&self.total_quantity)?
+            .saturating_sub(quantity)
+            .to_string();
+        Ok(())
+    }
+
+    fn remove(&mut self, order_id: OrderId, remaining: u128) -> Result<()> {
+        if let Some(index) = self.orders.iter().position(|id| *id == order_id) {
+            self.orders.remove(index);
+            self.order_count -= 1;
+            self.reduce(remaining)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<&PriceLevel> for BookLevel {
+    fn from(level: &PriceLevel) -> Self {
+        Self {
+            price: level.price.clone(),
+            quantity: level.total_quantity.clone(),
+            orders: level.order_count,
+        }
+    }
+}
+
+impl OrderBook {
+    /// Level 2 snapshot with at most `depth` levels per side
+    pub fn snapshot(&self, depth: usize) -> BookSnapshot {
+        BookSnapshot {
+            market_id: self.market_id.clone(),
+            bids: self
+                .bids
+                .values()
+                .rev()
+                .take(depth)
+                .map(BookLevel::from)
+                .collect(),
+            asks: self
+                .asks
+                .values()
+                .take(depth)
+                .map(BookLevel::from)
+                .collect(),
+        }
+    }
+
+    fn new(market: &Market) -> Self {
+        Self {
+            market_id: market.id.clone(),
+            matching_mode: market.matching_mode,
+            status: market.status,
+            bids: OrdMap::new(),
+            asks: OrdMap::new(),
+            unpriced: Vec::new(),
+            auction_pending: false,
+        }
+    }
+
+    /// Rest an order on its side of the book
+    fn rest(&mut self, order: &Order) -> Result<()> {
+        let remaining = remaining_quantity(order)?;
+        match limit_price(order)? {
+            Some(price) => {
+                let side = match order.side {
+                    OrderSide::Buy => &mut self.bids,
+                    OrderSide::Sell => &mut self.asks,
+                };
+                let level = side
+                    .entry(price)
+                    .or_insert_with(|| PriceLevel::new(order.price.clone().unwrap_or_default()));
+                level.push(order.id, remaining)
+            }
+            None => {
+                self.unpriced.push(order.id);
+                Ok(())
+            }
+        }
+    }
+
+    /// Take an order off the book
+    fn unrest(&mut self, order: &Order) -> Result<()> {
+        let remaining = remaining_quantity(order)?;
+        match limit_price(order)? {
+            Some(price) => {
+                let side = match order.side {
+                    OrderSide::Buy => &mut self.bids,
+                    OrderSide::Sell => &mut self.asks,
+                };
+                if let Some(level) = side.get_mut(&price) {
+                    level.remove(order.id, remaining)?;
+                    if level.orders.is_empty() {
+                        side.remove(&price);
+                    }
+                }
+            }
+            None => self.unpriced.retain(|id| *id != order.id),
+        }
+        Ok(())
+    }
+
+    /// Update the book after `order` was filled by `quantity`
+    fn apply_fill(&mut self, order: &Order, quantity: u128) -> Result<()> {
+        match limit_price(order)? {
+            Some(price) => {
+                let side = match order.side {
+                    OrderSide::Buy => &mut self.bids,
+                    OrderSide::Sell => &mut self.asks,
+                };
+                if let Some(level) = side.get_mut(&price) {
+                    level.reduce(quantity)?;
+                    if !order.is_open() {
+                        level.remove(order.id, 0)?;
+                    }
+                    if level.orders.is_empty() {
+                        side.remove(&price);
+                    }
+                }
+            }
+            None if !order.is_open() => self.unpriced.retain(|id| *id != order.id),
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Best price on the side opposite to `side`
+    fn best_opposite(&self, side: &OrderSide) -> Option<Decimal> {
+        match side {
+            OrderSide::Buy => self.asks.keys().next().copied(),
+            OrderSide::Sell => self.bids.keys().next_back().copied(),
+        }
+    }
+
+    /// Quantity available against an order up to its limit price
+    fn available_against(&self, side: &OrderSide, limit: Option<Decimal>) -> Result<u128> {
+        let levels: Box<dyn Iterator<Item = (&Decimal, &PriceLevel)>> = match side {
+            OrderSide::Buy => Box::new(
+                self.asks
+                    .iter()
+                    .take_while(|(p, _)| limit.is_none_or(|l| **p <= l)),
+            ),
+            OrderSide::Sell => Box::new(
+                self.bids
+                    .iter()
+                    .rev()
+                    .take_while(|(p, _)| limit.is_none_or(|l| **p >= l)),
+            ),
+        };
+        let mut total = 0u128;
+        for (_, level) in levels {
+            total = add(total, parse_amount(&level.total_quantity)?)?;
+        }
+        Ok(total)
+    }
+}
+
+/// Record a fill on an order
+fn fill(order: &mut Order, quantity: u128, time: Timestamp) -> Result<()> {
+    let filled = add(parse_amount(&order.filled_quantity)?, quantity)?;
+    order.filled_quantity = filled.to_string();
+    order.status = if filled >= parse_amount(&order.quantity)? {
+        OrderStatus::Filled
+    } else {
+        OrderStatus::PartiallyFilled
+    };
+    order.updated_at = time;
+    Ok(())
+}
+
+/// Build a trade between two orders
+fn make_trade(
+    next_trade_id: &mut TradeId,
+    buy: &Order,
+    sell: &Order,
+    quantity: u128,
+    price: Price,
+    time: Timestamp,
+) -> Trade {
+    let id = *next_trade_id;
+    *next_trade_id += 1;
+    Trade {
+        id,
+        market_id: buy.market.clone(),
+        buyer: buy.trader.clone(),
+        seller: sell.trader.clone(),
+        quantity: quantity.to_string(),
+        price,
+        timestamp: time,
+        buy_order_id: buy.id,
+        sell_order_id: sell.id,
+    }
 }
 
 impl TradingEngine {
     /// Create a new trading engine
     pub fn new() -> Self {
-        Self {
-            order_books: std::collections::HashMap::new(),
-            next_trade_id: 1,
+        Self::default()
+    }
+
+    /// Rebuild the order books from the open orders in `orders`
+    pub fn rebuild(
+        markets: &HashMap<MarketId, Market>,
+        orders: &HashMap<OrderId, Order>,
+    ) -> Result<Self> {
+        let mut engine = Self::new();
+        for market in markets.values() {
+            engine.add_market(market)?;
         }
+
+        // Re-insert in arrival order to restore time priority
+        let mut open: Vec<_> = orders.values().filter(|order| order.is_open()).collect();
+        open.sort_by_key(|order| (order.created_at, order.id));
+        for order in open {
+            let book = engine
+                .order_books
+                .get_mut(&order.market)
+                .ok_or_else(|| TradingError::UnknownMarket(order.market.clone()))?;
+            book.rest(order)?;
+            if book.matching_mode == MatchingMode::BatchAuction {
+                book.auction_pending = true;
+            }
+        }
+        Ok(engine)
     }
 
     /// Add a new market
-    pub fn add_market(&mut self, market_id: MarketId) -> Result<()> {
-        let order_book = OrderBook {
-            market_id: market_id.clone(),
-            bids: std::collections::BTreeMap::new(),
-            asks: std::collections::BTreeMap::new(),
-        };
-        
-        self.order_books.insert(market_id, order_book);
+    pub fn add_market(&mut self, market: &Market) -> Result<()> {
+        if self.order_books.contains_key(&market.id) {
+            return Err(TradingError::MarketExists(market.id.clone()));
+        }
+        self.order_books
+            .insert(market.id.clone(), OrderBook::new(market));
         Ok(())
     }
 
+    /// Switch how a market matches orders
+    ///
+    /// Leaving batch mode clears any pending auction first so that the
+    /// continuous book never starts out crossed.
+    pub fn set_matching_mode(
+        &mut self,
+        market_id: &MarketId,
+        mode: MatchingMode,
+        orders: &mut HashMap<OrderId, Order>,
+        next_trade_id: &mut TradeId,
+        time: Timestamp,
+        band: Option<PriceBand>,
+    ) -> Result<AuctionResult> {
+        let book = self
+            .order_books
+            .get_mut(market_id)
+            .ok_or_else(|| TradingError::UnknownMarket(market_id.clone()))?;
+        let result = if book.matching_mode == MatchingMode::BatchAuction
+            && mode == MatchingMode::Continuous
+        {
+            run_batch_auction(book, orders, next_trade_id, time, band)?
+        } else {
+            AuctionResult::default()
+        };
+        book.matching_mode = mode;
+        Ok(result)
+    }
+
+    /// Change the status of a market
+    ///
+    /// A suspended market keeps its resting orders, which can still be
+    /// cancelled; a pending batch auction clears once the market resumes.
+    /// Closing a market cancels every order left on its book and returns
+    /// their ids so the caller can release what they lock.
+    pub fn set_market_status(
+        &mut self,
+        market_id: &MarketId,
+        status: MarketStatus,
+        orders: &mut HashMap<OrderId, Order>,
+        time: Timestamp,
+    ) -> Result<Vec<OrderId>> {
+        let book = self
+            .order_books
+            .get_mut(market_id)
+            .ok_or_else(|| TradingError::UnknownMarket(market_id.clone()))?;
+        book.status = status;
+        if status != MarketStatus::Closed {
+            return Ok(Vec::new());
+        }
+
+        let mut cancelled: Vec<OrderId> = book
+            .bids
+            .values()
+            .chain(book.asks.values())
+            .flat_map(|level| level.orders.iter().copied())
+            .chain(book.unpriced.iter().copied())
+            .collect();
+        cancelled.sort();
+        for order_id in &cancelled {
+            let order = orders.get_mut(order_id).ok_or_else(|| {
+                TradingError::BookInconsistent(format!("resting order {} not found", order_id))
+            })?;
+            order.status = OrderStatus::Cancelled;
+            order.updated_at = time;
+        }
+        book.bids.clear();
+        book.asks.clear();
+        book.unpriced.clear();
+        book.auction_pending = false;
+        Ok(cancelled)
+    }
+
     /// Place a new order
-    pub fn place_order(&mut self, order: Order) -> Result<Vec<Trade>> {
-        warn!("Order placement not yet implemented");
-        // TODO: Implement order placement and matching
-        Ok(vec![])
+    ///
+    /// The order is recorded in `orders`. In continuous markets it matches
+    /// immediately against resting orders; in batch auction markets it waits
+    /// for the auction at the end of the block. `band` bounds the prices a
+    /// market or stop order may take.
+    pub fn place_order(
+        &mut self,
+        order: Order,
+        orders: &mut HashMap<OrderId, Order>,
+        next_trade_id: &mut TradeId,
+        band: Option<PriceBand>,
+    ) -> Result<Vec<Trade>> {
+        let book = self
+            .order_books
+            .get_mut(&order.market)
+            .ok_or_else(|| TradingError::UnknownMarket(order.market.clone()))?;
+        if book.status != MarketStatus::Active {
+            return Err(TradingError::MarketNotActive {
+                market: order.market.clone(),
+                status: book.status,
+            });
+        }
+        let order_id = order.id;
+
+        if book.matching_mode == MatchingMode::BatchAuction {
+            book.rest(&order)?;
+            book.auction_pending = true;
+            orders.insert(order_id, order);
+            return Ok(vec![]);
+        }
+
+        let limit = execution_limit(&order, band)?;
+        // Orders the band stops short never rest outside it
+        let can_rest = limit.is_some() && limit == limit_price(&order)?;
+        let mut remaining = remaining_quantity(&order)?;
+
+        // Fill-or-kill orders never touch the book unless they fill completely
+        if matches!(order.time_in_force, TimeInForce::FOK)
+            && book.available_against(&order.side, limit)? < remaining
+        {
+            let mut order = order;
+            order.status = OrderStatus::Cancelled;
+            orders.insert(order_id, order);
+            return Ok(vec![]);
+        }
+
+        orders.insert(order_id, order);
+        let mut trades = Vec::new();
+        let side = orders[&order_id].side.clone();
+        let time = orders[&order_id].created_at;
+
+        while remaining > 0 {
+            let Some(best) = book.best_opposite(&side) else {
+                break;
+            };
+            let crosses = match (&side, limit) {
+                (_, None) => true,
+                (OrderSide::Buy, Some(limit)) => best <= limit,
+                (OrderSide::Sell, Some(limit)) => best >= limit,
+            };
+            if !crosses {
+                break;
+            }
+
+            let levels = match side {
+                OrderSide::Buy => &mut book.asks,
+                OrderSide::Sell => &mut book.bids,
+            };
+            let level = levels.get_mut(&best).expect("best level exists");
+
+            while remaining > 0 && !level.orders.is_empty() {
+                let maker_id = level.orders[0];
+                let maker = orders.get(&maker_id).ok_or_else(|| {
+                    TradingError::BookInconsistent(format!("resting order {} not found", maker_id))
+                })?;
+                let quantity = remaining.min(remaining_quantity(maker)?);
+
+                let taker = &orders[&order_id];
+                let trade = match side {
+                    OrderSide::Buy => make_trade(
+                        next_trade_id,
+                        taker,
+                        maker,
+                        quantity,
+                        level.price.clone(),
+                        time,
+                    ),
+                    OrderSide::Sell => make_trade(
+                        next_trade_id,
+                        maker,
+                        taker,
+                        quantity,
+                        level.price.clone(),
+                        time,
+                    ),
+                };
+                trades.push(trade);
+
+                let maker = orders.get_mut(&maker_id).expect("maker exists");
+                fill(maker, quantity, time)?;
+                level.reduce(quantity)?;
+                if !maker.is_open() {
+                    level.orders.remove(0);
+                    level.order_count -= 1;
+                }
+                fill(
+                    orders.get_mut(&order_id).expect("taker exists"),
+                    quantity,
+                    time,
+                )?;
+                remaining -= quantity;
+            }
+
+            if level.orders.is_empty() {
+                levels.remove(&best);
+            }
+        }
+
+        let taker = orders.get_mut(&order_id).expect("taker exists");
+        if remaining > 0 {
+            let rests =
+                can_rest && matches!(taker.time_in_force, TimeInForce::GTC | TimeInForce::GTD(_));
+            if rests {
+                book.rest(taker)?;
+            } else {
+                taker.status = OrderStatus::Cancelled;
+            }
+        }
+
+        debug!("Order {} produced {} trades", order_id, trades.len());
+        Ok(trades)
     }
 
     /// Cancel an order
-    pub fn cancel_order(&mut self, market_id: &MarketId, order_id: OrderId) -> Result<()> {
-        warn!("Order cancellation not yet implemented");
-        // TODO: Implement order cancellation
+    pub fn cancel_order(
+        &mut self,
+        order_id: OrderId,
+        orders: &mut HashMap<OrderId, Order>,
+    ) -> Result<()> {
+        let order = orders
+            .get_mut(&order_id)
+            .ok_or(TradingError::UnknownOrder(order_id))?;
+        if !order.is_open() {
+            return Err(TradingError::OrderClosed {
+                order_id,
+                status: order.status.to_string(),
+            });
+        }
+        let book = self
+            .order_books
+            .get_mut(&order.market)
+            .ok_or_else(|| TradingError::UnknownMarket(order.market.clone()))?;
+        book.unrest(order)?;
+        order.status = OrderStatus::Cancelled;
         Ok(())
     }
 
+    /// Clear every batch auction market that received orders this block
+    ///
+    /// `bands` holds the price band of each market that has a mark price.
+    pub fn run_auctions(
+        &mut self,
+        orders: &mut HashMap<OrderId, Order>,
+        next_trade_id: &mut TradeId,
+        time: Timestamp,
+        bands: &BTreeMap<MarketId, PriceBand>,
+    ) -> Result<AuctionResult> {
+        let mut market_ids: Vec<_> = self
+            .order_books
+            .values()
+            .filter(|book| {
+                book.status == MarketStatus::Active
+                    && book.matching_mode == MatchingMode::BatchAuction
+                    && book.auction_pending
+            })
+            .map(|book| book.market_id.clone())
+            .collect();
+        market_ids.sort();
+
+        let mut result = AuctionResult::default();
+        for market_id in market_ids {
+            let book = self.order_books.get_mut(&market_id).expect("market exists");
+            let band = bands.get(&market_id).copied();
+            result.extend(run_batch_auction(book, orders, next_trade_id, time, band)?);
+        }
+        Ok(result)
+    }
+
+    /// Expire every resting good-till-date order whose date is at or
+    /// before `time`
+    ///
+    /// Returns the ids of the expired orders so the caller can release
+    /// what they lock.
+    pub fn expire_orders(
+        &mut self,
+        orders: &mut HashMap<OrderId, Order>,
+        time: Timestamp,
+    ) -> Result<Vec<OrderId>> {
+        // Only books that hold an expired order are touched
+        let mut expired: Vec<OrderId> = self
+            .order_books
+            .values()
+            .flat_map(|book| book.bids.values().chain(book.asks.values()))
+            .flat_map(|level| level.orders.iter().copied())
+            .filter(|id| {
+                orders.get(id).is_some_and(
+                    |order| matches!(order.time_in_force, TimeInForce::GTD(until) if until <= time),
+                )
+            })
+            .collect();
+        expired.sort();
+        for order_id in &expired {
+            let order = orders.get_mut(order_id).expect("order exists");
+            let book = self
+                .order_books
+                .get_mut(&order.market)
+                .ok_or_else(|| TradingError::UnknownMarket(order.market.clone()))?;
+            book.unrest(order)?;
+            order.status = OrderStatus::Expired;
+            order.updated_at = time;
+        }
+        Ok(expired)
+    }
+
+    /// Quote value of buying `quantity` at market from the resting asks
+    ///
+    /// Only the part the book can fill is priced; the rest of a market
+    /// order is cancelled.
+    pub fn market_buy_cost(&self, market_id: &MarketId, quantity: u128) -> Result<u128> {
+        let book = self
+            .order_books
+            .get(market_id)
+            .ok_or_else(|| TradingError::UnknownMarket(market_id.clone()))?;
+        let (mut remaining, mut cost) = (quantity, 0u128);
+        for (price, level) in &book.asks {
+            if remaining == 0 {
+                break;
+            }
+            let take = remaining.min(parse_amount(&level.total_quantity)?);
+            cost = add(cost, notional_ceil(take, *price)?)?;
+            remaining -= take;
+        }
+        Ok(cost)
+    }
+
     /// Get order book for a market
     pub fn get_order_book(&self, market_id: &MarketId) -> Option<&OrderBook> {
         self.order_books.get(market_id)
     }
 }
+
+/// An order taking part in a batch auction
+struct Bid {
+    id: OrderId,
+    limit: Option<Decimal>,
+    created_at: Timestamp,
+    quantity: u128,
+}
+
+/// Clear a batch auction at a single uniform price
+///
+/// The clearing price maximises matched volume, then minimises the
+/// imbalance between demand and supply; remaining ties take the median
+/// candidate. Orders are filled best price first, then by the block they
+/// arrived in. Orders from the same block at the same price share their fill
+/// pro rata, so their position inside the block does not matter.
+fn run_batch_auction(
+    book: &mut OrderBook,
+    orders: &mut HashMap<OrderId, Order>,
+    next_trade_id: &mut TradeId,
+    time: Timestamp,
+    band: Option<PriceBand>,
+) -> Result<AuctionResult> {
+    book.auction_pending = false;
+
+    let collect = |ids: Vec<OrderId>| -> Result<Vec<Bid>> {
+        ids.into_iter()
+            .map(|id| {
+                let order = &orders[&id];
+                Ok(Bid {
+                    id,
+                    limit: execution_limit(order, band)?,
+                    created_at: order.created_at,
+                    quantity: remaining_quantity(order)?,
+                })
+            })
+            .collect()
+    };
+    let resting = |levels: &OrdMap<Decimal, PriceLevel>| {
+        levels
+            .values()
+            .flat_map(|l| l.orders.clone())
+            .collect::<Vec<_>>()
+    };
+    let (unpriced_buys, unpriced_sells): (Vec<OrderId>, Vec<OrderId>) = book
+        .unpriced
+        .iter()
+        .partition(|id| matches!(orders[*id].side, OrderSide::Buy));
+
+    let mut buys = collect([resting(&book.bids), unpriced_buys].concat())?;
+    let mut sells = collect([resting(&book.asks), unpriced_sells].concat())?;
+
+    // Candidate prices are the limit prices present in the book
+    let mut candidates: Vec<(Decimal, Price)> = book
+        .bids
+        .iter()
+        .chain(book.asks.iter())
+        .map(|(price, level)| (*price, level.price.clone()))
+        .collect();
+    candidates.sort_by_key(|(price, _)| *price);
+    candidates.dedup_by_key(|(price, _)| *price);
+
+    let demand = |p: Decimal| {
+        sum(buys
+            .iter()
+            .filter(|b| b.limit.is_none_or(|l| l >= p))
+            .map(|b| b.quantity))
+    };
+    let supply = |p: Decimal| {
+        sum(sells
+            .iter()
+            .filter(|s| s.limit.is_none_or(|l| l <= p))
+            .map(|s| s.quantity))
+    };
+
+    let mut best: Option<(u128, u128)> = None;
+    let mut tied = Vec::new();
+    for (price, label) in &candidates {
+        let (d, s) = (demand(*price)?, supply(*price)?);
+        let key = (d.min(s), u128::MAX - d.abs_diff(s));
+        if key.0 == 0 {
+            continue;
+        }
+        match best {
+            Some(current) if key < current => {}
+            Some(current) if key == current => tied.push((*price, label.clone())),
+            _ => {
+                best = Some(key);
+                tied = vec![(*price, label.clone())];
+            }
+        }
+    }
+
+    let mut trades = Vec::new();
+    if let Some((volume, _)) = best {
+        let (clearing, label) = tied[(tied.len() - 1) / 2].clone();
+
+        // Most aggressive first: market orders, then by price, then by block
+        buys.retain(|b| b.limit.is_none_or(|l| l >= clearing));
+        buys.sort_by(|a, b| {
+            let price = |x: &Bid| x.limit.unwrap_or(Decimal::MAX);
+            price(b)
+                .cmp(&price(a))
+                .then(a.created_at.cmp(&b.created_at))
+        });
+        sells.retain(|s| s.limit.is_none_or(|l| l <= clearing));
+        sells.sort_by(|a, b| {
+            let price = |x: &Bid| x.limit.unwrap_or(Decimal::MIN);
+            price(a)
+                .cmp(&price(b))
+                .then(a.created_at.cmp(&b.created_at))
+        });
+
+        let buy_fills = allocate(&buys, volume)?;
+        let sell_fills = allocate(&sells, volume)?;
+
+        // Pair the allocations off against each other
+        let (mut i, mut j) = (0, 0);
+        let (mut buy_left, mut sell_left) = (
+            buy_fills.first().map_or(0, |f| f.1),
+            sell_fills.first().map_or(0, |f| f.1),
+        );
+        while i < buy_fills.len() && j < sell_fills.len() {
+            let quantity = buy_left.min(sell_left);
+            if quantity > 0 {
+                let (buy_id, sell_id) = (buy_fills[i].0, sell_fills[j].0);
+                trades.push(make_trade(
+                    next_trade_id,
+                    &orders[&buy_id],
+                    &orders[&sell_id],
+                    quantity,
+                    label.clone(),
+                    time,
+                ));
+                for id in [buy_id, sell_id] {
+                    let order = orders.get_mut(&id).expect("order exists");
+                    fill(order, quantity, time)?;
+                    book.apply_fill(order, quantity)?;
+                }
+            }
+            buy_left -= quantity;
+            sell_left -= quantity;
+            if buy_left == 0 {
+                i += 1;
+                buy_left = buy_fills.get(i).map_or(0, |f| f.1);
+            }
+            if sell_left == 0 {
+                j += 1;
+                sell_left = sell_fills.get(j).map_or(0, |f| f.1);
+            }
+        }
+        debug!(
+            "Batch auction {} cleared {} at {}",
+            book.market_id, volume, label
+        );
+    }
+
+    // Whatever is left of market and immediate-or-cancel orders expires, and
+    // so does a stop order the band keeps from its price
+    let mut leftovers = Vec::new();
+    for id in book.unpriced.iter().copied().chain(
+        book.bids
+            .values()
+            .chain(book.asks.values())
+            .flat_map(|l| l.orders.clone()),
+    ) {
+        let order = &orders[&id];
+        if matches!(order.time_in_force, TimeInForce::IOC | TimeInForce::FOK)
+            || matches!(order.order_type, OrderType::Market)
+            || execution_limit(order, band)? != limit_price(order)?
+        {
+            leftovers.push(id);
+        }
+    }
+    for id in &leftovers {
+        let order = orders.get_mut(id).expect("order exists");
+        book.unrest(order)?;
+        order.status = OrderStatus::Cancelled;
+        order.updated_at = time;
+    }
+
+    Ok(AuctionResult {
+        trades,
+        expired: leftovers,
+    })
+}
+
+/// Split `volume` across prioritised orders
+///
+/// Orders sharing the same price and arrival block form one group that is
+/// filled pro rata; rounding remainders go to the larger orders first.
+fn allocate(bids: &[Bid], volume: u128) -> Result<Vec<(OrderId, u128)>> {
+    let mut fills = Vec::new();
+    let mut left = volume;
+    let mut start = 0;
+    while start < bids.len() && left > 0 {
+        let end = start
+            + bids[start..]
+                .iter()
+                .take_while(|b| {
+                    b.limit == bids[start].limit && b.created_at == bids[start].created_at
+                })
+                .count();
+        let group = &bids[start..end];
+        let total = sum(group.iter().map(|b| b.quantity))?;
+
+        if total <= left {
+            fills.extend(group.iter().map(|b| (b.id, b.quantity)));
+            left -= total;
+        } else {
+            let mut shares = group
+                .iter()
+                .map(|b| Ok((b.id, mul_div(b.quantity, left, total)?, b.quantity)))
+                .collect::<Result<Vec<(OrderId, u128, u128)>>>()?;
+            // Each share is rounded down, so they sum to at most `left`
+            let mut remainder = left - shares.iter().map(|s| s.1).sum::<u128>();
+            shares.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(&b.0)));
+            for share in shares.iter_mut() {
+                if remainder == 0 {
+                    break;
+                }
+                if share.1 < share.2 {
+                    share.1 += 1;
+                    remainder -= 1;
+                }
+            }
+            fills.extend(shares.into_iter().filter(|s| s.1 > 0).map(|s| (s.0, s.1)));
+            left = 0;
+        }
+        start = end;
+    }
+    Ok(fills)
+}
+
+/// Add two quantities, failing instead of wrapping
+fn add(a: u128, b: u128) -> Result<u128> {
+    a.checked_add(b)
+        .ok_or_else(|| NumberError::Overflow(format!("{} + {}", a, b)).into())
+}
+
+/// Sum quantities, failing instead of wrapping
+fn sum(mut quantities: impl Iterator<Item = u128>) -> Result<u128> {
+    quantities.try_fold(0, add)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn market(mode: MatchingMode) -> Market {
+        Market {
+            id: "ETH-USD".to_string(),
+            base_asset: "ETH".to_string(),
+            quote_asset: "USD".to_string(),
+            min_order_size: "1".to_string(),
+            lot_size: "1".to_string(),
+            tick_size: "1".to_string(),
+            market_type: MarketType::Spot,
+            status: MarketStatus::Active,
+            matching_mode: mode,
+        }
+    }
+
+    fn order(
+        id: OrderId,
+        trader: &str,
+        side: OrderSide,
+        quantity: u128,
+        price: &str,
+        block: i64,
+    ) -> Order {
+        let time = Timestamp::from_timestamp(block, 0).unwrap();
+        Order {
+            id,
+            trader: trader.to_string(),
+            market: "ETH-USD".to_string(),
+            side,
+            order_type: OrderType::Limit,
+            quantity: quantity.to_string(),
+            price: Some(price.to_string()),
+            filled_quantity: "0".to_string(),
+            status: OrderStatus::Submitted,
+            created_at: time,
+            updated_at: time,
+            time_in_force: TimeInForce::GTC,
+            locked: "0".to_string(),
+            filled_value: "0".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_continuous_price_time_priority() {
+        let mut engine = TradingEngine::new();
+        engine
+            .add_market(&market(MatchingMode::Continuous))
+            .unwrap();
+        let (mut orders, mut trade_id) = (HashMap::new(), 1);
+
+        engine
+            .place_order(
+                order(1, "a", OrderSide::Sell, 5, "101", 1),
+                &mut orders,
+                &mut trade_id,
+                None,
+            )
+            .unwrap();
+        engine
+            .place_order(
+                order(2, "b", OrderSide::Sell, 5, "100", 1),
+                &mut orders,
+                &mut trade_id,
+                None,
+            )
+            .unwrap();
+        engine
+            .place_order(
+                order(3, "c", OrderSide::Sell, 5, "100", 1),
+                &mut orders,
+                &mut trade_id,
+                None,
+            )
+            .unwrap();
+
+        let trades = engine
+            .place_order(
+                order(4, "d", OrderSide::Buy, 12, "101", 2),
+                &mut orders,
+                &mut trade_id,
+                None,
+            )
+            .unwrap();
+        let fills: Vec<_> = trades
+            .iter()
+            .map(|t| (t.sell_order_id, t.quantity.as_str(), t.price.as_str()))
+            .collect();
+        assert_eq!(
+            fills,
+            vec![(2, "5", "100"), (3, "5", "100"), (1, "2", "101")]
+        );
+        assert!(matches!(orders[&4].status, OrderStatus::Filled));
+        assert_eq!(orders[&1].filled_quantity, "2");
+        assert_eq!(
+            engine.get_order_book(&"ETH-USD".to_string()).unwrap().asks[&Decimal::from(101)]
+                .total_quantity,
+            "3"
+        );
+    }
+
+    #[test]
+    fn test_batch_auction_is_independent_of_arrival_order() {
+        let run = |arrival: &[OrderId]| {
+            let mut engine = TradingEngine::new();
+            engine
+                .add_market(&market(MatchingMode::BatchAuction))
+                .unwrap();
+            let (mut orders, mut trade_id) = (HashMap::new(), 1);
+            let all = [
+                order(1, "maker", OrderSide::Sell, 10, "100", 1),
+                order(2, "x", OrderSide::Buy, 6, "102", 1),
+                order(3, "y", OrderSide::Buy, 6, "102", 1),
+                order(4, "z", OrderSide::Buy, 6, "99", 1),
+            ];
+            for id in arrival {
+                let trades = engine
+                    .place_order(
+                        all[*id as usize - 1].clone(),
+                        &mut orders,
+                        &mut trade_id,
+                        None,
+                    )
+                    .unwrap();
+                assert!(trades.is_empty());
+            }
+            let trades = engine
+                .run_auctions(
+                    &mut orders,
+                    &mut trade_id,
+                    Timestamp::UNIX_EPOCH,
+                    &BTreeMap::new(),
+                )
+                .unwrap()
+                .trades;
+            // 100 and 102 clear the same volume; the lower median wins the tie
+            assert!(trades.iter().all(|t| t.price == "100"));
+            let mut fills: Vec<_> = [2, 3, 4]
+                .iter()
+                .map(|id| orders[id].filled_quantity.clone())
+                .collect();
+            fills.push(orders[&1].filled_quantity.clone());
+            fills
+        };
+
+        // Both equal bids share the maker's 10 units regardless of their order
+        assert_eq!(run(&[1, 2, 3, 4]), vec!["5", "5", "0", "10"]);
+        assert_eq!(run(&[4, 3, 2, 1]), vec!["5", "5", "0", "10"]);
+    }
+
+    #[test]
+    fn test_market_and_stop_orders_stay_within_the_band() {
+        let mut engine = TradingEngine::new();
+        engine
+            .add_market(&market(MatchingMode::Continuous))
+            .unwrap();
+        let (mut orders, mut trade_id) = (HashMap::new(), 1);
+        let band = Some(PriceBand {
+            low: Decimal::from(90),
+            high: Decimal::from(110),
+        });
+        let typed = |order_type: OrderType, mut order: Order| {
+            if order_type == OrderType::Market {
+                order.price = None;
+            }
+            order.order_type = order_type;
+            order
+        };
+        for (id, price) in [(1, "100"), (2, "105"), (3, "120")] {
+            engine
+                .place_order(
+                    order(id, "maker", OrderSide::Sell, 1, price, 1),
+                    &mut orders,
+                    &mut trade_id,
+                    band,
+                )
+                .unwrap();
+        }
+
+        // A market buy sweeps no higher than the top of the band
+        let trades = engine
+            .place_order(
+                typed(OrderType::Market, order(4, "x", OrderSide::Buy, 3, "0", 2)),
+                &mut orders,
+                &mut trade_id,
+                band,
+            )
+            .unwrap();
+        let prices: Vec<_> = trades.iter().map(|t| t.price.as_str()).collect();
+        assert_eq!(prices, ["100", "105"]);
+        assert!(matches!(orders[&4].status, OrderStatus::Cancelled));
+
+        // A stop order is capped too, and does not rest beyond the band
+        engine
+            .place_order(
+                typed(OrderType::Stop, order(5, "x", OrderSide::Buy, 1, "130", 2)),
+                &mut orders,
+                &mut trade_id,
+                band,
+            )
+            .unwrap();
+        assert!(matches!(orders[&5].status, OrderStatus::Cancelled));
+        let book = engine.get_order_book(&"ETH-USD".to_string()).unwrap();
+        assert!(book.bids.is_empty());
+
+        // Inside the band a stop order fills and rests like a limit order
+        engine
+            .place_order(
+                order(6, "maker", OrderSide::Sell, 1, "107", 2),
+                &mut orders,
+                &mut trade_id,
+                band,
+            )
+            .unwrap();
+        let trades = engine
+            .place_order(
+                typed(OrderType::Stop, order(7, "x", OrderSide::Buy, 2, "108", 2)),
+                &mut orders,
+                &mut trade_id,
+                band,
+            )
+            .unwrap();
+        assert_eq!(trades.len(), 1);
+        let book = engine.get_order_book(&"ETH-USD".to_string()).unwrap();
+        assert_eq!(book.bids[&Decimal::from(108)].orders, [7]);
+
+        // Batch auctions hold market orders to the band as well
+        engine
+            .add_market(&Market {
+                id: "BTC-USD".to_string(),
+                ..market(MatchingMode::BatchAuction)
+            })
+            .unwrap();
+        let btc = |order: Order| Order {
+            market: "BTC-USD".to_string(),
+            ..order
+        };
+        for (id, price) in [(8, "80"), (9, "95")] {
+            engine
+                .place_order(
+                    btc(order(id, "maker", OrderSide::Buy, 1, price, 3)),
+                    &mut orders,
+                    &mut trade_id,
+                    None,
+                )
+                .unwrap();
+        }
+        engine
+            .place_order(
+                btc(typed(
+                    OrderType::Market,
+                    order(10, "y", OrderSide::Sell, 2, "0", 3),
+                )),
+                &mut orders,
+                &mut trade_id,
+                None,
+            )
+            .unwrap();
+        let bands = [("BTC-USD".to_string(), band.unwrap())].into();
+        let result = engine
+            .run_auctions(&mut orders, &mut trade_id, Timestamp::UNIX_EPOCH, &bands)
+            .unwrap();
+        let fills: Vec<_> = result
+            .trades
+            .iter()
+            .map(|t| (t.buy_order_id, t.quantity.as_str(), t.price.as_str()))
+            .collect();
+        assert_eq!(fills, [(9, "1", "95")]);
+        assert_eq!(result.expired, [10]);
+    }
+
+    #[test]
+    fn test_huge_quantities_fail_instead_of_wrapping() {
+        let mut engine = TradingEngine::new();
+        engine
+            .add_market(&market(MatchingMode::BatchAuction))
+            .unwrap();
+        let (mut orders, mut trade_id) = (HashMap::new(), 1);
+
+        // Pro rata shares of 3 * 2^125 * 2^127 / (3 * 2^126) need more than 128 bits in between
+        let (bid, ask) = (3 << 125, 1 << 127);
+        engine
+            .place_order(
+                order(1, "maker", OrderSide::Sell, ask, "100", 1),
+                &mut orders,
+                &mut trade_id,
+                None,
+            )
+            .unwrap();
+        engine
+            .place_order(
+                order(2, "x", OrderSide::Buy, bid, "102", 1),
+                &mut orders,
+                &mut trade_id,
+                None,
+            )
+            .unwrap();
+        engine
+            .place_order(
+                order(3, "y", OrderSide::Buy, bid, "102", 1),
+                &mut orders,
+                &mut trade_id,
+                None,
+            )
+            .unwrap();
+        engine
+            .run_auctions(
+                &mut orders,
+                &mut trade_id,
+                Timestamp::UNIX_EPOCH,
+                &BTreeMap::new(),
+            )
+            .unwrap();
+        assert_eq!(orders[&2].filled_quantity, (1u128 << 126).to_string());
+        assert_eq!(orders[&3].filled_quantity, (1u128 << 126).to_string());
+
+        // A price level holding more than u128::MAX is rejected
+        let half = u128::MAX / 2 + 1;
+        engine
+            .place_order(
+                order(4, "x", OrderSide::Buy, half, "90", 2),
+                &mut orders,
+                &mut trade_id,
+                None,
+            )
+            .unwrap();
+        let error = engine
+            .place_order(
+                order(5, "y", OrderSide::Buy, half, "90", 2),
+                &mut orders,
+                &mut trade_id,
+                None,
+            )
+            .unwrap_err();
+        assert_eq!(error.code(), codes::INVALID_NUMBER);
+        assert!(matches!(
+            error,
+            TradingError::Number(NumberError::Overflow(_))
+        ));
+    }
+}
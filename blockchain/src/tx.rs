@@ -0,0 +1,301 @@
+//! Transaction Module
+//!
+//! This module defines the signed transaction format accepted by the chain
+//! and the messages a transaction can carry.
+
+use crate::bridge::{Attestation, DepositClaim};
+use crate::codes;
+use crate::governance::{ProposalContent, ProposalId, VoteOption};
+use crate::keys::KeyPair;
+use crate::oracle::AssetPrice;
+use crate::types::*;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+/// Hex-encoded SHA-256 hash of the raw transaction bytes
+pub type TxHash = String;
+
+/// Gas charged for every transaction
+pub const GAS_PER_TX: u64 = 20_000;
+
+/// Gas charged per byte of the encoded transaction
+pub const GAS_PER_BYTE: u64 = 10;
+
+/// Gas charged per message
+pub const GAS_PER_MSG: u64 = 10_000;
+
+/// Reasons a transaction is not accepted for execution
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TxError {
+    #[error("failed to decode transaction: {0}")]
+    Decode(String),
+
+    #[error("transaction contains no messages")]
+    NoMessages,
+
+    #[error("wrong chain_id '{got}', expected '{expected}'")]
+    WrongChain { got: String, expected: String },
+
+    #[error("transaction timed out at height {0}")]
+    TimedOut(u64),
+
+    #[error("unknown sender {0}")]
+    UnknownSender(AccAddress),
+
+    #[error("unauthorized: {0}")]
+    Unauthorized(String),
+
+    #[error("sequence {sequence} already used, account sequence is {expected}")]
+    SequenceUsed { sequence: u64, expected: u64 },
+
+    #[error("invalid sequence {sequence}, expected {expected}")]
+    SequenceMismatch { sequence: u64, expected: u64 },
+
+    #[error("transaction of {size} bytes exceeds the {limit} byte limit")]
+    TooLarge { size: u64, limit: u64 },
+
+    #[error("transaction {0} is already in the mempool")]
+    Duplicate(TxHash),
+
+    #[error("mempool is full")]
+    MempoolFull,
+}
+
+impl TxError {
+    /// Stable result code, see [`crate::codes`]
+    pub fn code(&self) -> u32 {
+        match self {
+            TxError::Decode(_) => codes::TX_DECODE,
+            TxError::NoMessages => codes::TX_NO_MESSAGES,
+            TxError::WrongChain { .. } => codes::TX_WRONG_CHAIN,
+            TxError::TimedOut(_) => codes::TX_TIMED_OUT,
+            TxError::UnknownSender(_) => codes::TX_UNKNOWN_SENDER,
+            TxError::Unauthorized(_) => codes::TX_UNAUTHORIZED,
+            TxError::SequenceUsed { .. } => codes::TX_SEQUENCE_USED,
+            TxError::SequenceMismatch { .. } => codes::TX_SEQUENCE_MISMATCH,
+            TxError::TooLarge { .. } => codes::TX_TOO_LARGE,
+            TxError::Duplicate(_) => codes::TX_DUPLICATE,
+            TxError::MempoolFull => codes::TX_MEMPOOL_FULL,
+        }
+    }
+}
+
+/// A signed transaction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tx {
+    pub body: TxBody,
+
+    /// Hex-encoded ed25519 public key of the sender
+    pub public_key: String,
+
+    /// Hex-encoded ed25519 signature over the body's sign bytes
+    pub signature: String,
+}
+
+/// Transaction body covered by the signature
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TxBody {
+    pub chain_id: String,
+    pub sender: AccAddress,
+
+    /// Must equal the sender's account sequence when executed
+    pub sequence: u64,
+
+    /// Last block height the transaction may be included in (0 = no limit)
+    #[serde(default)]
+    pub timeout_height: u64,
+
+    pub msgs: Vec<Msg>,
+
+    #[serde(default)]
+    pub memo: String,
+}
+
+/// Transaction messages
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Msg {
+    /// Place a new order
+    PlaceOrder {
+        market: MarketId,
+        side: OrderSide,
+        order_type: OrderType,
+        quantity: Uint128,
+        price: Option<Price>,
+        time_in_force: TimeInForce,
+    },
+
+    /// Cancel a resting order
+    CancelOrder { order_id: OrderId },
+
+    /// Send coins to another account
+    Send { to: AccAddress, amount: Vec<Coin> },
+
+    /// Send coins from the sender to several accounts
+    MultiSend { outputs: Vec<Output> },
+
+    /// Mint a deposit made on another chain, signed by the bridge attesters
+    Deposit {
+        claim: DepositClaim,
+        attestations: Vec<Attestation>,
+    },
+
+    /// Burn coins and request their release on another chain
+    Withdraw {
+        asset: AssetId,
+        amount: Uint128,
+        destination: String,
+    },
+
+    /// Submit oracle prices as a feeder
+    SubmitPrices { prices: Vec<AssetPrice> },
+
+    /// Create a validator operated by the sender, bonding `amount` to it
+    CreateValidator {
+        consensus_pubkey: String,
+        commission: Commission,
+        amount: Uint128,
+    },
+
+    /// Change the commission rate of the validator operated by the sender
+    EditValidator { commission_rate: Price },
+
+    /// Bond native tokens to a validator
+    Delegate {
+        validator: ValAddress,
+        amount: Uint128,
+    },
+
+    /// Start unbonding tokens from a validator
+    Undelegate {
+        validator: ValAddress,
+        amount: Uint128,
+    },
+
+    /// Move bonded tokens from one validator to another
+    Redelegate {
+        src_validator: ValAddress,
+        dst_validator: ValAddress,
+        amount: Uint128,
+    },
+
+    /// Withdraw the rewards earned by a delegation
+    WithdrawRewards { validator: ValAddress },
+
+    /// Withdraw the commission of the validator operated by the sender
+    WithdrawCommission,
+
+    /// Release the validator operated by the sender from jail
+    Unjail,
+
+    /// Submit a governance proposal with an initial deposit of native tokens
+    SubmitProposal {
+        title: String,
+        description: String,
+        content: ProposalContent,
+        initial_deposit: Uint128,
+    },
+
+    /// Add native tokens to the deposit of a proposal
+    DepositProposal {
+        proposal_id: ProposalId,
+        amount: Uint128,
+    },
+
+    /// Vote on a proposal in its voting period
+    Vote {
+        proposal_id: ProposalId,
+        option: VoteOption,
+    },
+
+    /// List a new market as the market admin
+    ListMarket { market: Market },
+
+    /// Put a market in cancel-only mode as the market admin
+    SuspendMarket { market: MarketId },
+
+    /// Reopen a suspended market as the market admin
+    ResumeMarket { market: MarketId },
+
+    /// Close a market for good as the market admin, cancelling its orders
+    CloseMarket { market: MarketId },
+
+    /// Switch a market between continuous matching and batch auctions as
+    /// the market admin
+    SetMatchingMode {
+        market: MarketId,
+        mode: MatchingMode,
+    },
+}
+
+/// Recipient of a multi-send
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Output {
+    pub address: AccAddress,
+    pub coins: Vec<Coin>,
+}
+
+impl Msg {
+    /// Whether the message only removes liquidity from the book
+    pub fn is_cancel(&self) -> bool {
+        matches!(self, Msg::CancelOrder { .. })
+    }
+}
+
+impl TxBody {
+    /// Canonical bytes that are signed
+    pub fn sign_bytes(&self) -> Result<Vec<u8>> {
+        let value = serde_json::to_value(self)?;
+        Ok(serde_json::to_vec(&value)?)
+    }
+}
+
+impl Tx {
+    /// Sign `body` with `key`
+    pub fn sign(body: TxBody, key: &KeyPair) -> Result<Self> {
+        let signature = key.sign_hex(&body.sign_bytes()?);
+        Ok(Self {
+            body,
+            public_key: key.public_key_hex(),
+            signature,
+        })
+    }
+
+    /// Decode a transaction from its wire bytes
+    pub fn decode(tx_bytes: &[u8]) -> Result<Self, TxError> {
+        let tx: Tx =
+            serde_json::from_slice(tx_bytes).map_err(|e| TxError::Decode(e.to_string()))?;
+        if tx.body.msgs.is_empty() {
+            return Err(TxError::NoMessages);
+        }
+        Ok(tx)
+    }
+
+    /// Encode the transaction to its wire bytes
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    /// Whether every message in the transaction is a cancellation
+    pub fn is_cancel_only(&self) -> bool {
+        self.body.msgs.iter().all(Msg::is_cancel)
+    }
+}
+
+/// Whether a transaction with `timeout_height` may no longer be included at
+/// `height`; it stays valid while `height <= timeout_height`, and zero never
+/// times out
+pub fn timed_out(timeout_height: u64, height: u64) -> bool {
+    timeout_height != 0 && timeout_height < height
+}
+
+/// Gas used by a transaction of `size` bytes with `msgs` messages
+pub fn gas_used(size: usize, msgs: usize) -> u64 {
+    GAS_PER_TX + GAS_PER_BYTE * size as u64 + GAS_PER_MSG * msgs as u64
+}
+
+/// Hash raw transaction bytes
+pub fn tx_hash(tx_bytes: &[u8]) -> TxHash {
+    hex::encode(Sha256::digest(tx_bytes))
+}
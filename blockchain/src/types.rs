@@ -1,11 +1,11 @@
 //! Core Types for Optimic Protocol
-//! 
+//!
 //! This module defines the fundamental data structures used throughout
 //! the Optimic blockchain, including accounts, transactions, orders, and options.
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::fmt;
-use chrono::{DateTime, Utc};
 
 /// Account address type
 pub type AccAddress = String;
@@ -50,6 +50,28 @@ pub struct Account {
     pub balances: std::collections::HashMap<AssetId, Uint128>,
 }
 
+/// An amount of one asset
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Coin {
+    pub denom: AssetId,
+    pub amount: Uint128,
+}
+
+/// Registry entry describing an asset
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AssetMetadata {
+    pub id: AssetId,
+
+    /// Symbol shown to users
+    pub display: String,
+
+    /// Base units per display unit, as a power of ten
+    pub decimals: u8,
+
+    #[serde(default)]
+    pub description: String,
+}
+
 /// Validator structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Validator {
@@ -73,9 +95,13 @@ pub enum BondStatus {
 /// Commission structure for validators
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Commission {
-    pub rate: String,           // Commission rate (e.g., "0.10" for 10%)
-    pub max_rate: String,       // Maximum commission rate
+    pub rate: String,            // Commission rate (e.g., "0.10" for 10%)
+    pub max_rate: String,        // Maximum commission rate
     pub max_change_rate: String, // Maximum daily change rate
+
+    /// Block time of the last rate change
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update_time: Option<Timestamp>,
 }
 
 /// Order structure
@@ -93,17 +119,29 @@ pub struct Order {
     pub created_at: Timestamp,
     pub updated_at: Timestamp,
     pub time_in_force: TimeInForce,
+
+    /// Funds still reserved for the unfilled part of the order
+    #[serde(default = "zero_amount")]
+    pub locked: Uint128,
+
+    /// Exact quote value of the fills so far
+    #[serde(default = "zero_amount")]
+    pub filled_value: Price,
+}
+
+fn zero_amount() -> Uint128 {
+    "0".to_string()
 }
 
 /// Order side (Buy or Sell)
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum OrderSide {
     Buy,
     Sell,
 }
 
 /// Order type
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum OrderType {
     Market,
     Limit,
@@ -126,9 +164,9 @@ pub enum OrderStatus {
 /// Time in force for orders
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TimeInForce {
-    GTC, // Good Till Cancelled
-    IOC, // Immediate Or Cancel
-    FOK, // Fill Or Kill
+    GTC,            // Good Till Cancelled
+    IOC,            // Immediate Or Cancel
+    FOK,            // Fill Or Kill
     GTD(Timestamp), // Good Till Date
 }
 
@@ -139,9 +177,18 @@ pub struct Market {
     pub base_asset: AssetId,
     pub quote_asset: AssetId,
     pub min_order_size: Uint128,
+    /// Order quantities are multiples of the lot size
+    #[serde(default = "default_lot_size")]
+    pub lot_size: Uint128,
     pub tick_size: Price,
     pub market_type: MarketType,
     pub status: MarketStatus,
+    #[serde(default)]
+    pub matching_mode: MatchingMode,
+}
+
+fn default_lot_size() -> Uint128 {
+    "1".to_string()
 }
 
 /// Market type
@@ -151,11 +198,24 @@ pub enum MarketType {
     Options,
 }
 
+/// Order matching mode of a market
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MatchingMode {
+    /// Orders match on arrival with price-time priority
+    #[default]
+    Continuous,
+    /// Orders collected in a block clear together at one uniform price
+    BatchAuction,
+}
+
 /// Market status
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum MarketStatus {
+    /// Orders are accepted and matched
     Active,
+    /// Cancel-only: resting orders stay on the book but nothing matches
     Suspended,
+    /// Delisted for good; every resting order was cancelled
     Closed,
 }
 
@@ -242,6 +302,10 @@ pub struct Position {
     pub quantity: Int128, // Positive for long, negative for short
     pub average_price: Price,
     pub unrealized_pnl: Int128,
+
+    /// P&L realized by reducing or flipping this position
+    #[serde(default = "zero_amount")]
+    pub realized_pnl: Int128,
     pub last_update: Timestamp,
 }
 
@@ -255,6 +319,16 @@ pub struct Greeks {
     pub rho: f64,
 }
 
+impl Order {
+    /// Whether the order can still be filled or cancelled
+    pub fn is_open(&self) -> bool {
+        matches!(
+            self.status,
+            OrderStatus::Pending | OrderStatus::Submitted | OrderStatus::PartiallyFilled
+        )
+    }
+}
+
 /// Display implementations
 impl fmt::Display for OrderSide {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -276,6 +350,25 @@ impl fmt::Display for OrderType {
     }
 }
 
+impl fmt::Display for MatchingMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatchingMode::Continuous => write!(f, "Continuous"),
+            MatchingMode::BatchAuction => write!(f, "BatchAuction"),
+        }
+    }
+}
+
+impl fmt::Display for MarketStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MarketStatus::Active => write!(f, "Active"),
+            MarketStatus::Suspended => write!(f, "Suspended"),
+            MarketStatus::Closed => write!(f, "Closed"),
+        }
+    }
+}
+
 impl fmt::Display for OrderStatus {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {